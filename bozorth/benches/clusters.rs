@@ -0,0 +1,105 @@
+#![feature(test)]
+
+extern crate test;
+
+use std::time::Duration;
+
+use test::Bencher;
+
+use bozorth::{
+    find_edges, limit_edges, match_edges_into_pairs, match_score, match_score_with, BozorthState,
+    Degrees, Edge, Format, MatchConfig, Minutia, MinutiaKind, PairHolder, SortedEdges,
+};
+
+/// Minutiae on a slightly jittered square lattice with a few directions repeated all over it,
+/// so that many small clusters of different rotations are found.
+fn lattice(side: i32, spacing: i32, rotation: i32) -> (Vec<Minutia>, Vec<Edge>) {
+    let mut minutiae = vec![];
+    for x in 0..side {
+        for y in 0..side {
+            minutiae.push(Minutia {
+                x: 20 + x * spacing + (x * 7 + y * 13) % 5,
+                y: 20 + y * spacing + (x * 11 + y * 5) % 5,
+                theta: Degrees(((x * 37 + y * 11) % 8) * 45 + rotation),
+                kind: MinutiaKind::Ending,
+                quality: 0,
+            });
+        }
+    }
+    minutiae.sort_by_key(|it| (it.x, it.y));
+
+    let mut edges = vec![];
+    find_edges(&minutiae, &mut edges, Format::NistInternal);
+    edges.truncate(limit_edges(&edges));
+    (minutiae, edges)
+}
+
+type Lattice = (Vec<Minutia>, Vec<Edge>);
+
+/// Pairs of lattices with hundreds of clusters, close to the most that fit in the pair limit,
+/// so that the search for compatible clusters is a large part of the time.
+fn many_clusters() -> (Lattice, Lattice, PairHolder) {
+    let probe = lattice(10, 20, 0);
+    let gallery = lattice(10, 21, 3);
+
+    let mut pairs = PairHolder::new();
+    match_edges_into_pairs(
+        SortedEdges::from_sorted_unchecked(&probe.1),
+        &probe.0,
+        SortedEdges::from_sorted_unchecked(&gallery.1),
+        &gallery.0,
+        &mut pairs,
+        &MatchConfig::default(),
+        |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+    );
+    pairs.prepare();
+    (probe, gallery, pairs)
+}
+
+#[bench]
+fn many_clusters_of_different_rotations(b: &mut Bencher) {
+    let (probe, gallery, pairs) = many_clusters();
+    let mut state = BozorthState::new();
+    match_score(
+        &pairs,
+        &probe.0,
+        &gallery.0,
+        Format::NistInternal,
+        &mut state,
+    )
+    .unwrap();
+    assert!(state.cluster_count() > 500);
+
+    b.iter(|| {
+        match_score(
+            &pairs,
+            &probe.0,
+            &gallery.0,
+            Format::NistInternal,
+            &mut state,
+        )
+    });
+}
+
+/// Same as `many_clusters_of_different_rotations` with a deadline that never passes, for the
+/// cost of checking it.
+#[bench]
+fn many_clusters_with_a_deadline(b: &mut Bencher) {
+    let (probe, gallery, pairs) = many_clusters();
+    let mut state = BozorthState::new();
+    let config = MatchConfig {
+        deadline: Some(Duration::from_secs(3600)),
+        ..MatchConfig::default()
+    };
+
+    b.iter(|| {
+        match_score_with(
+            &pairs,
+            &probe.0,
+            &gallery.0,
+            Format::NistInternal,
+            &mut state,
+            &config,
+        )
+    });
+}
@@ -0,0 +1,72 @@
+#![feature(test)]
+
+extern crate test;
+
+use test::Bencher;
+
+use bozorth::{
+    find_edges, limit_edges, match_edges_into_pairs, Degrees, Edge, Format, MatchConfig, Minutia,
+    MinutiaKind, PairHolder, SortedEdges,
+};
+
+/// Minutiae on a square lattice; `spacing` controls the lengths of all edges.
+fn lattice(side: i32, spacing: i32) -> (Vec<Minutia>, Vec<Edge>) {
+    let mut minutiae = vec![];
+    for x in 0..side {
+        for y in 0..side {
+            minutiae.push(Minutia {
+                x: 20 + x * spacing,
+                y: 20 + y * spacing,
+                theta: Degrees((x * 37 + y * 11) % 180),
+                kind: MinutiaKind::Ending,
+                quality: 0,
+            });
+        }
+    }
+
+    let mut edges = vec![];
+    find_edges(&minutiae, &mut edges, Format::NistInternal);
+    edges.truncate(limit_edges(&edges));
+    (minutiae, edges)
+}
+
+fn bench_pairs(
+    b: &mut Bencher,
+    probe: (Vec<Minutia>, Vec<Edge>),
+    gallery: (Vec<Minutia>, Vec<Edge>),
+) {
+    let mut pairs = PairHolder::new();
+    b.iter(|| {
+        // clearing is skipped when there is nothing to clear, so that only matching is measured
+        if !pairs.pairs().is_empty() {
+            pairs.clear();
+        }
+        match_edges_into_pairs(
+            SortedEdges::from_sorted_unchecked(&probe.1),
+            &probe.0,
+            SortedEdges::from_sorted_unchecked(&gallery.1),
+            &gallery.0,
+            &mut pairs,
+            &MatchConfig::default(),
+            |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+        );
+        pairs.len()
+    });
+}
+
+/// All edges of the probe are much shorter than any edge of the gallery.
+#[bench]
+fn disjoint_distance_ranges_short_probe(b: &mut Bencher) {
+    bench_pairs(b, lattice(6, 3), lattice(6, 60));
+}
+
+/// All edges of the probe are much longer than any edge of the gallery.
+#[bench]
+fn disjoint_distance_ranges_long_probe(b: &mut Bencher) {
+    bench_pairs(b, lattice(6, 60), lattice(6, 3));
+}
+
+#[bench]
+fn overlapping_distance_ranges(b: &mut Bencher) {
+    bench_pairs(b, lattice(6, 20), lattice(6, 21));
+}
@@ -0,0 +1,59 @@
+#![feature(test)]
+
+extern crate test;
+
+use test::{black_box, Bencher};
+
+use bozorth::{find_edges, timeit, Degrees, Edge, Format, Minutia, MinutiaKind};
+
+/// Minutiae on a square lattice, with edges of many lengths.
+fn lattice(side: i32) -> Vec<Minutia> {
+    let mut minutiae = vec![];
+    for x in 0..side {
+        for y in 0..side {
+            minutiae.push(Minutia {
+                x: 20 + x * 17,
+                y: 20 + y * 17,
+                theta: Degrees((x * 37 + y * 11) % 180),
+                kind: MinutiaKind::Ending,
+                quality: 0,
+            });
+        }
+    }
+    minutiae
+}
+
+/// Step much cheaper than any call site of `timeit`, so that overhead of the wrapper would show.
+#[inline(never)]
+fn sum_of_distances(edges: &[Edge]) -> i64 {
+    edges.iter().map(|it| i64::from(it.distance_squared)).sum()
+}
+
+fn edges() -> Vec<Edge> {
+    let mut edges = vec![];
+    find_edges(&lattice(4), &mut edges, Format::NistInternal);
+    edges
+}
+
+/// Baseline for `wrapped_in_timeit`.
+#[bench]
+fn direct_call(b: &mut Bencher) {
+    let edges = edges();
+    b.iter(|| {
+        (0..100)
+            .map(|_| sum_of_distances(black_box(&edges)))
+            .sum::<i64>()
+    });
+}
+
+/// Same work as `direct_call` with every call wrapped in `timeit`; without the `profiling`
+/// feature both should take the same time.
+#[bench]
+fn wrapped_in_timeit(b: &mut Bencher) {
+    let edges = edges();
+    b.iter(|| {
+        (0..100)
+            .map(|_| timeit(|| sum_of_distances(black_box(&edges))))
+            .sum::<i64>()
+    });
+}
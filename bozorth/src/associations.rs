@@ -1,17 +1,22 @@
 use crate::consts::MAX_NUMBER_OF_MINUTIAE;
 use crate::types::Endpoint;
 
-pub(crate) struct EndpointAssociations {
-    probe_by_gallery: [u8; MAX_NUMBER_OF_MINUTIAE],
-    gallery_by_probe: [u8; MAX_NUMBER_OF_MINUTIAE],
+/// Associations between probe and gallery minutiae with indices below `N`.
+///
+/// An entry holds the index of the associated minutia plus one, 0 means no association.
+/// The tables stay inline, a heap allocation made matching measurably slower.
+pub(crate) struct EndpointAssociations<const N: usize = MAX_NUMBER_OF_MINUTIAE> {
+    probe_by_gallery: [u16; N],
+    gallery_by_probe: [u16; N],
 }
 
-impl EndpointAssociations {
+impl<const N: usize> EndpointAssociations<N> {
     #[inline]
     pub(crate) fn new() -> Self {
+        const { assert!(N < u16::MAX as usize, "minutia indices must fit in u16") };
         Self {
-            probe_by_gallery: [0; MAX_NUMBER_OF_MINUTIAE],
-            gallery_by_probe: [0; MAX_NUMBER_OF_MINUTIAE],
+            probe_by_gallery: [0; N],
+            gallery_by_probe: [0; N],
         }
     }
 
@@ -21,10 +26,29 @@ impl EndpointAssociations {
         self.gallery_by_probe.iter_mut().for_each(|it| *it = 0);
     }
 
+    #[inline]
+    fn encode(endpoint: Endpoint) -> u16 {
+        debug_assert!(
+            endpoint.as_usize() < N,
+            "minutia index {} out of bounds",
+            endpoint.as_usize()
+        );
+        endpoint.as_usize() as u16 + 1
+    }
+
+    #[inline]
+    fn decode(value: u16) -> Option<Endpoint> {
+        if value != 0 {
+            Some(Endpoint(u32::from(value - 1)))
+        } else {
+            None
+        }
+    }
+
     #[inline]
     pub(crate) fn associate(&mut self, probe_endpoint: Endpoint, gallery_endpoint: Endpoint) {
-        self.probe_by_gallery[gallery_endpoint.as_usize()] = probe_endpoint.as_usize() as u8 + 1;
-        self.gallery_by_probe[probe_endpoint.as_usize()] = gallery_endpoint.as_usize() as u8 + 1;
+        self.probe_by_gallery[gallery_endpoint.as_usize()] = Self::encode(probe_endpoint);
+        self.gallery_by_probe[probe_endpoint.as_usize()] = Self::encode(gallery_endpoint);
     }
 
     #[inline]
@@ -38,22 +62,12 @@ impl EndpointAssociations {
 
     #[inline]
     pub(crate) fn get_associated_by_gallery(&self, gallery_endpoint: Endpoint) -> Option<Endpoint> {
-        let endpoint = self.probe_by_gallery[gallery_endpoint.as_usize()];
-        if endpoint != 0 {
-            Some((endpoint - 1).into())
-        } else {
-            None
-        }
+        Self::decode(self.probe_by_gallery[gallery_endpoint.as_usize()])
     }
 
     #[inline]
     pub(crate) fn get_associated_by_probe(&self, probe_endpoint: Endpoint) -> Option<Endpoint> {
-        let endpoint = self.gallery_by_probe[probe_endpoint.as_usize()];
-        if endpoint != 0 {
-            Some((endpoint - 1).into())
-        } else {
-            None
-        }
+        Self::decode(self.gallery_by_probe[probe_endpoint.as_usize()])
     }
 
     #[inline]
@@ -68,8 +82,8 @@ impl EndpointAssociations {
             return EndpointRelation::Unassociated;
         }
 
-        if associated_gallery == gallery_endpoint.as_usize() as u8 + 1
-            && associated_probe == probe_endpoint.as_usize() as u8 + 1
+        if associated_gallery == Self::encode(gallery_endpoint)
+            && associated_probe == Self::encode(probe_endpoint)
         {
             return EndpointRelation::MutuallyAssociated;
         }
@@ -83,3 +97,44 @@ pub(crate) enum EndpointRelation {
     MutuallyAssociated,
     AssociatedToOther,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::associations::{EndpointAssociations, EndpointRelation};
+    use crate::types::Endpoint;
+
+    #[test]
+    fn indices_past_255_are_associated() {
+        let mut associations = EndpointAssociations::<300>::new();
+        associations.associate(Endpoint(299), Endpoint(256));
+        associations.associate(Endpoint(255), Endpoint(0));
+
+        assert_eq!(
+            associations.get_associated_by_probe(Endpoint(299)),
+            Some(Endpoint(256))
+        );
+        assert_eq!(
+            associations.get_associated_by_gallery(Endpoint(256)),
+            Some(Endpoint(299))
+        );
+        assert_eq!(
+            associations.get_associated_by_gallery(Endpoint(0)),
+            Some(Endpoint(255))
+        );
+        assert!(matches!(
+            associations.get_status(Endpoint(299), Endpoint(256)),
+            EndpointRelation::MutuallyAssociated
+        ));
+        assert!(matches!(
+            associations.get_status(Endpoint(299), Endpoint(0)),
+            EndpointRelation::AssociatedToOther
+        ));
+
+        associations.clear_by_probe(Endpoint(299));
+        assert_eq!(associations.get_associated_by_gallery(Endpoint(256)), None);
+        assert!(matches!(
+            associations.get_status(Endpoint(299), Endpoint(256)),
+            EndpointRelation::Unassociated
+        ));
+    }
+}
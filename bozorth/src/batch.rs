@@ -0,0 +1,819 @@
+//! Matching of many pairs of templates on several threads, see `BatchMatcher`.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use crate::fusion::{fuse_scores, FingerPosition, FusionStrategy};
+use crate::parsing::RawMinutiaCombined;
+use crate::{
+    match_edges_into_pairs, match_score_with, timeit, BozorthState, Fingerprint, Format,
+    MatchConfig, MatchError, MatchFeatures, MatchPruning, Minutia, PairHolder,
+};
+
+/// Fingers of a template matched by a `BatchMatcher`; usually one, the scores of more of them
+/// are fused.
+///
+/// Templates without fingers, e.g. ones that could not be loaded, have no score.
+pub trait BatchTemplate {
+    fn fingers(&self) -> &[Fingerprint];
+}
+
+impl BatchTemplate for Fingerprint {
+    fn fingers(&self) -> &[Fingerprint] {
+        core::slice::from_ref(self)
+    }
+}
+
+impl BatchTemplate for [Fingerprint] {
+    fn fingers(&self) -> &[Fingerprint] {
+        self
+    }
+}
+
+impl BatchTemplate for Vec<Fingerprint> {
+    fn fingers(&self) -> &[Fingerprint] {
+        self
+    }
+}
+
+impl<T: BatchTemplate + ?Sized> BatchTemplate for Box<T> {
+    fn fingers(&self) -> &[Fingerprint] {
+        (**self).fingers()
+    }
+}
+
+impl<T: BatchTemplate + ?Sized> BatchTemplate for Arc<T> {
+    fn fingers(&self) -> &[Fingerprint] {
+        (**self).fingers()
+    }
+}
+
+impl<T: BatchTemplate> BatchTemplate for Option<T> {
+    fn fingers(&self) -> &[Fingerprint] {
+        match self {
+            Some(template) => template.fingers(),
+            None => &[],
+        }
+    }
+}
+
+/// Pairs of probes and galleries matched by a `BatchMatcher`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BatchMode {
+    /// The n-th probe with the n-th gallery; the rest of the longer list is not matched.
+    Pairwise,
+    /// Every probe with every gallery, probe by probe.
+    AllPairs,
+}
+
+impl BatchMode {
+    fn pair_count(self, probes: usize, galleries: usize) -> usize {
+        match self {
+            BatchMode::Pairwise => probes.min(galleries),
+            BatchMode::AllPairs => probes * galleries,
+        }
+    }
+
+    /// Probe and gallery of the pair with the `index` in the order of the mode.
+    fn pair(self, index: usize, galleries: usize) -> (usize, usize) {
+        match self {
+            BatchMode::Pairwise => (index, index),
+            BatchMode::AllPairs => (index / galleries, index % galleries),
+        }
+    }
+}
+
+/// How `match_templates` matches a pair of templates.
+#[derive(Debug, Copy, Clone)]
+pub struct BatchSettings {
+    pub format: Format,
+    pub config: MatchConfig,
+    /// Matches with fewer candidate pairs score 0 without running `match_score_with`.
+    pub min_pairs: usize,
+    /// Fuses the scores of fingers paired by their order; without it only the first fingers are
+    /// matched.
+    pub fusion: Option<FusionStrategy>,
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        BatchSettings {
+            format: Format::NistInternal,
+            config: MatchConfig::default(),
+            min_pairs: 0,
+            fusion: None,
+        }
+    }
+}
+
+/// Score of a pair of templates together with counters of the match.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct TemplateScore {
+    pub score: u32,
+    /// Matched minutiae pairs, see `BozorthState::matched_pairs`.
+    pub matched_pairs: usize,
+    /// Edges of both templates removed by `Fingerprint::thin_edges`.
+    pub thinned_edges: usize,
+    pub features: MatchFeatures,
+    /// The match ran out of `MatchConfig::deadline`; the score is the best one found until then.
+    pub timed_out: bool,
+}
+
+/// Matches two templates; `None` when either of them has no fingers.
+///
+/// Fused templates report the totals of all fingers, with the best cluster of any finger.
+pub fn match_templates(
+    probe: &[Fingerprint],
+    gallery: &[Fingerprint],
+    settings: &BatchSettings,
+    pairs: &mut PairHolder,
+    state: &mut BozorthState,
+) -> Option<TemplateScore> {
+    if probe.is_empty() || gallery.is_empty() {
+        return None;
+    }
+    let mut total = match settings.fusion {
+        None => match_finger(&probe[0], &gallery[0], settings, pairs, state),
+        Some(strategy) => {
+            // fingers are paired by their order; extra fingers are ignored
+            let mut total = TemplateScore {
+                score: 0,
+                matched_pairs: 0,
+                thinned_edges: 0,
+                features: MatchFeatures::default(),
+                timed_out: false,
+            };
+            let mut scores = vec![];
+            for (probe, gallery) in probe.iter().zip(gallery.iter()) {
+                let finger = match_finger(probe, gallery, settings, pairs, state);
+                total.matched_pairs += finger.matched_pairs;
+                total.features = add_features(total.features, finger.features);
+                total.timed_out |= finger.timed_out;
+                scores.push((finger.score, FingerPosition::UNKNOWN));
+            }
+            total.score = fuse_scores(&scores, strategy);
+            total
+        }
+    };
+    total.thinned_edges = probe
+        .iter()
+        .chain(gallery.iter())
+        .map(|it| it.thinned_edges)
+        .sum();
+    Some(total)
+}
+
+/// Features of fused templates: totals of all fingers, with the best cluster of any finger.
+fn add_features(total: MatchFeatures, finger: MatchFeatures) -> MatchFeatures {
+    MatchFeatures {
+        cluster_count: total.cluster_count + finger.cluster_count,
+        max_cluster_points: total.max_cluster_points.max(finger.max_cluster_points),
+        winning_combination_size: total.winning_combination_size + finger.winning_combination_size,
+        probe_endpoints: total.probe_endpoints + finger.probe_endpoints,
+        gallery_endpoints: total.gallery_endpoints + finger.gallery_endpoints,
+    }
+}
+
+/// Matches a single pair of fingers; `thinned_edges` is left for the caller.
+fn match_finger(
+    probe: &Fingerprint,
+    gallery: &Fingerprint,
+    settings: &BatchSettings,
+    pairs: &mut PairHolder,
+    state: &mut BozorthState,
+) -> TemplateScore {
+    let unmatched = TemplateScore {
+        score: 0,
+        matched_pairs: 0,
+        thinned_edges: 0,
+        features: MatchFeatures::default(),
+        timed_out: false,
+    };
+    pairs.clear();
+    state.clear();
+
+    timeit(|| {
+        match_edges_into_pairs(
+            probe.sorted_edges(),
+            &probe.minutiae,
+            gallery.sorted_edges(),
+            &gallery.minutiae,
+            pairs,
+            &settings.config,
+            |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+        )
+    });
+    if pairs.pairs().is_empty() {
+        return unmatched;
+    }
+    timeit(|| pairs.prepare());
+    if pairs.len() < settings.min_pairs {
+        return unmatched;
+    }
+
+    let result = timeit(|| {
+        match_score_with(
+            pairs,
+            &probe.minutiae,
+            &gallery.minutiae,
+            settings.format,
+            state,
+            &settings.config,
+        )
+    });
+    let (score, timed_out) = match result {
+        Ok((score, _)) => (score, false),
+        Err(MatchError::DeadlineExceeded { score }) => (score, true),
+        Err(MatchError::TooFewMinutiae | MatchError::Busy | MatchError::Panicked) => (0, false),
+    };
+    TemplateScore {
+        score,
+        matched_pairs: state.matched_pairs().len(),
+        features: state.match_features(),
+        timed_out,
+        ..unmatched
+    }
+}
+
+/// Result of a single pair of a batch.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MatchResult {
+    /// Index of the probe.
+    pub probe: usize,
+    /// Index of the gallery.
+    pub gallery: usize,
+    /// `None` when either template has no fingers.
+    pub score: Option<TemplateScore>,
+}
+
+/// Where a batch stopped on `BatchMatcher::stop_when`; all the pairs before it were matched.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BatchStop {
+    /// Number of pairs matched.
+    pub completed: usize,
+    /// Probe of the first pair that was not matched.
+    pub probe: usize,
+    /// Gallery of the first pair that was not matched.
+    pub gallery: usize,
+}
+
+type Filter = Arc<dyn Fn(&MatchResult) -> bool + Send + Sync>;
+type Stop = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// Matches pairs of probes and galleries on several threads and reports the results as they
+/// come, in the order of the pairs unless `relaxed_order` is set.
+///
+/// Setters consume and return the matcher, so it is configured like
+/// `BatchMatcher::new(probes, galleries).threads(8).run()`; it can run any number of times.
+pub struct BatchMatcher<T> {
+    probes: Arc<[T]>,
+    galleries: Arc<[T]>,
+    mode: BatchMode,
+    settings: BatchSettings,
+    threads: usize,
+    chunk_size: usize,
+    filter: Filter,
+    first_match_per_probe: bool,
+    relaxed_order: bool,
+    stop: Stop,
+}
+
+impl BatchMatcher<Fingerprint> {
+    /// Matches templates loaded from files, pruning probes and galleries with their own options
+    /// of the `pruning`; the edges are found in the `format` of `BatchSettings`, which is set.
+    pub fn from_raw<M: AsRef<[RawMinutiaCombined]>>(
+        probes: &[M],
+        galleries: &[M],
+        pruning: MatchPruning,
+        format: Format,
+    ) -> Self {
+        let fingerprints = |templates: &[M], options| -> Vec<_> {
+            templates
+                .iter()
+                .map(|it| Fingerprint::from_raw(it.as_ref(), options, format))
+                .collect()
+        };
+        BatchMatcher::new(
+            fingerprints(probes, pruning.probe),
+            fingerprints(galleries, pruning.gallery),
+        )
+        .settings(BatchSettings {
+            format,
+            ..BatchSettings::default()
+        })
+    }
+}
+
+impl<T: BatchTemplate + Send + Sync + 'static> BatchMatcher<T> {
+    /// Matches every probe with every gallery on all available cores, reporting every result.
+    pub fn new(probes: impl Into<Arc<[T]>>, galleries: impl Into<Arc<[T]>>) -> Self {
+        BatchMatcher {
+            probes: probes.into(),
+            galleries: galleries.into(),
+            mode: BatchMode::AllPairs,
+            settings: BatchSettings::default(),
+            threads: std::thread::available_parallelism().map_or(1, |it| it.get()),
+            chunk_size: 64,
+            filter: Arc::new(|_: &MatchResult| true),
+            first_match_per_probe: false,
+            relaxed_order: false,
+            stop: Arc::new(|| false),
+        }
+    }
+
+    pub fn mode(self, mode: BatchMode) -> Self {
+        BatchMatcher { mode, ..self }
+    }
+
+    pub fn settings(self, settings: BatchSettings) -> Self {
+        BatchMatcher { settings, ..self }
+    }
+
+    /// Number of matching threads, at least one.
+    pub fn threads(self, threads: usize) -> Self {
+        BatchMatcher {
+            threads: threads.max(1),
+            ..self
+        }
+    }
+
+    /// Most pairs a thread takes at once; batches too small to keep all threads busy with whole
+    /// chunks are split into smaller ones.
+    pub fn chunk_size(self, chunk_size: usize) -> Self {
+        BatchMatcher {
+            chunk_size: chunk_size.max(1),
+            ..self
+        }
+    }
+
+    /// Reports only the results the `filter` accepts; it is called on the matching threads.
+    pub fn filter(self, filter: impl Fn(&MatchResult) -> bool + Send + Sync + 'static) -> Self {
+        BatchMatcher {
+            filter: Arc::new(filter),
+            ..self
+        }
+    }
+
+    /// Reports only the first accepted result of every probe and skips its remaining pairs.
+    pub fn first_match_per_probe(self, first_match_per_probe: bool) -> Self {
+        BatchMatcher {
+            first_match_per_probe,
+            ..self
+        }
+    }
+
+    /// Reports results as soon as they are done instead of in the order of the pairs.
+    pub fn relaxed_order(self, relaxed_order: bool) -> Self {
+        BatchMatcher {
+            relaxed_order,
+            ..self
+        }
+    }
+
+    /// Stops taking new pairs once `stop` returns true; the pairs already taken are matched and
+    /// `BatchResults::stopped` tells where to continue.
+    pub fn stop_when(self, stop: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        BatchMatcher {
+            stop: Arc::new(stop),
+            ..self
+        }
+    }
+
+    /// Starts the matching threads; the results are read from the returned iterator.
+    pub fn run(&self) -> BatchResults {
+        let pair_count = self
+            .mode
+            .pair_count(self.probes.len(), self.galleries.len());
+        // every thread should get a few chunks, so that none of them waits for the others
+        let chunk_size = self.chunk_size.min(pair_count / (4 * self.threads)).max(1);
+        let progress = Arc::new(Progress {
+            next: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+        });
+        let first_matches: Arc<[AtomicUsize]> = if self.first_match_per_probe {
+            (0..self.probes.len())
+                .map(|_| AtomicUsize::new(usize::MAX))
+                .collect()
+        } else {
+            Arc::new([])
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let workers = (0..self.threads)
+            .map(|_| {
+                let worker = Worker {
+                    probes: self.probes.clone(),
+                    galleries: self.galleries.clone(),
+                    mode: self.mode,
+                    settings: self.settings,
+                    pair_count,
+                    chunk_size,
+                    filter: self.filter.clone(),
+                    stop: self.stop.clone(),
+                    progress: progress.clone(),
+                    first_matches: first_matches.clone(),
+                };
+                let tx = tx.clone();
+                std::thread::spawn(move || worker.run(tx))
+            })
+            .collect();
+
+        BatchResults {
+            results: Some(rx),
+            workers,
+            progress,
+            pending: BTreeMap::new(),
+            next: 0,
+            relaxed_order: self.relaxed_order,
+            reported_probes: self
+                .first_match_per_probe
+                .then(|| vec![false; self.probes.len()]),
+            mode: self.mode,
+            galleries: self.galleries.len(),
+            pair_count,
+            stopped: None,
+        }
+    }
+}
+
+/// State of a batch shared by its threads.
+struct Progress {
+    /// First pair that no thread has taken yet.
+    next: AtomicUsize,
+    /// A thread stopped on `BatchMatcher::stop_when`.
+    stopped: AtomicBool,
+    /// The results are not read anymore.
+    cancelled: AtomicBool,
+}
+
+struct Worker<T> {
+    probes: Arc<[T]>,
+    galleries: Arc<[T]>,
+    mode: BatchMode,
+    settings: BatchSettings,
+    pair_count: usize,
+    chunk_size: usize,
+    filter: Filter,
+    stop: Stop,
+    progress: Arc<Progress>,
+    /// Index of the first accepted pair of every probe, with `first_match_per_probe`.
+    first_matches: Arc<[AtomicUsize]>,
+}
+
+impl<T: BatchTemplate> Worker<T> {
+    /// Sends the index of every pair it takes, with its result unless it was skipped or filtered
+    /// out.
+    fn run(self, results: mpsc::Sender<(usize, Option<MatchResult>)>) {
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        loop {
+            if (self.stop)() {
+                self.progress.stopped.store(true, Ordering::SeqCst);
+                return;
+            }
+            let first = self
+                .progress
+                .next
+                .fetch_add(self.chunk_size, Ordering::SeqCst);
+            if first >= self.pair_count {
+                return;
+            }
+
+            for index in first..(first + self.chunk_size).min(self.pair_count) {
+                if self.progress.cancelled.load(Ordering::Relaxed) {
+                    return;
+                }
+                let (probe, gallery) = self.mode.pair(index, self.galleries.len());
+                let result = if self.is_probe_matched_before(probe, index) {
+                    None
+                } else {
+                    let result = MatchResult {
+                        probe,
+                        gallery,
+                        score: match_templates(
+                            self.probes[probe].fingers(),
+                            self.galleries[gallery].fingers(),
+                            &self.settings,
+                            &mut pairs,
+                            &mut state,
+                        ),
+                    };
+                    if (self.filter)(&result) {
+                        if let Some(first_match) = self.first_matches.get(probe) {
+                            first_match.fetch_min(index, Ordering::SeqCst);
+                        }
+                        Some(result)
+                    } else {
+                        None
+                    }
+                };
+                if results.send((index, result)).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn is_probe_matched_before(&self, probe: usize, index: usize) -> bool {
+        self.first_matches
+            .get(probe)
+            .is_some_and(|it| it.load(Ordering::SeqCst) < index)
+    }
+}
+
+/// Results of a running batch, see `BatchMatcher::run`.
+///
+/// Dropping it stops the threads after their current pair.
+pub struct BatchResults {
+    results: Option<mpsc::Receiver<(usize, Option<MatchResult>)>>,
+    workers: Vec<JoinHandle<()>>,
+    progress: Arc<Progress>,
+    /// Results done ahead of `next`, unless the order is relaxed.
+    pending: BTreeMap<usize, Option<MatchResult>>,
+    next: usize,
+    relaxed_order: bool,
+    /// Probes whose first match was reported, with `first_match_per_probe`.
+    reported_probes: Option<Vec<bool>>,
+    mode: BatchMode,
+    galleries: usize,
+    pair_count: usize,
+    stopped: Option<BatchStop>,
+}
+
+impl BatchResults {
+    /// Where the batch stopped on `BatchMatcher::stop_when`, known once all results were read.
+    pub fn stopped(&self) -> Option<BatchStop> {
+        self.stopped
+    }
+
+    /// Whether the result is reported; only the first one of a probe is with
+    /// `first_match_per_probe`.
+    fn report(&mut self, result: &MatchResult) -> bool {
+        match &mut self.reported_probes {
+            Some(reported) => !std::mem::replace(&mut reported[result.probe], true),
+            None => true,
+        }
+    }
+
+    /// Waits for the threads once they sent everything.
+    fn join(&mut self) {
+        for worker in self.workers.drain(..) {
+            worker.join().expect("a matching thread panicked");
+        }
+        let next = self
+            .progress
+            .next
+            .load(Ordering::SeqCst)
+            .min(self.pair_count);
+        if self.progress.stopped.load(Ordering::SeqCst) && next < self.pair_count {
+            let (probe, gallery) = self.mode.pair(next, self.galleries);
+            self.stopped = Some(BatchStop {
+                completed: next,
+                probe,
+                gallery,
+            });
+        }
+    }
+}
+
+impl Iterator for BatchResults {
+    type Item = MatchResult;
+
+    fn next(&mut self) -> Option<MatchResult> {
+        loop {
+            let done = match self.pending.remove(&self.next) {
+                Some(done) => {
+                    self.next += 1;
+                    done
+                }
+                None => match self.results.as_ref().map(|it| it.recv()) {
+                    Some(Ok((_, done))) if self.relaxed_order => done,
+                    Some(Ok((index, done))) => {
+                        self.pending.insert(index, done);
+                        continue;
+                    }
+                    Some(Err(_)) => {
+                        self.results = None;
+                        self.join();
+                        continue;
+                    }
+                    // all sent; a cancelled thread can leave gaps, the rest is reported in order
+                    None => match self.pending.pop_first() {
+                        Some((_, done)) => done,
+                        None => return None,
+                    },
+                },
+            };
+            if let Some(result) = done {
+                if self.report(&result) {
+                    return Some(result);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for BatchResults {
+    fn drop(&mut self) {
+        self.progress.cancelled.store(true, Ordering::SeqCst);
+        self.results = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use crate::batch::{match_templates, BatchMatcher, BatchMode, BatchSettings, BatchStop};
+    use crate::synthetic::Generator;
+    use crate::{BozorthState, Fingerprint, Format, MatchPruning, PairHolder, PruneOptions};
+
+    fn templates(count: usize) -> Vec<Fingerprint> {
+        let mut generator = Generator::new(29);
+        let template = generator.raw_template(40);
+        (0..count)
+            .map(|index| {
+                // every other template is an impression of the same finger
+                let raw = if index % 2 == 0 {
+                    generator.impression(&template, index as i32, (3, -2))
+                } else {
+                    generator.raw_template(40)
+                };
+                Fingerprint::from_raw(&raw, PruneOptions::default(), Format::NistInternal)
+            })
+            .collect()
+    }
+
+    fn sequential_scores(probes: &[Fingerprint], galleries: &[Fingerprint]) -> Vec<u32> {
+        let (mut pairs, mut state) = (PairHolder::new(), BozorthState::new());
+        probes
+            .iter()
+            .flat_map(|probe| galleries.iter().map(move |gallery| (probe, gallery)))
+            .map(|(probe, gallery)| {
+                let settings = BatchSettings::default();
+                let (probe, gallery) = (std::slice::from_ref(probe), std::slice::from_ref(gallery));
+                match_templates(probe, gallery, &settings, &mut pairs, &mut state)
+                    .unwrap()
+                    .score
+            })
+            .collect()
+    }
+
+    #[test]
+    fn results_are_in_the_order_of_the_pairs() {
+        let probes: Arc<[_]> = templates(5).into();
+        let galleries: Arc<[_]> = templates(7).into();
+        let expected = sequential_scores(&probes, &galleries);
+        assert!(expected.iter().any(|&it| it > 0));
+
+        // uneven delays make the threads finish their pairs out of order
+        let calls = Arc::new(AtomicUsize::new(0));
+        let matcher = BatchMatcher::new(probes.clone(), galleries.clone())
+            .threads(4)
+            .chunk_size(2)
+            .filter(move |_| {
+                let call = calls.fetch_add(1, Ordering::Relaxed);
+                std::thread::sleep(std::time::Duration::from_millis((call * 7 % 5) as u64));
+                true
+            });
+        let results: Vec<_> = matcher.run().collect();
+        let pairs: Vec<_> = results.iter().map(|it| (it.probe, it.gallery)).collect();
+        let scores: Vec<_> = results.iter().map(|it| it.score.unwrap().score).collect();
+        assert_eq!(
+            pairs,
+            (0..5)
+                .flat_map(|p| (0..7).map(move |g| (p, g)))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(scores, expected);
+
+        let mut relaxed: Vec<_> = matcher.relaxed_order(true).run().collect();
+        relaxed.sort_by_key(|it| (it.probe, it.gallery));
+        assert_eq!(relaxed, results);
+    }
+
+    #[test]
+    fn raw_templates_are_pruned_by_their_side() {
+        let mut generator = Generator::new(31);
+        let galleries: Vec<_> = (0..3).map(|_| generator.raw_template(70)).collect();
+        let probes: Vec<_> = galleries
+            .iter()
+            .map(|it| generator.impression(it, -4, (2, 3)))
+            .collect();
+        let pruning = MatchPruning::default().with_max_minutiae(25, 0);
+
+        let fingerprints = |templates: &[Vec<_>], options| -> Vec<_> {
+            templates
+                .iter()
+                .map(|it| Fingerprint::from_raw(it, options, Format::NistInternal))
+                .collect()
+        };
+        let expected = sequential_scores(
+            &fingerprints(&probes, pruning.probe),
+            &fingerprints(&galleries, pruning.gallery),
+        );
+        let symmetric = sequential_scores(
+            &fingerprints(&probes, pruning.probe),
+            &fingerprints(&galleries, pruning.probe),
+        );
+        assert_ne!(expected, symmetric);
+
+        let scores: Vec<_> =
+            BatchMatcher::from_raw(&probes, &galleries, pruning, Format::NistInternal)
+                .threads(2)
+                .run()
+                .map(|it| it.score.unwrap().score)
+                .collect();
+        assert_eq!(scores, expected);
+    }
+
+    #[test]
+    fn pairwise_batches_match_the_pairs_and_skip_missing_templates() {
+        let fingerprints = templates(4);
+        let mut probes: Vec<_> = fingerprints.into_iter().map(Some).collect();
+        probes[1] = None;
+        let galleries: Vec<_> = templates(3).into_iter().map(Some).collect();
+
+        let results: Vec<_> = BatchMatcher::new(probes, galleries)
+            .mode(BatchMode::Pairwise)
+            .threads(2)
+            .run()
+            .collect();
+        let pairs: Vec<_> = results.iter().map(|it| (it.probe, it.gallery)).collect();
+        assert_eq!(pairs, [(0, 0), (1, 1), (2, 2)]);
+        assert!(results[0].score.is_some() && results[2].score.is_some());
+        assert_eq!(results[1].score, None);
+    }
+
+    #[test]
+    fn only_the_first_accepted_pair_of_a_probe_is_reported() {
+        let probes: Arc<[_]> = templates(3).into();
+        let galleries: Arc<[_]> = templates(8).into();
+        let expected = sequential_scores(&probes, &galleries);
+        let first_matches: Vec<_> = expected
+            .chunks(8)
+            .enumerate()
+            .filter_map(|(probe, scores)| Some((probe, scores.iter().position(|&it| it > 10)?)))
+            .collect();
+        assert!(!first_matches.is_empty());
+
+        let results = BatchMatcher::new(probes, galleries)
+            .threads(3)
+            .chunk_size(1)
+            .filter(|it| it.score.is_some_and(|it| it.score > 10))
+            .first_match_per_probe(true)
+            .run();
+        let pairs: Vec<_> = results.map(|it| (it.probe, it.gallery)).collect();
+        assert_eq!(pairs, first_matches);
+    }
+
+    #[test]
+    fn stopped_batches_report_where_to_continue() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let matched = Arc::new(AtomicUsize::new(0));
+        let matcher = BatchMatcher::new(templates(4), templates(6))
+            .threads(2)
+            .chunk_size(1)
+            .filter({
+                let (stop, matched) = (stop.clone(), matched.clone());
+                move |_| {
+                    if matched.fetch_add(1, Ordering::SeqCst) == 9 {
+                        stop.store(true, Ordering::SeqCst);
+                    }
+                    true
+                }
+            })
+            .stop_when({
+                let stop = stop.clone();
+                move || stop.load(Ordering::SeqCst)
+            });
+
+        let mut results = matcher.run();
+        let pairs: Vec<_> = results.by_ref().map(|it| (it.probe, it.gallery)).collect();
+        let stopped = results.stopped().unwrap();
+        // the pairs taken before the stop are all matched, and only those
+        assert!(stopped.completed >= 10 && stopped.completed < 24);
+        assert_eq!(pairs.len(), stopped.completed);
+        assert_eq!(
+            stopped,
+            BatchStop {
+                completed: stopped.completed,
+                probe: stopped.completed / 6,
+                gallery: stopped.completed % 6,
+            }
+        );
+
+        stop.store(false, Ordering::SeqCst);
+        let mut results = matcher.filter(|_| true).run();
+        assert_eq!(results.by_ref().count(), 24);
+        assert_eq!(results.stopped(), None);
+    }
+}
@@ -1,17 +1,26 @@
 use crate::associations::EndpointAssociations;
 use crate::clusters::{
-    calculate_averages, combine_clusters, encode_selected_endpoints,
-    find_compatible_disjoint_clusters_and_accumulate_points, ClusterAssigner, ClusterSimilar,
-    Clusters,
+    calculate_averages, combine_clusters, combine_clusters_2, encode_selected_endpoints,
+    find_compatible_disjoint_clusters_and_accumulate_points, ClusterAssigner, ClusterAverages,
+    ClusterSimilar, Clusters, EndpointSet,
 };
+use crate::config::{ClusterCombineStrategy, CompatQuirks, MatchConfig};
 use crate::consts::{
-    max_number_of_clusters, max_number_of_groups, min_number_of_pairs_to_build_cluster,
-    score_threshold,
+    max_number_of_clusters, min_number_of_pairs_to_build_cluster, score_threshold,
 };
-use crate::groups::{find_next_not_conflicting_associations, merge_endpoints_into_group, GroupVec};
-use crate::math::{are_angles_equal_with_tolerance, Averager};
-use crate::types::Endpoint;
-use crate::{is_strict_mode, timeit, Format, Minutia, PairHolder};
+use crate::deadline::Deadline;
+use crate::groups::{
+    find_next_not_conflicting_associations, merge_endpoints_into_group, AssociationSearch,
+    EndpointGroups,
+};
+use crate::math::{float, Averager};
+use crate::memory::{heap_size, ShrinkLimits, StateMemory};
+use crate::types::{Degrees, Endpoint, Pair};
+use crate::{timeit, Format, Minutia, PairHolder};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of_val;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 #[repr(u8)]
@@ -21,7 +30,7 @@ pub(crate) enum FingerprintKind {
 }
 
 #[inline]
-fn calculate_average_delta_theta_for_pairs(selected_pairs: &[u32], pairs: &PairHolder) -> i32 {
+fn calculate_average_delta_theta_for_pairs(selected_pairs: &[u32], pairs: &PairHolder) -> Degrees {
     let mut averager = Averager::new();
     for &pair in selected_pairs {
         averager.push(pairs.get(pair as usize).delta_theta);
@@ -32,15 +41,13 @@ fn calculate_average_delta_theta_for_pairs(selected_pairs: &[u32], pairs: &PairH
 #[inline]
 fn filter_selected(selected_pairs: &mut Vec<u32>, pairs: &PairHolder) {
     let average = calculate_average_delta_theta_for_pairs(selected_pairs, pairs);
-    selected_pairs.retain(|&pair| {
-        are_angles_equal_with_tolerance(pairs.get(pair as usize).delta_theta, average)
-    });
+    selected_pairs.retain(|&pair| pairs.get(pair as usize).delta_theta.is_close_to(average));
 }
 
 #[inline]
-fn cleanup_selected(cluster_assigner: &mut ClusterAssigner, selected_pairs: &[u32]) {
+fn cleanup_selected(cluster_assigner: &mut ClusterAssigner, selected_pairs: &[u32], marker: bool) {
     for &pair in selected_pairs {
-        cluster_assigner.unassign(pair)
+        cluster_assigner.unassign(pair, marker)
     }
 }
 
@@ -51,6 +58,7 @@ fn assign_cluster_to_endpoints(
     gallery_endpoint: Endpoint,
     state: &mut BozorthState,
     to_visit: &mut Vec<(Endpoint, Endpoint)>,
+    config: &MatchConfig,
 ) {
     // Check relation between given endpoints in current traversal.
     match (
@@ -84,7 +92,10 @@ fn assign_cluster_to_endpoints(
             state.selected_pairs.push(pair_index);
             state.assigner.assign(pair_index, cluster);
 
-            if is_strict_mode() {
+            if config
+                .quirks()
+                .contains(CompatQuirks::PAIR_INDEX_AS_ENDPOINT_CHECK)
+            {
                 // NOTE: this should be `pair_index` instead of `probe_endpoint`,
                 // but we are keeping this implementation strictly identical to the original one
                 let should_insert = to_visit
@@ -93,17 +104,13 @@ fn assign_cluster_to_endpoints(
                     .all(|(endpoint, _)| endpoint != pair_index.into());
                 if should_insert {
                     to_visit.push((probe_endpoint, gallery_endpoint));
+                    state
+                        .applied_quirks
+                        .insert(CompatQuirks::PAIR_INDEX_AS_ENDPOINT_CHECK);
                 }
             }
         }
         (existing_gallery_endpoint, existing_probe_endpoint) => {
-            if is_strict_mode() {
-                // Limit number of produced groups.
-                if state.groups.len() >= max_number_of_groups() {
-                    return;
-                }
-            }
-
             // there exists an association already so create a new group
             if let Some(endpoint) = existing_gallery_endpoint {
                 merge_endpoints_into_group(
@@ -112,6 +119,7 @@ fn assign_cluster_to_endpoints(
                     probe_endpoint,
                     endpoint,
                     gallery_endpoint,
+                    config.strict,
                 );
             }
 
@@ -123,6 +131,7 @@ fn assign_cluster_to_endpoints(
                     gallery_endpoint,
                     endpoint,
                     probe_endpoint,
+                    config.strict,
                 );
             }
         }
@@ -134,6 +143,7 @@ fn traverse_edges(
     start_pair: u32,
     cluster_index: u32,
     state: &mut BozorthState,
+    config: &MatchConfig,
 ) {
     // queue of endpoints to visit
     let mut to_visit = vec![];
@@ -150,6 +160,7 @@ fn traverse_edges(
             gallery_j,
             state,
             &mut to_visit,
+            config,
         );
     }
 
@@ -173,6 +184,7 @@ fn traverse_edges(
                     gallery_k,
                     state,
                     &mut to_visit,
+                    config,
                 );
             }
         }
@@ -191,6 +203,7 @@ fn traverse_edges(
                 gallery_j,
                 state,
                 &mut to_visit,
+                config,
             );
         }
     }
@@ -201,6 +214,21 @@ fn traverse_edges(
     }
 }
 
+/// Counters of a match besides its score, for telling borderline matches apart.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct MatchFeatures {
+    /// Number of clusters built.
+    pub cluster_count: usize,
+    /// Points of the best single cluster.
+    pub max_cluster_points: u32,
+    /// Number of clusters combined into the score.
+    pub winning_combination_size: usize,
+    /// Distinct probe minutiae in the clusters combined into the score.
+    pub probe_endpoints: usize,
+    /// Distinct gallery minutiae in the clusters combined into the score.
+    pub gallery_endpoints: usize,
+}
+
 pub struct BozorthState {
     pub clusters: Clusters,
     associator: EndpointAssociations,
@@ -209,8 +237,15 @@ pub struct BozorthState {
     /// from another fingerprint, a group is created that holds these endpoints.
     /// Later, a brute force checking is performed that looks for a combinations of associations
     /// for which there are no conflicts among all the groups.
-    groups: GroupVec,
+    groups: EndpointGroups,
     selected_pairs: Vec<u32>,
+    best_clusters: Vec<u32>,
+    matched_pairs: Vec<(Endpoint, Endpoint)>,
+    features: MatchFeatures,
+    clusters_truncated: bool,
+    unprocessed_start_pairs: usize,
+    association_searches_over_budget: usize,
+    applied_quirks: CompatQuirks,
 }
 
 impl BozorthState {
@@ -219,13 +254,58 @@ impl BozorthState {
             clusters: Clusters::with_capacity(max_number_of_clusters()),
             associator: EndpointAssociations::new(),
             assigner: ClusterAssigner::new(),
-            groups: GroupVec::new(),
+            groups: EndpointGroups::new(),
             selected_pairs: vec![],
+            best_clusters: vec![],
+            matched_pairs: vec![],
+            features: MatchFeatures::default(),
+            clusters_truncated: false,
+            unprocessed_start_pairs: 0,
+            association_searches_over_budget: 0,
+            applied_quirks: CompatQuirks::NONE,
         }
     }
 
-    pub fn len(&self) -> usize {
-        self.groups.len()
+    /// Number of clusters built by the last call of `match_score`.
+    pub fn cluster_count(&self) -> usize {
+        self.clusters.len()
+    }
+
+    /// Distinct pairs of corresponding probe and gallery minutiae in the clusters that
+    /// produced the score of the last call of `match_score`, sorted by the probe minutia.
+    pub fn matched_pairs(&self) -> &[(Endpoint, Endpoint)] {
+        &self.matched_pairs
+    }
+
+    /// Counters of the last call of `match_score`, collected while it was scoring.
+    pub fn match_features(&self) -> MatchFeatures {
+        self.features
+    }
+
+    /// Whether the last call of `match_score` stopped building clusters because it reached
+    /// `max_number_of_clusters`; the score was then computed from the clusters found so far.
+    pub fn clusters_truncated(&self) -> bool {
+        self.clusters_truncated
+    }
+
+    /// Number of start pairs the last call of `match_score` did not get to because of
+    /// `max_number_of_clusters`.
+    pub fn unprocessed_start_pairs(&self) -> usize {
+        self.unprocessed_start_pairs
+    }
+
+    /// Number of start pairs of the last call of `match_score` whose search for non-conflicting
+    /// associations of the groups ran out of the step budget of
+    /// `MatchConfig::association_search_steps`; their remaining clusters were not built.
+    pub fn association_searches_over_budget(&self) -> usize {
+        self.association_searches_over_budget
+    }
+
+    /// Quirks of `MatchConfig::quirks` that changed the course of the last call of
+    /// `match_score`; `CompatQuirks::SKIP_LAST_PROBE_EDGE` is reported only in the log, by
+    /// `match_edges_into_pairs`.
+    pub fn applied_quirks(&self) -> CompatQuirks {
+        self.applied_quirks
     }
 
     pub fn clear(&mut self) {
@@ -234,10 +314,93 @@ impl BozorthState {
         self.assigner.clear();
         self.groups.clear();
         self.selected_pairs.clear();
+        self.best_clusters.clear();
+        self.matched_pairs.clear();
+        self.features = MatchFeatures::default();
+        self.clusters_truncated = false;
+        self.unprocessed_start_pairs = 0;
+        self.association_searches_over_budget = 0;
+        self.applied_quirks = CompatQuirks::NONE;
+    }
+
+    /// Bytes held by every component of the state, unused capacity included.
+    pub fn memory_usage(&self) -> StateMemory {
+        StateMemory {
+            clusters: self.clusters.memory_usage(),
+            groups: self.groups.memory_usage(),
+            pairs: heap_size(&self.selected_pairs)
+                + heap_size(&self.best_clusters)
+                + heap_size(&self.matched_pairs),
+            fixed: size_of_val(&self.associator) + size_of_val(&self.assigner),
+        }
+    }
+
+    /// Clears the state and trims the capacities of its buffers back to the `limits`.
+    ///
+    /// The results of the last match, such as `matched_pairs`, are discarded.
+    pub fn shrink(&mut self, limits: &ShrinkLimits) {
+        self.clear();
+        self.clusters.shrink(limits.clusters);
+        self.groups.shrink_to(limits.groups);
+        self.selected_pairs.shrink_to(limits.pairs);
+        self.best_clusters.shrink_to(limits.clusters);
+        self.matched_pairs.shrink_to(limits.pairs);
+    }
+
+    /// Average location of the matched minutiae on the probe and on the gallery, computed from
+    /// the clusters that produced the score of the last call of `match_score`.
+    ///
+    /// Clusters are weighted by their number of pairs; `None` when no cluster produced the score.
+    pub fn matched_centroid(&self) -> Option<((i32, i32), (i32, i32))> {
+        let mut weight = 0;
+        let mut sums = [0i64; 4];
+        for &cluster in &self.best_clusters {
+            let pairs = self.clusters.pairs[cluster as usize].len() as i64;
+            let averages = &self.clusters.averages[cluster as usize];
+            let coordinates = [
+                averages.probe_x,
+                averages.probe_y,
+                averages.gallery_x,
+                averages.gallery_y,
+            ];
+            for (sum, coordinate) in sums.iter_mut().zip(coordinates) {
+                *sum += i64::from(coordinate) * pairs;
+            }
+            weight += pairs;
+        }
+        if weight == 0 {
+            return None;
+        }
+
+        let [probe_x, probe_y, gallery_x, gallery_y] =
+            sums.map(|sum| float::round(sum as f32 / weight as f32) as i32);
+        Some(((probe_x, probe_y), (gallery_x, gallery_y)))
+    }
+
+    fn collect_matched_pairs(&mut self, pairs: &PairHolder) {
+        self.matched_pairs.clear();
+        let mut probe = EndpointSet::default();
+        let mut gallery = EndpointSet::default();
+        for &cluster in &self.best_clusters {
+            for &pair in &self.clusters.pairs[cluster as usize] {
+                let pair = pairs.get(pair as usize);
+                self.matched_pairs.push((pair.probe_k, pair.gallery_k));
+                self.matched_pairs.push((pair.probe_j, pair.gallery_j));
+                probe.insert(pair.probe_k.as_usize());
+                probe.insert(pair.probe_j.as_usize());
+                gallery.insert(pair.gallery_k.as_usize());
+                gallery.insert(pair.gallery_j.as_usize());
+            }
+        }
+        self.matched_pairs.sort_unstable();
+        self.matched_pairs.dedup();
+        self.features.probe_endpoints = probe.len();
+        self.features.gallery_endpoints = gallery.len();
     }
 }
 
-const MINIMAL_NUMBER_OF_MINUTIA: usize = 10;
+/// Fewer minutiae in either template fail `match_score` with `MatchError::TooFewMinutiae`.
+pub(crate) const MINIMAL_NUMBER_OF_MINUTIA: usize = 10;
 
 fn calculate_points(pairs: &PairHolder, selected_pairs: &[u32]) -> u32 {
     selected_pairs
@@ -252,22 +415,26 @@ fn maybe_create_cluster(
     pairs: &PairHolder,
     start_pair: u32,
     state: &mut BozorthState,
+    config: &MatchConfig,
 ) {
     let new_cluster_index = state.clusters.len();
     state.selected_pairs.clear();
 
-    traverse_edges(pairs, start_pair, new_cluster_index as u32, state);
+    traverse_edges(pairs, start_pair, new_cluster_index as u32, state, config);
 
     if state.selected_pairs.len() >= min_number_of_pairs_to_build_cluster() {
         filter_selected(&mut state.selected_pairs, pairs);
     }
 
     if state.selected_pairs.len() < min_number_of_pairs_to_build_cluster() {
-        cleanup_selected(&mut state.assigner, &state.selected_pairs);
+        let marker = config.quirks().contains(CompatQuirks::UNASSIGN_MARKER);
+        cleanup_selected(&mut state.assigner, &state.selected_pairs, marker);
     } else {
+        let points = calculate_points(&pairs, &state.selected_pairs);
+        state.features.max_cluster_points = state.features.max_cluster_points.max(points);
         state.clusters.push(
             ClusterSimilar {
-                points: calculate_points(&pairs, &state.selected_pairs),
+                points,
                 points_including_compatible_clusters: 0,
                 compatible_clusters: vec![],
             },
@@ -294,44 +461,125 @@ fn maybe_create_cluster(
     }
 }
 
+/// Error of `match_score`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MatchError {
+    /// One of the templates has too few minutiae to be matched.
+    TooFewMinutiae,
+    /// The match ran longer than `MatchConfig::deadline`; `score` is the best score found so
+    /// far, which may be lower than the one of the complete match.
+    DeadlineExceeded { score: u32 },
+    /// The queue of a `Matcher` is full; the match was not started.
+    Busy,
+    /// The match panicked on a thread of a `Matcher`.
+    Panicked,
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchError::TooFewMinutiae => write!(f, "too few minutiae to match"),
+            MatchError::DeadlineExceeded { score } => {
+                write!(f, "deadline exceeded with score {} so far", score)
+            }
+            MatchError::Busy => write!(f, "too many matches queued"),
+            MatchError::Panicked => write!(f, "the match panicked"),
+        }
+    }
+}
+
+impl core::error::Error for MatchError {}
+
 pub fn match_score(
     pairs: &PairHolder,
     probe_minutiae: &[Minutia],
     gallery_minutiae: &[Minutia],
     format: Format,
     state: &mut BozorthState,
-) -> Result<(u32, Vec<u32>), ()> {
+) -> Result<(u32, Vec<u32>), MatchError> {
+    match_score_with(
+        pairs,
+        probe_minutiae,
+        gallery_minutiae,
+        format,
+        state,
+        &MatchConfig::default(),
+    )
+}
+
+/// Same as `match_score`, honoring options of the `config`.
+pub fn match_score_with(
+    pairs: &PairHolder,
+    probe_minutiae: &[Minutia],
+    gallery_minutiae: &[Minutia],
+    format: Format,
+    state: &mut BozorthState,
+    config: &MatchConfig,
+) -> Result<(u32, Vec<u32>), MatchError> {
+    match_score_until(
+        pairs,
+        probe_minutiae,
+        gallery_minutiae,
+        format,
+        state,
+        config,
+        &Deadline::start(config),
+    )
+}
+
+/// Same as `match_score_with`, giving up at the `deadline` instead of the one of the `config`.
+pub(crate) fn match_score_until(
+    pairs: &PairHolder,
+    probe_minutiae: &[Minutia],
+    gallery_minutiae: &[Minutia],
+    format: Format,
+    state: &mut BozorthState,
+    config: &MatchConfig,
+    deadline: &Deadline,
+) -> Result<(u32, Vec<u32>), MatchError> {
     if probe_minutiae.len() < MINIMAL_NUMBER_OF_MINUTIA
         || gallery_minutiae.len() < MINIMAL_NUMBER_OF_MINUTIA
     {
-        return Err(());
+        state.best_clusters.clear();
+        state.matched_pairs.clear();
+        state.features = MatchFeatures::default();
+        return Err(MatchError::TooFewMinutiae);
     }
     debug_assert!(!pairs.is_empty());
 
     timeit(|| state.clear());
-    for (start_pair_index, start_pair) in pairs
-        .iter()
-        .take(if is_strict_mode() {
-            pairs.len() - 1
-        } else {
-            pairs.len()
-        })
-        .enumerate()
-    {
+    let quirks = config.quirks();
+    let start_pairs = if quirks.contains(CompatQuirks::SKIP_LAST_START_PAIR) {
+        pairs.len() - 1
+    } else {
+        pairs.len()
+    };
+    for (start_pair_index, start_pair) in pairs.iter().take(start_pairs).enumerate() {
         if state
             .assigner
             .get_cluster(start_pair_index as u32)
             .is_some()
         {
             // Was assigned to some cluster already so it was visited - no need to do it again
+            if state.assigner.is_marked_unassigned(start_pair_index as u32) {
+                // ... or it was removed from a cluster too small to be kept
+                state.applied_quirks.insert(CompatQuirks::UNASSIGN_MARKER);
+            }
             continue;
         }
         state
             .associator
             .associate(start_pair.probe_k, start_pair.gallery_k);
         state.groups.clear();
+        let mut search_steps = 0;
 
         loop {
+            if deadline.has_passed() {
+                // the best combination is not known yet, the best single cluster is
+                return Err(MatchError::DeadlineExceeded {
+                    score: state.features.max_cluster_points,
+                });
+            }
             timeit(|| {
                 maybe_create_cluster(
                     probe_minutiae,
@@ -339,6 +587,7 @@ pub fn match_score(
                     pairs,
                     start_pair_index as u32,
                     state,
+                    config,
                 );
             });
 
@@ -346,22 +595,60 @@ pub fn match_score(
                 break;
             }
 
-            if !find_next_not_conflicting_associations(
-                state.groups.as_mut_slice(),
+            match find_next_not_conflicting_associations(
+                &mut state.groups,
                 &mut state.associator,
+                &mut search_steps,
+                config.association_search_steps,
+                config.strict,
             ) {
+                AssociationSearch::Found => {}
                 // no more clusters can be created
-                break;
+                AssociationSearch::Exhausted => break,
+                AssociationSearch::OverBudget => {
+                    state.association_searches_over_budget += 1;
+                    break;
+                }
             }
         }
 
         if state.clusters.len() > max_number_of_clusters() - 1 {
+            // Like the original implementation, score the clusters found so far. Which clusters
+            // these are depends only on the order of pairs, so the score is still deterministic.
+            state.clusters_truncated = true;
+            state.unprocessed_start_pairs = (start_pair_index + 1..start_pairs)
+                .filter(|&index| state.assigner.get_cluster(index as u32).is_none())
+                .count();
+            #[cfg(feature = "log")]
+            log::warn!(
+                clusters = state.clusters.len(),
+                unprocessed_start_pairs = state.unprocessed_start_pairs;
+                "too many clusters, remaining start pairs are skipped"
+            );
             break;
         }
         state.associator.clear_by_probe(start_pair.probe_k);
     }
+    let last_pair = pairs.len() as u32 - 1;
+    if start_pairs == last_pair as usize
+        && !state.clusters_truncated
+        && state.assigner.get_cluster(last_pair).is_none()
+    {
+        state
+            .applied_quirks
+            .insert(CompatQuirks::SKIP_LAST_START_PAIR);
+    }
+    #[cfg(feature = "log")]
+    if !state.applied_quirks.is_empty() {
+        log::debug!(
+            quirks:? = state.applied_quirks;
+            "compatibility quirks changed the match"
+        );
+    }
 
-    timeit(|| find_compatible_disjoint_clusters_and_accumulate_points(&mut state.clusters, format));
+    let origin = config.coordinate_origin_of(format);
+    timeit(|| find_compatible_disjoint_clusters_and_accumulate_points(&mut state.clusters, origin));
+    state.features.cluster_count = state.clusters.len();
 
     // NOTE: some interesting heuristics?
     let (initial_score, clusters) = state
@@ -372,7 +659,7 @@ pub fn match_score(
         .map(|(idx, cluster)| {
             (
                 cluster.points_including_compatible_clusters,
-                std::iter::once(idx as u32)
+                core::iter::once(idx as u32)
                     .chain(cluster.compatible_clusters.iter().copied())
                     .collect(),
             )
@@ -380,9 +667,1207 @@ pub fn match_score(
         .max_by_key(|item| item.0)
         .unwrap_or((0, vec![]));
 
-    Ok(if initial_score < score_threshold() {
-        (initial_score, clusters)
+    let threshold = config.score_threshold.unwrap_or_else(score_threshold);
+    let result = if initial_score < threshold && !config.force_combine {
+        state.best_clusters.clear();
+        state.best_clusters.extend_from_slice(&clusters);
+        Ok((initial_score, clusters))
     } else {
-        timeit(|| combine_clusters(&mut state.clusters, false))
+        let combine = match config.combine_strategy {
+            ClusterCombineStrategy::IterativeStack => combine_clusters,
+            ClusterCombineStrategy::VisitedSet => combine_clusters_2,
+        };
+        timeit(|| combine(&state.clusters, false, &mut state.best_clusters, deadline))
+    };
+    state.features.winning_combination_size = state.best_clusters.len();
+    state.collect_matched_pairs(pairs);
+    result.map_err(|score| MatchError::DeadlineExceeded { score })
+}
+
+/// Cluster of a match with its pairs resolved, independent of the state and the pairs it was
+/// built from.
+#[derive(Debug, Clone)]
+pub struct OwnedCluster {
+    pub points: u32,
+    pub pairs: Vec<Pair>,
+    pub averages: ClusterAverages,
+}
+
+/// Result of `match_outcome`.
+#[derive(Debug, Clone)]
+pub struct MatchOutcome {
+    pub score: u32,
+    /// Indices of the clusters that produced the score.
+    pub best_clusters: Vec<u32>,
+    pub features: MatchFeatures,
+    clusters: Vec<OwnedCluster>,
+}
+
+impl MatchOutcome {
+    /// All clusters built by the match; `best_clusters` are indices into them.
+    ///
+    /// Empty unless the match was run with `MatchConfig::collect_clusters`.
+    pub fn into_clusters(self) -> Vec<OwnedCluster> {
+        self.clusters
+    }
+}
+
+/// Same as `match_score_with`, but returns a `MatchOutcome` that can carry the clusters.
+///
+/// Clusters are copied only when `config.collect_clusters` is set, otherwise the outcome costs
+/// no more than `match_score_with`.
+pub fn match_outcome(
+    pairs: &PairHolder,
+    probe_minutiae: &[Minutia],
+    gallery_minutiae: &[Minutia],
+    format: Format,
+    state: &mut BozorthState,
+    config: &MatchConfig,
+) -> Result<MatchOutcome, MatchError> {
+    let (score, best_clusters) = match_score_with(
+        pairs,
+        probe_minutiae,
+        gallery_minutiae,
+        format,
+        state,
+        config,
+    )?;
+    let clusters = if config.collect_clusters {
+        let clusters = &state.clusters;
+        (0..clusters.len())
+            .map(|index| OwnedCluster {
+                points: clusters.similar[index].points,
+                pairs: clusters.pairs[index]
+                    .iter()
+                    .map(|&pair| *pairs.get(pair as usize))
+                    .collect(),
+                averages: clusters.averages[index],
+            })
+            .collect()
+    } else {
+        vec![]
+    };
+    Ok(MatchOutcome {
+        score,
+        best_clusters,
+        features: state.match_features(),
+        clusters,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::bozorth::{assign_cluster_to_endpoints, FingerprintKind};
+    use crate::config::MatchConfig;
+    use crate::consts::max_number_of_groups;
+    use crate::consts::{max_number_of_clusters, score_threshold, MAX_NUMBER_OF_MINUTIAE};
+    use crate::parsing::RawMinutiaCombined;
+    use crate::synthetic::Generator;
+    use crate::types::Endpoint;
+    use crate::{
+        find_edges, limit_edges, match_edges_into_pairs, match_fingerprints,
+        match_fingerprints_with, match_outcome, match_score, match_score_with, prune, AngleOrigin,
+        BozorthState, ClusterAverages, ClusterCompatibility, CoordinateOrigin, Degrees,
+        Fingerprint, Format, MatchFeatures, Minutia, OwnedCluster, Pair, PairHolder, PruneOptions,
+        ShrinkLimits, SortedEdges,
+    };
+
+    #[test]
+    fn cluster_count_reflects_last_match() {
+        let mut generator = Generator::new(13);
+        let template = generator.raw_template(40);
+        let impression = generator.impression(&template, 4, (6, -3));
+        let probe = prune(&template, 150);
+        let gallery = prune(&impression, 150);
+
+        let edges_of = |minutiae: &[Minutia]| {
+            let mut edges = vec![];
+            find_edges(minutiae, &mut edges, Format::NistInternal);
+            edges.truncate(limit_edges(&edges));
+            edges
+        };
+        let mut pairs = PairHolder::new();
+        match_edges_into_pairs(
+            SortedEdges::from_sorted_unchecked(&edges_of(&probe)),
+            &probe,
+            SortedEdges::from_sorted_unchecked(&edges_of(&gallery)),
+            &gallery,
+            &mut pairs,
+            &MatchConfig::default(),
+            |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+        );
+        pairs.prepare();
+
+        let mut state = BozorthState::new();
+        assert_eq!(state.cluster_count(), 0);
+        match_score(&pairs, &probe, &gallery, Format::NistInternal, &mut state).unwrap();
+        assert!(state.cluster_count() > 0);
+        assert_eq!(state.cluster_count(), state.clusters.len());
+        assert_eq!(state.association_searches_over_budget(), 0);
+
+        state.clear();
+        assert_eq!(state.cluster_count(), 0);
+    }
+
+    #[test]
+    fn matched_centroid_follows_the_translation() {
+        let mut generator = Generator::new(53);
+        let format = Format::NistInternal;
+        let options = PruneOptions::default();
+        let template = generator.raw_template(60);
+        let impression = generator.impression(&template, 0, (12, -7));
+        let probe = Fingerprint::from_raw(&template, options, format);
+        let gallery = Fingerprint::from_raw(&impression, options, format);
+
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        assert_eq!(state.matched_centroid(), None);
+        let score = match_fingerprints(&probe, &gallery, format, &mut pairs, &mut state);
+        assert!(score > 0);
+
+        let ((probe_x, probe_y), (gallery_x, gallery_y)) = state.matched_centroid().unwrap();
+        assert!((gallery_x - probe_x - 12).abs() <= 2);
+        assert!((gallery_y - probe_y + 7).abs() <= 2);
+        let matched = state.matched_pairs();
+        let xs = matched
+            .iter()
+            .map(|&(p, _)| probe.minutiae[Into::<usize>::into(p)].x);
+        let (min, max) = (xs.clone().min().unwrap(), xs.max().unwrap());
+        assert!((min..=max).contains(&probe_x));
+
+        state.clear();
+        assert_eq!(state.matched_centroid(), None);
+    }
+
+    #[test]
+    fn match_features_are_consistent_with_the_score() {
+        let format = Format::NistInternal;
+        let options = PruneOptions::default();
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        assert_eq!(state.match_features(), MatchFeatures::default());
+
+        let mut genuine_features = None;
+        for seed in 0..6 {
+            let mut generator = Generator::new(70 + seed);
+            let template = generator.raw_template(50);
+            let impression = generator.impression(&template, 5, (3, -8));
+            let other = generator.raw_template(50);
+            let probe = Fingerprint::from_raw(&template, options, format);
+            for raw in [impression, other] {
+                let gallery = Fingerprint::from_raw(&raw, options, format);
+                let score = match_fingerprints(&probe, &gallery, format, &mut pairs, &mut state);
+                let features = state.match_features();
+
+                assert!(features.max_cluster_points <= score);
+                assert!(features.winning_combination_size <= features.cluster_count);
+                assert_eq!(features.cluster_count, state.cluster_count());
+                assert_eq!(score > 0, features.winning_combination_size > 0);
+                let distinct = |endpoints: Vec<usize>| {
+                    endpoints
+                        .iter()
+                        .collect::<std::collections::BTreeSet<_>>()
+                        .len()
+                };
+                let matched = state.matched_pairs();
+                assert_eq!(
+                    features.probe_endpoints,
+                    distinct(matched.iter().map(|it| it.0 .0 as usize).collect())
+                );
+                assert_eq!(
+                    features.gallery_endpoints,
+                    distinct(matched.iter().map(|it| it.1 .0 as usize).collect())
+                );
+                assert!(features.probe_endpoints <= probe.minutiae.len());
+                assert!(features.gallery_endpoints <= gallery.minutiae.len());
+                if score > 0 && genuine_features.is_none() {
+                    genuine_features = Some(features);
+                }
+            }
+        }
+        assert!(genuine_features.unwrap().max_cluster_points > 0);
+
+        state.clear();
+        assert_eq!(state.match_features(), MatchFeatures::default());
+    }
+
+    #[test]
+    fn matched_pairs_come_from_winning_clusters() {
+        let mut generator = Generator::new(17);
+        let template = generator.raw_template(40);
+        let impression = generator.impression(&template, -6, (4, 9));
+        let other = generator.raw_template(40);
+        let probe = prune(&template, 150);
+
+        let edges_of = |minutiae: &[Minutia]| {
+            let mut edges = vec![];
+            find_edges(minutiae, &mut edges, Format::NistInternal);
+            edges.truncate(limit_edges(&edges));
+            edges
+        };
+        let mut state = BozorthState::new();
+        let mut matched_count = |gallery: &[Minutia]| {
+            let mut pairs = PairHolder::new();
+            match_edges_into_pairs(
+                SortedEdges::from_sorted_unchecked(&edges_of(&probe)),
+                &probe,
+                SortedEdges::from_sorted_unchecked(&edges_of(gallery)),
+                gallery,
+                &mut pairs,
+                &MatchConfig::default(),
+                |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+            );
+            if pairs.pairs().is_empty() {
+                return 0;
+            }
+            pairs.prepare();
+            match_score(&pairs, &probe, gallery, Format::NistInternal, &mut state).unwrap();
+
+            let matched = state.matched_pairs();
+            assert!(matched.windows(2).all(|it| it[0] < it[1]));
+            assert!(matched.iter().all(|&(p, g)| {
+                Into::<usize>::into(p) < probe.len() && Into::<usize>::into(g) < gallery.len()
+            }));
+            matched.len()
+        };
+
+        let genuine = matched_count(&prune(&impression, 150));
+        let impostor = matched_count(&prune(&other, 150));
+        assert!(genuine >= 10);
+        assert!(genuine > impostor);
+
+        state.clear();
+        assert!(state.matched_pairs().is_empty());
+    }
+
+    #[test]
+    fn shrink_releases_memory_without_changing_scores() {
+        let mut generator = Generator::new(19);
+        let format = Format::NistInternal;
+        let options = PruneOptions::default();
+        let template = generator.raw_template(150);
+        let impression = generator.impression(&template, 7, (-5, 8));
+        let probe = Fingerprint::from_raw(&template, options, format);
+        let gallery = Fingerprint::from_raw(&impression, options, format);
+
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        let score = match_fingerprints(&probe, &gallery, format, &mut pairs, &mut state);
+
+        const LIMIT: usize = 16 * 1024;
+        let limits = ShrinkLimits {
+            clusters: 16,
+            pairs: 64,
+            groups: 4,
+        };
+        let grown = state.memory_usage();
+        assert!(grown.total() - grown.fixed > LIMIT);
+        let grown_pairs = pairs.memory_usage();
+        assert!(grown_pairs.pairs > LIMIT);
+
+        state.shrink(&limits);
+        pairs.shrink(&limits);
+        let shrunk = state.memory_usage();
+        assert!(shrunk.total() - shrunk.fixed <= LIMIT, "{:?}", shrunk);
+        assert_eq!(shrunk.fixed, grown.fixed);
+        assert!(pairs.memory_usage().pairs <= LIMIT);
+        assert_eq!(pairs.memory_usage().ranges, grown_pairs.ranges);
+        assert!(state.matched_pairs().is_empty());
+
+        assert!(score > 0);
+        assert_eq!(
+            match_fingerprints(&probe, &gallery, format, &mut pairs, &mut state),
+            score
+        );
+        assert_eq!(
+            match_fingerprints(
+                &probe,
+                &gallery,
+                format,
+                &mut PairHolder::new(),
+                &mut BozorthState::new()
+            ),
+            score
+        );
+    }
+
+    #[test]
+    fn exceeding_the_cluster_limit_is_reported_and_deterministic() {
+        // clumps of nearly coincident minutiae give every endpoint several candidates,
+        // so the combinations of associations create more clusters than the limit
+        let mut generator = Generator::new(3);
+        let mut template = vec![];
+        for minutia in generator.raw_template(40) {
+            for copy in 0..4 {
+                template.push(RawMinutiaCombined {
+                    x: minutia.x + 2 * copy,
+                    ..minutia
+                });
+            }
+        }
+        let impression = generator.impression(&template, 3, (2, 1));
+        let format = Format::NistInternal;
+        let options = PruneOptions {
+            max_minutiae: 200,
+            ..PruneOptions::default()
+        };
+        let probe = Fingerprint::from_raw(&template, options, format);
+        let gallery = Fingerprint::from_raw(&impression, options, format);
+
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        let score = match_fingerprints(&probe, &gallery, format, &mut pairs, &mut state);
+        assert!(state.clusters_truncated());
+        assert_eq!(state.cluster_count(), max_number_of_clusters());
+        assert!(state.unprocessed_start_pairs() > 0);
+        let unprocessed = state.unprocessed_start_pairs();
+
+        for _ in 0..2 {
+            let mut fresh = BozorthState::new();
+            assert_eq!(
+                match_fingerprints(&probe, &gallery, format, &mut PairHolder::new(), &mut fresh),
+                score
+            );
+            assert!(fresh.clusters_truncated());
+            assert_eq!(fresh.unprocessed_start_pairs(), unprocessed);
+        }
+
+        // a regular match resets the flag of the reused state
+        let template = generator.raw_template(40);
+        let probe = Fingerprint::from_raw(&template, options, format);
+        let gallery =
+            Fingerprint::from_raw(&generator.impression(&template, 2, (3, 3)), options, format);
+        match_fingerprints(&probe, &gallery, format, &mut pairs, &mut state);
+        assert!(!state.clusters_truncated());
+        assert_eq!(state.unprocessed_start_pairs(), 0);
+    }
+
+    #[test]
+    fn score_threshold_of_the_config_decides_about_combining() {
+        let mut generator = Generator::new(31);
+        let template = generator.raw_template(50);
+        let impression = generator.impression(&template, -3, (5, 2));
+        let format = Format::NistInternal;
+        let probe = Fingerprint::from_raw(&template, PruneOptions::default(), format);
+        let gallery = Fingerprint::from_raw(&impression, PruneOptions::default(), format);
+
+        let mut pairs = PairHolder::new();
+        match_edges_into_pairs(
+            probe.sorted_edges(),
+            &probe.minutiae,
+            gallery.sorted_edges(),
+            &gallery.minutiae,
+            &mut pairs,
+            &MatchConfig::default(),
+            |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+        );
+        pairs.prepare();
+
+        let mut state = BozorthState::new();
+        let mut score = |score_threshold| {
+            let config = MatchConfig {
+                score_threshold,
+                ..MatchConfig::default()
+            };
+            let score = match_score_with(
+                &pairs,
+                &probe.minutiae,
+                &gallery.minutiae,
+                format,
+                &mut state,
+                &config,
+            );
+            let initial = state
+                .clusters
+                .similar
+                .iter()
+                .map(|it| it.points_including_compatible_clusters)
+                .max();
+            (score.unwrap().0, initial.unwrap())
+        };
+
+        let (default, initial) = score(None);
+        assert!(initial >= score_threshold());
+        assert_eq!(score(Some(score_threshold())).0, default);
+        // with an unreachable threshold clusters are never combined
+        assert_eq!(score(Some(u32::MAX)).0, initial);
+
+        let config = MatchConfig {
+            score_threshold: Some(u32::MAX),
+            force_combine: true,
+            ..MatchConfig::default()
+        };
+        let forced = match_score_with(
+            &pairs,
+            &probe.minutiae,
+            &gallery.minutiae,
+            format,
+            &mut state,
+            &config,
+        );
+        assert_eq!(forced.unwrap().0, default);
+        assert_eq!(
+            match_score(
+                &pairs,
+                &probe.minutiae,
+                &gallery.minutiae,
+                format,
+                &mut state
+            )
+            .unwrap()
+            .0,
+            default
+        );
+    }
+
+    #[test]
+    fn matches_in_different_modes_can_run_concurrently() {
+        let mut generator = Generator::new(43);
+        let format = Format::NistInternal;
+        let fingerprints: Vec<_> = (0..4)
+            .map(|_| {
+                let template = generator.raw_template(60);
+                let impression = generator.impression(&template, 4, (3, -5));
+                [template, impression]
+            })
+            .collect();
+
+        let scores = |strict: bool| -> Vec<u32> {
+            let options = PruneOptions {
+                strict,
+                ..PruneOptions::default()
+            };
+            let config = MatchConfig {
+                strict,
+                ..MatchConfig::default()
+            };
+            let mut pairs = PairHolder::new();
+            let mut state = BozorthState::new();
+            let mut scores = vec![];
+            for _ in 0..10 {
+                for [template, impression] in &fingerprints {
+                    let probe = Fingerprint::from_raw(template, options, format);
+                    let gallery = Fingerprint::from_raw(impression, options, format);
+                    scores.push(match_fingerprints_with(
+                        &probe, &gallery, format, &mut pairs, &mut state, &config,
+                    ));
+                }
+            }
+            scores
+        };
+
+        let (strict, relaxed) = (scores(true), scores(false));
+        assert!(strict.iter().all(|&it| it > 0));
+        let (concurrent_strict, concurrent_relaxed) = std::thread::scope(|scope| {
+            let strict = scope.spawn(|| scores(true));
+            let relaxed = scope.spawn(|| scores(false));
+            (strict.join().unwrap(), relaxed.join().unwrap())
+        });
+        assert_eq!(concurrent_strict, strict);
+        assert_eq!(concurrent_relaxed, relaxed);
+    }
+
+    #[test]
+    fn origins_of_the_config_score_flipped_templates_like_the_originals() {
+        let mut generator = Generator::new(71);
+        let template = generator.raw_template(60);
+        let impression = generator.impression(&template, 20, (9, -4));
+        let probe = prune(&template, 150);
+        let gallery = prune(&impression, 150);
+
+        // the same minutiae written down with the origins
+        let written_with = |minutiae: &[Minutia], origins: (CoordinateOrigin, AngleOrigin)| {
+            minutiae
+                .iter()
+                .map(|it| Minutia {
+                    x: it.x,
+                    y: match origins.0 {
+                        CoordinateOrigin::TopLeft => 499 - it.y,
+                        CoordinateOrigin::BottomLeft => it.y,
+                    },
+                    theta: match origins.1 {
+                        AngleOrigin::TopLeft => (-it.theta).normalized(),
+                        AngleOrigin::BottomLeft => it.theta,
+                    },
+                    kind: it.kind,
+                    quality: it.quality,
+                })
+                .collect::<Vec<_>>()
+        };
+        let format = Format::NistInternal;
+        let score_of = |origins, written_as, config: &MatchConfig| {
+            let probe = written_with(&probe, origins);
+            let gallery = written_with(written_as, origins);
+            let probe = Fingerprint::new_with(probe, format, config);
+            let gallery = Fingerprint::new_with(gallery, format, config);
+            let mut state = BozorthState::new();
+            let mut pairs = PairHolder::new();
+            match_fingerprints_with(&probe, &gallery, format, &mut pairs, &mut state, config)
+        };
+
+        let nist = (CoordinateOrigin::BottomLeft, AngleOrigin::BottomLeft);
+        let self_score = score_of(nist, &probe, &MatchConfig::default());
+        let score = score_of(nist, &gallery, &MatchConfig::default());
+        assert!(score > 0 && score < self_score);
+
+        for coordinate_origin in [CoordinateOrigin::TopLeft, CoordinateOrigin::BottomLeft] {
+            for angle_origin in [AngleOrigin::TopLeft, AngleOrigin::BottomLeft] {
+                let origins = (coordinate_origin, angle_origin);
+                let config = MatchConfig {
+                    coordinate_origin: Some(coordinate_origin),
+                    angle_origin,
+                    ..MatchConfig::default()
+                };
+                assert_eq!(score_of(origins, &probe, &config), self_score);
+                assert_eq!(score_of(origins, &gallery, &config), score);
+
+                // with only one of the origins right, the rotation between the impressions
+                // looks different on the edges and on the minutiae
+                let wrong_coordinates = MatchConfig {
+                    coordinate_origin: Some(match coordinate_origin {
+                        CoordinateOrigin::TopLeft => CoordinateOrigin::BottomLeft,
+                        CoordinateOrigin::BottomLeft => CoordinateOrigin::TopLeft,
+                    }),
+                    ..config
+                };
+                assert!(score_of(origins, &gallery, &wrong_coordinates) < score / 2);
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn pathological_matches_stop_at_the_deadline() {
+        use super::match_score_until;
+        use crate::deadline::Deadline;
+        use crate::{MatchError, MinutiaKind};
+        use std::sync::atomic::Ordering;
+
+        // a dense lattice of parallel minutiae matched with itself builds as many clusters as
+        // allowed, which takes many more readings of the clock than the deadline allows
+        let raw: Vec<_> = (0..36)
+            .map(|index| RawMinutiaCombined {
+                x: 50 + index % 6 * 12,
+                y: 50 + index / 6 * 12,
+                t: 0,
+                q: 50,
+                kind: MinutiaKind::Ending,
+            })
+            .collect();
+        let format = Format::NistInternal;
+        let fingerprint = Fingerprint::from_raw(&raw, PruneOptions::default(), format);
+
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        let config = MatchConfig::default();
+        match_edges_into_pairs(
+            fingerprint.sorted_edges(),
+            &fingerprint.minutiae,
+            fingerprint.sorted_edges(),
+            &fingerprint.minutiae,
+            &mut pairs,
+            &config,
+            |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+        );
+        pairs.prepare();
+
+        // the clock counts its readings, so the test does not depend on the speed of the machine
+        let (deadline, clock) = Deadline::after_readings(20);
+        let result = match_score_until(
+            &pairs,
+            &fingerprint.minutiae,
+            &fingerprint.minutiae,
+            format,
+            &mut state,
+            &config,
+            &deadline,
+        );
+        // the match gives up right at the reading that found the deadline passed
+        assert_eq!(clock.readings.load(Ordering::Relaxed), clock.limit);
+        match result {
+            Err(MatchError::DeadlineExceeded { score }) => assert!(score > 0),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn owned_clusters_have_the_endpoints_of_the_match() {
+        let mut generator = Generator::new(37);
+        let template = generator.raw_template(50);
+        let impression = generator.impression(&template, 5, (-4, 6));
+        let format = Format::NistInternal;
+        let probe = Fingerprint::from_raw(&template, PruneOptions::default(), format);
+        let gallery = Fingerprint::from_raw(&impression, PruneOptions::default(), format);
+
+        let mut pairs = PairHolder::new();
+        match_edges_into_pairs(
+            probe.sorted_edges(),
+            &probe.minutiae,
+            gallery.sorted_edges(),
+            &gallery.minutiae,
+            &mut pairs,
+            &MatchConfig::default(),
+            |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+        );
+        pairs.prepare();
+
+        let mut state = BozorthState::new();
+        let mut outcome = |collect_clusters| {
+            let config = MatchConfig {
+                collect_clusters,
+                ..MatchConfig::default()
+            };
+            let outcome = match_outcome(
+                &pairs,
+                &probe.minutiae,
+                &gallery.minutiae,
+                format,
+                &mut state,
+                &config,
+            );
+            outcome.unwrap()
+        };
+
+        let cheap = outcome(false);
+        assert!(cheap.into_clusters().is_empty());
+        let full = outcome(true);
+        let (score, best_clusters) = (full.score, full.best_clusters.clone());
+        let features = full.features;
+        let clusters = full.into_clusters();
+
+        assert_eq!(
+            (score, best_clusters),
+            match_score(
+                &pairs,
+                &probe.minutiae,
+                &gallery.minutiae,
+                format,
+                &mut state
+            )
+            .unwrap()
+        );
+        assert_eq!(features, state.match_features());
+        assert_eq!(clusters.len(), state.cluster_count());
+        for (cluster, endpoints) in clusters.iter().zip(&state.clusters.endpoints) {
+            let bits = |endpoints: &crate::clusters::EndpointSet| -> Vec<usize> {
+                (0..MAX_NUMBER_OF_MINUTIAE)
+                    .filter(|&i| endpoints.contains(i))
+                    .collect()
+            };
+            let mut probe: Vec<_> = cluster
+                .pairs
+                .iter()
+                .flat_map(|it| [it.probe_k.as_usize(), it.probe_j.as_usize()])
+                .collect();
+            probe.sort_unstable();
+            probe.dedup();
+            let mut gallery: Vec<_> = cluster
+                .pairs
+                .iter()
+                .flat_map(|it| [it.gallery_k.as_usize(), it.gallery_j.as_usize()])
+                .collect();
+            gallery.sort_unstable();
+            gallery.dedup();
+            assert_eq!(probe, bits(&endpoints.probe));
+            assert_eq!(gallery, bits(&endpoints.gallery));
+        }
+    }
+
+    #[test]
+    fn cluster_compatibility_agrees_with_combined_clusters() {
+        let format = Format::NistInternal;
+        let mut rejections = std::collections::HashMap::new();
+        for seed in 0..4 {
+            let mut generator = Generator::new(seed);
+            let template = generator.raw_template(60);
+            let impression = generator.impression(&template, 8, (5, 3));
+            let probe = Fingerprint::from_raw(&template, PruneOptions::default(), format);
+            let gallery = Fingerprint::from_raw(&impression, PruneOptions::default(), format);
+
+            let mut pairs = PairHolder::new();
+            match_edges_into_pairs(
+                probe.sorted_edges(),
+                &probe.minutiae,
+                gallery.sorted_edges(),
+                &gallery.minutiae,
+                &mut pairs,
+                &MatchConfig::default(),
+                |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+            );
+            pairs.prepare();
+
+            let mut state = BozorthState::new();
+            let config = MatchConfig {
+                collect_clusters: true,
+                ..MatchConfig::default()
+            };
+            let clusters = match_outcome(
+                &pairs,
+                &probe.minutiae,
+                &gallery.minutiae,
+                format,
+                &mut state,
+                &config,
+            )
+            .unwrap()
+            .into_clusters();
+
+            for (i, first) in clusters.iter().enumerate() {
+                for (j, second) in clusters.iter().enumerate().skip(i + 1) {
+                    let compatibility = ClusterCompatibility::of(first, second, format);
+                    let combined = state.clusters.similar[i]
+                        .compatible_clusters
+                        .contains(&(j as u32));
+                    assert_eq!(compatibility == ClusterCompatibility::Compatible, combined);
+                    *rejections.entry(compatibility).or_insert(0) += 1;
+                }
+            }
+        }
+        assert!(rejections.len() > 1, "{:?}", rejections);
+    }
+
+    #[test]
+    fn cluster_compatibility_reports_the_failed_check() {
+        let cluster = |endpoints: [u32; 4], delta_theta, probe: (i32, i32), gallery: (i32, i32)| {
+            OwnedCluster {
+                points: 1,
+                pairs: vec![Pair {
+                    delta_theta: Degrees(delta_theta),
+                    probe_k: Endpoint(endpoints[0]),
+                    gallery_k: Endpoint(endpoints[1]),
+                    probe_j: Endpoint(endpoints[2]),
+                    gallery_j: Endpoint(endpoints[3]),
+                    points: 1,
+                }],
+                averages: ClusterAverages {
+                    delta_theta: Degrees(delta_theta),
+                    probe_x: probe.0,
+                    probe_y: probe.1,
+                    gallery_x: gallery.0,
+                    gallery_y: gallery.1,
+                },
+            }
+        };
+        let first = cluster([0, 0, 1, 1], 0, (0, 0), (0, 0));
+        let check =
+            |second: &OwnedCluster| ClusterCompatibility::of(&first, second, Format::NistInternal);
+
+        assert_eq!(
+            check(&cluster([2, 2, 3, 3], 0, (100, 0), (100, 0))),
+            ClusterCompatibility::Compatible
+        );
+        assert_eq!(
+            check(&cluster([2, 2, 1, 3], 0, (100, 0), (100, 0))),
+            ClusterCompatibility::CommonEndpoints
+        );
+        assert_eq!(
+            check(&cluster([2, 2, 3, 3], 90, (100, 0), (100, 0))),
+            ClusterCompatibility::Rotation
+        );
+        assert_eq!(
+            check(&cluster([2, 2, 3, 3], 0, (100, 0), (50, 0))),
+            ClusterCompatibility::Distance
+        );
+        assert_eq!(
+            check(&cluster([2, 2, 3, 3], 0, (100, 0), (0, 100))),
+            ClusterCompatibility::Direction
+        );
+    }
+
+    #[test]
+    fn combine_strategies_find_the_same_clusters() {
+        use crate::clusters::{combine_clusters, combine_clusters_2, ClusterSimilar, Clusters};
+        use crate::deadline::Deadline;
+        use crate::ClusterCombineStrategy;
+
+        let format = Format::NistInternal;
+        let mut combined = 0;
+        for seed in 0..12 {
+            let mut generator = Generator::new(seed);
+            let template = generator.raw_template(70);
+            let impression = generator.impression(&template, 6, (4, -2));
+            let probe = Fingerprint::from_raw(&template, PruneOptions::default(), format);
+            let gallery = Fingerprint::from_raw(&impression, PruneOptions::default(), format);
+
+            let mut pairs = PairHolder::new();
+            match_edges_into_pairs(
+                probe.sorted_edges(),
+                &probe.minutiae,
+                gallery.sorted_edges(),
+                &gallery.minutiae,
+                &mut pairs,
+                &MatchConfig::default(),
+                |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+            );
+            pairs.prepare();
+
+            let mut state = BozorthState::new();
+            let mut score_with = |combine_strategy| {
+                let config = MatchConfig {
+                    force_combine: true,
+                    combine_strategy,
+                    ..MatchConfig::default()
+                };
+                match_score_with(
+                    &pairs,
+                    &probe.minutiae,
+                    &gallery.minutiae,
+                    format,
+                    &mut state,
+                    &config,
+                )
+                .unwrap()
+            };
+            let visited_set = score_with(ClusterCombineStrategy::VisitedSet);
+            assert_eq!(
+                score_with(ClusterCombineStrategy::IterativeStack),
+                visited_set
+            );
+
+            for collect in [false, true] {
+                let (mut stack_best, mut visited_best) = (vec![], vec![]);
+                assert_eq!(
+                    combine_clusters(&state.clusters, collect, &mut stack_best, &Deadline::NEVER),
+                    combine_clusters_2(
+                        &state.clusters,
+                        collect,
+                        &mut visited_best,
+                        &Deadline::NEVER
+                    )
+                );
+                assert_eq!(stack_best, visited_best);
+            }
+        }
+
+        // matches of synthetic templates rarely combine clusters, so random graphs of
+        // compatible clusters cover the search itself
+        let mut generator = Generator::new(5);
+        for density in [10, 30, 60] {
+            let mut clusters = Clusters::with_capacity(40);
+            for index in 0..40u32 {
+                let compatible_clusters: Vec<u32> = (index + 1..40)
+                    .filter(|_| generator.next(100) < density)
+                    .collect();
+                clusters.similar.push(ClusterSimilar {
+                    points: 1 + generator.next(20) as u32,
+                    compatible_clusters,
+                    points_including_compatible_clusters: 0,
+                });
+            }
+            for index in 0..clusters.similar.len() {
+                let others: u32 = clusters.similar[index]
+                    .compatible_clusters
+                    .iter()
+                    .map(|&it| clusters.similar[it as usize].points)
+                    .sum();
+                clusters.similar[index].points_including_compatible_clusters =
+                    clusters.similar[index].points + others;
+            }
+
+            for collect in [false, true] {
+                let (mut stack_best, mut visited_best) = (vec![], vec![]);
+                assert_eq!(
+                    combine_clusters(&clusters, collect, &mut stack_best, &Deadline::NEVER),
+                    combine_clusters_2(&clusters, collect, &mut visited_best, &Deadline::NEVER)
+                );
+                assert_eq!(stack_best, visited_best);
+                combined += (stack_best.len() > 2) as usize;
+            }
+        }
+        assert!(combined > 0);
+    }
+
+    // `assign_cluster_to_endpoints` in strict mode for every state of the associations of the
+    // two endpoints
+
+    const CLUSTER: u32 = 7;
+
+    fn endpoints(probe: u32, gallery: u32) -> (Endpoint, Endpoint) {
+        (Endpoint(probe), Endpoint(gallery))
+    }
+
+    fn assign(
+        state: &mut BozorthState,
+        pair: u32,
+        probe: u32,
+        gallery: u32,
+    ) -> Vec<(Endpoint, Endpoint)> {
+        let mut to_visit = vec![];
+        assign_to(state, pair, probe, gallery, &mut to_visit);
+        to_visit
+    }
+
+    fn assign_to(
+        state: &mut BozorthState,
+        pair: u32,
+        probe: u32,
+        gallery: u32,
+        to_visit: &mut Vec<(Endpoint, Endpoint)>,
+    ) {
+        assign_in_mode(true, state, pair, probe, gallery, to_visit);
+    }
+
+    fn assign_in_mode(
+        strict: bool,
+        state: &mut BozorthState,
+        pair: u32,
+        probe: u32,
+        gallery: u32,
+        to_visit: &mut Vec<(Endpoint, Endpoint)>,
+    ) {
+        assign_cluster_to_endpoints(
+            CLUSTER,
+            pair,
+            Endpoint(probe),
+            Endpoint(gallery),
+            state,
+            to_visit,
+            &MatchConfig {
+                strict,
+                ..MatchConfig::default()
+            },
+        );
+    }
+
+    fn groups(state: &BozorthState) -> Vec<(FingerprintKind, Endpoint, Vec<Endpoint>)> {
+        state
+            .groups
+            .describe()
+            .map(|(source, endpoint, matching)| (source, endpoint, matching.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn unassociated_endpoints_are_added_to_the_cluster() {
+        let mut state = BozorthState::new();
+        let to_visit = assign(&mut state, 3, 10, 20);
+
+        assert_eq!(state.selected_pairs, [3]);
+        assert_eq!(state.assigner.get_cluster(3), Some(CLUSTER));
+        assert_eq!(
+            state.associator.get_associated_by_probe(Endpoint(10)),
+            Some(Endpoint(20))
+        );
+        assert_eq!(
+            state.associator.get_associated_by_gallery(Endpoint(20)),
+            Some(Endpoint(10))
+        );
+        assert_eq!(to_visit, [endpoints(10, 20)]);
+        assert!(state.groups.is_empty());
+    }
+
+    #[test]
+    fn unassociated_endpoints_of_a_pair_already_in_the_cluster_are_only_associated() {
+        let mut state = BozorthState::new();
+        state.assigner.assign(3, CLUSTER);
+        let to_visit = assign(&mut state, 3, 10, 20);
+
+        assert!(state.selected_pairs.is_empty());
+        assert_eq!(
+            state.associator.get_associated_by_probe(Endpoint(10)),
+            Some(Endpoint(20))
+        );
+        assert_eq!(to_visit, [endpoints(10, 20)]);
+    }
+
+    #[test]
+    fn mutually_associated_endpoints_of_a_pair_in_the_cluster_change_nothing() {
+        let mut state = BozorthState::new();
+        let mut to_visit = vec![];
+        assign_to(&mut state, 3, 10, 20, &mut to_visit);
+        assign_to(&mut state, 3, 10, 20, &mut to_visit);
+
+        assert_eq!(state.selected_pairs, [3]);
+        assert_eq!(to_visit, [endpoints(10, 20)]);
+        assert!(state.groups.is_empty());
+    }
+
+    #[test]
+    fn mutually_associated_endpoints_add_another_pair_to_the_cluster() {
+        let mut state = BozorthState::new();
+        let mut to_visit = vec![];
+        assign_to(&mut state, 3, 10, 20, &mut to_visit);
+        // another pair with the same endpoints, e.g. from a different edge
+        assign_to(&mut state, 4, 10, 20, &mut to_visit);
+
+        assert_eq!(state.selected_pairs, [3, 4]);
+        assert_eq!(state.assigner.get_cluster(4), Some(CLUSTER));
+        // strict mode compares the probe endpoints of `to_visit` with the pair index 4, which
+        // is not among them, so the endpoints are queued again
+        assert_eq!(to_visit, [endpoints(10, 20), endpoints(10, 20)]);
+        assert!(state.groups.is_empty());
+    }
+
+    #[test]
+    fn mutually_associated_endpoints_are_not_queued_when_the_pair_index_is_a_queued_probe() {
+        let mut state = BozorthState::new();
+        let mut to_visit = vec![];
+        assign_to(&mut state, 3, 10, 20, &mut to_visit);
+        assign_to(&mut state, 5, 4, 30, &mut to_visit);
+        // pair 4 matches the queued probe endpoint 4 by accident, see the NOTE in
+        // `assign_cluster_to_endpoints`
+        assign_to(&mut state, 4, 10, 20, &mut to_visit);
+
+        assert_eq!(state.selected_pairs, [3, 5, 4]);
+        assert_eq!(to_visit, [endpoints(10, 20), endpoints(4, 30)]);
+    }
+
+    #[test]
+    fn probe_associated_to_another_gallery_creates_a_probe_group() {
+        let mut state = BozorthState::new();
+        assign(&mut state, 3, 10, 20);
+        let to_visit = assign(&mut state, 4, 10, 21);
+
+        assert!(to_visit.is_empty());
+        assert_eq!(state.selected_pairs, [3]);
+        assert_eq!(state.assigner.get_cluster(4), None);
+        assert_eq!(
+            groups(&state),
+            [(
+                FingerprintKind::Probe,
+                Endpoint(10),
+                vec![Endpoint(20), Endpoint(21)]
+            )]
+        );
+        // the associations are left as they were
+        assert_eq!(
+            state.associator.get_associated_by_probe(Endpoint(10)),
+            Some(Endpoint(20))
+        );
+        assert_eq!(
+            state.associator.get_associated_by_gallery(Endpoint(21)),
+            None
+        );
+
+        // further candidates join the same group once
+        assign(&mut state, 5, 10, 22);
+        assign(&mut state, 6, 10, 22);
+        assert_eq!(
+            groups(&state),
+            [(
+                FingerprintKind::Probe,
+                Endpoint(10),
+                vec![Endpoint(20), Endpoint(21), Endpoint(22)]
+            )]
+        );
+    }
+
+    #[test]
+    fn gallery_associated_to_another_probe_creates_a_gallery_group() {
+        let mut state = BozorthState::new();
+        assign(&mut state, 3, 10, 20);
+        let to_visit = assign(&mut state, 4, 11, 20);
+
+        assert!(to_visit.is_empty());
+        assert_eq!(state.selected_pairs, [3]);
+        assert_eq!(
+            groups(&state),
+            [(
+                FingerprintKind::Gallery,
+                Endpoint(20),
+                vec![Endpoint(10), Endpoint(11)]
+            )]
+        );
+        assert_eq!(state.associator.get_associated_by_probe(Endpoint(11)), None);
+    }
+
+    #[test]
+    fn both_endpoints_associated_to_others_create_two_groups() {
+        let mut state = BozorthState::new();
+        assign(&mut state, 3, 10, 20);
+        assign(&mut state, 4, 11, 21);
+        let to_visit = assign(&mut state, 5, 10, 21);
+
+        assert!(to_visit.is_empty());
+        assert_eq!(state.selected_pairs, [3, 4]);
+        assert_eq!(
+            groups(&state),
+            [
+                (
+                    FingerprintKind::Probe,
+                    Endpoint(10),
+                    vec![Endpoint(20), Endpoint(21)]
+                ),
+                (
+                    FingerprintKind::Gallery,
+                    Endpoint(21),
+                    vec![Endpoint(11), Endpoint(10)]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn probe_associated_to_the_gallery_counts_as_mutual_association() {
+        let mut state = BozorthState::new();
+        state.associator.associate(Endpoint(10), Endpoint(20));
+        // the gallery endpoint is taken over by another probe endpoint, the probe keeps its
+        // association
+        state.associator.associate(Endpoint(11), Endpoint(20));
+        let to_visit = assign(&mut state, 4, 10, 20);
+
+        assert_eq!(state.selected_pairs, [4]);
+        assert_eq!(to_visit, [endpoints(10, 20)]);
+        assert!(state.groups.is_empty());
+    }
+
+    // the limit of groups holds in both modes
+
+    #[test]
+    fn no_groups_are_created_beyond_the_limit() {
+        for &strict in &[true, false] {
+            let mut state = BozorthState::new();
+            let mut assign = |pair, probe, gallery| {
+                assign_in_mode(strict, &mut state, pair, probe, gallery, &mut vec![])
+            };
+            let limit = max_number_of_groups() as u32;
+            for probe in 0..limit + 1 {
+                assign(2 * probe, probe, 100 + probe);
+                assign(2 * probe + 1, probe, 150 + probe);
+            }
+
+            let groups = groups(&state);
+            assert_eq!(groups.len(), limit as usize, "strict: {}", strict);
+            assert!(groups.iter().all(|it| it.0 == FingerprintKind::Probe));
+            assert_eq!(groups.last().unwrap().1, Endpoint(limit - 1));
+        }
+    }
+
+    #[test]
+    fn two_new_groups_at_the_limit_do_not_exceed_it() {
+        for &strict in &[true, false] {
+            let mut state = BozorthState::new();
+            let assign = |state: &mut BozorthState, pair, probe, gallery| {
+                assign_in_mode(strict, state, pair, probe, gallery, &mut vec![])
+            };
+            let limit = max_number_of_groups() as u32;
+            // one group short of the limit
+            for probe in 0..limit - 1 {
+                assign(&mut state, 2 * probe, probe, 100 + probe);
+                assign(&mut state, 2 * probe + 1, probe, 150 + probe);
+            }
+            assert_eq!(state.groups.len(), limit as usize - 1, "strict: {}", strict);
+
+            // both endpoints are associated to others, so this pair asks for two new groups
+            let (probe, gallery) = (limit, 100 + limit);
+            assign(&mut state, 2 * limit, probe, 190);
+            assign(&mut state, 2 * limit + 1, 189, gallery);
+            assign(&mut state, 2 * limit + 2, probe, gallery);
+            assert_eq!(state.groups.len(), limit as usize);
+            assert_eq!(
+                groups(&state).last().unwrap(),
+                &(
+                    FingerprintKind::Probe,
+                    Endpoint(probe),
+                    vec![Endpoint(190), Endpoint(gallery)]
+                )
+            );
+
+            // full groups take no more candidates, not even into existing groups
+            let before = groups(&state);
+            assign(&mut state, 2 * limit + 3, probe, 191);
+            assign(&mut state, 2 * limit + 4, 0, 192);
+            assert_eq!(groups(&state), before);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! Mapping of match scores to probabilities that the match is genuine.
+//!
+//! A calibration is fitted on labelled scores (e.g. collected by the `evaluate` tool)
+//! either with Platt scaling or with isotonic regression, and stored as JSON.
+
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// Fitted mapping of scores to `P(genuine | score)`.
+///
+/// The mapping is non-decreasing in the score; scores outside of the fitted range
+/// are clamped to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Calibration {
+    /// Logistic function `1 / (1 + exp(-(a * score + b)))` with `a >= 0`.
+    Platt {
+        a: f64,
+        b: f64,
+        min_score: u32,
+        max_score: u32,
+    },
+    /// Piecewise linear interpolation between non-decreasing `probabilities` at increasing `scores`.
+    Isotonic {
+        scores: Vec<u32>,
+        probabilities: Vec<f64>,
+    },
+}
+
+impl Calibration {
+    /// Fits Platt scaling to `(score, is_genuine)` samples.
+    ///
+    /// Targets are smoothed as proposed by Platt, so the fit is finite even for
+    /// perfectly separable samples.
+    pub fn fit_platt(samples: &[(u32, bool)]) -> Self {
+        let (min_score, max_score) = score_range(samples);
+        let positives = samples.iter().filter(|it| it.1).count() as f64;
+        let negatives = samples.len() as f64 - positives;
+        let high = (positives + 1.0) / (positives + 2.0);
+        let low = 1.0 / (negatives + 2.0);
+        let data: Vec<(f64, f64)> = samples
+            .iter()
+            .map(|&(score, genuine)| (score as f64, if genuine { high } else { low }))
+            .collect();
+
+        let (mut a, mut b) = (0.0, ((positives + 1.0) / (negatives + 1.0)).ln());
+        let mut loss = platt_loss(&data, a, b);
+        for _ in 0..100 {
+            let (mut ga, mut gb) = (0.0, 0.0);
+            let (mut haa, mut hab, mut hbb) = (1e-12, 0.0, 1e-12);
+            for &(score, target) in &data {
+                let p = sigmoid(a * score + b);
+                let d = p - target;
+                let w = (p * (1.0 - p)).max(1e-12);
+                ga += d * score;
+                gb += d;
+                haa += w * score * score;
+                hab += w * score;
+                hbb += w;
+            }
+            if ga.abs() < 1e-9 && gb.abs() < 1e-9 {
+                break;
+            }
+
+            let det = haa * hbb - hab * hab;
+            let da = -(hbb * ga - hab * gb) / det;
+            let db = -(haa * gb - hab * ga) / det;
+
+            // backtracking line search
+            let mut step = 1.0;
+            while step > 1e-10 {
+                let candidate = platt_loss(&data, a + step * da, b + step * db);
+                if candidate < loss + 1e-4 * step * (ga * da + gb * db) {
+                    a += step * da;
+                    b += step * db;
+                    loss = candidate;
+                    break;
+                }
+                step /= 2.0;
+            }
+            if step <= 1e-10 {
+                break;
+            }
+        }
+
+        if a < 0.0 {
+            // scores do not separate the classes; a constant is the best monotonic mapping
+            let mean = data.iter().map(|it| it.1).sum::<f64>() / data.len().max(1) as f64;
+            a = 0.0;
+            b = (mean / (1.0 - mean)).ln();
+        }
+
+        Calibration::Platt {
+            a,
+            b,
+            min_score,
+            max_score,
+        }
+    }
+
+    /// Fits isotonic regression to `(score, is_genuine)` samples with pool adjacent violators.
+    pub fn fit_isotonic(samples: &[(u32, bool)]) -> Self {
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+
+        // blocks of (first score, last score, sum of labels, weight)
+        let mut blocks: Vec<(u32, u32, f64, f64)> = vec![];
+        for (score, genuine) in sorted {
+            let label = if genuine { 1.0 } else { 0.0 };
+            match blocks.last_mut() {
+                Some(last) if last.1 == score => {
+                    last.2 += label;
+                    last.3 += 1.0;
+                }
+                _ => blocks.push((score, score, label, 1.0)),
+            }
+
+            while blocks.len() > 1 {
+                let (previous, last) = (blocks[blocks.len() - 2], blocks[blocks.len() - 1]);
+                if previous.2 / previous.3 <= last.2 / last.3 {
+                    break;
+                }
+                blocks.pop();
+                *blocks.last_mut().unwrap() =
+                    (previous.0, last.1, previous.2 + last.2, previous.3 + last.3);
+            }
+        }
+
+        let mut scores = vec![];
+        let mut probabilities = vec![];
+        for (first, last, sum, weight) in blocks {
+            scores.push(first);
+            probabilities.push(sum / weight);
+            if last != first {
+                scores.push(last);
+                probabilities.push(sum / weight);
+            }
+        }
+
+        Calibration::Isotonic {
+            scores,
+            probabilities,
+        }
+    }
+
+    /// Returns the probability that a match with the given score is genuine.
+    pub fn probability(&self, score: u32) -> f64 {
+        match self {
+            Calibration::Platt {
+                a,
+                b,
+                min_score,
+                max_score,
+            } => {
+                let score = score.max(*min_score).min(*max_score);
+                sigmoid(a * score as f64 + b)
+            }
+            Calibration::Isotonic {
+                scores,
+                probabilities,
+            } => match scores.binary_search(&score) {
+                _ if scores.is_empty() => 0.5,
+                Ok(index) => probabilities[index],
+                Err(0) => probabilities[0],
+                Err(index) if index == scores.len() => probabilities[index - 1],
+                Err(index) => {
+                    let (x0, x1) = (scores[index - 1] as f64, scores[index] as f64);
+                    let (y0, y1) = (probabilities[index - 1], probabilities[index]);
+                    y0 + (y1 - y0) * (score as f64 - x0) / (x1 - x0)
+                }
+            },
+        }
+    }
+
+    /// Loads a calibration saved by `save`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        serde_json::from_reader(io::BufReader::new(file))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Saves the calibration as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self).map_err(io::Error::other)
+    }
+}
+
+fn score_range(samples: &[(u32, bool)]) -> (u32, u32) {
+    let min = samples.iter().map(|it| it.0).min().unwrap_or(0);
+    let max = samples.iter().map(|it| it.0).max().unwrap_or(0);
+    (min, max)
+}
+
+#[inline]
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Computes `ln(1 + exp(x))` without overflow.
+#[inline]
+fn log_one_plus_exp(x: f64) -> f64 {
+    if x > 0.0 {
+        x + (-x).exp().ln_1p()
+    } else {
+        x.exp().ln_1p()
+    }
+}
+
+/// Cross-entropy of the logistic function with parameters `a` and `b` on `(score, target)` data.
+fn platt_loss(data: &[(f64, f64)], a: f64, b: f64) -> f64 {
+    data.iter()
+        .map(|&(score, target)| {
+            let z = a * score + b;
+            target * log_one_plus_exp(-z) + (1.0 - target) * log_one_plus_exp(z)
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calibration::Calibration;
+
+    /// Impostor scores are spread over 0..=60 and genuine ones over 40..=100.
+    fn overlapping() -> Vec<(u32, bool)> {
+        let impostors = (0..=60).map(|score| (score, false));
+        let genuine = (40..=100).map(|score| (score, true));
+        impostors.chain(genuine).collect()
+    }
+
+    fn assert_calibrated(calibration: &Calibration) {
+        assert!(calibration.probability(0) < 0.05);
+        assert!(calibration.probability(100) > 0.95);
+        assert!((calibration.probability(50) - 0.5).abs() < 0.05);
+
+        // clamped beyond the fitted range
+        assert_eq!(calibration.probability(100), calibration.probability(1000));
+
+        let probabilities: Vec<_> = (0..=120).map(|it| calibration.probability(it)).collect();
+        assert!(probabilities.windows(2).all(|it| it[0] <= it[1]));
+    }
+
+    #[test]
+    fn platt_scaling_of_overlapping_scores() {
+        assert_calibrated(&Calibration::fit_platt(&overlapping()));
+    }
+
+    #[test]
+    fn isotonic_regression_of_overlapping_scores() {
+        assert_calibrated(&Calibration::fit_isotonic(&overlapping()));
+    }
+
+    #[test]
+    fn separable_scores_are_calibrated_to_extremes() {
+        let samples: Vec<_> = (0..=20)
+            .map(|score| (score, false))
+            .chain((60..=200).map(|score| (score, true)))
+            .collect();
+
+        for calibration in [
+            Calibration::fit_platt(&samples),
+            Calibration::fit_isotonic(&samples),
+        ] {
+            assert!(calibration.probability(0) < 0.05);
+            assert!(calibration.probability(200) > 0.95);
+            assert!((calibration.probability(40) - 0.5).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn decreasing_scores_give_constant_probability() {
+        let samples = vec![(10, true), (20, true), (80, false), (90, false)];
+        for calibration in [
+            Calibration::fit_platt(&samples),
+            Calibration::fit_isotonic(&samples),
+        ] {
+            assert!((calibration.probability(0) - calibration.probability(100)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let calibration = Calibration::fit_isotonic(&overlapping());
+        let path = std::env::temp_dir().join(format!("calibration-{}.json", std::process::id()));
+        calibration.save(&path).unwrap();
+        let loaded = Calibration::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(calibration, loaded);
+    }
+}
@@ -1,14 +1,15 @@
-use bitarray::BitArray;
-use typenum::U256;
+use alloc::vec;
+use alloc::vec::Vec;
 
-use crate::consts::{factor, MAX_NUMBER_OF_PAIRS};
-use crate::math::{
-    are_angles_equal_with_tolerance, average_angles, calculate_slope_in_degrees, normalize_angle,
-    Averager,
+use crate::consts::{
+    angle_lower_bound, angle_upper_bound, factor, MAX_NUMBER_OF_MINUTIAE, MAX_NUMBER_OF_PAIRS,
 };
-use crate::set_intersection::intersection_of_sorted;
-use crate::{is_strict_mode, Format, Minutia, PairHolder};
-use std::collections::{HashSet, VecDeque};
+use crate::deadline::Deadline;
+use crate::math::{average_angles, calculate_slope_in_degrees, Averager};
+use crate::memory::heap_size;
+use crate::util::intersection_of_sorted;
+use crate::{CoordinateOrigin, Degrees, Format, MatchConfig, Minutia, OwnedCluster, PairHolder};
+use alloc::collections::BTreeMap;
 
 pub(crate) struct ClusterAssigner {
     cluster_by_pair: [u32; MAX_NUMBER_OF_PAIRS],
@@ -45,50 +46,87 @@ impl ClusterAssigner {
         self.cluster_by_pair[pair_index as usize] = cluster + 1;
     }
 
+    /// Removes the pair from its cluster; with `marker`, see `CompatQuirks::UNASSIGN_MARKER`,
+    /// it is marked instead of being cleared.
     #[inline]
-    pub(crate) fn unassign(&mut self, pair_index: u32) {
-        if is_strict_mode() {
+    pub(crate) fn unassign(&mut self, pair_index: u32, marker: bool) {
+        if marker {
             self.cluster_by_pair[pair_index as usize] = MARKER_UNASSIGNED;
         } else {
-            // XXX: clearing makes more sense
             self.cluster_by_pair[pair_index as usize] = 0;
         }
     }
+
+    /// Whether the pair was marked by `unassign`.
+    #[inline]
+    pub(crate) fn is_marked_unassigned(&self, pair_index: u32) -> bool {
+        self.cluster_by_pair[pair_index as usize] == MARKER_UNASSIGNED
+    }
 }
 
 /// Structure containing various averages for pairs in a cluster.
-pub(crate) struct ClusterAverages {
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ClusterAverages {
     /// Average of `delta_theta` angles
-    delta_theta: i32,
+    pub delta_theta: Degrees,
     /// Average value of `x` coordinates in fingerprint P
-    probe_x: i32,
+    pub probe_x: i32,
     /// Average value of `y` coordinates in fingerprint P
-    probe_y: i32,
+    pub probe_y: i32,
     /// Average value of `x` coordinates in fingerprint G
-    gallery_x: i32,
+    pub gallery_x: i32,
     /// Average value of `y` coordinates in fingerprint G
-    gallery_y: i32,
+    pub gallery_y: i32,
+}
+
+/// Words of an `EndpointSet`, enough for one bit per minutia index.
+const ENDPOINT_WORDS: usize = MAX_NUMBER_OF_MINUTIAE.div_ceil(64);
+
+/// Set of minutia indices below `MAX_NUMBER_OF_MINUTIAE`, one bit per minutia.
+#[derive(Default)]
+pub(crate) struct EndpointSet([u64; ENDPOINT_WORDS]);
+
+impl EndpointSet {
+    #[inline]
+    pub(crate) fn insert(&mut self, index: usize) {
+        debug_assert!(index < MAX_NUMBER_OF_MINUTIAE);
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn contains(&self, index: usize) -> bool {
+        self.0[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.iter().map(|it| it.count_ones() as usize).sum()
+    }
+
+    #[inline]
+    pub(crate) fn intersects(&self, other: &EndpointSet) -> bool {
+        self.0.iter().zip(&other.0).any(|(a, b)| a & b != 0)
+    }
 }
 
 /// Packed structure that contains all minutiae that are included in the cluster.
 /// Optimized for fast comparison between different clusters.
 pub(crate) struct ClusterEndpoints {
     /// Minutiae of fingerprint P
-    probe: BitArray<u64, U256>,
+    pub(crate) probe: EndpointSet,
     /// Minutiae of fingerprint G
-    gallery: BitArray<u64, U256>,
+    pub(crate) gallery: EndpointSet,
 }
 
 /// Builds a `ClusterEndpoints` structure for given collection of pairs.
 pub(crate) fn encode_selected_endpoints(pairs: &PairHolder, selected: &[u32]) -> ClusterEndpoints {
-    let mut probe = BitArray::new();
-    let mut gallery = BitArray::new();
+    let mut probe = EndpointSet::default();
+    let mut gallery = EndpointSet::default();
     for &idx in selected {
         let pair = pairs.get(idx as usize);
-        probe.set(pair.probe_k.as_usize(), true);
-        probe.set(pair.probe_j.as_usize(), true);
-        gallery.set(pair.gallery_k.as_usize(), true);
-        gallery.set(pair.gallery_j.as_usize(), true);
+        probe.insert(pair.probe_k.as_usize());
+        probe.insert(pair.probe_j.as_usize());
+        gallery.insert(pair.gallery_k.as_usize());
+        gallery.insert(pair.gallery_j.as_usize());
     }
     ClusterEndpoints { probe, gallery }
 }
@@ -106,8 +144,8 @@ pub(crate) struct ClusterSimilar {
 
 pub struct Clusters {
     pub(crate) similar: Vec<ClusterSimilar>,
-    averages: Vec<ClusterAverages>,
-    endpoints: Vec<ClusterEndpoints>,
+    pub(crate) averages: Vec<ClusterAverages>,
+    pub(crate) endpoints: Vec<ClusterEndpoints>,
     // pub(crate) e2e: Vec<Vec<(Endpoint, Endpoint)>>,
     pub pairs: Vec<Vec<u32>>,
 }
@@ -149,16 +187,104 @@ impl Clusters {
         self.endpoints.clear();
         self.pairs.clear();
     }
+
+    /// Bytes held by the clusters, including their nested vectors.
+    pub(crate) fn memory_usage(&self) -> usize {
+        heap_size(&self.similar)
+            + self
+                .similar
+                .iter()
+                .map(|it| heap_size(&it.compatible_clusters))
+                .sum::<usize>()
+            + heap_size(&self.averages)
+            + heap_size(&self.endpoints)
+            + heap_size(&self.pairs)
+            + self.pairs.iter().map(heap_size).sum::<usize>()
+    }
+
+    /// Drops all clusters and trims the capacity to `capacity` clusters.
+    pub(crate) fn shrink(&mut self, capacity: usize) {
+        self.clear();
+        self.similar.shrink_to(capacity);
+        self.averages.shrink_to(capacity);
+        self.endpoints.shrink_to(capacity);
+        self.pairs.shrink_to(capacity);
+    }
+}
+
+/// Result of the checks that decide whether two clusters can add up their points.
+///
+/// The checks run in the order of the variants; the first one that fails is reported.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ClusterCompatibility {
+    Compatible,
+    /// The clusters share a probe or a gallery minutia.
+    CommonEndpoints,
+    /// The average rotations of the clusters differ.
+    Rotation,
+    /// The distance between the clusters differs on the probe and the gallery.
+    Distance,
+    /// The direction from one cluster to the other, corrected by the rotation, differs on the
+    /// probe and the gallery.
+    Direction,
+}
+
+impl ClusterCompatibility {
+    /// Runs the checks `match_score` uses to combine clusters on two clusters of a match, as
+    /// returned by `MatchOutcome::into_clusters`.
+    pub fn of(first: &OwnedCluster, second: &OwnedCluster, format: Format) -> Self {
+        ClusterCompatibility::of_with(first, second, format, &MatchConfig::default())
+    }
+
+    /// Same as `of`, for clusters of a match run with the `config`.
+    pub fn of_with(
+        first: &OwnedCluster,
+        second: &OwnedCluster,
+        format: Format,
+        config: &MatchConfig,
+    ) -> Self {
+        let endpoints = |cluster: &OwnedCluster| {
+            let mut endpoints = ClusterEndpoints {
+                probe: EndpointSet::default(),
+                gallery: EndpointSet::default(),
+            };
+            for pair in &cluster.pairs {
+                endpoints.probe.insert(pair.probe_k.as_usize());
+                endpoints.probe.insert(pair.probe_j.as_usize());
+                endpoints.gallery.insert(pair.gallery_k.as_usize());
+                endpoints.gallery.insert(pair.gallery_j.as_usize());
+            }
+            endpoints
+        };
+        if have_common_endpoints(&endpoints(first), &endpoints(second)) {
+            return ClusterCompatibility::CommonEndpoints;
+        }
+        check_cluster_averages(
+            &first.averages,
+            &second.averages,
+            config.coordinate_origin_of(format),
+        )
+    }
 }
 
 /// Check if one cluster is compatible to another by comparing their various averages.
+#[inline]
 fn are_clusters_compatible(
     averages1: &ClusterAverages,
     averages2: &ClusterAverages,
-    format: Format,
+    origin: CoordinateOrigin,
 ) -> bool {
-    if !are_angles_equal_with_tolerance(averages2.delta_theta, averages1.delta_theta) {
-        return false;
+    check_cluster_averages(averages1, averages2, origin) == ClusterCompatibility::Compatible
+}
+
+#[inline]
+fn check_cluster_averages(
+    averages1: &ClusterAverages,
+    averages2: &ClusterAverages,
+    origin: CoordinateOrigin,
+) -> ClusterCompatibility {
+    if !averages2.delta_theta.is_close_to(averages1.delta_theta) {
+        return ClusterCompatibility::Rotation;
     }
 
     let probe_dx = averages2.probe_x - averages1.probe_x;
@@ -169,75 +295,144 @@ fn are_clusters_compatible(
     let probe_distance_squared = probe_dx.pow(2) + probe_dy.pow(2);
     let gallery_distance_squared = gallery_dx.pow(2) + gallery_dy.pow(2);
 
-    let a = 2.0 * factor() * (probe_distance_squared + gallery_distance_squared) as f32;
+    // the sum of distances far apart does not fit in i32
+    let a = 2.0
+        * factor()
+        * (i64::from(probe_distance_squared) + i64::from(gallery_distance_squared)) as f32;
     let b = ((probe_distance_squared - gallery_distance_squared) as f32).abs();
     if b > a {
-        return false;
+        return ClusterCompatibility::Distance;
     }
 
     let average = average_angles(averages1.delta_theta, averages2.delta_theta);
-    let difference = match format {
-        Format::NistInternal => {
-            calculate_slope_in_degrees(probe_dx, probe_dy)
-                - calculate_slope_in_degrees(gallery_dx, gallery_dy)
-        }
-        Format::Ansi => {
-            calculate_slope_in_degrees(probe_dx, -probe_dy)
-                - calculate_slope_in_degrees(gallery_dx, -gallery_dy)
-        }
-    };
+    let difference = calculate_slope_in_degrees(probe_dx, origin.upwards(probe_dy))
+        - calculate_slope_in_degrees(gallery_dx, origin.upwards(gallery_dy));
 
-    are_angles_equal_with_tolerance(average, normalize_angle(difference))
+    if average.is_close_to(Degrees(difference).normalized()) {
+        ClusterCompatibility::Compatible
+    } else {
+        ClusterCompatibility::Direction
+    }
 }
 
 /// Check whether clusters include common minutiae.
 fn have_common_endpoints(first: &ClusterEndpoints, second: &ClusterEndpoints) -> bool {
-    first
-        .probe
-        .blocks()
-        .zip(second.probe.blocks())
-        .any(|(a, b)| a & b != 0)
-        || first
-            .gallery
-            .blocks()
-            .zip(second.gallery.blocks())
-            .any(|(a, b)| a & b != 0)
+    first.probe.intersects(&second.probe) || first.gallery.intersects(&second.gallery)
+}
+
+/// Clusters grouped by their average rotation into equal buckets wider than the angle
+/// tolerance, so that clusters of compatible rotations are in the same or in neighboring
+/// buckets, counted around the circle.
+struct RotationBuckets {
+    bucket_of: Vec<usize>,
+    /// Indices of the clusters of every bucket, in increasing order.
+    members: Vec<Vec<u32>>,
+}
+
+impl RotationBuckets {
+    /// `None` when the buckets would not prune anything: with fewer than 3 buckets every
+    /// bucket neighbors all others.
+    fn of(averages: &[ClusterAverages]) -> Option<Self> {
+        let tolerance = angle_lower_bound();
+        // `is_close_to` compares circular distances only for angles within a single turn
+        if tolerance < 0
+            || angle_upper_bound() != 360 - tolerance
+            || averages
+                .iter()
+                .any(|it| !(-180..=180).contains(&it.delta_theta.0))
+        {
+            return None;
+        }
+        let count = (360 / (tolerance + 1)) as usize;
+        if count < 3 {
+            return None;
+        }
+
+        let mut members = vec![vec![]; count];
+        let bucket_of: Vec<_> = averages
+            .iter()
+            .enumerate()
+            .map(|(index, it)| {
+                let bucket = it.delta_theta.0.rem_euclid(360) as usize * count / 360;
+                members[bucket].push(index as u32);
+                bucket
+            })
+            .collect();
+        Some(RotationBuckets { bucket_of, members })
+    }
+
+    /// Clusters after `cluster` whose rotation can be compatible with it, in increasing order.
+    fn candidates(&self, cluster: usize, candidates: &mut Vec<u32>) {
+        let count = self.members.len();
+        let bucket = self.bucket_of[cluster];
+        candidates.clear();
+        for neighbor in [(bucket + count - 1) % count, bucket, (bucket + 1) % count] {
+            let members = &self.members[neighbor];
+            let after = members.partition_point(|&it| it as usize <= cluster);
+            candidates.extend_from_slice(&members[after..]);
+        }
+        candidates.sort_unstable();
+    }
 }
 
 /// Go through all the clusters and try to find ones that do not have common minutiae
 /// and are compatible.
+///
+/// Only clusters of neighboring `RotationBuckets` are checked; the others fail the rotation
+/// check anyway, so the result is the same as when all pairs of clusters are checked.
 pub(crate) fn find_compatible_disjoint_clusters_and_accumulate_points(
     clusters: &mut Clusters,
-    format: Format,
+    origin: CoordinateOrigin,
 ) {
+    let buckets = RotationBuckets::of(&clusters.averages);
+    let mut candidates = vec![];
     for cluster in 0..clusters.similar.len() {
-        let mut points_from_others = 0;
-        let mut compatible_clusters = vec![];
-
-        for other_cluster in cluster + 1..clusters.similar.len() {
-            if have_common_endpoints(
-                &clusters.endpoints[cluster],
-                &clusters.endpoints[other_cluster],
-            ) {
-                continue;
+        match &buckets {
+            Some(buckets) => buckets.candidates(cluster, &mut candidates),
+            None => {
+                candidates.clear();
+                candidates.extend(cluster as u32 + 1..clusters.similar.len() as u32);
             }
+        }
+        accumulate_compatible_clusters(clusters, cluster, &candidates, origin);
+    }
+}
 
-            if !are_clusters_compatible(
-                &clusters.averages[cluster],
-                &clusters.averages[other_cluster],
-                format,
-            ) {
-                continue;
-            }
+/// Checks `cluster` against the `candidates` after it and adds up the points of the compatible
+/// ones.
+fn accumulate_compatible_clusters(
+    clusters: &mut Clusters,
+    cluster: usize,
+    candidates: &[u32],
+    origin: CoordinateOrigin,
+) {
+    let mut points_from_others = 0;
+    let mut compatible_clusters = vec![];
+
+    for &other_cluster in candidates {
+        let other_cluster = other_cluster as usize;
+        if have_common_endpoints(
+            &clusters.endpoints[cluster],
+            &clusters.endpoints[other_cluster],
+        ) {
+            continue;
+        }
 
-            points_from_others += clusters.similar[other_cluster].points;
-            compatible_clusters.push(other_cluster as u32);
+        if !are_clusters_compatible(
+            &clusters.averages[cluster],
+            &clusters.averages[other_cluster],
+            origin,
+        ) {
+            continue;
         }
 
-        clusters.similar[cluster].points_including_compatible_clusters =
-            clusters.similar[cluster].points + points_from_others;
-        clusters.similar[cluster].compatible_clusters = compatible_clusters;
+        points_from_others += clusters.similar[other_cluster].points;
+        compatible_clusters.push(other_cluster as u32);
     }
+
+    clusters.similar[cluster].points_including_compatible_clusters =
+        clusters.similar[cluster].points + points_from_others;
+    clusters.similar[cluster].compatible_clusters = compatible_clusters;
 }
 
 /// Calculate averages of various properties for a collection of pairs.
@@ -248,7 +443,7 @@ pub(crate) fn calculate_averages(
     selected_pairs: &[u32],
 ) -> ClusterAverages {
     let mut average = ClusterAverages {
-        delta_theta: 0,
+        delta_theta: Degrees(0),
         probe_x: 0,
         probe_y: 0,
         gallery_x: 0,
@@ -279,10 +474,18 @@ pub(crate) fn calculate_averages(
 }
 
 /// Calculates the highest sum of points for compatible clusters.
+/// Finds the set of mutually compatible clusters with the highest score.
+///
+/// Indices of the clusters of the best set are stored in `best_clusters`. When the `deadline`
+/// passes, the search stops with the best score found so far as the error and `best_clusters`
+/// holds the set of that score.
 pub(crate) fn combine_clusters(
     clusters: &Clusters,
     collect_compatible_clusters: bool,
-) -> (u32, Vec<u32>) {
+    best_clusters: &mut Vec<u32>,
+    deadline: &Deadline,
+) -> Result<(u32, Vec<u32>), u32> {
+    best_clusters.clear();
     #[derive(Debug)]
     struct Item {
         cluster: u32,
@@ -293,6 +496,7 @@ pub(crate) fn combine_clusters(
     let mut items = vec![];
     let mut best_score = 0;
     let mut minutiae_of_biggest = vec![];
+    let mut steps = 0;
 
     for (cluster_index, cluster) in clusters.similar.iter().enumerate() {
         // NOTE: it looks like a heuristic, it helps to avoid unnecessary calculations
@@ -307,6 +511,9 @@ pub(crate) fn combine_clusters(
         });
 
         while let Some(last) = items.last() {
+            if deadline.has_passed_after_step(&mut steps) {
+                return Err(best_score);
+            }
             if (last.index as usize) < last.connected.len() {
                 let next_cluster = last.connected[last.index as usize] as usize;
 
@@ -337,6 +544,8 @@ pub(crate) fn combine_clusters(
 
                     if score > best_score {
                         best_score = score;
+                        best_clusters.clear();
+                        best_clusters.extend(items.iter().map(|it| it.cluster));
                         if collect_compatible_clusters {
                             minutiae_of_biggest = items
                                 .iter()
@@ -363,44 +572,300 @@ pub(crate) fn combine_clusters(
         }
     }
 
-    (best_score, minutiae_of_biggest)
+    Ok((best_score, minutiae_of_biggest))
 }
 
-#[allow(unused)]
+/// Same as `combine_clusters`, but remembers the sets of candidate clusters it has expanded.
+///
+/// The clusters that can extend a path depend only on the set of candidates left, so a path
+/// that reaches an expanded set with no more points than before cannot lead to a better score
+/// and is skipped. The best score, `best_clusters` and the compatible clusters are the same as
+/// those of `combine_clusters`; it trades memory for fewer steps on dense clusters.
 pub(crate) fn combine_clusters_2(
     clusters: &Clusters,
     collect_compatible_clusters: bool,
-) -> (u32, Vec<u32>) {
-    assert!(!collect_compatible_clusters);
+    best_clusters: &mut Vec<u32>,
+    deadline: &Deadline,
+) -> Result<(u32, Vec<u32>), u32> {
+    best_clusters.clear();
+    struct Item {
+        cluster: u32,
+        connected: Vec<u32>,
+        index: u32,
+        /// Points of the path up to and including this cluster.
+        score: u32,
+    }
 
+    let mut items: Vec<Item> = vec![];
     let mut best_score = 0;
-    let mut stack = VecDeque::new();
-    let mut visited = HashSet::new();
+    let mut minutiae_of_biggest = vec![];
+    // points of the best path that reached each expanded set of candidates
+    let mut visited: BTreeMap<Vec<u32>, u32> = BTreeMap::new();
+    let mut steps = 0;
 
     for (cluster_index, cluster) in clusters.similar.iter().enumerate() {
         if best_score >= cluster.points_including_compatible_clusters {
             continue;
         }
 
-        stack.push_back(cluster_index as u32);
-        let mut val = 0;
-        visited.clear();
-        while let Some(n) = stack.pop_back() {
-            if visited.insert(n) {
-                val += clusters.similar[n as usize].points;
+        items.push(Item {
+            cluster: cluster_index as u32,
+            index: 0,
+            connected: cluster.compatible_clusters.clone(),
+            score: cluster.points,
+        });
+
+        while let Some(last) = items.last_mut() {
+            if deadline.has_passed_after_step(&mut steps) {
+                return Err(best_score);
             }
-            for node in clusters.similar[n as usize]
-                .compatible_clusters
-                .iter()
+            if (last.index as usize) < last.connected.len() {
+                let next_cluster = last.connected[last.index as usize] as usize;
+                let connected: Vec<u32> = intersection_of_sorted(
+                    last.connected.iter(),
+                    clusters.similar[next_cluster].compatible_clusters.iter(),
+                )
                 .copied()
-            {
-                if !visited.contains(&node) {
-                    stack.push_back(node);
+                .collect();
+                let score = last.score + clusters.similar[next_cluster].points;
+
+                match visited.get_mut(&connected) {
+                    Some(seen) if *seen >= score => {
+                        last.index += 1;
+                        continue;
+                    }
+                    Some(seen) => *seen = score,
+                    None => {
+                        visited.insert(connected.clone(), score);
+                    }
+                }
+                items.push(Item {
+                    cluster: next_cluster as u32,
+                    connected,
+                    index: 0,
+                    score,
+                });
+            } else {
+                if last.connected.is_empty() && last.score > best_score {
+                    best_score = last.score;
+                    best_clusters.clear();
+                    best_clusters.extend(items.iter().map(|it| it.cluster));
+                    if collect_compatible_clusters {
+                        minutiae_of_biggest = items
+                            .iter()
+                            .flat_map(|it| {
+                                clusters.similar[it.cluster as usize]
+                                    .compatible_clusters
+                                    .iter()
+                            })
+                            .copied()
+                            .collect();
+                        minutiae_of_biggest.sort();
+                        minutiae_of_biggest.dedup();
+                    }
                 }
+
+                items.pop().unwrap();
+                if let Some(last) = items.last_mut() {
+                    last.index += 1;
+                }
+            }
+        }
+    }
+
+    Ok((best_score, minutiae_of_biggest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        accumulate_compatible_clusters, find_compatible_disjoint_clusters_and_accumulate_points,
+        ClusterAverages, ClusterEndpoints, ClusterSimilar, Clusters, EndpointSet, RotationBuckets,
+    };
+    use crate::config::MatchConfig;
+    use crate::consts::MAX_NUMBER_OF_MINUTIAE;
+    use crate::synthetic::Generator;
+    use crate::{
+        match_edges_into_pairs, match_score, BozorthState, CoordinateOrigin, Degrees, Fingerprint,
+        Format, Minutia, PairHolder, PruneOptions,
+    };
+
+    /// Results of the search for compatible clusters, for every cluster.
+    fn compatibility(clusters: &Clusters) -> Vec<(u32, Vec<u32>)> {
+        clusters
+            .similar
+            .iter()
+            .map(|it| {
+                (
+                    it.points_including_compatible_clusters,
+                    it.compatible_clusters.clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn compatible_count(clusters: &Clusters) -> usize {
+        clusters
+            .similar
+            .iter()
+            .map(|it| it.compatible_clusters.len())
+            .sum()
+    }
+
+    /// Checks every pair of clusters, the way the search worked before rotation buckets.
+    fn brute_force(clusters: &mut Clusters, origin: CoordinateOrigin) {
+        for cluster in 0..clusters.len() {
+            let candidates: Vec<_> = (cluster as u32 + 1..clusters.len() as u32).collect();
+            accumulate_compatible_clusters(clusters, cluster, &candidates, origin);
+        }
+    }
+
+    fn assert_same_as_brute_force(clusters: &mut Clusters, origin: CoordinateOrigin) {
+        find_compatible_disjoint_clusters_and_accumulate_points(clusters, origin);
+        let bucketed = compatibility(clusters);
+        brute_force(clusters, origin);
+        assert_eq!(bucketed, compatibility(clusters));
+    }
+
+    fn random_clusters(generator: &mut Generator, count: usize) -> Clusters {
+        let mut clusters = Clusters::with_capacity(count);
+        for _ in 0..count {
+            let mut probe = EndpointSet::default();
+            let mut gallery = EndpointSet::default();
+            for _ in 0..3 {
+                probe.insert(generator.next(150) as usize);
+                gallery.insert(generator.next(150) as usize);
             }
+            // a quarter of the rotations is close to the wrap-around at 180 degrees
+            let delta_theta = if generator.next(4) == 0 {
+                180 - generator.next(30) * if generator.next(2) == 0 { 1 } else { -1 }
+            } else {
+                generator.next(361) - 180
+            };
+            let delta_theta = if delta_theta > 180 {
+                delta_theta - 360
+            } else {
+                delta_theta
+            };
+            clusters.push(
+                ClusterSimilar {
+                    points: 1 + generator.next(20) as u32,
+                    compatible_clusters: vec![],
+                    points_including_compatible_clusters: 0,
+                },
+                ClusterAverages {
+                    delta_theta: Degrees(delta_theta),
+                    probe_x: generator.next(400),
+                    probe_y: generator.next(400),
+                    gallery_x: generator.next(400),
+                    gallery_y: generator.next(400),
+                },
+                ClusterEndpoints { probe, gallery },
+                vec![],
+            );
+        }
+        clusters
+    }
+
+    #[test]
+    fn endpoint_sets_do_not_alias_indices() {
+        let boundaries = [0, 1, 63, 64, 127, 128, 191, 192, MAX_NUMBER_OF_MINUTIAE - 1];
+        for &index in &boundaries {
+            let mut set = EndpointSet::default();
+            set.insert(index);
+            assert_eq!(set.len(), 1);
+            for other in 0..MAX_NUMBER_OF_MINUTIAE {
+                assert_eq!(set.contains(other), other == index, "{} {}", index, other);
+
+                let mut other_set = EndpointSet::default();
+                other_set.insert(other);
+                assert_eq!(set.intersects(&other_set), other == index);
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_buckets_find_the_same_clusters_as_brute_force() {
+        assert!(RotationBuckets::of(&[]).is_some());
+        let mut compatible = 0;
+        for seed in 0..50 {
+            let mut generator = Generator::new(seed);
+            let count = 1 + generator.next(300) as usize;
+            let mut clusters = random_clusters(&mut generator, count);
+            assert_same_as_brute_force(&mut clusters, CoordinateOrigin::TopLeft);
+            assert_same_as_brute_force(&mut clusters, CoordinateOrigin::BottomLeft);
+            compatible += compatible_count(&clusters);
         }
-        best_score = best_score.max(val);
+        assert!(compatible > 0);
     }
 
-    (best_score, vec![])
+    #[test]
+    fn rotation_buckets_find_the_same_clusters_as_brute_force_in_real_matches() {
+        let format = Format::NistInternal;
+        let mut compatible = 0;
+        for seed in 0..20 {
+            let mut generator = Generator::new(100 + seed);
+            let template = generator.raw_template(150);
+            let impression = if seed % 4 == 0 {
+                generator.raw_template(150)
+            } else {
+                generator.impression(&template, seed as i32 * 3 - 30, (5, -8))
+            };
+            let probe = Fingerprint::from_raw(&template, PruneOptions::default(), format);
+            let gallery = Fingerprint::from_raw(&impression, PruneOptions::default(), format);
+
+            let mut pairs = PairHolder::new();
+            match_edges_into_pairs(
+                probe.sorted_edges(),
+                &probe.minutiae,
+                gallery.sorted_edges(),
+                &gallery.minutiae,
+                &mut pairs,
+                &MatchConfig::default(),
+                |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+            );
+            pairs.prepare();
+
+            let mut state = BozorthState::new();
+            match_score(
+                &pairs,
+                &probe.minutiae,
+                &gallery.minutiae,
+                format,
+                &mut state,
+            )
+            .unwrap();
+            assert_same_as_brute_force(&mut state.clusters, format.coordinate_origin());
+            compatible += compatible_count(&state.clusters);
+        }
+        assert!(compatible > 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn combining_clusters_stops_at_the_deadline() {
+        use super::combine_clusters;
+        use crate::deadline::Deadline;
+        use std::sync::atomic::Ordering;
+
+        // every cluster is compatible with every other one, so the search never ends on its own
+        let count = 64;
+        let mut clusters = Clusters::with_capacity(count);
+        for index in 0..count as u32 {
+            clusters.similar.push(ClusterSimilar {
+                points: 1,
+                compatible_clusters: (index + 1..count as u32).collect(),
+                points_including_compatible_clusters: count as u32 - index,
+            });
+        }
+
+        // the clock counts its readings, so the test does not depend on the speed of the machine
+        let (deadline, clock) = Deadline::after_readings(20);
+        let mut best_clusters = vec![];
+        let result = combine_clusters(&clusters, false, &mut best_clusters, &deadline);
+        assert_eq!(clock.readings.load(Ordering::Relaxed), clock.limit);
+        // the first path of the search takes all the clusters
+        assert_eq!(result, Err(count as u32));
+        assert_eq!(best_clusters.len(), count);
+    }
 }
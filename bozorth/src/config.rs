@@ -0,0 +1,239 @@
+use core::fmt;
+use core::ops::{BitOr, Sub};
+#[cfg(feature = "std")]
+use core::time::Duration;
+
+use crate::{default_mode, AngleOrigin, CoordinateOrigin, Format};
+
+/// Additional filtering applied to candidate pairs in `match_edges_into_pairs`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PairFilter {
+    /// Only betas of the edges are compared, like in the original Bozorth3.
+    Off,
+    /// Rejects pairs whose endpoints' orientations do not agree with the `delta_theta`
+    /// of the pair (within the angle tolerance).
+    Orientation,
+}
+
+/// Algorithm that looks for the best set of mutually compatible clusters.
+///
+/// Both find the same score and clusters, they differ only in speed and memory.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ClusterCombineStrategy {
+    /// Depth-first search over the compatible clusters with an explicit stack, like the
+    /// original Bozorth3.
+    IterativeStack,
+    /// The same search, but it skips sets of candidate clusters it has already expanded with at
+    /// least as many points.
+    VisitedSet,
+}
+
+/// Behaviors of the original Bozorth3 that look like bugs, such as off-by-one errors, kept for
+/// identical scores; see `MatchConfig::quirks`.
+///
+/// `BozorthState::applied_quirks` tells which of them changed the course of the last match.
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
+pub struct CompatQuirks(u8);
+
+impl CompatQuirks {
+    pub const NONE: CompatQuirks = CompatQuirks(0);
+    /// `match_edges_into_pairs` does not match the last probe edge.
+    pub const SKIP_LAST_PROBE_EDGE: CompatQuirks = CompatQuirks(1);
+    /// `match_score` does not start a cluster from the last pair.
+    pub const SKIP_LAST_START_PAIR: CompatQuirks = CompatQuirks(1 << 1);
+    /// Mutually associated endpoints met again while building a cluster are queued again,
+    /// unless the index of their pair equals one of the queued probe endpoints.
+    pub const PAIR_INDEX_AS_ENDPOINT_CHECK: CompatQuirks = CompatQuirks(1 << 2);
+    /// Pairs removed from a cluster too small to be kept are marked as assigned to no cluster,
+    /// so that they never start a cluster, instead of being cleared.
+    pub const UNASSIGN_MARKER: CompatQuirks = CompatQuirks(1 << 3);
+    pub const ALL: CompatQuirks = CompatQuirks((1 << 4) - 1);
+
+    const NAMES: [(CompatQuirks, &'static str); 4] = [
+        (CompatQuirks::SKIP_LAST_PROBE_EDGE, "SkipLastProbeEdge"),
+        (CompatQuirks::SKIP_LAST_START_PAIR, "SkipLastStartPair"),
+        (
+            CompatQuirks::PAIR_INDEX_AS_ENDPOINT_CHECK,
+            "PairIndexAsEndpointCheck",
+        ),
+        (CompatQuirks::UNASSIGN_MARKER, "UnassignMarker"),
+    ];
+
+    /// All quirks in the strict mode, none in the relaxed one.
+    pub const fn of_mode(strict: bool) -> Self {
+        if strict {
+            CompatQuirks::ALL
+        } else {
+            CompatQuirks::NONE
+        }
+    }
+
+    pub const fn contains(self, other: CompatQuirks) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub(crate) fn insert(&mut self, other: CompatQuirks) {
+        self.0 |= other.0;
+    }
+
+    /// Every quirk of the set on its own, with its name.
+    pub fn iter(self) -> impl Iterator<Item = (CompatQuirks, &'static str)> {
+        CompatQuirks::NAMES
+            .iter()
+            .copied()
+            .filter(move |&(quirk, _)| self.contains(quirk))
+    }
+}
+
+impl BitOr for CompatQuirks {
+    type Output = CompatQuirks;
+
+    fn bitor(self, other: CompatQuirks) -> CompatQuirks {
+        CompatQuirks(self.0 | other.0)
+    }
+}
+
+impl Sub for CompatQuirks {
+    type Output = CompatQuirks;
+
+    fn sub(self, other: CompatQuirks) -> CompatQuirks {
+        CompatQuirks(self.0 & !other.0)
+    }
+}
+
+impl fmt::Debug for CompatQuirks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CompatQuirks(")?;
+        for (index, (_, name)) in self.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" | ")?;
+            }
+            f.write_str(name)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Whether matches look for quirks that changed them only to log it, see `CompatQuirks`.
+#[inline]
+pub(crate) fn quirks_are_logged() -> bool {
+    #[cfg(feature = "log")]
+    {
+        log::log_enabled!(log::Level::Debug)
+    }
+    #[cfg(not(feature = "log"))]
+    {
+        false
+    }
+}
+
+/// Options of a single match that are not part of the original algorithm.
+///
+/// `MatchConfig::default()` reproduces the original behavior, unless the deprecated `set_mode`
+/// switched to the relaxed mode.
+#[derive(Debug, Copy, Clone)]
+pub struct MatchConfig {
+    /// Filter applied to candidate pairs.
+    pub pair_filter: PairFilter,
+    /// Collapse pairs with identical endpoints in `PairHolder::prepare_with`,
+    /// keeping the one with the most points.
+    ///
+    /// Endpoints at the same position and angle count as one minutia, so duplicated minutiae,
+    /// which some extractors emit, do not inflate scores.
+    pub dedup_pairs: bool,
+    /// Overrides `consts::score_threshold` for this match; `None` uses the global value.
+    pub score_threshold: Option<u32>,
+    /// Always run `combine_clusters`, even when the initial estimate is below the score threshold.
+    ///
+    /// By default a match whose best cluster scores below the threshold returns that estimate,
+    /// which is much cheaper than combining clusters and almost always low for impostors.
+    /// Forcing the combination gives the exact score for every pair at the cost of a slower
+    /// match, which is useful for studying the error of the estimate.
+    pub force_combine: bool,
+    /// Copy the clusters out of the matching state into the `MatchOutcome`.
+    pub collect_clusters: bool,
+    /// Algorithm that combines compatible clusters into the score.
+    pub combine_strategy: ClusterCombineStrategy,
+    /// Skip pairs of minutiae with exactly opposite orientations in `find_edges_with`, like the
+    /// original Bozorth3.
+    ///
+    /// Templates of extractors whose angle conventions differ by 180° lose useful edges to it.
+    pub skip_opposite_angles: bool,
+    /// Strict mode, identical to the original Bozorth3, or the relaxed one.
+    ///
+    /// The relaxed mode differs from the strict one in that:
+    /// - `limit_edges_with` cuts the edges with a binary search over their lengths;
+    /// - groups keep track of associations that already existed, and mutually associated
+    ///   endpoints do not overwrite the last association of a group;
+    /// - none of the `CompatQuirks` apply, unless `quirks` chooses them.
+    ///
+    /// Pruning has its own `PruneOptions::strict`; `bz3 compare-modes` reports how the mode
+    /// changes the scores of a dataset.
+    pub strict: bool,
+    /// Quirks of the original Bozorth3 reproduced by the match; `None` takes
+    /// `CompatQuirks::of_mode` of `strict`.
+    pub quirks: Option<CompatQuirks>,
+    /// Time after which `match_score_with` gives up with `MatchError::DeadlineExceeded`; `None`
+    /// lets it run to the end.
+    ///
+    /// It is checked before every cluster is built and every few steps of combining clusters,
+    /// so a match can overrun it by the time of a single such step.
+    #[cfg(feature = "std")]
+    pub deadline: Option<Duration>,
+    /// Steps of the search for non-conflicting associations of the groups allowed for every
+    /// group and candidate endpoint of a group; `None` does not limit the search, like the
+    /// original implementation.
+    ///
+    /// The search tries every combination of candidates, which can grow exponentially for
+    /// templates with many similar minutiae. Start pairs that run out of the budget build no
+    /// more clusters, see `BozorthState::association_searches_over_budget`.
+    pub association_search_steps: Option<usize>,
+    /// Origin of the coordinates of both templates; `None` takes the one of the `Format` of the
+    /// match.
+    ///
+    /// Edges and the directions between clusters are measured with y growing upwards, so the
+    /// origin decides whether `dy` is flipped, independently of the angles. The edges have to be
+    /// found with the same origins, e.g. by `Fingerprint::new_with` with this config.
+    pub coordinate_origin: Option<CoordinateOrigin>,
+    /// Origin of the angles of both templates; every `Format` has `AngleOrigin::BottomLeft`.
+    pub angle_origin: AngleOrigin,
+}
+
+impl MatchConfig {
+    /// Origin of the coordinates in a match of templates in the `format`.
+    pub(crate) fn coordinate_origin_of(&self, format: Format) -> CoordinateOrigin {
+        self.coordinate_origin
+            .unwrap_or_else(|| format.coordinate_origin())
+    }
+
+    /// Quirks reproduced by a match with this config.
+    pub(crate) fn quirks(&self) -> CompatQuirks {
+        self.quirks
+            .unwrap_or_else(|| CompatQuirks::of_mode(self.strict))
+    }
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        MatchConfig {
+            pair_filter: PairFilter::Off,
+            dedup_pairs: false,
+            score_threshold: None,
+            force_combine: false,
+            collect_clusters: false,
+            combine_strategy: ClusterCombineStrategy::IterativeStack,
+            skip_opposite_angles: true,
+            strict: default_mode(),
+            quirks: None,
+            #[cfg(feature = "std")]
+            deadline: None,
+            association_search_steps: None,
+            coordinate_origin: None,
+            angle_origin: AngleOrigin::BottomLeft,
+        }
+    }
+}
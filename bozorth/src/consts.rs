@@ -1,46 +1,138 @@
-use std::sync::atomic::{AtomicI32, AtomicU32, AtomicUsize, Ordering};
+use core::sync::atomic::Ordering;
+#[cfg(feature = "std")]
+pub(crate) use core::sync::atomic::{
+    AtomicBool as GlobalBool, AtomicI32 as GlobalI32, AtomicU32 as GlobalU32,
+    AtomicUsize as GlobalUsize,
+};
+
+/// Replaces the atomics without `std`, where the constants cannot be set and keep `DEFAULTS`.
+#[cfg(not(feature = "std"))]
+pub(crate) struct Fixed<T>(T);
+
+#[cfg(not(feature = "std"))]
+impl<T: Copy> Fixed<T> {
+    pub(crate) const fn new(value: T) -> Self {
+        Fixed(value)
+    }
+
+    #[inline(always)]
+    pub(crate) fn load(&self, _: Ordering) -> T {
+        self.0
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) type GlobalBool = Fixed<bool>;
+#[cfg(not(feature = "std"))]
+type GlobalI32 = Fixed<i32>;
+#[cfg(not(feature = "std"))]
+type GlobalU32 = Fixed<u32>;
+#[cfg(not(feature = "std"))]
+type GlobalUsize = Fixed<usize>;
+
+/// Initial values of the tunable constants, the ones of the original Bozorth3.
+///
+/// Without the `std` feature the constants cannot be set and always have these values; per-call
+/// options go through `MatchConfig` instead.
+pub const DEFAULTS: Snapshot = Snapshot {
+    edge_generation_max_distance: 125,
+    edge_retention_max_distance: 75,
+    min_number_of_pairs_to_build_cluster: 3,
+    max_number_of_clusters: 2000,
+    score_threshold: 8,
+    angle_diff: 11,
+    max_number_of_groups: 10,
+    factor: 0.05,
+};
 
 /*pub(crate)*/
-static MAX_MINUTIA_DISTANCE: AtomicI32 = AtomicI32::new(125);
+static EDGE_GENERATION_MAX_DISTANCE: GlobalI32 =
+    GlobalI32::new(DEFAULTS.edge_generation_max_distance);
+/*pub(crate)*/
+static EDGE_GENERATION_MAX_DISTANCE_SQUARED: GlobalI32 =
+    GlobalI32::new(DEFAULTS.edge_generation_max_distance.pow(2));
 /*pub(crate)*/
-static MAX_MINUTIA_DISTANCE_SQUARED: AtomicI32 = AtomicI32::new(75i32.pow(2));
+static EDGE_RETENTION_MAX_DISTANCE: GlobalI32 =
+    GlobalI32::new(DEFAULTS.edge_retention_max_distance);
 /*pub(crate)*/
-static MIN_NUMBER_OF_PAIRS_TO_BUILD_CLUSTER: AtomicUsize = AtomicUsize::new(3);
+static EDGE_RETENTION_MAX_DISTANCE_SQUARED: GlobalI32 =
+    GlobalI32::new(DEFAULTS.edge_retention_max_distance.pow(2));
 /*pub(crate)*/
-static MAX_NUMBER_OF_CLUSTERS: AtomicUsize = AtomicUsize::new(2000);
+static MIN_NUMBER_OF_PAIRS_TO_BUILD_CLUSTER: GlobalUsize =
+    GlobalUsize::new(DEFAULTS.min_number_of_pairs_to_build_cluster);
 /*pub(crate)*/
-static SCORE_THRESHOLD: AtomicU32 = AtomicU32::new(8);
+static MAX_NUMBER_OF_CLUSTERS: GlobalUsize = GlobalUsize::new(DEFAULTS.max_number_of_clusters);
 /*pub(crate)*/
-static ANGLE_LOWER_BOUND: AtomicI32 = AtomicI32::new(11);
+static SCORE_THRESHOLD: GlobalU32 = GlobalU32::new(DEFAULTS.score_threshold);
 /*pub(crate)*/
-static ANGLE_UPPER_BOUND: AtomicI32 = AtomicI32::new(360 - 11);
+static ANGLE_LOWER_BOUND: GlobalI32 = GlobalI32::new(DEFAULTS.angle_diff);
 /*pub(crate)*/
-static MAX_NUMBER_OF_GROUPS: AtomicUsize = AtomicUsize::new(10);
+static ANGLE_UPPER_BOUND: GlobalI32 = GlobalI32::new(360 - DEFAULTS.angle_diff);
 /*pub(crate)*/
-static FACTOR: AtomicU32 = AtomicU32::new(0.05f32.to_bits());
+static MAX_NUMBER_OF_GROUPS: GlobalUsize = GlobalUsize::new(DEFAULTS.max_number_of_groups);
+/*pub(crate)*/
+static FACTOR: GlobalU32 = GlobalU32::new(DEFAULTS.factor.to_bits());
 
 pub(crate) const MAX_FILE_MINUTIAE: usize = 1000;
 pub(crate) const MAX_NUMBER_OF_PAIRS: usize = 20000;
-pub(crate) const MAX_NUMBER_OF_MINUTIAE: usize = 200;
+pub const MAX_NUMBER_OF_MINUTIAE: usize = 200;
 pub(crate) const MIN_NUMBER_OF_EDGES: usize = 500;
 pub(crate) const MAX_NUMBER_OF_EDGES: usize = 20000;
 
+/// Longest edge `find_edges` creates.
+///
+/// Edges longer than `edge_retention_max_distance` are dropped again by `limit_edges`, unless
+/// they are needed to reach `MIN_NUMBER_OF_EDGES`; both defaults follow the original C code.
+pub fn edge_generation_max_distance() -> i32 {
+    EDGE_GENERATION_MAX_DISTANCE.load(Ordering::Relaxed)
+}
+
+pub fn edge_generation_max_distance_squared() -> i32 {
+    EDGE_GENERATION_MAX_DISTANCE_SQUARED.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "std")]
+pub fn set_edge_generation_max_distance(n: i32) {
+    EDGE_GENERATION_MAX_DISTANCE.store(n, Ordering::SeqCst);
+    EDGE_GENERATION_MAX_DISTANCE_SQUARED.store(n.pow(2), Ordering::SeqCst);
+}
+
+/// Longest edge `limit_edges` keeps beyond the first `MIN_NUMBER_OF_EDGES` edges.
+pub fn edge_retention_max_distance() -> i32 {
+    EDGE_RETENTION_MAX_DISTANCE.load(Ordering::Relaxed)
+}
+
+pub fn edge_retention_max_distance_squared() -> i32 {
+    EDGE_RETENTION_MAX_DISTANCE_SQUARED.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "std")]
+pub fn set_edge_retention_max_distance(n: i32) {
+    EDGE_RETENTION_MAX_DISTANCE.store(n, Ordering::SeqCst);
+    EDGE_RETENTION_MAX_DISTANCE_SQUARED.store(n.pow(2), Ordering::SeqCst);
+}
+
+#[deprecated(note = "use `edge_generation_max_distance`")]
 pub fn max_minutia_distance() -> i32 {
-    MAX_MINUTIA_DISTANCE.load(Ordering::Relaxed)
+    edge_generation_max_distance()
 }
 
+#[cfg(feature = "std")]
+#[deprecated(note = "use `set_edge_generation_max_distance`")]
 pub fn set_max_minutia_distance(n: i32) {
-    MAX_MINUTIA_DISTANCE.store(n, Ordering::SeqCst)
+    set_edge_generation_max_distance(n)
 }
 
+#[deprecated(note = "use `edge_retention_max_distance_squared`")]
 pub fn max_minutia_distance_squared() -> i32 {
-    MAX_MINUTIA_DISTANCE_SQUARED.load(Ordering::Relaxed)
+    edge_retention_max_distance_squared()
 }
 
 pub fn min_number_of_pairs_to_build_cluster() -> usize {
     MIN_NUMBER_OF_PAIRS_TO_BUILD_CLUSTER.load(Ordering::Relaxed)
 }
 
+#[cfg(feature = "std")]
 pub fn set_min_number_of_pairs_to_build_cluster(n: usize) {
     MIN_NUMBER_OF_PAIRS_TO_BUILD_CLUSTER.store(n, Ordering::SeqCst)
 }
@@ -49,14 +141,22 @@ pub fn max_number_of_clusters() -> usize {
     MAX_NUMBER_OF_CLUSTERS.load(Ordering::Relaxed)
 }
 
+#[cfg(feature = "std")]
 pub fn set_max_number_of_clusters(n: usize) {
     MAX_NUMBER_OF_CLUSTERS.store(n, Ordering::SeqCst);
 }
 
+/// Clusters scoring below this are not combined by `match_score`, see
+/// `MatchConfig::score_threshold`.
 pub fn score_threshold() -> u32 {
     SCORE_THRESHOLD.load(Ordering::Relaxed)
 }
 
+#[cfg(feature = "std")]
+pub fn set_score_threshold(n: u32) {
+    SCORE_THRESHOLD.store(n, Ordering::SeqCst);
+}
+
 pub fn angle_lower_bound() -> i32 {
     ANGLE_LOWER_BOUND.load(Ordering::Relaxed)
 }
@@ -65,6 +165,7 @@ pub fn angle_upper_bound() -> i32 {
     ANGLE_UPPER_BOUND.load(Ordering::Relaxed)
 }
 
+#[cfg(feature = "std")]
 pub fn set_angle_diff(n: i32) {
     ANGLE_LOWER_BOUND.store(n, Ordering::SeqCst);
     ANGLE_UPPER_BOUND.store(360 - n, Ordering::SeqCst);
@@ -74,6 +175,7 @@ pub fn max_number_of_groups() -> usize {
     MAX_NUMBER_OF_GROUPS.load(Ordering::Relaxed)
 }
 
+#[cfg(feature = "std")]
 pub fn set_max_number_of_groups(n: usize) {
     MAX_NUMBER_OF_GROUPS.store(n, Ordering::Relaxed);
 }
@@ -82,6 +184,49 @@ pub fn factor() -> f32 {
     f32::from_bits(FACTOR.load(Ordering::Relaxed))
 }
 
+#[cfg(feature = "std")]
 pub fn set_factor(x: f32) {
     FACTOR.store(x.to_bits(), Ordering::SeqCst)
 }
+
+/// Values of all the tunable constants, e.g. to restore them after an experiment.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Snapshot {
+    pub edge_generation_max_distance: i32,
+    pub edge_retention_max_distance: i32,
+    pub min_number_of_pairs_to_build_cluster: usize,
+    pub max_number_of_clusters: usize,
+    pub score_threshold: u32,
+    pub angle_diff: i32,
+    pub max_number_of_groups: usize,
+    pub factor: f32,
+}
+
+/// Captures the current values of the constants.
+pub fn snapshot() -> Snapshot {
+    Snapshot {
+        edge_generation_max_distance: edge_generation_max_distance(),
+        edge_retention_max_distance: edge_retention_max_distance(),
+        min_number_of_pairs_to_build_cluster: min_number_of_pairs_to_build_cluster(),
+        max_number_of_clusters: max_number_of_clusters(),
+        score_threshold: score_threshold(),
+        angle_diff: angle_lower_bound(),
+        max_number_of_groups: max_number_of_groups(),
+        factor: factor(),
+    }
+}
+
+impl Snapshot {
+    /// Sets all the constants to the captured values.
+    #[cfg(feature = "std")]
+    pub fn restore(&self) {
+        set_edge_generation_max_distance(self.edge_generation_max_distance);
+        set_edge_retention_max_distance(self.edge_retention_max_distance);
+        set_min_number_of_pairs_to_build_cluster(self.min_number_of_pairs_to_build_cluster);
+        set_max_number_of_clusters(self.max_number_of_clusters);
+        set_score_threshold(self.score_threshold);
+        set_angle_diff(self.angle_diff);
+        set_max_number_of_groups(self.max_number_of_groups);
+        set_factor(self.factor);
+    }
+}
@@ -0,0 +1,155 @@
+//! Time limit of a single match, see `MatchConfig::deadline`.
+
+#[cfg(all(test, feature = "std"))]
+use std::sync::atomic::AtomicU32;
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::MatchConfig;
+
+/// Steps of a search between two readings of the clock.
+#[cfg(feature = "std")]
+const STEPS_PER_CHECK: u32 = 256;
+
+/// Point in time a match gives up at, or a flag that gives up the match once it is set; it never
+/// passes without `std`.
+#[derive(Debug, Clone)]
+pub(crate) struct Deadline {
+    #[cfg(feature = "std")]
+    at: Option<Instant>,
+    #[cfg(feature = "std")]
+    cancelled: Option<Arc<AtomicBool>>,
+    /// Clock of tests, see `after_readings`.
+    #[cfg(all(test, feature = "std"))]
+    readings: Option<Arc<TestClock>>,
+}
+
+/// Clock that counts its readings instead of measuring time, so that tests of deadlines do not
+/// depend on the speed of the machine.
+#[cfg(all(test, feature = "std"))]
+#[derive(Debug)]
+pub(crate) struct TestClock {
+    /// Readings so far.
+    pub(crate) readings: AtomicU32,
+    /// The deadline passes at this reading.
+    pub(crate) limit: u32,
+}
+
+impl Deadline {
+    /// Deadline that never passes.
+    #[cfg(test)]
+    pub(crate) const NEVER: Deadline = Deadline {
+        #[cfg(feature = "std")]
+        at: None,
+        #[cfg(feature = "std")]
+        cancelled: None,
+        #[cfg(all(test, feature = "std"))]
+        readings: None,
+    };
+
+    /// Deadline that passes at the `limit`-th reading of the clock; the returned clock tells how
+    /// often it was read.
+    #[cfg(all(test, feature = "std"))]
+    pub(crate) fn after_readings(limit: u32) -> (Self, Arc<TestClock>) {
+        let clock = Arc::new(TestClock {
+            readings: AtomicU32::new(0),
+            limit,
+        });
+        let deadline = Deadline {
+            readings: Some(Arc::clone(&clock)),
+            ..Deadline::NEVER
+        };
+        (deadline, clock)
+    }
+
+    /// Deadline of a match that starts now.
+    pub(crate) fn start(config: &MatchConfig) -> Self {
+        #[cfg(feature = "std")]
+        {
+            Deadline {
+                // a budget too long to be represented never runs out
+                at: config
+                    .deadline
+                    .and_then(|budget| Instant::now().checked_add(budget)),
+                cancelled: None,
+                #[cfg(test)]
+                readings: None,
+            }
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            let _ = config;
+            Deadline {}
+        }
+    }
+
+    /// Same deadline that also passes as soon as `cancelled` is set.
+    #[cfg(feature = "async")]
+    pub(crate) fn cancelled_by(self, cancelled: Arc<AtomicBool>) -> Self {
+        Deadline {
+            cancelled: Some(cancelled),
+            ..self
+        }
+    }
+
+    #[inline]
+    pub(crate) fn has_passed(&self) -> bool {
+        #[cfg(feature = "std")]
+        {
+            self.is_cancelled() || self.is_past()
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            false
+        }
+    }
+
+    /// Same as `has_passed`, but reads the clock only once in `STEPS_PER_CHECK` steps, which are
+    /// counted in `steps`.
+    #[inline]
+    pub(crate) fn has_passed_after_step(&self, steps: &mut u32) -> bool {
+        #[cfg(feature = "std")]
+        {
+            if self.is_unlimited() {
+                return false;
+            }
+            *steps = steps.wrapping_add(1);
+            steps.is_multiple_of(STEPS_PER_CHECK) && self.has_passed()
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            let _ = steps;
+            false
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn is_unlimited(&self) -> bool {
+        #[cfg(test)]
+        if self.readings.is_some() {
+            return false;
+        }
+        self.at.is_none() && self.cancelled.is_none()
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn is_past(&self) -> bool {
+        #[cfg(test)]
+        if let Some(clock) = &self.readings {
+            return clock.readings.fetch_add(1, Ordering::Relaxed) + 1 >= clock.limit;
+        }
+        matches!(self.at, Some(at) if Instant::now() >= at)
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn is_cancelled(&self) -> bool {
+        matches!(&self.cancelled, Some(cancelled) if cancelled.load(Ordering::Relaxed))
+    }
+}
@@ -0,0 +1,357 @@
+//! Text dump of the minutiae and edges of a fingerprint, for comparing edge generation with
+//! other implementations line by line and for replaying matches from their edges.
+//!
+//! ```text
+//! bozorth3 edge dump 1
+//! format nist
+//! minutiae 2
+//! 10 20 45 ending 60
+//! 30 20 90 bifurcation 0
+//! edges 1
+//! 0 1 400 45 90 0 KJ
+//! ```
+//!
+//! After the version line comes the format the edges were found with (`nist` or `ansi`), the
+//! minutiae as `x y theta kind quality` and the edges as `k j distance_squared min_beta max_beta
+//! theta_kj beta_order`, where `k` and `j` index the minutiae and `beta_order` is `KJ` or `JK`.
+//! Edges are sorted by `distance_squared`. Empty lines and lines starting with `#` are ignored.
+
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::sorted_edges::is_sorted;
+use crate::types::{BetaOrder, Degrees, Endpoint, MinutiaKind};
+use crate::{Edge, Fingerprint, Format, Minutia};
+
+const VERSION_LINE: &str = "bozorth3 edge dump 1";
+
+/// Error of loading an edge dump.
+#[derive(Debug)]
+pub enum EdgeDumpError {
+    /// The dump could not be read.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// A line could not be parsed; lines are numbered from 1.
+    InvalidLine { line: usize, content: String },
+    /// The dump ended before all the minutiae and edges it declares.
+    Truncated,
+    /// An edge on the line refers to a minutia that is not in the dump, or twice to the same one.
+    InvalidEndpoint { line: usize },
+    /// The edge on the line is shorter than the one before it.
+    UnsortedEdges { line: usize },
+}
+
+impl fmt::Display for EdgeDumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            EdgeDumpError::Io(e) => write!(f, "cannot read edge dump: {}", e),
+            EdgeDumpError::InvalidLine { line, content } => {
+                write!(f, "invalid line {}: {:?}", line, content)
+            }
+            EdgeDumpError::Truncated => write!(f, "edge dump is truncated"),
+            EdgeDumpError::InvalidEndpoint { line } => {
+                write!(f, "edge on line {} has an invalid endpoint", line)
+            }
+            EdgeDumpError::UnsortedEdges { line } => {
+                write!(f, "edge on line {} is shorter than the previous one", line)
+            }
+        }
+    }
+}
+
+impl core::error::Error for EdgeDumpError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            EdgeDumpError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for EdgeDumpError {
+    fn from(e: io::Error) -> Self {
+        EdgeDumpError::Io(e)
+    }
+}
+
+fn format_name(format: Format) -> &'static str {
+    match format {
+        Format::NistInternal => "nist",
+        Format::Ansi => "ansi",
+    }
+}
+
+fn kind_name(kind: MinutiaKind) -> &'static str {
+    match kind {
+        MinutiaKind::Ending => "ending",
+        MinutiaKind::Bifurcation => "bifurcation",
+        MinutiaKind::Other => "other",
+        MinutiaKind::Unknown => "unknown",
+    }
+}
+
+impl Fingerprint {
+    /// Minutiae and edges in the text format described in the `edge_dump` module.
+    pub fn edge_dump(&self) -> String {
+        let mut dump = String::new();
+        self.write_edge_dump(&mut dump)
+            .expect("writing into a string does not fail");
+        dump
+    }
+
+    fn write_edge_dump(&self, out: &mut impl Write) -> fmt::Result {
+        writeln!(out, "{}", VERSION_LINE)?;
+        writeln!(out, "format {}", format_name(self.format))?;
+        writeln!(out, "minutiae {}", self.minutiae.len())?;
+        for it in self.minutiae.iter() {
+            writeln!(
+                out,
+                "{} {} {} {} {}",
+                it.x,
+                it.y,
+                it.theta.0,
+                kind_name(it.kind),
+                it.quality
+            )?;
+        }
+        writeln!(out, "edges {}", self.edges.len())?;
+        for it in self.edges.iter() {
+            writeln!(
+                out,
+                "{} {} {} {} {} {} {}",
+                it.endpoint_k.as_usize(),
+                it.endpoint_j.as_usize(),
+                it.distance_squared,
+                it.min_beta,
+                it.max_beta,
+                it.theta_kj,
+                match it.beta_order {
+                    BetaOrder::KJ => "KJ",
+                    BetaOrder::JK => "JK",
+                }
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Loads a fingerprint from an edge dump, see `edge_dump`; the edges are used as they are,
+    /// so they are neither limited nor thinned again.
+    #[cfg(feature = "std")]
+    pub fn from_edge_dump(mut reader: impl io::Read) -> Result<Fingerprint, EdgeDumpError> {
+        let mut content = String::new();
+        reader.read_to_string(&mut content)?;
+        Fingerprint::from_edge_dump_str(&content)
+    }
+
+    /// Like `from_edge_dump`, for a dump already in memory.
+    pub fn from_edge_dump_str(content: &str) -> Result<Fingerprint, EdgeDumpError> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        let mut next = || lines.next().ok_or(EdgeDumpError::Truncated);
+
+        let (line, content) = next()?;
+        if content != VERSION_LINE {
+            return Err(invalid(line, content));
+        }
+
+        let (line, content) = next()?;
+        let format = match content {
+            "format nist" => Format::NistInternal,
+            "format ansi" => Format::Ansi,
+            _ => return Err(invalid(line, content)),
+        };
+
+        let (line, content) = next()?;
+        let count = header(line, content, "minutiae")?;
+        let mut minutiae = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (line, content) = next()?;
+            minutiae.push(parse_minutia(content).ok_or_else(|| invalid(line, content))?);
+        }
+
+        let (line, content) = next()?;
+        let count = header(line, content, "edges")?;
+        let mut edges: Vec<Edge> = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (line, content) = next()?;
+            let edge = parse_edge(content).ok_or_else(|| invalid(line, content))?;
+            let (k, j) = (edge.endpoint_k.as_usize(), edge.endpoint_j.as_usize());
+            if k >= minutiae.len() || j >= minutiae.len() || k == j {
+                return Err(EdgeDumpError::InvalidEndpoint { line });
+            }
+            edges.push(edge);
+            if !is_sorted(&edges[edges.len().saturating_sub(2)..]) {
+                return Err(EdgeDumpError::UnsortedEdges { line });
+            }
+        }
+        if let Some((line, content)) = lines.next() {
+            return Err(invalid(line, content));
+        }
+
+        Ok(Fingerprint {
+            minutiae_used: minutiae.len(),
+            edges_generated: edges.len(),
+            edges_kept: edges.len(),
+            edges_truncated: false,
+            minutiae: minutiae.into_boxed_slice(),
+            edges: edges.into_boxed_slice(),
+            position: None,
+            format,
+            thinned_edges: 0,
+        })
+    }
+}
+
+fn invalid(line: usize, content: &str) -> EdgeDumpError {
+    EdgeDumpError::InvalidLine {
+        line,
+        content: content.to_owned(),
+    }
+}
+
+/// Parses a line such as `edges 10`.
+fn header(line: usize, content: &str, name: &str) -> Result<usize, EdgeDumpError> {
+    content
+        .strip_prefix(name)
+        .and_then(|it| it.strip_prefix(' '))
+        .and_then(|it| it.parse().ok())
+        .ok_or_else(|| invalid(line, content))
+}
+
+fn parse_minutia(content: &str) -> Option<Minutia> {
+    let mut fields = content.split_whitespace();
+    let mut number = || fields.next()?.parse::<i32>().ok();
+    let (x, y, theta) = (number()?, number()?, number()?);
+    let kind = match fields.next()? {
+        "ending" => MinutiaKind::Ending,
+        "bifurcation" => MinutiaKind::Bifurcation,
+        "other" => MinutiaKind::Other,
+        "unknown" => MinutiaKind::Unknown,
+        _ => return None,
+    };
+    let quality = fields.next()?.parse().ok()?;
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(Minutia {
+        x,
+        y,
+        theta: Degrees(theta),
+        kind,
+        quality,
+    })
+}
+
+fn parse_edge(content: &str) -> Option<Edge> {
+    let mut fields = content.split_whitespace();
+    let mut index = || fields.next()?.parse::<u32>().ok();
+    let (k, j) = (index()?, index()?);
+    let mut number = || fields.next()?.parse::<i32>().ok();
+    let (distance_squared, min_beta, max_beta, theta_kj) =
+        (number()?, number()?, number()?, number()?);
+    let beta_order = match fields.next()? {
+        "KJ" => BetaOrder::KJ,
+        "JK" => BetaOrder::JK,
+        _ => return None,
+    };
+    if fields.next().is_some() || min_beta > max_beta {
+        return None;
+    }
+    Some(Edge {
+        distance_squared,
+        min_beta,
+        max_beta,
+        endpoint_k: Endpoint(k),
+        endpoint_j: Endpoint(j),
+        theta_kj,
+        beta_order,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::synthetic::Generator;
+    use crate::{
+        match_fingerprints, BozorthState, EdgeDumpError, Fingerprint, Format, PairHolder,
+        PruneOptions,
+    };
+
+    #[test]
+    fn dumps_round_trip_to_the_same_score() {
+        let mut generator = Generator::new(47);
+        let template = generator.raw_template(60);
+        let impression = generator.impression(&template, 5, (-4, 6));
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+
+        for format in [Format::NistInternal, Format::Ansi] {
+            let [probe, gallery] = [&template, &impression]
+                .map(|it| Fingerprint::from_raw(it, PruneOptions::default(), format));
+            let score = match_fingerprints(&probe, &gallery, format, &mut pairs, &mut state);
+            assert!(score > 0);
+
+            let dump = probe.edge_dump();
+            let loaded = Fingerprint::from_edge_dump_str(&dump).unwrap();
+            assert_eq!(loaded.format, format);
+            assert_eq!(loaded.edge_dump(), dump);
+            assert_eq!(
+                match_fingerprints(&loaded, &gallery, format, &mut pairs, &mut state),
+                score
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_dumps_are_rejected() {
+        let template = Generator::new(53).template(10);
+        let dump = Fingerprint::new(template, Format::NistInternal).edge_dump();
+        let lines: Vec<_> = dump.lines().collect();
+        let edges = lines.iter().position(|it| it.starts_with("edges")).unwrap();
+        let with_line = |index: usize, line: &str| {
+            let mut lines = lines.clone();
+            lines[index] = line;
+            lines.join("\n")
+        };
+        let load = |dump: &str| Fingerprint::from_edge_dump_str(dump).unwrap_err();
+
+        assert!(matches!(
+            load(&with_line(0, "bozorth3 edge dump 2")),
+            EdgeDumpError::InvalidLine { line: 1, .. }
+        ));
+        assert!(matches!(
+            load(&lines[..lines.len() - 1].join("\n")),
+            EdgeDumpError::Truncated
+        ));
+        assert!(matches!(
+            load(&with_line(edges + 1, "0 10 1 0 0 0 KJ")),
+            EdgeDumpError::InvalidEndpoint { .. }
+        ));
+        assert!(matches!(
+            load(&with_line(edges + 2, "0 1 0 0 0 0 KJ")),
+            EdgeDumpError::UnsortedEdges { .. }
+        ));
+        assert!(matches!(
+            load(&with_line(edges + 1, "0 1 1 90 0 0 KJ")),
+            EdgeDumpError::InvalidLine { .. }
+        ));
+
+        // comments and empty lines are ignored
+        let annotated = dump.replace("\nedges", "\n# edges of the C build\n\nedges");
+        assert_eq!(
+            Fingerprint::from_edge_dump_str(&annotated)
+                .unwrap()
+                .edge_dump(),
+            dump
+        );
+    }
+}
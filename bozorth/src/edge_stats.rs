@@ -0,0 +1,149 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::find_edges::{find_edges_counting, SkippedPairs};
+use crate::math::float;
+use crate::utils::limit_edges;
+use crate::{Edge, Format, Minutia};
+
+/// Width (in pixels) of a single bucket of the distance histogram.
+pub const DISTANCE_BUCKET_WIDTH: i32 = 10;
+
+/// Diagnostic statistics of edges found on a single fingerprint.
+#[derive(Debug, Clone)]
+pub struct EdgeStats {
+    /// Number of edges produced by `find_edges`.
+    pub generated: usize,
+    /// Number of edges left after `limit_edges`.
+    pub kept: usize,
+    /// Number of pairs of minutiae skipped because of opposite orientations.
+    pub opposite_angle_skips: usize,
+    /// Generation stopped at `MAX_NUMBER_OF_EDGES - 1` edges, skipping the remaining pairs.
+    pub truncated: bool,
+    /// Number of generated edges by length, in buckets of `DISTANCE_BUCKET_WIDTH` pixels.
+    pub distance_histogram: Vec<usize>,
+    /// The smallest `beta` angle among all generated edges.
+    pub min_beta: i32,
+    /// The greatest `beta` angle among all generated edges.
+    pub max_beta: i32,
+    /// Average of all `beta` angles of generated edges.
+    pub mean_beta: f32,
+}
+
+impl EdgeStats {
+    fn from_edges(edges: &[Edge], skipped: SkippedPairs) -> Self {
+        let mut distance_histogram = vec![];
+        let mut min_beta = i32::MAX;
+        let mut max_beta = i32::MIN;
+        let mut sum_of_betas = 0i64;
+
+        for edge in edges {
+            let bucket =
+                (float::sqrt(edge.distance_squared as f32) as i32 / DISTANCE_BUCKET_WIDTH) as usize;
+            if bucket >= distance_histogram.len() {
+                distance_histogram.resize(bucket + 1, 0);
+            }
+            distance_histogram[bucket] += 1;
+
+            min_beta = min_beta.min(edge.min_beta);
+            max_beta = max_beta.max(edge.max_beta);
+            sum_of_betas += (edge.min_beta + edge.max_beta) as i64;
+        }
+
+        EdgeStats {
+            generated: edges.len(),
+            kept: edges.len(),
+            opposite_angle_skips: skipped.opposite,
+            truncated: skipped.truncated,
+            distance_histogram,
+            min_beta: if edges.is_empty() { 0 } else { min_beta },
+            max_beta: if edges.is_empty() { 0 } else { max_beta },
+            mean_beta: if edges.is_empty() {
+                0.0
+            } else {
+                sum_of_betas as f32 / (2 * edges.len()) as f32
+            },
+        }
+    }
+}
+
+/// Finds edges like `find_edges` followed by `limit_edges` and collects statistics on the way.
+pub fn find_edges_with_stats(
+    minutiae: &[Minutia],
+    edges: &mut Vec<Edge>,
+    format: Format,
+) -> EdgeStats {
+    let skipped = find_edges_counting(
+        minutiae,
+        edges,
+        format.coordinate_origin(),
+        format.angle_origin(),
+        true,
+    );
+    let mut stats = EdgeStats::from_edges(edges, skipped);
+    edges.truncate(limit_edges(edges));
+    stats.kept = edges.len();
+    stats
+}
+
+impl fmt::Display for EdgeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<24}{:>10}", "edges generated", self.generated)?;
+        writeln!(f, "{:<24}{:>10}", "edges kept", self.kept)?;
+        writeln!(
+            f,
+            "{:<24}{:>10}",
+            "edges cut by limit",
+            self.generated - self.kept
+        )?;
+        writeln!(
+            f,
+            "{:<24}{:>10}",
+            "opposite-angle skips", self.opposite_angle_skips
+        )?;
+        if self.truncated {
+            writeln!(f, "{:<24}{:>10}", "truncated at", self.generated)?;
+        }
+        writeln!(f, "{:<24}{:>10}", "min beta", self.min_beta)?;
+        writeln!(f, "{:<24}{:>10.2}", "mean beta", self.mean_beta)?;
+        writeln!(f, "{:<24}{:>10}", "max beta", self.max_beta)?;
+        writeln!(f, "distance histogram:")?;
+        for (bucket, count) in self.distance_histogram.iter().enumerate() {
+            let start = bucket as i32 * DISTANCE_BUCKET_WIDTH;
+            writeln!(
+                f,
+                "  {:>4}-{:<4}{:>26}",
+                start,
+                start + DISTANCE_BUCKET_WIDTH - 1,
+                count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::edge_stats::find_edges_with_stats;
+    use crate::synthetic::Generator;
+    use crate::types::Format;
+
+    #[test]
+    fn histogram_covers_all_generated_edges() {
+        let mut generator = Generator::new(3);
+        for count in [10, 60, 150] {
+            let minutiae = generator.template(count);
+            let mut edges = vec![];
+            let stats = find_edges_with_stats(&minutiae, &mut edges, Format::NistInternal);
+
+            assert_eq!(
+                stats.distance_histogram.iter().sum::<usize>(),
+                stats.generated
+            );
+            assert_eq!(stats.kept, edges.len());
+            assert!(stats.min_beta as f32 <= stats.mean_beta);
+            assert!(stats.mean_beta <= stats.max_beta as f32);
+        }
+    }
+}
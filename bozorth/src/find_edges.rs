@@ -1,57 +1,216 @@
-use crate::consts::{max_minutia_distance, MAX_NUMBER_OF_EDGES};
-use crate::math::{are_angles_opposite, atan2_round_degree, normalize_angle};
-use crate::{BetaOrder, Edge, Format, Minutia};
+use crate::consts::{
+    edge_generation_max_distance, edge_generation_max_distance_squared, MAX_NUMBER_OF_EDGES,
+};
+use crate::math::atan2_round_degree;
+use crate::{
+    AngleOrigin, CoordinateOrigin, Degrees, Edge, Format, MatchConfig, Minutia, SortedEdges,
+};
+use alloc::vec::Vec;
 
-pub fn find_edges(minutiae: &[Minutia], edges: &mut Vec<Edge>, format: Format) {
-    assert!(!minutiae.is_empty());
+/// Appends edges of the minutiae to `edges` and sorts all of them for matching.
+///
+/// Dense templates with more than `MAX_NUMBER_OF_EDGES - 1` edges keep only the edges of the
+/// first pairs of minutiae, like the C implementation; `SortedEdges::is_truncated` tells so.
+pub fn find_edges<'e>(
+    minutiae: &[Minutia],
+    edges: &'e mut Vec<Edge>,
+    format: Format,
+) -> SortedEdges<'e> {
+    find_edges_with(minutiae, edges, format, &MatchConfig::default())
+}
+
+/// Same as `find_edges`, with pairs of opposite orientations kept unless
+/// `config.skip_opposite_angles` is set and with the origins of the `config`.
+pub fn find_edges_with<'e>(
+    minutiae: &[Minutia],
+    edges: &'e mut Vec<Edge>,
+    format: Format,
+    config: &MatchConfig,
+) -> SortedEdges<'e> {
+    let counts = find_edges_counting(
+        minutiae,
+        edges,
+        config.coordinate_origin_of(format),
+        config.angle_origin,
+        config.skip_opposite_angles,
+    );
+    SortedEdges::from_sorted_unchecked(edges).truncated(counts.truncated)
+}
 
-    'main: for k in 0..minutiae.len() - 1 {
+/// Pairs of minutiae `find_edges_counting` found no edges for.
+pub(crate) struct SkippedPairs {
+    /// Pairs skipped because of opposite orientations; none when `skip_opposite` is false.
+    pub(crate) opposite: usize,
+    /// The edges reached `MAX_NUMBER_OF_EDGES - 1` and the remaining pairs were not visited.
+    pub(crate) truncated: bool,
+}
+
+/// Same as `find_edges`, but also returns which pairs of minutiae were skipped.
+pub(crate) fn find_edges_counting(
+    minutiae: &[Minutia],
+    edges: &mut Vec<Edge>,
+    coordinate_origin: CoordinateOrigin,
+    angle_origin: AngleOrigin,
+    skip_opposite: bool,
+) -> SkippedPairs {
+    let mut opposite = 0;
+    let mut truncated = false;
+
+    'main: for k in 0..minutiae.len().saturating_sub(1) {
         for j in k + 1..minutiae.len() {
-            if are_angles_opposite(minutiae[k].theta, minutiae[j].theta) {
+            if skip_opposite && minutiae[k].theta.is_opposite_to(minutiae[j].theta) {
+                opposite += 1;
                 continue;
             }
 
             let dx = minutiae[j].x - minutiae[k].x;
             let dy = minutiae[j].y - minutiae[k].y;
             let distance_squared = dx.pow(2) + dy.pow(2);
-            if distance_squared > max_minutia_distance().pow(2) {
-                if dx > max_minutia_distance() {
+            if distance_squared > edge_generation_max_distance_squared() {
+                if dx > edge_generation_max_distance() {
                     break;
                 } else {
                     continue;
                 }
             }
 
-            let theta_kj = atan2_round_degree(
-                dx,
-                match format {
-                    Format::NistInternal => dy,
-                    Format::Ansi => -dy,
-                },
-            );
-
-            let beta_k = normalize_angle(theta_kj - minutiae[k].theta);
-            let beta_j = normalize_angle(theta_kj - minutiae[j].theta + 180);
-            let (min_beta, max_beta, beta_order) = if beta_k < beta_j {
-                (beta_k, beta_j, BetaOrder::KJ)
-            } else {
-                (beta_j, beta_k, BetaOrder::JK)
-            };
-
-            edges.push(Edge {
+            let theta_kj = atan2_round_degree(dx, coordinate_origin.upwards(dy));
+
+            let theta_k = angle_origin.counterclockwise(minutiae[k].theta);
+            let theta_j = angle_origin.counterclockwise(minutiae[j].theta);
+            let beta_k = (Degrees(theta_kj) - theta_k).normalized().0;
+            let beta_j = (Degrees(theta_kj) - theta_j + Degrees(180)).normalized().0;
+            edges.push(Edge::with_betas(
+                k.into(),
+                j.into(),
                 distance_squared,
-                min_beta,
-                max_beta,
-                endpoint_k: k.into(),
-                endpoint_j: j.into(),
                 theta_kj,
-                beta_order,
-            });
+                beta_k,
+                beta_j,
+            ));
             if edges.len() == MAX_NUMBER_OF_EDGES - 1 {
+                #[cfg(feature = "log")]
+                log::warn!(
+                    edges = edges.len(), minutiae = minutiae.len();
+                    "too many edges, remaining pairs of minutiae are skipped"
+                );
+                truncated = true;
                 break 'main;
             }
         }
     }
 
-    edges.sort_by_key(|edge| (edge.distance_squared, edge.min_beta, edge.max_beta));
+    // edges equal in length and both betas are ordered by their endpoints, which decides the
+    // order of pairs and which edge is the last one, whatever the sort
+    edges.sort_by_key(|edge| {
+        (
+            edge.distance_squared,
+            edge.min_beta,
+            edge.max_beta,
+            edge.endpoint_k,
+            edge.endpoint_j,
+        )
+    });
+    SkippedPairs {
+        opposite,
+        truncated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::MAX_NUMBER_OF_EDGES;
+    use crate::edge_stats::find_edges_with_stats;
+    use crate::find_edges::{find_edges, find_edges_with};
+    use crate::types::MinutiaKind;
+    use crate::{Degrees, Edge, Fingerprint, Format, MatchConfig, Minutia};
+
+    fn minutia(x: i32, y: i32, theta: i32) -> Minutia {
+        Minutia {
+            x,
+            y,
+            theta: Degrees(theta),
+            kind: MinutiaKind::Ending,
+            quality: 50,
+        }
+    }
+
+    #[test]
+    fn opposite_angles_are_skipped_unless_disabled() {
+        let minutiae = [
+            minutia(10, 10, 30),
+            minutia(40, 20, -150),
+            minutia(60, 50, 90),
+        ];
+
+        let mut edges = vec![];
+        find_edges(&minutiae, &mut edges, Format::NistInternal);
+        let endpoints: Vec<_> = edges
+            .iter()
+            .map(|it| (it.endpoint_k.0, it.endpoint_j.0))
+            .collect();
+        assert_eq!(endpoints.len(), 2);
+        assert!(!endpoints.contains(&(0, 1)));
+
+        let config = MatchConfig {
+            skip_opposite_angles: false,
+            ..MatchConfig::default()
+        };
+        let mut all = vec![];
+        find_edges_with(&minutiae, &mut all, Format::NistInternal, &config);
+        assert_eq!(all.len(), 3);
+        assert!(all
+            .iter()
+            .any(|it| (it.endpoint_k.0, it.endpoint_j.0) == (0, 1)));
+    }
+
+    #[test]
+    fn edges_equal_in_length_and_betas_are_ordered_by_their_endpoints() {
+        // every row and column of a lattice of parallel minutiae has edges that collide
+        let minutiae: Vec<_> = (0..25)
+            .map(|index| minutia(50 + index / 5 * 20, 50 + index % 5 * 20, 45))
+            .collect();
+        let mut edges = vec![];
+        find_edges(&minutiae, &mut edges, Format::NistInternal);
+
+        let length_and_betas = |it: &Edge| (it.distance_squared, it.min_beta, it.max_beta);
+        let key = |it: &Edge| (length_and_betas(it), it.endpoint_k, it.endpoint_j);
+        let collisions = edges
+            .windows(2)
+            .filter(|it| length_and_betas(&it[0]) == length_and_betas(&it[1]))
+            .count();
+        assert!(collisions > 10);
+        assert!(edges.windows(2).all(|it| key(&it[0]) < key(&it[1])));
+
+        // the same edges in any order and sorted by any algorithm end up the same
+        let mut again = vec![];
+        find_edges(&minutiae, &mut again, Format::NistInternal);
+        again.reverse();
+        again.sort_unstable_by_key(key);
+        assert_eq!(format!("{:?}", again), format!("{:?}", edges));
+    }
+
+    #[test]
+    fn dense_templates_report_truncated_edges() {
+        let sparse: Vec<_> = (0..25)
+            .map(|index| minutia(50 + index / 5 * 20, 50 + index % 5 * 20, 45))
+            .collect();
+        let mut edges = vec![];
+        assert!(!find_edges(&sparse, &mut edges, Format::NistInternal).is_truncated());
+
+        // all 32640 pairs of a 256-minutiae lattice are close enough to be edges
+        let dense: Vec<_> = (0..256)
+            .map(|index| minutia(50 + index / 16 * 3, 50 + index % 16 * 3, 45))
+            .collect();
+        let mut edges = vec![];
+        let sorted = find_edges(&dense, &mut edges, Format::NistInternal);
+        assert!(sorted.is_truncated());
+        assert!(sorted.limited().is_truncated());
+        assert_eq!(edges.len(), MAX_NUMBER_OF_EDGES - 1);
+
+        let stats = find_edges_with_stats(&dense, &mut vec![], Format::NistInternal);
+        assert!(stats.truncated);
+        let fingerprint = Fingerprint::new(dense, Format::NistInternal);
+        assert!(fingerprint.edges_truncated);
+    }
 }
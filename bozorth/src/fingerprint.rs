@@ -0,0 +1,606 @@
+use crate::bozorth::match_score_until;
+#[cfg(feature = "std")]
+use crate::bozorth::MINIMAL_NUMBER_OF_MINUTIA;
+use crate::config::MatchConfig;
+use crate::deadline::Deadline;
+use crate::fusion::FingerPosition;
+use crate::parsing::RawMinutiaCombined;
+#[cfg(feature = "std")]
+use crate::parsing::{parse, ParsingError};
+use crate::utils::{limit_edges_with, prune_with, thin_edges, PruneOptions, ThinOptions};
+use crate::{
+    find_edges_with, match_edges_into_pairs, BozorthState, Edge, Format, MatchError, Minutia,
+    PairHolder, SortedEdges,
+};
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Minutiae of a single finger together with their edges, ready for matching.
+#[derive(Debug)]
+pub struct Fingerprint {
+    pub minutiae: Box<[Minutia]>,
+    /// Edges sorted by length; code changing them has to keep them sorted.
+    pub edges: Box<[Edge]>,
+    /// Position of the finger, when known from the template.
+    pub position: Option<FingerPosition>,
+    /// Format the edges were found with.
+    pub format: Format,
+    /// Edges removed by `thin_edges` since the edges were found.
+    pub thinned_edges: usize,
+    /// Minutiae left after pruning, the ones the edges were found for.
+    pub minutiae_used: usize,
+    /// Edges found before `limit_edges`.
+    pub edges_generated: usize,
+    /// Edges kept by `limit_edges`; `thin_edges` removes more, see `thinned_edges`.
+    pub edges_kept: usize,
+    /// `find_edges` stopped at its limit of edges, see `SortedEdges::is_truncated`.
+    pub edges_truncated: bool,
+}
+
+impl Fingerprint {
+    /// Finds and limits edges of already pruned minutiae.
+    pub fn new(minutiae: Vec<Minutia>, format: Format) -> Self {
+        Fingerprint::new_with(minutiae, format, &MatchConfig::default())
+    }
+
+    /// Same as `new`, finding and limiting the edges as set in the `config`.
+    pub fn new_with(minutiae: Vec<Minutia>, format: Format, config: &MatchConfig) -> Self {
+        let mut edges = vec![];
+        let edges_truncated = find_edges_with(&minutiae, &mut edges, format, config).is_truncated();
+        let edges_generated = edges.len();
+        edges.truncate(limit_edges_with(&edges, config));
+        Fingerprint {
+            minutiae_used: minutiae.len(),
+            edges_generated,
+            edges_kept: edges.len(),
+            edges_truncated,
+            minutiae: minutiae.into_boxed_slice(),
+            edges: edges.into_boxed_slice(),
+            position: None,
+            format,
+            thinned_edges: 0,
+        }
+    }
+
+    /// Prunes minutiae loaded from a file and finds their edges; the edges are limited in the
+    /// mode of the `options`.
+    pub fn from_raw(
+        minutiae: &[RawMinutiaCombined],
+        options: PruneOptions,
+        format: Format,
+    ) -> Self {
+        Fingerprint::from_pruned(prune_with(minutiae, options), options, format)
+    }
+
+    /// Parses a .xyt file like `parse` and prunes its minutiae like `from_raw`.
+    ///
+    /// Files with too few minutiae left to be matched, empty ones included, fail with
+    /// `ParsingError::TooFewMinutiae` instead of failing every match later.
+    #[cfg(feature = "std")]
+    pub fn from_xyt(
+        path: impl AsRef<std::path::Path>,
+        options: PruneOptions,
+        format: Format,
+    ) -> Result<Self, ParsingError> {
+        let minutiae = prune_with(&parse(path)?, options);
+        if minutiae.len() < MINIMAL_NUMBER_OF_MINUTIA {
+            return Err(ParsingError::TooFewMinutiae {
+                found: minutiae.len(),
+                required: MINIMAL_NUMBER_OF_MINUTIA,
+            });
+        }
+        Ok(Fingerprint::from_pruned(minutiae, options, format))
+    }
+
+    /// Finds the edges of minutiae pruned with the `options`, in their mode.
+    fn from_pruned(minutiae: Vec<Minutia>, options: PruneOptions, format: Format) -> Self {
+        let config = MatchConfig {
+            strict: options.strict,
+            ..MatchConfig::default()
+        };
+        Fingerprint::new_with(minutiae, format, &config)
+    }
+
+    /// Edges ready for `match_edges_into_pairs`.
+    pub fn sorted_edges(&self) -> SortedEdges<'_> {
+        SortedEdges::from_sorted_unchecked(&self.edges)
+    }
+
+    /// Merges minutiae closer than `max_distance` pixels to each other, keeping the one of the
+    /// highest quality, and finds the edges again; returns how many minutiae were merged away.
+    ///
+    /// Some extractors emit several minutiae at the same position with different angles, which
+    /// adds edges and skews scores. A `max_distance` of 0 merges only minutiae at equal positions.
+    pub fn dedupe_coincident(&mut self, max_distance: i32) -> usize {
+        let mut by_quality: Vec<_> = core::mem::take(&mut self.minutiae).into_vec();
+        // stable, so minutiae of equal quality are kept in the order of position
+        by_quality.sort_by_key(|it| -it.quality);
+
+        let mut kept: Vec<Minutia> = Vec::with_capacity(by_quality.len());
+        let mut merged = 0;
+        for minutia in by_quality {
+            let is_coincident = kept.iter().any(|it| {
+                (it.x - minutia.x).pow(2) + (it.y - minutia.y).pow(2) <= max_distance.pow(2)
+            });
+            if is_coincident {
+                merged += 1;
+            } else {
+                kept.push(minutia);
+            }
+        }
+        kept.sort_by_key(|it| (it.x, it.y));
+
+        #[cfg(feature = "log")]
+        if merged > 0 {
+            log::warn!(merged, kept = kept.len(); "coincident minutiae merged");
+        }
+        let position = self.position;
+        *self = Fingerprint {
+            position,
+            ..Fingerprint::new(kept, self.format)
+        };
+        merged
+    }
+
+    /// Thins the edges, see `bozorth::thin_edges`; returns how many edges were removed.
+    ///
+    /// Finding the edges again, e.g. in `dedupe_coincident`, brings the removed edges back.
+    pub fn thin_edges(&mut self, options: &ThinOptions) -> usize {
+        let mut edges = core::mem::take(&mut self.edges).into_vec();
+        let removed = thin_edges(&mut edges, options);
+        self.edges = edges.into_boxed_slice();
+        self.thinned_edges += removed;
+        removed
+    }
+
+    pub fn with_position(self, position: FingerPosition) -> Self {
+        Fingerprint {
+            position: Some(position),
+            ..self
+        }
+    }
+
+    /// Hash of the minutiae and the format that is stable across runs, platforms and releases,
+    /// so it can key caches of templates or scores instead of file names.
+    ///
+    /// Minutiae are hashed in sorted order. Neither their quality nor the position of the finger
+    /// are part of the content.
+    pub fn content_id(&self) -> u64 {
+        let mut minutiae: Vec<_> = self
+            .minutiae
+            .iter()
+            .map(|it| (it.x, it.y, it.theta.0, it.kind as u8))
+            .collect();
+        minutiae.sort_unstable();
+
+        let format: u8 = match self.format {
+            Format::NistInternal => 0,
+            Format::Ansi => 1,
+        };
+        let mut hasher = Fnv1a::new();
+        hasher.write(&[format]);
+        for (x, y, theta, kind) in minutiae {
+            hasher.write(&x.to_le_bytes());
+            hasher.write(&y.to_le_bytes());
+            hasher.write(&theta.to_le_bytes());
+            hasher.write(&[kind]);
+        }
+        hasher.finish()
+    }
+}
+
+/// FNV-1a; unlike the std hasher it has no random seed and does not change between releases.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Matches two fingerprints with the default configuration and returns the score.
+///
+/// Every pair of edges is worth 1 point and a failed match scores 0.
+pub fn match_fingerprints(
+    probe: &Fingerprint,
+    gallery: &Fingerprint,
+    format: Format,
+    pairs: &mut PairHolder,
+    state: &mut BozorthState,
+) -> u32 {
+    match_fingerprints_with(
+        probe,
+        gallery,
+        format,
+        pairs,
+        state,
+        &MatchConfig::default(),
+    )
+}
+
+/// Same as `match_fingerprints`, honoring options of the `config`.
+pub fn match_fingerprints_with(
+    probe: &Fingerprint,
+    gallery: &Fingerprint,
+    format: Format,
+    pairs: &mut PairHolder,
+    state: &mut BozorthState,
+    config: &MatchConfig,
+) -> u32 {
+    match_fingerprints_until(
+        probe,
+        gallery,
+        format,
+        pairs,
+        state,
+        config,
+        &Deadline::start(config),
+    )
+    .unwrap_or(0)
+}
+
+/// Same as `match_fingerprints_with`, reporting errors and giving up at the `deadline`.
+pub(crate) fn match_fingerprints_until(
+    probe: &Fingerprint,
+    gallery: &Fingerprint,
+    format: Format,
+    pairs: &mut PairHolder,
+    state: &mut BozorthState,
+    config: &MatchConfig,
+    deadline: &Deadline,
+) -> Result<u32, MatchError> {
+    pairs.clear();
+    match_edges_into_pairs(
+        probe.sorted_edges(),
+        &probe.minutiae,
+        gallery.sorted_edges(),
+        &gallery.minutiae,
+        pairs,
+        config,
+        |_pk: &Minutia, _pj: &Minutia, _gk: &Minutia, _gj: &Minutia| 1,
+    );
+    if pairs.pairs().is_empty() {
+        return Ok(0);
+    }
+    pairs.prepare_with(config);
+
+    match_score_until(
+        pairs,
+        &probe.minutiae,
+        &gallery.minutiae,
+        format,
+        state,
+        config,
+        deadline,
+    )
+    .map(|(score, _)| score)
+}
+
+/// Counts the candidate pairs of edges of two fingerprints without clustering them.
+///
+/// This is the cheap first stage of `match_fingerprints`, useful to pre-filter a gallery; the
+/// pairs are left unprepared in `pairs`.
+pub fn count_candidate_pairs(
+    probe: &Fingerprint,
+    gallery: &Fingerprint,
+    pairs: &mut PairHolder,
+) -> usize {
+    pairs.clear();
+    match_edges_into_pairs(
+        probe.sorted_edges(),
+        &probe.minutiae,
+        gallery.sorted_edges(),
+        &gallery.minutiae,
+        pairs,
+        &MatchConfig::default(),
+        |_pk: &Minutia, _pj: &Minutia, _gk: &Minutia, _gj: &Minutia| 1,
+    );
+    pairs.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::MAX_NUMBER_OF_PAIRS;
+    use crate::parsing::{RawMinutiaCombined, MAX_COORDINATE};
+    use crate::synthetic::Generator;
+    use crate::{
+        count_candidate_pairs, find_edges, match_fingerprints, match_fingerprints_with,
+        BozorthState, Degrees, FingerPosition, Fingerprint, Format, MatchConfig, Minutia,
+        MinutiaKind, PairHolder, PruneOptions, ThinOptions,
+    };
+
+    #[test]
+    fn construction_counts_minutiae_and_edges() {
+        let mut generator = Generator::new(41);
+        let template = generator.raw_template(80);
+        let options = PruneOptions {
+            max_minutiae: 50,
+            ..PruneOptions::default()
+        };
+        let mut fingerprint = Fingerprint::from_raw(&template, options, Format::NistInternal);
+        assert_eq!(fingerprint.minutiae_used, 50);
+        assert_eq!(fingerprint.minutiae_used, fingerprint.minutiae.len());
+
+        let mut edges = vec![];
+        find_edges(&fingerprint.minutiae, &mut edges, Format::NistInternal);
+        assert_eq!(fingerprint.edges_generated, edges.len());
+        assert_eq!(fingerprint.edges_kept, fingerprint.edges.len());
+        assert!(fingerprint.edges_kept <= fingerprint.edges_generated);
+
+        let removed = fingerprint.thin_edges(&ThinOptions {
+            max_per_sector: 1,
+            ..ThinOptions::default()
+        });
+        assert!(removed > 0);
+        assert_eq!(fingerprint.edges.len() + removed, fingerprint.edges_kept);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn xyt_files_with_too_few_minutiae_fail_at_construction() {
+        use crate::parsing::ParsingError;
+
+        let directory = std::env::temp_dir().join(format!("from-xyt-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let from_xyt = |name: &str, minutiae: usize, options: PruneOptions| {
+            let content: String = (0..minutiae)
+                .map(|index| format!("{} {} {} {}\n", 40 + index * 17, 300 - index * 11, 0, index))
+                .collect();
+            std::fs::write(directory.join(name), content).unwrap();
+            Fingerprint::from_xyt(directory.join(name), options, Format::NistInternal)
+        };
+        let options = PruneOptions::default();
+
+        let empty = from_xyt("empty.xyt", 0, options);
+        let few = from_xyt("few.xyt", 9, options);
+        let enough = from_xyt("enough.xyt", 10, options);
+        // pruning by quality counts as well
+        let pruned = from_xyt(
+            "pruned.xyt",
+            12,
+            PruneOptions {
+                min_quality: 3,
+                ..options
+            },
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(matches!(
+            empty,
+            Err(ParsingError::TooFewMinutiae {
+                found: 0,
+                required: 10
+            })
+        ));
+        assert!(matches!(
+            few,
+            Err(ParsingError::TooFewMinutiae { found: 9, .. })
+        ));
+        assert_eq!(enough.unwrap().minutiae_used, 10);
+        assert!(matches!(
+            pruned,
+            Err(ParsingError::TooFewMinutiae { found: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn content_id_depends_only_on_the_content() {
+        let mut generator = Generator::new(23);
+        let template = generator.raw_template(40);
+        let options = PruneOptions::default();
+        let fingerprint = Fingerprint::from_raw(&template, options, Format::NistInternal);
+        let id = fingerprint.content_id();
+
+        let mut reversed = template.clone();
+        reversed.reverse();
+        let reordered = Fingerprint::from_raw(&reversed, options, Format::NistInternal);
+        assert_eq!(reordered.content_id(), id);
+
+        let ansi = Fingerprint::from_raw(&template, options, Format::Ansi);
+        assert_ne!(ansi.content_id(), id);
+
+        let other =
+            Fingerprint::from_raw(&generator.raw_template(40), options, Format::NistInternal);
+        assert_ne!(other.content_id(), id);
+
+        // the value must not change between runs or releases
+        let minutia = Minutia {
+            x: 10,
+            y: -20,
+            theta: Degrees(-45),
+            kind: MinutiaKind::Bifurcation,
+            quality: 0,
+        };
+        let fixed = Fingerprint::new(vec![minutia], Format::NistInternal);
+        assert_eq!(fixed.content_id(), 0x6057_ed70_1604_8a61);
+    }
+
+    #[test]
+    fn candidate_pairs_are_the_pairs_before_clustering() {
+        let format = Format::NistInternal;
+        let options = PruneOptions::default();
+        let mut generator = Generator::new(43);
+        let template = generator.raw_template(60);
+        let impression = generator.impression(&template, 5, (-4, 6));
+        let [probe, genuine, impostor] = [&template, &impression, &generator.raw_template(60)]
+            .map(|it| Fingerprint::from_raw(it, options, format));
+
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        for gallery in [&genuine, &impostor] {
+            match_fingerprints(&probe, gallery, format, &mut pairs, &mut state);
+            let expected = pairs.len();
+            assert_eq!(count_candidate_pairs(&probe, gallery, &mut pairs), expected);
+            // the holder is cleared before every use
+            assert_eq!(count_candidate_pairs(&probe, gallery, &mut pairs), expected);
+        }
+
+        let genuine_pairs = count_candidate_pairs(&probe, &genuine, &mut pairs);
+        let impostor_pairs = count_candidate_pairs(&probe, &impostor, &mut pairs);
+        assert!(genuine_pairs > impostor_pairs);
+        let empty = Fingerprint::new(vec![], format);
+        assert_eq!(count_candidate_pairs(&probe, &empty, &mut pairs), 0);
+    }
+
+    #[test]
+    fn degenerate_templates_match_without_panicking() {
+        let format = Format::NistInternal;
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        let template = Generator::new(31).template(30);
+
+        // no edges at all
+        let empty = Fingerprint::new(vec![], format);
+        let single = Fingerprint::new(Generator::new(31).template(1), format);
+        let full = Fingerprint::new(Generator::new(31).template(30), format);
+        for (probe, gallery) in [(&empty, &full), (&full, &single), (&single, &single)] {
+            assert_eq!(
+                match_fingerprints(probe, gallery, format, &mut pairs, &mut state),
+                0
+            );
+        }
+
+        // copies of the template in opposite corners form clusters as far apart as the parser
+        // allows, so their squared distances only just fit in i32
+        let offset = MAX_COORDINATE - 500;
+        let corners: Vec<_> = template
+            .iter()
+            .map(|it| Minutia {
+                x: it.x - offset,
+                y: it.y - offset,
+                theta: it.theta,
+                kind: it.kind,
+                quality: it.quality,
+            })
+            .chain(template.iter().map(|it| Minutia {
+                x: it.x + offset - 500,
+                y: it.y + offset - 500,
+                theta: it.theta,
+                kind: it.kind,
+                quality: it.quality,
+            }))
+            .collect();
+        let corners = Fingerprint::new(corners, format);
+        assert!(match_fingerprints(&corners, &corners, format, &mut pairs, &mut state) > 0);
+    }
+
+    #[test]
+    fn pairs_beyond_the_table_are_dropped() {
+        // parallel minutiae on a lattice give more pairs than the clusters can be assigned
+        let format = Format::NistInternal;
+        let lattice: Vec<_> = (0..49)
+            .map(|i| RawMinutiaCombined {
+                x: 40 + 14 * (i % 7),
+                y: 40 + 14 * (i / 7),
+                t: 0,
+                q: 50,
+                kind: MinutiaKind::Ending,
+            })
+            .collect();
+        let lattice = Fingerprint::from_raw(&lattice, PruneOptions::default(), format);
+        assert_eq!(
+            count_candidate_pairs(&lattice, &lattice, &mut PairHolder::new()),
+            MAX_NUMBER_OF_PAIRS
+        );
+
+        for strict in [true, false] {
+            let config = MatchConfig {
+                strict,
+                ..MatchConfig::default()
+            };
+            let mut pairs = PairHolder::new();
+            let mut state = BozorthState::new();
+            let score = match_fingerprints_with(
+                &lattice, &lattice, format, &mut pairs, &mut state, &config,
+            );
+            assert!(score > 0);
+        }
+    }
+
+    #[test]
+    fn coincident_minutiae_are_merged() {
+        let format = Format::NistInternal;
+        let template = Generator::new(37).template(30);
+        let mut fingerprint = Fingerprint::new(Generator::new(37).template(30), format);
+        assert_eq!(fingerprint.dedupe_coincident(0), 0);
+        assert_eq!(fingerprint.minutiae.len(), 30);
+
+        // a second minutia next to every fifth one, with another angle and a lower quality
+        let duplicates = template.iter().step_by(5).map(|it| Minutia {
+            x: it.x + 1,
+            y: it.y,
+            theta: (it.theta + Degrees(45)).normalized(),
+            kind: it.kind,
+            quality: it.quality - 1,
+        });
+        let mut with_duplicates: Vec<_> = Generator::new(37).template(30);
+        with_duplicates.extend(duplicates);
+        with_duplicates.sort_by_key(|it| (it.x, it.y));
+        let mut fingerprint =
+            Fingerprint::new(with_duplicates, format).with_position(FingerPosition::RIGHT_THUMB);
+        assert!(fingerprint.edges.len() > Fingerprint::new(template, format).edges.len());
+
+        assert_eq!(fingerprint.dedupe_coincident(0), 0);
+        assert_eq!(fingerprint.dedupe_coincident(2), 6);
+        let expected = Fingerprint::new(Generator::new(37).template(30), format);
+        assert_eq!(fingerprint.content_id(), expected.content_id());
+        assert_eq!(fingerprint.edges.len(), expected.edges.len());
+        assert_eq!(fingerprint.position, Some(FingerPosition::RIGHT_THUMB));
+    }
+
+    #[test]
+    fn thinning_drops_pairs_and_keeps_most_of_the_genuine_score() {
+        let format = Format::NistInternal;
+        let options = PruneOptions::default();
+        let mut generator = Generator::new(37);
+        // 100 minutiae within 200x200 pixels, much denser than a real finger
+        let dense = |mut template: Vec<RawMinutiaCombined>| {
+            for minutia in &mut template {
+                minutia.x = 150 + (minutia.x - 50) / 2;
+                minutia.y = 150 + (minutia.y - 50) / 2;
+            }
+            template
+        };
+        let template = dense(generator.raw_template(100));
+        let impression = generator.impression(&template, 5, (-4, 6));
+        let other = dense(generator.raw_template(100));
+
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        let mut scores = |thin: &ThinOptions| {
+            let [probe, gallery, impostor] = [&template, &impression, &other].map(|it| {
+                let mut fingerprint = Fingerprint::from_raw(it, options, format);
+                let removed = fingerprint.thin_edges(thin);
+                assert_eq!(fingerprint.thinned_edges, removed);
+                fingerprint
+            });
+            let genuine = match_fingerprints(&probe, &gallery, format, &mut pairs, &mut state);
+            let genuine_pairs = pairs.len();
+            let impostor = match_fingerprints(&probe, &impostor, format, &mut pairs, &mut state);
+            (genuine, genuine_pairs, impostor, probe.thinned_edges)
+        };
+
+        let (full, full_pairs, _, none_thinned) = scores(&ThinOptions::default());
+        assert_eq!(none_thinned, 0);
+        let thin = ThinOptions {
+            max_per_sector: 2,
+            ..ThinOptions::default()
+        };
+        let (genuine, genuine_pairs, impostor, thinned) = scores(&thin);
+        assert!(thinned > 0);
+        assert!(genuine_pairs * 10 <= full_pairs * 7);
+        assert!(genuine * 10 >= full * 6);
+        assert!(genuine > 10 * impostor.max(1));
+    }
+}
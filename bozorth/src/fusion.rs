@@ -0,0 +1,219 @@
+//! Fusion of scores of several fingers of a single subject.
+
+use crate::fingerprint::{match_fingerprints, Fingerprint};
+use crate::math::float;
+use crate::{BozorthState, Format, PairHolder};
+use alloc::vec;
+
+/// Finger position code as defined by ISO/IEC 19794-2 (and ANSI/NIST-ITL).
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct FingerPosition(pub u8);
+
+impl FingerPosition {
+    pub const UNKNOWN: FingerPosition = FingerPosition(0);
+    pub const RIGHT_THUMB: FingerPosition = FingerPosition(1);
+    pub const RIGHT_INDEX: FingerPosition = FingerPosition(2);
+    pub const RIGHT_MIDDLE: FingerPosition = FingerPosition(3);
+    pub const RIGHT_RING: FingerPosition = FingerPosition(4);
+    pub const RIGHT_LITTLE: FingerPosition = FingerPosition(5);
+    pub const LEFT_THUMB: FingerPosition = FingerPosition(6);
+    pub const LEFT_INDEX: FingerPosition = FingerPosition(7);
+    pub const LEFT_MIDDLE: FingerPosition = FingerPosition(8);
+    pub const LEFT_RING: FingerPosition = FingerPosition(9);
+    pub const LEFT_LITTLE: FingerPosition = FingerPosition(10);
+}
+
+/// Weights of fingers used by `FusionStrategy::WeightedSum`, indexed by the position code.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FingerWeights([f32; 11]);
+
+impl FingerWeights {
+    /// Sets the weight of a finger; positions outside of the ISO range are ignored.
+    pub fn with(mut self, position: FingerPosition, weight: f32) -> Self {
+        if let Some(it) = self.0.get_mut(position.0 as usize) {
+            *it = weight;
+        }
+        self
+    }
+
+    /// Weight of a finger; unknown positions have weight 1.
+    pub fn get(&self, position: FingerPosition) -> f32 {
+        self.0.get(position.0 as usize).copied().unwrap_or(1.0)
+    }
+}
+
+impl Default for FingerWeights {
+    fn default() -> Self {
+        FingerWeights([1.0; 11])
+    }
+}
+
+/// How scores of individual fingers are combined into the score of a subject.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FusionStrategy {
+    Sum,
+    Mean,
+    Max,
+    WeightedSum(FingerWeights),
+}
+
+/// How fingers of the probe are paired with fingers of the gallery.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FingerPairing {
+    /// Fingers with the same position are paired; fingers without a counterpart are skipped.
+    ByPosition,
+    /// The n-th probe finger is paired with the n-th gallery finger.
+    ByIndex,
+}
+
+/// Combines scores of individual fingers. Returns 0 for no scores.
+pub fn fuse_scores(scores: &[(u32, FingerPosition)], strategy: FusionStrategy) -> u32 {
+    if scores.is_empty() {
+        return 0;
+    }
+
+    match strategy {
+        FusionStrategy::Sum => scores.iter().map(|it| it.0).sum(),
+        FusionStrategy::Mean => {
+            let sum: u32 = scores.iter().map(|it| it.0).sum();
+            float::round(sum as f32 / scores.len() as f32) as u32
+        }
+        FusionStrategy::Max => scores.iter().map(|it| it.0).max().unwrap_or(0),
+        FusionStrategy::WeightedSum(weights) => float::round(
+            scores
+                .iter()
+                .map(|&(score, position)| score as f32 * weights.get(position))
+                .sum::<f32>(),
+        ) as u32,
+    }
+}
+
+/// Matches corresponding fingers of two subjects and fuses their scores.
+///
+/// With `FingerPairing::ByPosition`, fingers without a known position are not matched.
+pub fn match_subject(
+    probe: &[Fingerprint],
+    gallery: &[Fingerprint],
+    pairing: FingerPairing,
+    strategy: FusionStrategy,
+    format: Format,
+    pairs: &mut PairHolder,
+    state: &mut BozorthState,
+) -> u32 {
+    let mut scores = vec![];
+    let mut add = |probe: &Fingerprint, gallery: &Fingerprint, position| {
+        let score = match_fingerprints(probe, gallery, format, pairs, state);
+        scores.push((score, position));
+    };
+
+    match pairing {
+        FingerPairing::ByIndex => {
+            for (probe, gallery) in probe.iter().zip(gallery.iter()) {
+                let position = probe
+                    .position
+                    .or(gallery.position)
+                    .unwrap_or(FingerPosition::UNKNOWN);
+                add(probe, gallery, position);
+            }
+        }
+        FingerPairing::ByPosition => {
+            for probe in probe {
+                let position = match probe.position {
+                    Some(position) => position,
+                    None => continue,
+                };
+                if let Some(gallery) = gallery.iter().find(|it| it.position == Some(position)) {
+                    add(probe, gallery, position);
+                }
+            }
+        }
+    }
+
+    fuse_scores(&scores, strategy)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprint::{match_fingerprints, Fingerprint};
+    use crate::fusion::{
+        fuse_scores, match_subject, FingerPairing, FingerPosition, FingerWeights, FusionStrategy,
+    };
+    use crate::synthetic::Generator;
+    use crate::{BozorthState, Format, PairHolder, PruneOptions};
+
+    #[test]
+    fn strategies_combine_scores() {
+        let scores = [
+            (10, FingerPosition::RIGHT_INDEX),
+            (30, FingerPosition::LEFT_INDEX),
+        ];
+        assert_eq!(fuse_scores(&scores, FusionStrategy::Sum), 40);
+        assert_eq!(fuse_scores(&scores, FusionStrategy::Mean), 20);
+        assert_eq!(fuse_scores(&scores, FusionStrategy::Max), 30);
+
+        let weights = FingerWeights::default().with(FingerPosition::LEFT_INDEX, 0.5);
+        assert_eq!(
+            fuse_scores(&scores, FusionStrategy::WeightedSum(weights)),
+            25
+        );
+        assert_eq!(fuse_scores(&[], FusionStrategy::Mean), 0);
+    }
+
+    #[test]
+    fn fused_genuine_scores_dominate_impostor_fingers() {
+        const FINGERS: [FingerPosition; 2] =
+            [FingerPosition::RIGHT_INDEX, FingerPosition::LEFT_INDEX];
+        let mut generator = Generator::new(21);
+        let format = Format::NistInternal;
+
+        let mut enrolled = vec![];
+        let mut probes = vec![];
+        for _ in 0..4 {
+            let mut gallery = vec![];
+            let mut probe = vec![];
+            for &position in FINGERS.iter() {
+                let template = generator.raw_template(40);
+                let impression = generator.impression(&template, 8, (-10, 6));
+                let options = PruneOptions::default();
+                gallery.push(
+                    Fingerprint::from_raw(&template, options, format).with_position(position),
+                );
+                // the probe lists the fingers in the opposite order
+                probe.insert(
+                    0,
+                    Fingerprint::from_raw(&impression, options, format).with_position(position),
+                );
+            }
+            enrolled.push(gallery);
+            probes.push(probe);
+        }
+
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+
+        let mut best_impostor_finger = 0;
+        for (i, probe) in probes.iter().enumerate() {
+            for (_, gallery) in enrolled.iter().enumerate().filter(|it| it.0 != i) {
+                for p in probe {
+                    for g in gallery {
+                        let score = match_fingerprints(p, g, format, &mut pairs, &mut state);
+                        best_impostor_finger = best_impostor_finger.max(score);
+                    }
+                }
+            }
+        }
+
+        for (probe, gallery) in probes.iter().zip(enrolled.iter()) {
+            let fused = match_subject(
+                probe,
+                gallery,
+                FingerPairing::ByPosition,
+                FusionStrategy::Sum,
+                format,
+                &mut pairs,
+                &mut state,
+            );
+            assert!(fused > best_impostor_finger);
+        }
+    }
+}
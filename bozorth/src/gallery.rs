@@ -0,0 +1,247 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use rayon::prelude::*;
+
+use crate::fingerprint::{match_fingerprints, Fingerprint};
+use crate::parsing::RawMinutiaCombined;
+use crate::{BozorthState, Format, MatchPruning, PairHolder};
+
+/// Gallery fingerprints loaded once and matched against many probes.
+///
+/// Sequential queries reuse a single `PairHolder` and `BozorthState`, parallel queries keep one
+/// per rayon worker.
+///
+/// Entries keep the order in which they were added, so queries report equal scores in that order
+/// also after the gallery has been edited.
+///
+/// Fingerprints made by the index from raw minutiae are pruned with its `MatchPruning`, which
+/// may keep more minutiae of the gallery than of the probes.
+pub struct GalleryIndex<Id> {
+    entries: Vec<(Id, Fingerprint)>,
+    format: Format,
+    pruning: MatchPruning,
+    pairs: PairHolder,
+    state: BozorthState,
+}
+
+impl<Id> GalleryIndex<Id> {
+    pub fn new(entries: Vec<(Id, Fingerprint)>, format: Format) -> Self {
+        GalleryIndex {
+            entries,
+            format,
+            pruning: MatchPruning::default(),
+            pairs: PairHolder::new(),
+            state: BozorthState::new(),
+        }
+    }
+
+    /// Pruning of the fingerprints made by `add_raw` and `probe`; the existing entries are kept
+    /// as they are.
+    pub fn pruning(self, pruning: MatchPruning) -> Self {
+        GalleryIndex { pruning, ..self }
+    }
+
+    pub fn entries(&self) -> &[(Id, Fingerprint)] {
+        &self.entries
+    }
+
+    /// Takes the entries out of the index, e.g. to match them with each other in a
+    /// `BatchMatcher`.
+    pub fn into_entries(self) -> Vec<(Id, Fingerprint)> {
+        self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds the fingerprint under `id` and returns the fingerprint previously stored under it.
+    ///
+    /// A new id is appended after the existing entries, an existing one keeps its position.
+    /// Costs O(n) comparisons of ids to find an existing entry.
+    pub fn add(&mut self, id: Id, fingerprint: Fingerprint) -> Option<Fingerprint>
+    where
+        Id: PartialEq,
+    {
+        match self.entries.iter_mut().find(|(it, _)| *it == id) {
+            Some((_, existing)) => Some(core::mem::replace(existing, fingerprint)),
+            None => {
+                self.entries.push((id, fingerprint));
+                None
+            }
+        }
+    }
+
+    /// Same as `add` with the minutiae pruned as a gallery.
+    pub fn add_raw(&mut self, id: Id, minutiae: &[RawMinutiaCombined]) -> Option<Fingerprint>
+    where
+        Id: PartialEq,
+    {
+        let fingerprint = Fingerprint::from_raw(minutiae, self.pruning.gallery, self.format);
+        self.add(id, fingerprint)
+    }
+
+    /// Fingerprint of the minutiae pruned as a probe, ready for `query`.
+    pub fn probe(&self, minutiae: &[RawMinutiaCombined]) -> Fingerprint {
+        Fingerprint::from_raw(minutiae, self.pruning.probe, self.format)
+    }
+
+    /// Removes the entry stored under `id` and returns its fingerprint.
+    ///
+    /// Costs O(n): the id is searched for and the following entries are shifted to keep
+    /// their order.
+    pub fn remove(&mut self, id: &Id) -> Option<Fingerprint>
+    where
+        Id: PartialEq,
+    {
+        let position = self.entries.iter().position(|(it, _)| it == id)?;
+        Some(self.entries.remove(position).1)
+    }
+
+    /// Matches the probe with every gallery entry and returns the entries scoring at least
+    /// `threshold`, best first; equal scores keep the gallery order.
+    pub fn query(&mut self, probe: &Fingerprint, threshold: u32) -> Vec<(Id, u32)>
+    where
+        Id: Clone,
+    {
+        let (format, pairs, state) = (self.format, &mut self.pairs, &mut self.state);
+        let hits = self
+            .entries
+            .iter()
+            .map(|(id, gallery)| (id, match_fingerprints(probe, gallery, format, pairs, state)))
+            .filter(|&(_, score)| score >= threshold)
+            .map(|(id, score)| (id.clone(), score))
+            .collect();
+        sorted_by_score(hits)
+    }
+
+    /// Same as `query`, but matches the gallery entries in parallel on the rayon thread pool.
+    #[cfg(feature = "std")]
+    pub fn par_query(&self, probe: &Fingerprint, threshold: u32) -> Vec<(Id, u32)>
+    where
+        Id: Clone + Send + Sync,
+    {
+        let format = self.format;
+        let hits = self
+            .entries
+            .par_iter()
+            .map_init(
+                || (PairHolder::new(), BozorthState::new()),
+                |(pairs, state), (id, gallery)| {
+                    (id, match_fingerprints(probe, gallery, format, pairs, state))
+                },
+            )
+            .filter(|&(_, score)| score >= threshold)
+            .map(|(id, score)| (id.clone(), score))
+            .collect();
+        sorted_by_score(hits)
+    }
+}
+
+fn sorted_by_score<Id>(mut hits: Vec<(Id, u32)>) -> Vec<(Id, u32)> {
+    hits.sort_by_key(|&(_, score)| core::cmp::Reverse(score));
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gallery::GalleryIndex;
+    use crate::synthetic::Generator;
+    use crate::{Fingerprint, Format, MatchPruning, PruneOptions};
+
+    #[test]
+    fn query_finds_the_enrolled_finger() {
+        let mut generator = Generator::new(5);
+        let format = Format::NistInternal;
+        let options = PruneOptions::default();
+
+        let mut entries = vec![];
+        let mut probes = vec![];
+        for id in 0..6u32 {
+            let template = generator.raw_template(40);
+            let impression = generator.impression(&template, -6, (4, -9));
+            entries.push((id, Fingerprint::from_raw(&template, options, format)));
+            probes.push(Fingerprint::from_raw(&impression, options, format));
+        }
+        let mut index = GalleryIndex::new(entries, format);
+        assert_eq!(index.len(), 6);
+
+        for (id, probe) in probes.iter().enumerate() {
+            let all = index.query(probe, 0);
+            assert_eq!(all.len(), 6);
+            assert_eq!(all[0].0, id as u32);
+            assert!(all.windows(2).all(|it| it[0].1 >= it[1].1));
+            #[cfg(feature = "std")]
+            assert_eq!(index.par_query(probe, 0), all);
+
+            let threshold = all[0].1;
+            assert_eq!(index.query(probe, threshold), &all[..1]);
+            assert!(index.query(probe, threshold + 1).is_empty());
+        }
+    }
+
+    #[test]
+    fn edited_gallery_matches_a_rebuilt_one() {
+        let mut generator = Generator::new(7);
+        let format = Format::NistInternal;
+        let options = PruneOptions::default();
+
+        let templates: Vec<_> = (0..5).map(|_| generator.raw_template(40)).collect();
+        let fingerprint = |id: usize| Fingerprint::from_raw(&templates[id], options, format);
+        let probe = Fingerprint::from_raw(
+            &generator.impression(&templates[2], 4, (-3, 5)),
+            options,
+            format,
+        );
+
+        let mut index = GalleryIndex::new(vec![], format);
+        for id in 0..4 {
+            assert!(index.add(id, fingerprint(id)).is_none());
+        }
+        assert!(index.remove(&1).is_some());
+        assert!(index.remove(&1).is_none());
+        assert!(index.add(4, fingerprint(4)).is_none());
+        // replacing an entry keeps its position
+        assert!(index.add(0, fingerprint(0)).is_some());
+
+        let ids: Vec<_> = index.entries().iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, [0, 2, 3, 4]);
+
+        let mut rebuilt = GalleryIndex::new(
+            ids.iter().map(|&id| (id, fingerprint(id))).collect(),
+            format,
+        );
+        let all = index.query(&probe, 0);
+        assert_eq!(all[0].0, 2);
+        assert_eq!(all, rebuilt.query(&probe, 0));
+        #[cfg(feature = "std")]
+        assert_eq!(index.par_query(&probe, 0), all);
+    }
+
+    #[test]
+    fn probes_and_galleries_are_pruned_separately() {
+        let mut generator = Generator::new(9);
+        let template = generator.raw_template(60);
+        let impression = generator.impression(&template, 3, (2, -4));
+        let pruning = MatchPruning::default().with_max_minutiae(20, 0);
+
+        let mut index = GalleryIndex::new(vec![], Format::NistInternal).pruning(pruning);
+        assert!(index.add_raw(0u32, &template).is_none());
+        let probe = index.probe(&impression);
+        assert_eq!(index.entries()[0].1.minutiae_used, template.len());
+        assert_eq!(probe.minutiae_used, 20);
+
+        let expected = crate::match_fingerprints(
+            &Fingerprint::from_raw(&impression, pruning.probe, Format::NistInternal),
+            &Fingerprint::from_raw(&template, pruning.gallery, Format::NistInternal),
+            Format::NistInternal,
+            &mut crate::PairHolder::new(),
+            &mut crate::BozorthState::new(),
+        );
+        assert_eq!(index.query(&probe, 0), [(0, expected)]);
+    }
+}
@@ -1,10 +1,94 @@
 use crate::associations::{EndpointAssociations, EndpointRelation};
 use crate::bozorth::FingerprintKind;
 use crate::consts::max_number_of_groups;
-use crate::is_strict_mode;
+use crate::memory::heap_size;
 use crate::types::Endpoint;
+use alloc::vec::Vec;
 
-pub(crate) type GroupVec = Vec<EndpointGroup>;
+/// Initial number of candidate endpoints a group has room for; most groups never grow beyond it.
+const INITIAL_GROUP_CAPACITY: usize = 4;
+
+/// Groups of the current start pair.
+///
+/// The candidate endpoints of all groups share a single buffer, so that groups created for
+/// every start pair reuse its allocation. A group that outgrows its range is moved to the end
+/// of the buffer with twice the room; the buffer is reset with the groups.
+#[derive(Default)]
+pub(crate) struct EndpointGroups {
+    groups: Vec<EndpointGroup>,
+    endpoints: Vec<Endpoint>,
+}
+
+impl EndpointGroups {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.groups.clear();
+        self.endpoints.clear();
+    }
+
+    /// Trims the capacity to `groups` groups with the initial room for their endpoints.
+    pub(crate) fn shrink_to(&mut self, groups: usize) {
+        self.groups.shrink_to(groups);
+        self.endpoints
+            .shrink_to(groups.saturating_mul(INITIAL_GROUP_CAPACITY));
+    }
+
+    /// Bytes held by the groups, including their candidate endpoints.
+    pub(crate) fn memory_usage(&self) -> usize {
+        heap_size(&self.groups) + heap_size(&self.endpoints)
+    }
+
+    /// Candidate endpoints of the group.
+    #[inline]
+    fn matching_endpoints(&self, group: &EndpointGroup) -> &[Endpoint] {
+        &self.endpoints[group.start..group.start + group.len]
+    }
+
+    /// Adds `endpoint` to the candidates of the group at `index`.
+    fn push_matching_endpoint(&mut self, index: usize, endpoint: Endpoint) {
+        let group = &mut self.groups[index];
+        if group.len == group.capacity {
+            let start = self.endpoints.len();
+            if group.start + group.capacity != start {
+                self.endpoints
+                    .extend_from_within(group.start..group.start + group.len);
+                group.start = start;
+            }
+            group.capacity *= 2;
+            self.endpoints
+                .resize(group.start + group.capacity, Endpoint(0));
+        }
+        self.endpoints[group.start + group.len] = endpoint;
+        group.len += 1;
+    }
+
+    /// Source, endpoint and candidate endpoints of every group.
+    #[cfg(test)]
+    pub(crate) fn describe(
+        &self,
+    ) -> impl Iterator<Item = (FingerprintKind, Endpoint, &[Endpoint])> {
+        self.groups.iter().map(move |group| {
+            (
+                group.endpoint_source,
+                group.endpoint,
+                self.matching_endpoints(group),
+            )
+        })
+    }
+}
 
 /// Represents a minutia from one fingerprint and collection of
 /// possible corresponding ones from another fingerprint.
@@ -13,10 +97,12 @@ pub(crate) struct EndpointGroup {
     endpoint: Endpoint,
     /// Kind of fingerprint that contains this minutia.
     endpoint_source: FingerprintKind,
-    /// Collection of endpoints from another fingerprint
-    /// that may match one from first fingerprint.
+    /// Range of `EndpointGroups::endpoints` with the endpoints from another fingerprint
+    /// that may match one from first fingerprint; `capacity` endpoints are reserved for it.
     /// These minutiae are located on a fingerprint with opposite kind.
-    matching_endpoints: Vec<Endpoint>,
+    start: usize,
+    len: usize,
+    capacity: usize,
 
     /// Index of the currently selected endpoint in the list of potential corresponding minutiae.
     /// This is used during search of not conflicting pairs of endpoints among all the groups.
@@ -29,45 +115,58 @@ pub(crate) struct EndpointGroup {
 /// Merges given endpoints into a group.
 /// If endpoint is already in a group, it takes that one and adds `new_endpoint` into it.
 /// Otherwise, it creates a new group.
+/// Nothing is merged once there are `max_number_of_groups()` groups, in both modes.
 pub(crate) fn merge_endpoints_into_group(
-    groups: &mut Vec<EndpointGroup>,
+    groups: &mut EndpointGroups,
     endpoint_source: FingerprintKind,
     endpoint: Endpoint,
     existing_endpoint: Endpoint,
     new_endpoint: Endpoint,
+    strict: bool,
 ) {
     debug_assert_ne!(existing_endpoint, new_endpoint);
 
-    if !is_strict_mode() {
-        if groups.len() == max_number_of_groups() {
-            return;
-        }
+    // Limit number of produced groups.
+    if groups.len() >= max_number_of_groups() {
+        return;
     }
 
     let existing_group = groups
-        .iter_mut()
-        .find(|g| g.endpoint_source == endpoint_source && g.endpoint == endpoint);
+        .groups
+        .iter()
+        .position(|g| g.endpoint_source == endpoint_source && g.endpoint == endpoint);
 
     match existing_group {
-        Some(group) => {
+        Some(index) => {
             // There is no need to add `existing_endpoint` since it had to be inserted earlier
             // during creation of this group.
-            if !group.matching_endpoints.contains(&new_endpoint) {
-                group.matching_endpoints.push(new_endpoint);
+            if !groups
+                .matching_endpoints(&groups.groups[index])
+                .contains(&new_endpoint)
+            {
+                groups.push_matching_endpoint(index, new_endpoint);
             }
         }
         None => {
-            let last_associated_from_probe = if is_strict_mode() {
+            let last_associated_from_probe = if strict {
                 None
             } else {
                 // there is an old association that probably should be taken into account
                 Some(existing_endpoint)
             };
 
-            groups.push(EndpointGroup {
+            let start = groups.endpoints.len();
+            groups
+                .endpoints
+                .resize(start + INITIAL_GROUP_CAPACITY, Endpoint(0));
+            groups.endpoints[start] = existing_endpoint;
+            groups.endpoints[start + 1] = new_endpoint;
+            groups.groups.push(EndpointGroup {
                 endpoint,
                 endpoint_source,
-                matching_endpoints: vec![existing_endpoint, new_endpoint],
+                start,
+                len: 2,
+                capacity: INITIAL_GROUP_CAPACITY,
                 endpoint_index: 0,
                 last_associated_from_probe,
             });
@@ -87,71 +186,572 @@ pub(crate) fn cleanup_associations(
     }
 }
 
+/// Associates the currently selected endpoints of all the groups, starting from the last one.
+///
+/// On a conflict, returns the index of the conflicting group; only the groups after it have
+/// made associations.
 pub(crate) fn try_associate_current_endpoints(
-    groups: &mut [EndpointGroup],
+    groups: &mut EndpointGroups,
     associator: &mut EndpointAssociations,
-) -> bool {
+    strict: bool,
+) -> Result<(), usize> {
     // NOTE: it's not clear why iteration goes in a reverse order
-    for group_index in (0..groups.len()).rev() {
-        let group = &mut groups[group_index];
-        let (probe_endpoint, gallery_endpoint) = match group.endpoint_source {
-            FingerprintKind::Probe => (
-                group.endpoint,
-                group.matching_endpoints[group.endpoint_index],
-            ),
-            FingerprintKind::Gallery => (
-                group.matching_endpoints[group.endpoint_index],
-                group.endpoint,
-            ),
-        };
+    for group_index in (0..groups.groups.len()).rev() {
+        let (probe_endpoint, gallery_endpoint) = selected_pair(groups, &groups.groups[group_index]);
 
         match associator.get_status(probe_endpoint, gallery_endpoint) {
             EndpointRelation::Unassociated => {
                 associator.associate(probe_endpoint, gallery_endpoint);
-                groups[group_index].last_associated_from_probe = Some(probe_endpoint);
+                groups.groups[group_index].last_associated_from_probe = Some(probe_endpoint);
             }
             EndpointRelation::MutuallyAssociated => {
-                if is_strict_mode() {
+                if strict {
                     // NOTE: probably this should not be here
                     // since in many cases it does not preserve the previous state
                     // and affects following iterations
-                    groups[group_index].last_associated_from_probe = Some(probe_endpoint);
+                    groups.groups[group_index].last_associated_from_probe = Some(probe_endpoint);
                 }
             }
             EndpointRelation::AssociatedToOther => {
-                return false;
+                return Err(group_index);
             }
         }
     }
-    return true;
+    Ok(())
 }
 
+/// Probe and gallery endpoint of the currently selected candidate of the group.
+#[inline]
+fn selected_pair(groups: &EndpointGroups, group: &EndpointGroup) -> (Endpoint, Endpoint) {
+    let selected = groups.endpoints[group.start + group.endpoint_index];
+    match group.endpoint_source {
+        FingerprintKind::Probe => (group.endpoint, selected),
+        FingerprintKind::Gallery => (selected, group.endpoint),
+    }
+}
+
+/// Last group that has to change for the `conflict` found by `try_associate_current_endpoints`
+/// to go away; the combinations keeping the groups up to it conflict the same way.
+///
+/// The conflict is with an association made before the search, which only a change of the
+/// conflicting group avoids, or with one made by a later group, which lasts as long as that
+/// group keeps its candidate.
+fn last_group_kept_by_conflict(
+    groups: &EndpointGroups,
+    associator: &EndpointAssociations,
+    conflict: usize,
+) -> usize {
+    let (probe, gallery) = selected_pair(groups, &groups.groups[conflict]);
+    let made_by = |pair| {
+        groups.groups[conflict + 1..]
+            .iter()
+            .position(|group| {
+                group.last_associated_from_probe.is_some() && selected_pair(groups, group) == pair
+            })
+            .map_or(conflict, |it| conflict + 1 + it)
+    };
+    let by_probe = associator
+        .get_associated_by_probe(probe)
+        .map(|it| made_by((probe, it)));
+    let by_gallery = associator
+        .get_associated_by_gallery(gallery)
+        .map(|it| made_by((it, gallery)));
+    by_probe
+        .into_iter()
+        .chain(by_gallery)
+        .min()
+        .unwrap_or(conflict)
+}
+
+/// Steps the search over the associations of `groups` may take for a single start pair, with
+/// `steps_per_endpoint` steps for every group and candidate endpoint of a group.
+///
+/// The search tries every combination of the candidate endpoints of the groups, which grows
+/// exponentially with the number of groups; the budget grows only with their sizes.
+pub(crate) fn association_search_budget(
+    groups: &EndpointGroups,
+    steps_per_endpoint: usize,
+) -> usize {
+    let endpoints: usize = groups.groups.iter().map(|it| it.len).sum();
+    steps_per_endpoint
+        .saturating_mul(max_number_of_groups().max(1))
+        .saturating_mul(endpoints)
+}
+
+/// Result of `find_next_not_conflicting_associations`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum AssociationSearch {
+    /// The groups select endpoints that do not conflict with each other.
+    Found,
+    /// Every combination of endpoints was tried.
+    Exhausted,
+    /// The search took more than `association_search_budget` steps and was given up.
+    OverBudget,
+}
+
+/// Selects the next combination of endpoints of the groups that can be associated together.
+///
+/// `steps` counts the steps taken for the current start pair; with `steps_per_endpoint` they
+/// are checked against `association_search_budget`, without it the search is not limited, like
+/// the original implementation.
+///
+/// After a conflict the strict mode tries the next combination from the last group again, like
+/// the original implementation, so that it takes the same steps. The relaxed mode skips the
+/// combinations that keep the association the conflict was with, see
+/// `last_group_kept_by_conflict`; it finds the same combinations in the same order, in fewer
+/// steps.
 pub(crate) fn find_next_not_conflicting_associations(
-    groups: &mut [EndpointGroup],
+    groups: &mut EndpointGroups,
+    associator: &mut EndpointAssociations,
+    steps: &mut usize,
+    steps_per_endpoint: Option<usize>,
+    strict: bool,
+) -> AssociationSearch {
+    search_associations(
+        groups,
+        associator,
+        steps,
+        steps_per_endpoint,
+        strict,
+        !strict,
+    )
+}
+
+fn search_associations(
+    groups: &mut EndpointGroups,
     associator: &mut EndpointAssociations,
-) -> bool {
-    cleanup_associations(groups, associator);
+    steps: &mut usize,
+    steps_per_endpoint: Option<usize>,
+    strict: bool,
+    resume: bool,
+) -> AssociationSearch {
+    cleanup_associations(&mut groups.groups, associator);
+    let budget = steps_per_endpoint.map_or(usize::MAX, |it| association_search_budget(groups, it));
 
     // NOTE: probably order does not matter here... it should work just fine with forward iteration.
     // scores would be different, though
-    let mut it = groups.iter_mut().rev();
-    while let Some(group) = it.next() {
-        if group.endpoint_index + 1 < group.matching_endpoints.len() {
+    let mut group_index = groups.len();
+    while group_index > 0 {
+        group_index -= 1;
+        *steps += 1;
+        if *steps > budget {
+            return AssociationSearch::OverBudget;
+        }
+
+        let group = &mut groups.groups[group_index];
+        if group.endpoint_index + 1 < group.len {
             group.endpoint_index += 1;
 
             // Try to associate currently selected endpoint for all the groups.
             // All changes are restored after a failed association.
-            if try_associate_current_endpoints(groups, associator) {
-                return true;
+            if let Err(conflict) = try_associate_current_endpoints(groups, associator, strict) {
+                // There is a conflict, so clear all made associations
+                // and start from the beginning... The groups before the conflicting one
+                // were not reached, so they have nothing to clear.
+                let kept = if resume {
+                    last_group_kept_by_conflict(groups, associator, conflict)
+                } else {
+                    groups.len() - 1
+                };
+                cleanup_associations(&mut groups.groups[conflict..], associator);
+                // the groups after it start over, as if the skipped combinations were tried
+                for group in &mut groups.groups[kept + 1..] {
+                    group.endpoint_index = 0;
+                }
+                group_index = kept + 1;
+            } else {
+                return AssociationSearch::Found;
             }
-
-            // There is a conflict, so clear all made associations
-            // and start from the beginning...
-            cleanup_associations(groups, associator);
-            it = groups.iter_mut().rev();
         } else {
             group.endpoint_index = 0;
         }
     }
-    return false;
+    AssociationSearch::Exhausted
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::associations::EndpointAssociations;
+    use crate::associations::EndpointRelation;
+    use crate::bozorth::FingerprintKind;
+    use crate::consts::max_number_of_groups;
+    use crate::groups::{
+        association_search_budget, find_next_not_conflicting_associations,
+        merge_endpoints_into_group, search_associations, AssociationSearch, EndpointGroups,
+    };
+    use crate::synthetic::Generator;
+    use crate::types::Endpoint;
+
+    const STEPS_PER_ENDPOINT: usize = 64;
+
+    /// Probe groups with `size` candidates each that never conflict with each other.
+    fn independent_groups(count: u32, size: u32) -> EndpointGroups {
+        let mut groups = EndpointGroups::new();
+        for probe in 0..count {
+            let first = 100 + probe * size;
+            for gallery in first + 1..first + size {
+                merge_endpoints_into_group(
+                    &mut groups,
+                    FingerprintKind::Probe,
+                    Endpoint(probe),
+                    Endpoint(first),
+                    Endpoint(gallery),
+                    true,
+                );
+            }
+        }
+        groups
+    }
+
+    /// Calls the search until it stops; returns how it stopped and how often it found
+    /// associations.
+    fn search(groups: &mut EndpointGroups, steps: &mut usize) -> (AssociationSearch, usize) {
+        let mut associator = EndpointAssociations::new();
+        let mut found = 0;
+        loop {
+            match find_next_not_conflicting_associations(
+                groups,
+                &mut associator,
+                steps,
+                Some(STEPS_PER_ENDPOINT),
+                true,
+            ) {
+                AssociationSearch::Found => found += 1,
+                stop => return (stop, found),
+            }
+        }
+    }
+
+    #[test]
+    fn small_searches_try_every_combination() {
+        let mut groups = independent_groups(3, 2);
+        let mut steps = 0;
+        // the first combination is the initial one
+        assert_eq!(
+            search(&mut groups, &mut steps),
+            (AssociationSearch::Exhausted, 7)
+        );
+        assert!(steps <= association_search_budget(&groups, STEPS_PER_ENDPOINT));
+    }
+
+    #[test]
+    fn large_searches_give_up_after_their_budget() {
+        let count = max_number_of_groups() as u32;
+        let mut groups = independent_groups(count, 4);
+        assert_eq!(groups.len(), count as usize);
+        let budget = association_search_budget(&groups, STEPS_PER_ENDPOINT);
+
+        let mut steps = 0;
+        let (stop, found) = search(&mut groups, &mut steps);
+        assert_eq!(stop, AssociationSearch::OverBudget);
+        assert_eq!(steps, budget + 1);
+        assert!(found > 0);
+        assert!(found < 4usize.pow(count) - 1);
+
+        // the steps are counted across calls for the same start pair
+        assert_eq!(
+            find_next_not_conflicting_associations(
+                &mut groups,
+                &mut EndpointAssociations::new(),
+                &mut steps,
+                Some(STEPS_PER_ENDPOINT),
+                true
+            ),
+            AssociationSearch::OverBudget
+        );
+    }
+
+    #[test]
+    fn searches_without_steps_per_endpoint_are_not_limited() {
+        let run = |steps_per_endpoint| {
+            let mut groups = independent_groups(6, 4);
+            let mut associator = EndpointAssociations::new();
+            let (mut steps, mut found) = (0, 0);
+            loop {
+                match find_next_not_conflicting_associations(
+                    &mut groups,
+                    &mut associator,
+                    &mut steps,
+                    steps_per_endpoint,
+                    true,
+                ) {
+                    AssociationSearch::Found => found += 1,
+                    stop => return (stop, found),
+                }
+            }
+        };
+        assert_eq!(run(None), (AssociationSearch::Exhausted, 4usize.pow(6) - 1));
+        assert_eq!(run(Some(1)).0, AssociationSearch::OverBudget);
+    }
+
+    /// The groups as they were stored before `EndpointGroups`, a vector of candidates each,
+    /// searched by restarting from scratch after every conflict.
+    struct ReferenceGroup {
+        endpoint: Endpoint,
+        endpoint_source: FingerprintKind,
+        matching_endpoints: Vec<Endpoint>,
+        endpoint_index: usize,
+        last_associated_from_probe: Option<Endpoint>,
+    }
+
+    fn reference_merge(
+        groups: &mut Vec<ReferenceGroup>,
+        endpoint_source: FingerprintKind,
+        endpoint: Endpoint,
+        existing_endpoint: Endpoint,
+        new_endpoint: Endpoint,
+        strict: bool,
+    ) {
+        if groups.len() >= max_number_of_groups() {
+            return;
+        }
+        match groups
+            .iter_mut()
+            .find(|g| g.endpoint_source == endpoint_source && g.endpoint == endpoint)
+        {
+            Some(group) => {
+                if !group.matching_endpoints.contains(&new_endpoint) {
+                    group.matching_endpoints.push(new_endpoint);
+                }
+            }
+            None => groups.push(ReferenceGroup {
+                endpoint,
+                endpoint_source,
+                matching_endpoints: vec![existing_endpoint, new_endpoint],
+                endpoint_index: 0,
+                last_associated_from_probe: if strict {
+                    None
+                } else {
+                    Some(existing_endpoint)
+                },
+            }),
+        }
+    }
+
+    fn reference_cleanup(groups: &mut [ReferenceGroup], associator: &mut EndpointAssociations) {
+        for group in groups.iter_mut() {
+            if let Some(probe) = group.last_associated_from_probe.take() {
+                associator.clear_by_probe(probe)
+            }
+        }
+    }
+
+    fn reference_try_associate(
+        groups: &mut [ReferenceGroup],
+        associator: &mut EndpointAssociations,
+        strict: bool,
+    ) -> bool {
+        for group in groups.iter_mut().rev() {
+            let selected = group.matching_endpoints[group.endpoint_index];
+            let (probe, gallery) = match group.endpoint_source {
+                FingerprintKind::Probe => (group.endpoint, selected),
+                FingerprintKind::Gallery => (selected, group.endpoint),
+            };
+            match associator.get_status(probe, gallery) {
+                EndpointRelation::Unassociated => {
+                    associator.associate(probe, gallery);
+                    group.last_associated_from_probe = Some(probe);
+                }
+                EndpointRelation::MutuallyAssociated => {
+                    if strict {
+                        group.last_associated_from_probe = Some(probe);
+                    }
+                }
+                EndpointRelation::AssociatedToOther => return false,
+            }
+        }
+        true
+    }
+
+    fn reference_search(
+        groups: &mut [ReferenceGroup],
+        associator: &mut EndpointAssociations,
+        steps: &mut usize,
+        budget: usize,
+        strict: bool,
+    ) -> AssociationSearch {
+        reference_cleanup(groups, associator);
+        let mut index = groups.len();
+        while index > 0 {
+            index -= 1;
+            *steps += 1;
+            if *steps > budget {
+                return AssociationSearch::OverBudget;
+            }
+            let group = &mut groups[index];
+            if group.endpoint_index + 1 < group.matching_endpoints.len() {
+                group.endpoint_index += 1;
+                if reference_try_associate(groups, associator, strict) {
+                    return AssociationSearch::Found;
+                }
+                reference_cleanup(groups, associator);
+                index = groups.len();
+            } else {
+                group.endpoint_index = 0;
+            }
+        }
+        AssociationSearch::Exhausted
+    }
+
+    fn associations(associator: &EndpointAssociations) -> Vec<Option<Endpoint>> {
+        (0..16)
+            .map(|probe| associator.get_associated_by_probe(Endpoint(probe)))
+            .collect()
+    }
+
+    /// Random groups together with the same groups of the reference and an associator for each
+    /// of them, with the same associations made before the search.
+    fn random_groups(
+        generator: &mut Generator,
+        strict: bool,
+    ) -> (
+        EndpointGroups,
+        Vec<ReferenceGroup>,
+        EndpointAssociations,
+        EndpointAssociations,
+    ) {
+        let (mut groups, mut reference) = (EndpointGroups::new(), vec![]);
+        let (mut associator, mut reference_associator) =
+            (EndpointAssociations::new(), EndpointAssociations::new());
+        // few distinct endpoints, so that groups grow and their candidates conflict
+        let endpoint = |generator: &mut Generator| Endpoint(generator.next(12) as u32);
+        for _ in 0..generator.next(10) {
+            let (probe, gallery) = (endpoint(generator), endpoint(generator));
+            associator.associate(probe, gallery);
+            reference_associator.associate(probe, gallery);
+        }
+        for _ in 0..generator.next(40) {
+            let source = if generator.next(2) == 0 {
+                FingerprintKind::Probe
+            } else {
+                FingerprintKind::Gallery
+            };
+            let owner = endpoint(generator);
+            let (existing, new) = (endpoint(generator), endpoint(generator));
+            if existing == new {
+                continue;
+            }
+            merge_endpoints_into_group(&mut groups, source, owner, existing, new, strict);
+            reference_merge(&mut reference, source, owner, existing, new, strict);
+        }
+        (groups, reference, associator, reference_associator)
+    }
+
+    /// Associations of every combination the reference finds until it is exhausted, and the
+    /// steps it took.
+    fn reference_combinations(
+        reference: &mut [ReferenceGroup],
+        associator: &mut EndpointAssociations,
+        strict: bool,
+    ) -> (Vec<Vec<Option<Endpoint>>>, usize) {
+        let (mut found, mut steps) = (vec![], 0);
+        while reference_search(reference, associator, &mut steps, usize::MAX, strict)
+            == AssociationSearch::Found
+        {
+            found.push(associations(associator));
+        }
+        (found, steps)
+    }
+
+    #[test]
+    fn searches_match_the_reference_implementation() {
+        let mut generator = Generator::new(41);
+        let (mut relaxed_steps, mut relaxed_reference_steps) = (0, 0);
+        for round in 0..400 {
+            let strict = round % 2 == 0;
+            let (mut groups, mut reference, mut associator, mut reference_associator) =
+                random_groups(&mut generator, strict);
+            let expected: Vec<_> = reference
+                .iter()
+                .map(|it| {
+                    (
+                        it.endpoint_source,
+                        it.endpoint,
+                        it.matching_endpoints.clone(),
+                    )
+                })
+                .collect();
+            let actual: Vec<_> = groups
+                .describe()
+                .map(|(source, endpoint, matching)| (source, endpoint, matching.to_vec()))
+                .collect();
+            assert_eq!(actual, expected, "round {}", round);
+
+            if !strict {
+                // the relaxed search skips combinations after conflicts, so it finds the same
+                // combinations as the reference in the same order, in fewer steps
+                let (expected, reference_steps) =
+                    reference_combinations(&mut reference, &mut reference_associator, strict);
+                let (mut found, mut steps) = (vec![], 0);
+                while find_next_not_conflicting_associations(
+                    &mut groups,
+                    &mut associator,
+                    &mut steps,
+                    None,
+                    strict,
+                ) == AssociationSearch::Found
+                {
+                    found.push(associations(&associator));
+                }
+                assert_eq!(found, expected, "round {}", round);
+                assert!(steps <= reference_steps, "round {}", round);
+                relaxed_steps += steps;
+                relaxed_reference_steps += reference_steps;
+                continue;
+            }
+
+            let budget = association_search_budget(&groups, STEPS_PER_ENDPOINT);
+            let (mut steps, mut reference_steps) = (0, 0);
+            loop {
+                let result = find_next_not_conflicting_associations(
+                    &mut groups,
+                    &mut associator,
+                    &mut steps,
+                    Some(STEPS_PER_ENDPOINT),
+                    strict,
+                );
+                let expected = reference_search(
+                    &mut reference,
+                    &mut reference_associator,
+                    &mut reference_steps,
+                    budget,
+                    strict,
+                );
+                assert_eq!(result, expected, "round {}", round);
+                assert_eq!(steps, reference_steps, "round {}", round);
+                assert_eq!(
+                    associations(&associator),
+                    associations(&reference_associator),
+                    "round {}",
+                    round
+                );
+                if result != AssociationSearch::Found {
+                    break;
+                }
+            }
+        }
+        assert!(relaxed_steps < relaxed_reference_steps);
+    }
+
+    #[test]
+    fn resuming_after_conflicts_would_change_the_strict_steps() {
+        // the reason the strict mode still restarts from the last group: with
+        // `association_search_steps` the steps decide which combinations are tried
+        let mut generator = Generator::new(43);
+        let mut changed = 0;
+        for _ in 0..200 {
+            let (mut groups, mut reference, mut associator, mut reference_associator) =
+                random_groups(&mut generator, true);
+            let (_, expected_steps) =
+                reference_combinations(&mut reference, &mut reference_associator, true);
+            let mut steps = 0;
+            while search_associations(&mut groups, &mut associator, &mut steps, None, true, true)
+                == AssociationSearch::Found
+            {}
+            if steps != expected_steps {
+                changed += 1;
+            }
+        }
+        assert!(changed > 0);
+    }
 }
@@ -1,41 +1,117 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![allow(incomplete_features)]
 #![feature(trait_alias)]
 #![feature(const_float_bits_conv)]
 // #![feature(const_int_pow)]
 
-pub use bozorth::{match_score, BozorthState};
-pub use find_edges::find_edges;
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub use batch::{
+    match_templates, BatchMatcher, BatchMode, BatchResults, BatchSettings, BatchStop,
+    BatchTemplate, MatchResult, TemplateScore,
+};
+pub use bozorth::{
+    match_outcome, match_score, match_score_with, BozorthState, MatchError, MatchFeatures,
+    MatchOutcome, OwnedCluster,
+};
+#[cfg(feature = "std")]
+pub use calibration::Calibration;
+pub use clusters::{ClusterAverages, ClusterCompatibility};
+pub use config::{ClusterCombineStrategy, CompatQuirks, MatchConfig, PairFilter};
+use consts::GlobalBool;
+use core::sync::atomic::Ordering;
+pub use edge_dump::EdgeDumpError;
+pub use edge_stats::{find_edges_with_stats, EdgeStats};
+pub use find_edges::{find_edges, find_edges_with};
+pub use fingerprint::{
+    count_candidate_pairs, match_fingerprints, match_fingerprints_with, Fingerprint,
+};
+pub use fusion::{
+    fuse_scores, match_subject, FingerPairing, FingerPosition, FingerWeights, FusionStrategy,
+};
+pub use gallery::GalleryIndex;
 pub use match_edges::match_edges_into_pairs;
+#[cfg(feature = "async")]
+pub use matcher::{score_async, Matcher, MatcherOptions};
+pub use memory::{PairHolderMemory, ShrinkLimits, StateMemory};
+pub use merge::{merge_templates, MergeOptions};
+pub use normalization::Normalization;
 pub use pair_holder::PairHolder;
-pub use parsing::parse;
+pub use parsing::{
+    detect_angle_convention, normalize_angles, parse_combined_with_angles,
+    parse_combined_with_limit, AngleConvention,
+};
+#[cfg(feature = "std")]
+pub use parsing::{parse, parse_with, parse_with_angles, parse_with_limit};
+pub use parsing::{parse_combined, parse_combined_with, ParsingError, QualitySource};
 pub use prof::timeit;
-use std::sync::atomic::{AtomicBool, Ordering};
-pub use types::{BetaOrder, Edge, Format, Minutia, Pair};
-pub use utils::{limit_edges, prune};
+pub use sorted_edges::SortedEdges;
+pub use types::{
+    kind_points, quality_points, AngleOrigin, BetaOrder, CoordinateOrigin, Degrees, Edge, Format,
+    InvalidEdge, Minutia, MinutiaKind, Pair,
+};
+pub use utils::{
+    limit_edges, limit_edges_with, prune, prune_with, prune_with_indices, thin_edges, MatchPruning,
+    PruneOptions, PrunedMinutiae, ThinOptions,
+};
 
-static STRICT_MODE: AtomicBool = AtomicBool::new(true);
+static STRICT_MODE: GlobalBool = GlobalBool::new(true);
 
+/// Mode set by `set_mode`, the default of `MatchConfig::strict` and `PruneOptions::strict`.
 #[inline(always)]
-pub fn is_strict_mode() -> bool {
+pub(crate) fn default_mode() -> bool {
     STRICT_MODE.load(Ordering::Relaxed)
 }
 
+/// Always true without `std`, where the mode cannot be changed.
+#[deprecated(note = "read `MatchConfig::strict` instead")]
+#[inline(always)]
+pub fn is_strict_mode() -> bool {
+    default_mode()
+}
+
+/// Switches the default of `MatchConfig::strict` and `PruneOptions::strict` between the strict
+/// mode, identical to the original Bozorth3, and the relaxed one.
+///
+/// The mode is global and affects every thread; set it per match in the configs instead.
+#[cfg(feature = "std")]
+#[deprecated(note = "set `MatchConfig::strict` and `PruneOptions::strict` instead")]
 pub fn set_mode(strict: bool) {
     STRICT_MODE.store(strict, Ordering::SeqCst);
 }
 
 mod associations;
+#[cfg(feature = "std")]
+mod batch;
 mod bozorth;
+#[cfg(feature = "std")]
+pub mod calibration;
 mod clusters;
+pub mod config;
 pub mod consts;
+mod deadline;
+pub mod edge_dump;
+pub mod edge_stats;
 mod find_edges;
+mod fingerprint;
+pub mod fusion;
+mod gallery;
 mod groups;
 mod match_edges;
+#[cfg(feature = "async")]
+mod matcher;
 mod math;
+mod memory;
+mod merge;
+pub mod normalization;
 mod pair_holder;
 pub mod parsing;
-mod prof;
-mod set_intersection;
+pub mod prof;
+mod sorted_edges;
+#[cfg(any(test, feature = "testing"))]
+pub mod synthetic;
 pub mod types;
+pub mod util;
 mod utils;
 mod weird_sort;
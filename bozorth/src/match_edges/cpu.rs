@@ -6,11 +6,16 @@ use crate::pair_holder::PairHolder;
 // use crate::simd::F32x8;
 // use crate::simd::I32x8;
 // use crate::simd::Mx8;
-use crate::consts::factor;
-use crate::is_strict_mode;
+use crate::config::{quirks_are_logged, CompatQuirks, MatchConfig, PairFilter};
+use crate::consts::{factor, MAX_NUMBER_OF_PAIRS};
 use crate::types::Edge;
+use crate::types::Endpoint;
 use crate::types::Minutia;
 use crate::types::Pair;
+use crate::types::{AngleOrigin, Degrees};
+use crate::SortedEdges;
+use alloc::vec;
+use alloc::vec::Vec;
 
 /*
 #[inline(always)]
@@ -33,6 +38,8 @@ fn are_angles_not_equal_with_tolerance_2v8(a: I32x8, b: I32x8, c: I32x8, d: I32x
     )
 }*/
 
+/// Points of a pair of edges, given the endpoints of both edges; see `kind_points` and
+/// `quality_points`.
 pub trait CalculatePoints = Fn(
     /*probe_k: */ &Minutia,
     /*probe_j:*/ &Minutia,
@@ -42,12 +49,13 @@ pub trait CalculatePoints = Fn(
 
 #[inline(always)]
 pub fn match_edges_into_pairs(
-    probe_edges: &[Edge],
+    probe_edges: SortedEdges,
     probe_minutiae: &[Minutia],
-    gallery_edges: &[Edge],
+    gallery_edges: SortedEdges,
     // gallery_edges_soa: &EdgeHolder,
     gallery_minutiae: &[Minutia],
     pairs: &mut PairHolder,
+    config: &MatchConfig,
     calculate_points: impl CalculatePoints,
 ) {
     if probe_edges.is_empty() || gallery_edges.is_empty() {
@@ -58,16 +66,84 @@ pub fn match_edges_into_pairs(
     //     unsafe { simd_match_edges_into_pairs(probe_edges, probe_minutiae, gallery_edges_soa, gallery_minutiae, pairs, calculate_points) }
     // } else {
     scalar_match_edges_into_pairs(
-        probe_edges,
+        probe_edges.as_slice(),
         probe_minutiae,
-        gallery_edges,
+        gallery_edges.as_slice(),
         gallery_minutiae,
         pairs,
+        config,
         calculate_points,
     )
     // }
 }
 
+/// Checks whether edges of the given squared lengths are similar enough to form a pair.
+#[inline]
+fn are_distances_compatible(probe_distance_squared: i32, gallery_distance_squared: i32) -> bool {
+    let dz = gallery_distance_squared - probe_distance_squared;
+    let fi = 2.0 * factor() * (gallery_distance_squared + probe_distance_squared) as f32;
+    dz.abs() as f32 <= fi
+}
+
+/// Cheap check whether any pair can be formed from edges sorted by their length.
+///
+/// When the ranges of lengths do not overlap, the closest lengths from both ranges are
+/// the most similar ones, so it is enough to compare only them.
+#[inline]
+fn can_distance_ranges_overlap(probe_edges: &[Edge], gallery_edges: &[Edge]) -> bool {
+    let (probe_min, probe_max) = match (probe_edges.first(), probe_edges.last()) {
+        (Some(first), Some(last)) => (first.distance_squared, last.distance_squared),
+        _ => return false,
+    };
+    let (gallery_min, gallery_max) = match (gallery_edges.first(), gallery_edges.last()) {
+        (Some(first), Some(last)) => (first.distance_squared, last.distance_squared),
+        _ => return false,
+    };
+
+    if gallery_max < probe_min {
+        are_distances_compatible(probe_min, gallery_max)
+    } else if probe_max < gallery_min {
+        are_distances_compatible(probe_max, gallery_min)
+    } else {
+        true
+    }
+}
+
+/// Checks whether rotation of the corresponding minutiae agrees with rotation of the pair.
+#[inline]
+fn are_orientations_consistent(
+    probe: &Minutia,
+    gallery: &Minutia,
+    delta_theta: Degrees,
+    origin: AngleOrigin,
+) -> bool {
+    (origin.counterclockwise(probe.theta) - origin.counterclockwise(gallery.theta))
+        .normalized()
+        .is_close_to(delta_theta)
+}
+
+/// Index of the first minutia at the same position and angle as each minutia.
+///
+/// With `MatchConfig::dedup_pairs` the endpoints of pairs are replaced by these, so edges
+/// to duplicated minutiae give the same pair and are collapsed by `PairHolder::prepare_with`.
+fn coincident_minutiae(minutiae: &[Minutia]) -> Vec<Endpoint> {
+    let place = |index: u32| {
+        let minutia = &minutiae[index as usize];
+        (minutia.x, minutia.y, minutia.theta)
+    };
+    let mut order: Vec<u32> = (0..minutiae.len() as u32).collect();
+    order.sort_by_key(|&index| (place(index), index));
+
+    let mut canonical = vec![Endpoint(0); minutiae.len()];
+    for (position, &index) in order.iter().enumerate() {
+        canonical[index as usize] = match position.checked_sub(1).map(|it| order[it]) {
+            Some(previous) if place(previous) == place(index) => canonical[previous as usize],
+            _ => Endpoint(index),
+        };
+    }
+    canonical
+}
+
 #[allow(unused)]
 pub fn scalar_match_edges_into_pairs(
     probe_edges: &[Edge],
@@ -75,20 +151,42 @@ pub fn scalar_match_edges_into_pairs(
     gallery_edges: &[Edge],
     gallery_minutiae: &[Minutia],
     pairs: &mut PairHolder,
+    config: &MatchConfig,
     calculate_points: impl CalculatePoints,
 ) {
-    debug_assert!(!probe_edges.is_empty());
-    debug_assert!(!gallery_edges.is_empty());
+    // templates with less than two minutiae have no edges and nothing to match
+    if probe_edges.is_empty() || gallery_edges.is_empty() {
+        return;
+    }
 
     let mut start = 0;
 
-    let probe_edges = if is_strict_mode() {
-        &probe_edges[..probe_edges.len() - 1]
+    // the skipped edge is still matched when it is logged, only to count its pairs
+    let skipped_edge = if config.quirks().contains(CompatQuirks::SKIP_LAST_PROBE_EDGE) {
+        Some(probe_edges.len() - 1)
+    } else {
+        None
+    };
+    let probe_edges = match skipped_edge {
+        Some(last) if !quirks_are_logged() => &probe_edges[..last],
+        _ => probe_edges,
+    };
+    let mut skipped_pairs = 0;
+
+    if !can_distance_ranges_overlap(probe_edges, gallery_edges) {
+        return;
+    }
+
+    let canonical = if config.dedup_pairs {
+        Some((
+            coincident_minutiae(probe_minutiae),
+            coincident_minutiae(gallery_minutiae),
+        ))
     } else {
-        &probe_edges[..]
+        None
     };
 
-    for probe in probe_edges {
+    'probe: for (index, probe) in probe_edges.iter().enumerate() {
         for (j, gallery) in gallery_edges.iter().enumerate().skip(start) {
             let dz = gallery.distance_squared - probe.distance_squared;
             let fi = 2.0 * factor() * (gallery.distance_squared + probe.distance_squared) as f32;
@@ -111,21 +209,62 @@ pub fn scalar_match_edges_into_pairs(
             if probe.beta_order != gallery.beta_order {
                 delta_theta -= 180;
             }
+            let delta_theta = Degrees(normalize_angle(delta_theta));
+
+            let (gallery_k, gallery_j) = if probe.beta_order == gallery.beta_order {
+                (gallery.endpoint_k, gallery.endpoint_j)
+            } else {
+                (gallery.endpoint_j, gallery.endpoint_k)
+            };
+
+            if config.pair_filter == PairFilter::Orientation
+                && !(are_orientations_consistent(
+                    &probe_minutiae[probe.endpoint_k.as_usize()],
+                    &gallery_minutiae[gallery_k.as_usize()],
+                    delta_theta,
+                    config.angle_origin,
+                ) && are_orientations_consistent(
+                    &probe_minutiae[probe.endpoint_j.as_usize()],
+                    &gallery_minutiae[gallery_j.as_usize()],
+                    delta_theta,
+                    config.angle_origin,
+                ))
+            {
+                continue;
+            }
+
+            if Some(index) == skipped_edge {
+                skipped_pairs += 1;
+                continue;
+            }
+
+            let (probe_k, probe_j, gallery_k, gallery_j) = match &canonical {
+                Some((probe_canonical, gallery_canonical)) => {
+                    let endpoints = (
+                        probe_canonical[probe.endpoint_k.as_usize()],
+                        probe_canonical[probe.endpoint_j.as_usize()],
+                        gallery_canonical[gallery_k.as_usize()],
+                        gallery_canonical[gallery_j.as_usize()],
+                    );
+                    // an edge between two copies of one minutia has nothing to match
+                    if endpoints.0 == endpoints.1 || endpoints.2 == endpoints.3 {
+                        continue;
+                    }
+                    endpoints
+                }
+                None => (probe.endpoint_k, probe.endpoint_j, gallery_k, gallery_j),
+            };
 
+            // clusters are assigned in a table of this size, as in the reference implementation
+            if pairs.len() >= MAX_NUMBER_OF_PAIRS {
+                break 'probe;
+            }
             pairs.push(Pair {
-                delta_theta: normalize_angle(delta_theta),
-                probe_k: probe.endpoint_k,
-                probe_j: probe.endpoint_j,
-                gallery_k: if probe.beta_order == gallery.beta_order {
-                    gallery.endpoint_k
-                } else {
-                    gallery.endpoint_j
-                },
-                gallery_j: if probe.beta_order == gallery.beta_order {
-                    gallery.endpoint_j
-                } else {
-                    gallery.endpoint_k
-                },
+                delta_theta,
+                probe_k,
+                probe_j,
+                gallery_k,
+                gallery_j,
                 points: calculate_points(
                     &probe_minutiae[probe.endpoint_k.as_usize()],
                     &probe_minutiae[probe.endpoint_j.as_usize()],
@@ -135,6 +274,16 @@ pub fn scalar_match_edges_into_pairs(
             });
         }
     }
+
+    #[cfg(feature = "log")]
+    if skipped_pairs > 0 {
+        log::debug!(
+            quirk = "SkipLastProbeEdge", skipped_pairs;
+            "compatibility quirk changed the match"
+        );
+    }
+    #[cfg(not(feature = "log"))]
+    let _ = skipped_pairs;
 }
 
 /*
@@ -207,7 +356,7 @@ pub unsafe fn simd_match_edges_into_pairs(
                 }
 
                 pairs.push(Pair {
-                    delta_theta: normalize_angle(delta_theta),
+                    delta_theta: Degrees(normalize_angle(delta_theta)),
                     probe_k: probe.endpoint_k,
                     probe_j: probe.endpoint_j,
                     gallery_k: if probe.beta_order == v_g_beta_order[i] { v_g_endpoint_k[i] } else { v_g_endpoint_j[i] },
@@ -251,7 +400,7 @@ pub unsafe fn simd_match_edges_into_pairs(
             }
 
             pairs.push(Pair {
-                delta_theta: normalize_angle(delta_theta),
+                delta_theta: Degrees(normalize_angle(delta_theta)),
                 probe_k: probe.endpoint_k,
                 probe_j: probe.endpoint_j,
                 gallery_k: if probe.beta_order == gallery.beta_order { gallery.endpoint_k } else { gallery.endpoint_j },
@@ -269,3 +418,117 @@ pub unsafe fn simd_match_edges_into_pairs(
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{MatchConfig, PairFilter};
+    use crate::find_edges::find_edges;
+    use crate::match_edges::cpu::{are_distances_compatible, can_distance_ranges_overlap};
+    use crate::match_edges::match_edges_into_pairs;
+    use crate::pair_holder::PairHolder;
+    use crate::synthetic::Generator;
+    use crate::types::Degrees;
+    use crate::types::{quality_points, Format, Minutia, Pair};
+    use crate::SortedEdges;
+
+    fn template() -> Vec<Minutia> {
+        Generator::new(7).template(30)
+    }
+
+    fn match_pairs(probe: &[Minutia], gallery: &[Minutia], pair_filter: PairFilter) -> Vec<Pair> {
+        // Edges of both templates are taken from the unmodified template,
+        // so only the orientation check can notice the rotated minutia.
+        let mut edges = vec![];
+        find_edges(probe, &mut edges, Format::NistInternal);
+
+        let mut pairs = PairHolder::new();
+        match_edges_into_pairs(
+            SortedEdges::from_sorted_unchecked(&edges),
+            probe,
+            SortedEdges::from_sorted_unchecked(&edges),
+            gallery,
+            &mut pairs,
+            &MatchConfig {
+                pair_filter,
+                ..MatchConfig::default()
+            },
+            |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+        );
+        pairs.pairs().to_vec()
+    }
+
+    fn touches(pair: &Pair, endpoint: usize) -> bool {
+        (pair.probe_k.as_usize() == endpoint && pair.gallery_k.as_usize() == endpoint)
+            || (pair.probe_j.as_usize() == endpoint && pair.gallery_j.as_usize() == endpoint)
+    }
+
+    #[test]
+    fn orientation_filter_rejects_rotated_minutia() {
+        const ROTATED: usize = 5;
+        let probe = template();
+        let mut gallery = template();
+        gallery[ROTATED].theta = (gallery[ROTATED].theta + Degrees(90)).normalized();
+
+        let unfiltered = match_pairs(&probe, &gallery, PairFilter::Off);
+        let filtered = match_pairs(&probe, &gallery, PairFilter::Orientation);
+
+        assert!(unfiltered.iter().any(|it| touches(it, ROTATED)));
+        assert!(!filtered.iter().any(|it| touches(it, ROTATED)));
+        assert!(filtered.len() < unfiltered.len());
+
+        // the filter keeps all the pairs of an unmodified template
+        assert_eq!(
+            match_pairs(&probe, &probe, PairFilter::Orientation).len(),
+            match_pairs(&probe, &probe, PairFilter::Off).len()
+        );
+    }
+
+    #[test]
+    fn distance_precheck_agrees_with_exhaustive_search() {
+        let mut edges = vec![];
+        find_edges(&template(), &mut edges, Format::NistInternal);
+        let short = &edges[..edges.len() / 8];
+
+        for start in (0..edges.len()).step_by(7) {
+            let window = &edges[start..(start + 20).min(edges.len())];
+            let exhaustive = short.iter().any(|probe| {
+                window.iter().any(|gallery| {
+                    are_distances_compatible(probe.distance_squared, gallery.distance_squared)
+                })
+            });
+            assert_eq!(can_distance_ranges_overlap(short, window), exhaustive);
+            assert_eq!(can_distance_ranges_overlap(window, short), exhaustive);
+        }
+    }
+
+    #[test]
+    fn points_callback_sees_minutia_quality() {
+        let minutiae = template();
+        assert!(minutiae.iter().all(|it| it.quality > 0));
+        let mut edges = vec![];
+        find_edges(&minutiae, &mut edges, Format::NistInternal);
+
+        let mut pairs = PairHolder::new();
+        match_edges_into_pairs(
+            SortedEdges::from_sorted_unchecked(&edges),
+            &minutiae,
+            SortedEdges::from_sorted_unchecked(&edges),
+            &minutiae,
+            &mut pairs,
+            &MatchConfig::default(),
+            |pk: &Minutia, pj: &Minutia, gk: &Minutia, gj: &Minutia| {
+                quality_points(10_000, pk, pj, gk, gj)
+            },
+        );
+        assert!(!pairs.pairs().is_empty());
+        for pair in pairs.pairs() {
+            let [pk, pj, gk, gj] = [pair.probe_k, pair.probe_j, pair.gallery_k, pair.gallery_j]
+                .map(|it| &minutiae[it.as_usize()]);
+            assert_eq!(pair.points, quality_points(10_000, pk, pj, gk, gj));
+        }
+        assert!(pairs
+            .pairs()
+            .iter()
+            .any(|it| it.points != pairs.pairs()[0].points));
+    }
+}
@@ -1,3 +1,3 @@
-mod cpu;
+pub(crate) mod cpu;
 
 pub use cpu::match_edges_into_pairs;
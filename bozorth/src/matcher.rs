@@ -0,0 +1,206 @@
+//! Matching from async code on threads owned by a `Matcher`, see `score_async`.
+
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+
+use crate::deadline::Deadline;
+use crate::fingerprint::match_fingerprints_until;
+use crate::{BozorthState, Fingerprint, Format, MatchConfig, MatchError, PairHolder};
+
+/// How a `Matcher` runs its matches.
+#[derive(Debug, Copy, Clone)]
+pub struct MatcherOptions {
+    /// Number of matching threads, at least one.
+    pub threads: usize,
+    /// Most matches waiting for a thread; more of them fail with `MatchError::Busy`.
+    pub queue_capacity: usize,
+    pub format: Format,
+    pub config: MatchConfig,
+}
+
+impl Default for MatcherOptions {
+    fn default() -> Self {
+        MatcherOptions {
+            threads: std::thread::available_parallelism().map_or(1, |it| it.get()),
+            queue_capacity: 64,
+            format: Format::NistInternal,
+            config: MatchConfig::default(),
+        }
+    }
+}
+
+/// Threads that run the matches of `score_async`, each with its own `PairHolder` and
+/// `BozorthState`.
+///
+/// Dropping it waits for the matches already queued.
+pub struct Matcher {
+    jobs: Option<SyncSender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    counters: Arc<Counters>,
+}
+
+struct Counters {
+    /// Matches given up because their future was dropped, before or while they ran.
+    cancelled: AtomicUsize,
+}
+
+struct Job {
+    probe: Arc<Fingerprint>,
+    gallery: Arc<Fingerprint>,
+    shared: Arc<Shared>,
+}
+
+/// State of a match shared by its future and the thread running it.
+struct Shared {
+    /// Set when the future is dropped.
+    cancelled: Arc<AtomicBool>,
+    slot: Mutex<Slot>,
+}
+
+#[derive(Default)]
+struct Slot {
+    result: Option<Result<u32, MatchError>>,
+    waker: Option<Waker>,
+}
+
+impl Matcher {
+    pub fn new(options: MatcherOptions) -> Self {
+        let (jobs, queue) = mpsc::sync_channel(options.queue_capacity);
+        let queue = Arc::new(Mutex::new(queue));
+        let counters = Arc::new(Counters {
+            cancelled: AtomicUsize::new(0),
+        });
+        let workers = (0..options.threads.max(1))
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let counters = Arc::clone(&counters);
+                std::thread::spawn(move || run_worker(&queue, &counters, options))
+            })
+            .collect();
+        Matcher {
+            jobs: Some(jobs),
+            workers,
+            counters,
+        }
+    }
+
+    /// Number of matches given up because their future was dropped.
+    pub fn cancelled_matches(&self) -> usize {
+        self.counters.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn submit(&self, probe: Arc<Fingerprint>, gallery: Arc<Fingerprint>) -> PendingMatch {
+        let shared = Arc::new(Shared {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            slot: Mutex::new(Slot::default()),
+        });
+        let job = Job {
+            probe,
+            gallery,
+            shared: Arc::clone(&shared),
+        };
+        let jobs = self.jobs.as_ref().expect("the queue is open until drop");
+        if let Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) = jobs.try_send(job) {
+            shared.slot.lock().unwrap().result = Some(Err(MatchError::Busy));
+        }
+        PendingMatch { shared }
+    }
+}
+
+impl Drop for Matcher {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run_worker(queue: &Mutex<Receiver<Job>>, counters: &Counters, options: MatcherOptions) {
+    let mut pairs = PairHolder::new();
+    let mut state = BozorthState::new();
+    loop {
+        // the lock is released before the match
+        let job = match queue.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        let cancelled = &job.shared.cancelled;
+        if cancelled.load(Ordering::SeqCst) {
+            counters.cancelled.fetch_add(1, Ordering::SeqCst);
+            continue;
+        }
+        let deadline = Deadline::start(&options.config).cancelled_by(Arc::clone(cancelled));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            match_fingerprints_until(
+                &job.probe,
+                &job.gallery,
+                options.format,
+                &mut pairs,
+                &mut state,
+                &options.config,
+                &deadline,
+            )
+        }))
+        .unwrap_or_else(|_| {
+            // the panic may have left the buffers in any state
+            pairs = PairHolder::new();
+            state = BozorthState::new();
+            Err(MatchError::Panicked)
+        });
+        if cancelled.load(Ordering::SeqCst) {
+            counters.cancelled.fetch_add(1, Ordering::SeqCst);
+            continue;
+        }
+        let mut slot = job.shared.slot.lock().unwrap();
+        slot.result = Some(result);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Match queued on a `Matcher`; dropping it gives the match up.
+struct PendingMatch {
+    shared: Arc<Shared>,
+}
+
+impl Future for PendingMatch {
+    type Output = Result<u32, MatchError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.shared.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for PendingMatch {
+    fn drop(&mut self) {
+        self.shared.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Score of `probe` against `gallery`, computed on a thread of the `matcher`.
+///
+/// Dropping the future gives the match up at its next deadline check, which is counted in
+/// `Matcher::cancelled_matches`. A full queue fails with `MatchError::Busy`, a match that
+/// panics with `MatchError::Panicked`; the thread keeps serving other matches.
+pub async fn score_async(
+    matcher: &Matcher,
+    probe: Arc<Fingerprint>,
+    gallery: Arc<Fingerprint>,
+) -> Result<u32, MatchError> {
+    matcher.submit(probe, gallery).await
+}
@@ -1,4 +1,5 @@
 use crate::consts::{angle_lower_bound, angle_upper_bound};
+use crate::types::Degrees;
 
 #[inline]
 pub(crate) fn are_angles_opposite(a: i32, b: i32) -> bool {
@@ -14,14 +15,56 @@ pub(crate) fn are_angles_opposite(a: i32, b: i32) -> bool {
     false
 }
 
+/// Float functions of `std`, taken from `libm` when the crate is built without it.
+pub(crate) mod float {
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn round(x: f32) -> f32 {
+        x.round()
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn sqrt(x: f32) -> f32 {
+        x.sqrt()
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn atan(x: f32) -> f32 {
+        x.atan()
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn atan2(y: f32, x: f32) -> f32 {
+        y.atan2(x)
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn sin_cos(x: f32) -> (f32, f32) {
+        x.sin_cos()
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) use libm::{atan2f as atan2, atanf as atan, roundf as round, sqrtf as sqrt};
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    pub(crate) fn sin_cos(x: f32) -> (f32, f32) {
+        libm::sincosf(x)
+    }
+}
+
 #[inline]
 pub(crate) fn rounded(x: f32) -> i32 {
-    x.round() as i32
+    float::round(x) as i32
 }
 
 #[inline]
 pub(crate) fn rad_to_deg(rad: f32) -> f32 {
-    180.0 / std::f32::consts::PI * rad
+    180.0 / core::f32::consts::PI * rad
 }
 
 #[inline]
@@ -29,7 +72,7 @@ pub(crate) fn atan2_round_degree(dx: i32, dy: i32) -> i32 {
     if dx == 0 {
         90
     } else {
-        rounded(rad_to_deg(f32::atan2(dy as f32, dx as f32)))
+        rounded(rad_to_deg(float::atan2(dy as f32, dx as f32)))
     }
 }
 
@@ -45,7 +88,7 @@ pub(crate) fn normalize_angle(deg: i32) -> i32 {
 }
 
 #[inline]
-pub(crate) fn average_angles(a: i32, b: i32) -> i32 {
+pub(crate) fn average_angles(a: Degrees, b: Degrees) -> Degrees {
     let mut avg = Averager::new();
     avg.push(a);
     avg.push(b);
@@ -54,7 +97,7 @@ pub(crate) fn average_angles(a: i32, b: i32) -> i32 {
 
 pub(crate) fn calculate_slope_in_degrees(dx: i32, dy: i32) -> i32 {
     if dx != 0 {
-        let mut fi = rad_to_deg((dy as f32 / dx as f32).atan());
+        let mut fi = rad_to_deg(float::atan(dy as f32 / dx as f32));
         if fi < 0.0 {
             if dx < 0 {
                 fi += 180.0;
@@ -99,7 +142,8 @@ impl Averager {
     }
 
     #[inline]
-    pub(crate) fn push(&mut self, value: i32) {
+    pub(crate) fn push(&mut self, value: Degrees) {
+        let Degrees(value) = value;
         if value < 0 {
             self.sum_of_negative += value;
             self.number_of_negative += 1;
@@ -110,7 +154,7 @@ impl Averager {
     }
 
     #[inline]
-    pub(crate) fn average(self) -> i32 {
+    pub(crate) fn average(self) -> Degrees {
         let number_of_negative = self.number_of_negative.max(1);
         let number_of_positive = self.number_of_positive.max(1);
         let number_of_all = self.number_of_positive + self.number_of_negative;
@@ -136,7 +180,7 @@ impl Averager {
 
         assert!(average > -180 && average <= 180);
 
-        average
+        Degrees(average)
     }
 }
 
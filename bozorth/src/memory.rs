@@ -0,0 +1,66 @@
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// Bytes held by the components of a `BozorthState`, as reported by `BozorthState::memory_usage`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct StateMemory {
+    /// Clusters together with their nested vectors of compatible clusters and pairs.
+    pub clusters: usize,
+    /// Groups of endpoints together with their candidate endpoints.
+    pub groups: usize,
+    /// Selected pairs, best clusters and matched pairs of the last match.
+    pub pairs: usize,
+    /// Fixed size tables that are never resized.
+    pub fixed: usize,
+}
+
+impl StateMemory {
+    pub fn total(&self) -> usize {
+        self.clusters + self.groups + self.pairs + self.fixed
+    }
+}
+
+/// Bytes held by a `PairHolder`, as reported by `PairHolder::memory_usage`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PairHolderMemory {
+    /// Pairs with the buffers used for sorting and backward lookups.
+    pub pairs: usize,
+    /// Lookup ranges indexed by endpoints, they have a fixed size.
+    pub ranges: usize,
+}
+
+impl PairHolderMemory {
+    pub fn total(&self) -> usize {
+        self.pairs + self.ranges
+    }
+}
+
+/// Capacities that `shrink` trims the buffers back to.
+///
+/// A single match of large templates can grow the buffers far beyond what typical matches need;
+/// servers that keep states around may call `shrink` periodically to release that memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ShrinkLimits {
+    /// Capacity kept for buffers with an element per cluster.
+    pub clusters: usize,
+    /// Capacity kept for buffers with an element per pair.
+    pub pairs: usize,
+    /// Capacity kept for groups of endpoints.
+    pub groups: usize,
+}
+
+impl Default for ShrinkLimits {
+    fn default() -> Self {
+        ShrinkLimits {
+            clusters: 256,
+            pairs: 2048,
+            groups: 16,
+        }
+    }
+}
+
+/// Bytes allocated by the vector, including unused capacity.
+#[inline]
+pub(crate) fn heap_size<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * size_of::<T>()
+}
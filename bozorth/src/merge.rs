@@ -0,0 +1,236 @@
+//! Merging of several impressions of the same finger into a single template for enrollment.
+
+use alloc::vec::Vec;
+
+use crate::math::{float, normalize_angle, rounded};
+use crate::parsing::RawMinutiaCombined;
+use crate::{
+    match_fingerprints_with, BozorthState, Fingerprint, Format, MatchConfig, PairHolder,
+    PruneOptions,
+};
+
+/// Options of `merge_templates`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MergeOptions {
+    /// Pruning of the impressions before they are matched to align them; the merged minutiae
+    /// are taken from the impressions before pruning.
+    pub prune: PruneOptions,
+    /// Impressions scoring lower against the first one are not merged, their alignment is not
+    /// reliable.
+    pub min_score: u32,
+    /// Aligned minutiae at most this many pixels apart are the same minutia.
+    pub max_distance: i32,
+    /// Aligned minutiae whose directions differ by more degrees are different minutiae.
+    pub max_angle: i32,
+    /// Maximum number of minutiae of the merged template, the ones of the highest quality;
+    /// 0 means no limit.
+    pub max_minutiae: u32,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            prune: PruneOptions::default(),
+            min_score: 20,
+            max_distance: 8,
+            max_angle: 30,
+            max_minutiae: 150,
+        }
+    }
+}
+
+/// Rigid transformation of the minutiae of one impression into the frame of another.
+#[derive(Debug, Copy, Clone)]
+struct Alignment {
+    /// Rotation of the coordinates around the centroid of the matched minutiae, in radians.
+    rotation: f32,
+    /// Rotation of the minutia directions, in degrees.
+    direction: i32,
+    from: (f32, f32),
+    to: (f32, f32),
+}
+
+impl Alignment {
+    /// Least-squares alignment of the minutiae of the matched pairs of the last match in the
+    /// `state`, from the gallery onto the probe; `None` with fewer than 2 pairs.
+    fn of_match(state: &BozorthState, probe: &Fingerprint, gallery: &Fingerprint) -> Option<Self> {
+        let pairs: Vec<_> = state
+            .matched_pairs()
+            .iter()
+            .map(|&(p, g)| {
+                (
+                    &probe.minutiae[p.as_usize()],
+                    &gallery.minutiae[g.as_usize()],
+                )
+            })
+            .collect();
+        if pairs.len() < 2 {
+            return None;
+        }
+
+        let count = pairs.len() as f32;
+        let centroid = |points: &mut dyn Iterator<Item = (i32, i32)>| {
+            let (x, y) = points.fold((0.0, 0.0), |(x, y), it| (x + it.0 as f32, y + it.1 as f32));
+            (x / count, y / count)
+        };
+        let to = centroid(&mut pairs.iter().map(|(p, _)| (p.x, p.y)));
+        let from = centroid(&mut pairs.iter().map(|(_, g)| (g.x, g.y)));
+
+        let (mut dot, mut cross) = (0.0, 0.0);
+        let (mut direction_sin, mut direction_cos) = (0.0, 0.0);
+        for (p, g) in &pairs {
+            let (px, py) = (p.x as f32 - to.0, p.y as f32 - to.1);
+            let (gx, gy) = (g.x as f32 - from.0, g.y as f32 - from.1);
+            dot += gx * px + gy * py;
+            cross += gx * py - gy * px;
+            // directions are averaged separately, they need not follow the coordinate axes
+            let (sin, cos) = float::sin_cos(((p.theta - g.theta).0 as f32).to_radians());
+            direction_sin += sin;
+            direction_cos += cos;
+        }
+        Some(Alignment {
+            rotation: float::atan2(cross, dot),
+            direction: rounded(float::atan2(direction_sin, direction_cos).to_degrees()),
+            from,
+            to,
+        })
+    }
+
+    fn apply(&self, minutia: &RawMinutiaCombined) -> RawMinutiaCombined {
+        let (sin, cos) = float::sin_cos(self.rotation);
+        let (x, y) = (
+            minutia.x as f32 - self.from.0,
+            minutia.y as f32 - self.from.1,
+        );
+        RawMinutiaCombined {
+            x: rounded(x * cos - y * sin + self.to.0),
+            y: rounded(x * sin + y * cos + self.to.1),
+            t: normalize_angle(minutia.t + self.direction),
+            ..*minutia
+        }
+    }
+}
+
+/// Merges impressions of the same finger into a template with the minutiae of all of them.
+///
+/// Every further impression is matched against the first one, aligned to it by the minutiae of
+/// the winning clusters and added; a minutia found in several impressions is kept once, with
+/// the highest quality. Impressions scoring below `MergeOptions::min_score` are skipped.
+pub fn merge_templates(
+    impressions: &[&[RawMinutiaCombined]],
+    options: &MergeOptions,
+) -> Vec<RawMinutiaCombined> {
+    let (reference, others) = match impressions.split_first() {
+        Some(it) => it,
+        None => return Vec::new(),
+    };
+    let config = MatchConfig {
+        strict: options.prune.strict,
+        ..MatchConfig::default()
+    };
+    let probe = Fingerprint::from_raw(reference, options.prune, Format::NistInternal);
+    let (mut pairs, mut state) = (PairHolder::new(), BozorthState::new());
+
+    let mut merged = reference.to_vec();
+    for impression in others {
+        let gallery = Fingerprint::from_raw(impression, options.prune, Format::NistInternal);
+        let score = match_fingerprints_with(
+            &probe,
+            &gallery,
+            Format::NistInternal,
+            &mut pairs,
+            &mut state,
+            &config,
+        );
+        let alignment = match Alignment::of_match(&state, &probe, &gallery) {
+            Some(alignment) if score >= options.min_score => alignment,
+            _ => {
+                #[cfg(feature = "log")]
+                log::debug!(score; "impression not aligned, skipped");
+                continue;
+            }
+        };
+
+        for minutia in impression.iter().map(|it| alignment.apply(it)) {
+            let same = merged.iter_mut().find(|it| {
+                (it.x - minutia.x).pow(2) + (it.y - minutia.y).pow(2) <= options.max_distance.pow(2)
+                    && normalize_angle(it.t - minutia.t).abs() <= options.max_angle
+            });
+            match same {
+                Some(same) if same.q < minutia.q => *same = minutia,
+                Some(_) => {}
+                None => merged.push(minutia),
+            }
+        }
+    }
+
+    if options.max_minutiae > 0 && merged.len() > options.max_minutiae as usize {
+        // stable, so minutiae of equal quality are kept in the order of the impressions
+        merged.sort_by_key(|it| -it.q);
+        merged.truncate(options.max_minutiae as usize);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{merge_templates, MergeOptions};
+    use crate::parsing::RawMinutiaCombined;
+    use crate::synthetic::Generator;
+    use crate::{match_fingerprints, BozorthState, Fingerprint, Format, PairHolder, PruneOptions};
+
+    fn score(probe: &[RawMinutiaCombined], gallery: &[RawMinutiaCombined]) -> u32 {
+        let options = PruneOptions::default();
+        match_fingerprints(
+            &Fingerprint::from_raw(probe, options, Format::NistInternal),
+            &Fingerprint::from_raw(gallery, options, Format::NistInternal),
+            Format::NistInternal,
+            &mut PairHolder::new(),
+            &mut BozorthState::new(),
+        )
+    }
+
+    #[test]
+    fn merged_impressions_score_higher_than_any_of_them() {
+        let mut generator = Generator::new(17);
+        let finger = generator.raw_template(90);
+        // every impression sees another part of the finger
+        let part = |left: i32, top: i32| -> Vec<_> {
+            finger
+                .iter()
+                .copied()
+                .filter(|it| it.x >= left && it.x < left + 250 && it.y >= top && it.y < top + 250)
+                .collect()
+        };
+        let impressions = [
+            generator.impression(&part(50, 50), 0, (0, 0)),
+            generator.impression(&part(200, 50), 12, (15, -10)),
+            generator.impression(&part(50, 200), -9, (-12, 8)),
+        ];
+        let fourth = generator.impression(&part(125, 125), 5, (6, 4));
+
+        let views: Vec<&[RawMinutiaCombined]> = impressions.iter().map(|it| &it[..]).collect();
+        let merged = merge_templates(&views, &MergeOptions::default());
+        assert!(merged.len() > impressions[0].len());
+
+        let merged_score = score(&merged, &fourth);
+        for impression in &impressions {
+            let single = score(impression, &fourth);
+            assert!(merged_score > single, "{} <= {}", merged_score, single);
+        }
+    }
+
+    #[test]
+    fn unrelated_impressions_are_not_merged() {
+        let mut generator = Generator::new(18);
+        let reference = generator.raw_template(40);
+        let unrelated = generator.raw_template(40);
+
+        let merged = merge_templates(&[&reference, &unrelated], &MergeOptions::default());
+        let positions = |minutiae: &[RawMinutiaCombined]| -> Vec<_> {
+            minutiae.iter().map(|it| (it.x, it.y, it.t, it.q)).collect()
+        };
+        assert_eq!(positions(&merged), positions(&reference));
+        assert!(merge_templates(&[], &MergeOptions::default()).is_empty());
+    }
+}
@@ -0,0 +1,114 @@
+//! Scores relative to the self-scores of the matched templates.
+//!
+//! Raw scores grow with the number of minutiae, so a score of a rich template is not comparable
+//! to a score of a poor one; dividing by the scores of the templates matched with themselves
+//! makes them comparable.
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::math::float;
+
+/// Which self-score a score is divided by.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Normalization {
+    /// Lower of the self-scores, as the original `match` tool does.
+    Min,
+    /// Higher of the self-scores.
+    Max,
+    /// Geometric mean of the self-scores.
+    GeometricMean,
+    /// No normalization, the raw score is returned as a float.
+    None,
+}
+
+impl Normalization {
+    /// Normalizes `score` by the self-scores of the probe and the gallery.
+    ///
+    /// Normalized scores are clamped to the range 0-1. Returns `None` when the divisor is 0,
+    /// which happens when a template does not match itself.
+    pub fn apply(self, score: u32, self_scores: (u32, u32)) -> Option<f32> {
+        let (probe, gallery) = self_scores;
+        let divisor = match self {
+            Normalization::Min => probe.min(gallery) as f32,
+            Normalization::Max => probe.max(gallery) as f32,
+            Normalization::GeometricMean => float::sqrt(probe as f32 * gallery as f32),
+            Normalization::None => return Some(score as f32),
+        };
+        if divisor == 0.0 {
+            return None;
+        }
+        Some((score as f32 / divisor).clamp(0.0, 1.0))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Normalization::Min => "min",
+            Normalization::Max => "max",
+            Normalization::GeometricMean => "geomean",
+            Normalization::None => "none",
+        }
+    }
+}
+
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "min" => Ok(Normalization::Min),
+            "max" => Ok(Normalization::Max),
+            "geomean" => Ok(Normalization::GeometricMean),
+            "none" => Ok(Normalization::None),
+            _ => Err(format!("unknown normalization: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalization::Normalization;
+
+    #[test]
+    fn strategies_divide_by_their_self_score() {
+        let self_scores = (100, 400);
+        let cases = [
+            (Normalization::Min, 0.5),
+            (Normalization::Max, 0.125),
+            (Normalization::GeometricMean, 0.25),
+            (Normalization::None, 50.0),
+        ];
+        for &(normalization, expected) in &cases {
+            assert_eq!(normalization.apply(50, self_scores), Some(expected));
+            assert_eq!(normalization.name().parse(), Ok(normalization));
+        }
+
+        // a score above the self-score is clamped, the raw score is not
+        assert_eq!(Normalization::Min.apply(150, self_scores), Some(1.0));
+        assert_eq!(Normalization::None.apply(150, self_scores), Some(150.0));
+        assert!("mean".parse::<Normalization>().is_err());
+    }
+
+    #[test]
+    fn zero_self_score_has_no_normalized_score() {
+        for &normalization in &[
+            Normalization::Min,
+            Normalization::Max,
+            Normalization::GeometricMean,
+        ] {
+            assert_eq!(normalization.apply(0, (0, 0)), None);
+        }
+        assert_eq!(Normalization::Min.apply(0, (0, 30)), None);
+        assert_eq!(Normalization::GeometricMean.apply(0, (0, 30)), None);
+        assert_eq!(Normalization::Max.apply(0, (0, 30)), Some(0.0));
+        assert_eq!(Normalization::None.apply(7, (0, 0)), Some(7.0));
+    }
+}
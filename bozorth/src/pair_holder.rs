@@ -1,7 +1,11 @@
-use std::ops::Range;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
 
+use crate::config::MatchConfig;
 use crate::consts::MAX_NUMBER_OF_MINUTIAE;
 use crate::consts::MAX_NUMBER_OF_PAIRS;
+use crate::memory::{heap_size, PairHolderMemory, ShrinkLimits};
 use crate::types::Endpoint;
 use crate::{timeit, Pair};
 
@@ -42,6 +46,10 @@ pub struct PairHolder {
     forward_ranges: Vec<SmallOptionalRange>,
     backward: Vec<u32>,
     backward_ranges: Vec<SmallOptionalRange>,
+    /// Sort keys reused between calls of `prepare_with`.
+    keys: Vec<u64>,
+    /// Reordered pairs, swapped with `forward` after sorting.
+    scratch: Vec<Pair>,
     dirty: bool,
 }
 
@@ -58,6 +66,8 @@ impl PairHolder {
                 SmallOptionalRange::empty();
                 MAX_NUMBER_OF_MINUTIAE * MAX_NUMBER_OF_MINUTIAE
             ],
+            keys: Vec::with_capacity(MAX_NUMBER_OF_PAIRS),
+            scratch: Vec::with_capacity(MAX_NUMBER_OF_PAIRS),
             dirty: false,
         }
     }
@@ -92,6 +102,28 @@ impl PairHolder {
         self.dirty = false;
     }
 
+    /// Bytes held by the pairs and the lookup tables, unused capacity included.
+    pub fn memory_usage(&self) -> PairHolderMemory {
+        PairHolderMemory {
+            pairs: heap_size(&self.forward)
+                + heap_size(&self.backward)
+                + heap_size(&self.keys)
+                + heap_size(&self.scratch),
+            ranges: heap_size(&self.forward_ranges) + heap_size(&self.backward_ranges),
+        }
+    }
+
+    /// Clears the pairs and trims the capacities of the pair buffers back to `limits.pairs`.
+    pub fn shrink(&mut self, limits: &ShrinkLimits) {
+        self.clear();
+        self.forward.shrink_to(limits.pairs);
+        self.backward.shrink_to(limits.pairs);
+        self.keys.clear();
+        self.keys.shrink_to(limits.pairs);
+        self.scratch.clear();
+        self.scratch.shrink_to(limits.pairs);
+    }
+
     #[inline]
     pub(crate) fn push(&mut self, pair: Pair) {
         self.forward.push(pair);
@@ -99,29 +131,48 @@ impl PairHolder {
     }
 
     pub fn prepare(&mut self) {
+        self.prepare_with(&MatchConfig::default());
+    }
+
+    /// Prepares pairs for lookups, honoring options of the `config`.
+    pub fn prepare_with(&mut self, config: &MatchConfig) {
         if !self.dirty {
             return;
         }
 
+        let with_gallery_j = config.dedup_pairs;
         timeit(|| {
-            self.forward
-                .sort_by_key(|pair| (pair.probe_k, pair.gallery_k, pair.probe_j));
-        });
-        timeit(|| self.backward.clear());
-        timeit(|| {
-            for index in 0..self.forward.len() {
-                self.backward.push(index as u32);
-            }
+            self.keys.clear();
+            self.keys.extend(
+                self.forward
+                    .iter()
+                    .enumerate()
+                    .map(|(index, pair)| forward_key(pair, with_gallery_j, index)),
+            );
+            self.keys.sort_unstable();
+
+            self.scratch.clear();
+            let forward = &self.forward;
+            self.scratch
+                .extend(self.keys.iter().map(|&key| forward[key_index(key)]));
+            core::mem::swap(&mut self.forward, &mut self.scratch);
         });
+        if config.dedup_pairs {
+            timeit(|| dedup_keeping_max_points(&mut self.forward));
+        }
         timeit(|| {
-            self.backward.sort_by_key({
-                let forward = &self.forward;
-                move |&index| {
-                    let index = index as usize;
-                    let pair = &forward[index];
-                    (pair.probe_j, pair.gallery_j)
-                }
-            });
+            self.keys.clear();
+            self.keys.extend(
+                self.forward
+                    .iter()
+                    .enumerate()
+                    .map(|(index, pair)| backward_key(pair, index)),
+            );
+            self.keys.sort_unstable();
+
+            self.backward.clear();
+            self.backward
+                .extend(self.keys.iter().map(|&key| key_index(key) as u32));
         });
         timeit(|| {
             make_range_cache(&self.forward, &mut self.forward_ranges, |pair| {
@@ -203,6 +254,61 @@ impl PairHolder {
     }
 }
 
+// Every endpoint takes 8 bits of a sort key, the lower 32 bits hold the index of the pair.
+const _: () = assert!(MAX_NUMBER_OF_MINUTIAE <= 1 << 8);
+
+/// Packs endpoints of the pair and its current index into a single sort key.
+///
+/// The index is the least significant part of the key, so sorting the keys with an unstable
+/// sort gives exactly the same order as a stable sort by the endpoints alone.
+#[inline]
+fn pack_key(endpoints: [Endpoint; 4], index: usize) -> u64 {
+    let endpoints = endpoints
+        .iter()
+        .fold(0u64, |key, endpoint| (key << 8) | endpoint.0 as u64);
+    (endpoints << 32) | index as u64
+}
+
+#[inline]
+fn key_index(key: u64) -> usize {
+    (key & u32::MAX as u64) as usize
+}
+
+/// Key of the forward order: `(probe_k, gallery_k, probe_j)`, and `gallery_j` if requested.
+#[inline]
+fn forward_key(pair: &Pair, with_gallery_j: bool, index: usize) -> u64 {
+    let gallery_j = if with_gallery_j {
+        pair.gallery_j
+    } else {
+        Endpoint(0)
+    };
+    pack_key(
+        [pair.probe_k, pair.gallery_k, pair.probe_j, gallery_j],
+        index,
+    )
+}
+
+/// Key of the backward order: `(probe_j, gallery_j)`.
+#[inline]
+fn backward_key(pair: &Pair, index: usize) -> u64 {
+    pack_key(
+        [pair.probe_j, pair.gallery_j, Endpoint(0), Endpoint(0)],
+        index,
+    )
+}
+
+/// Collapses runs of sorted pairs with identical endpoints into the one with the most points.
+fn dedup_keeping_max_points(pairs: &mut Vec<Pair>) {
+    pairs.dedup_by(|next, kept| {
+        let same = (next.probe_k, next.gallery_k, next.probe_j, next.gallery_j)
+            == (kept.probe_k, kept.gallery_k, kept.probe_j, kept.gallery_j);
+        if same && next.points > kept.points {
+            *kept = *next;
+        }
+        same
+    });
+}
+
 #[inline]
 fn make_range_cache<T, F>(slice: &[T], ranges: &mut [SmallOptionalRange], extractor: F)
 where
@@ -244,3 +350,196 @@ fn left_trim_range(range: Range<usize>, offset: usize) -> Range<usize> {
         range
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::MatchConfig;
+    use crate::parsing::RawMinutiaCombined;
+    use crate::synthetic::Generator;
+    use crate::{
+        find_edges, limit_edges, match_edges_into_pairs, match_score, prune, BozorthState, Edge,
+        Format, Minutia, Pair, PairHolder, SortedEdges,
+    };
+
+    fn edges_of(minutiae: &[Minutia]) -> Vec<Edge> {
+        let mut edges = vec![];
+        find_edges(minutiae, &mut edges, Format::NistInternal);
+        edges.truncate(limit_edges(&edges));
+        edges
+    }
+
+    fn run(
+        probe: (&[Minutia], &[Edge]),
+        gallery: (&[Minutia], &[Edge]),
+        dedup_pairs: bool,
+    ) -> (Vec<(u32, u32, u32, u32)>, u32) {
+        let config = MatchConfig {
+            dedup_pairs,
+            ..MatchConfig::default()
+        };
+        let mut pairs = PairHolder::new();
+        match_edges_into_pairs(
+            SortedEdges::from_sorted_unchecked(probe.1),
+            probe.0,
+            SortedEdges::from_sorted_unchecked(gallery.1),
+            gallery.0,
+            &mut pairs,
+            &config,
+            |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+        );
+        pairs.prepare_with(&config);
+
+        let endpoints = pairs
+            .pairs()
+            .iter()
+            .map(|it| (it.probe_k.0, it.gallery_k.0, it.probe_j.0, it.gallery_j.0))
+            .collect();
+        let score = match_score(
+            &pairs,
+            probe.0,
+            gallery.0,
+            Format::NistInternal,
+            &mut BozorthState::new(),
+        )
+        .unwrap()
+        .0;
+        (endpoints, score)
+    }
+
+    #[test]
+    fn dedup_collapses_repeated_edges() {
+        let mut generator = Generator::new(11);
+        let template = generator.raw_template(40);
+        let impression = generator.impression(&template, 5, (10, -5));
+        let probe = prune(&template, 150);
+        let gallery = prune(&impression, 150);
+        let probe_edges = edges_of(&probe);
+        let gallery_edges = edges_of(&gallery);
+
+        // `find_edges` never produces the same pair twice, so dedup changes nothing
+        let plain = run((&probe, &probe_edges), (&gallery, &gallery_edges), false);
+        let deduped = run((&probe, &probe_edges), (&gallery, &gallery_edges), true);
+        assert_eq!(plain.1, deduped.1);
+        assert_eq!(plain.0.len(), deduped.0.len());
+
+        // an externally built edge list with every edge repeated inflates the pairs
+        let repeated: Vec<Edge> = gallery_edges.iter().flat_map(|&it| vec![it, it]).collect();
+        let inflated = run((&probe, &probe_edges), (&gallery, &repeated), false);
+        let collapsed = run((&probe, &probe_edges), (&gallery, &repeated), true);
+
+        assert_eq!(inflated.0.len(), 2 * plain.0.len());
+        assert_eq!(collapsed.0.len(), plain.0.len());
+        let mut unique = collapsed.0.clone();
+        unique.dedup();
+        assert_eq!(unique, collapsed.0);
+        assert!(collapsed.1 <= inflated.1);
+        assert_eq!(
+            collapsed.1,
+            run((&probe, &probe_edges), (&gallery, &repeated), true).1
+        );
+    }
+
+    #[test]
+    fn dedup_lowers_scores_of_duplicated_minutiae() {
+        let mut generator = Generator::new(11);
+        let template = generator.raw_template(40);
+        let impression = generator.impression(&template, 5, (10, -5));
+
+        // some extractors emit the same minutia more than once; copies on both sides pair up
+        // with each other as well as with the originals
+        let duplicated = |raw: &[RawMinutiaCombined]| {
+            let mut minutiae = raw.to_vec();
+            minutiae.extend_from_slice(&raw[..12]);
+            let minutiae = prune(&minutiae, 150);
+            assert_eq!(minutiae.len(), raw.len() + 12);
+            minutiae
+        };
+        let probe = duplicated(&template);
+        let probe_edges = edges_of(&probe);
+        let gallery = duplicated(&impression);
+        let gallery_edges = edges_of(&gallery);
+
+        let inflated = run((&probe, &probe_edges), (&gallery, &gallery_edges), false);
+        let collapsed = run((&probe, &probe_edges), (&gallery, &gallery_edges), true);
+        assert!(collapsed.0.len() < inflated.0.len());
+        assert!(
+            collapsed.1 < inflated.1,
+            "{} is not below {}",
+            collapsed.1,
+            inflated.1
+        );
+        assert_eq!(
+            collapsed,
+            run((&probe, &probe_edges), (&gallery, &gallery_edges), true)
+        );
+
+        // without duplicates dedup keeps every pair
+        let probe = prune(&template, 150);
+        let probe_edges = edges_of(&probe);
+        let gallery = prune(&impression, 150);
+        let gallery_edges = edges_of(&gallery);
+        let mut plain = run((&probe, &probe_edges), (&gallery, &gallery_edges), false);
+        let mut deduped = run((&probe, &probe_edges), (&gallery, &gallery_edges), true);
+        plain.0.sort_unstable();
+        deduped.0.sort_unstable();
+        assert_eq!(plain, deduped);
+    }
+
+    fn endpoints(pair: &Pair) -> (u32, u32, u32, u32) {
+        (
+            pair.probe_k.0,
+            pair.gallery_k.0,
+            pair.probe_j.0,
+            pair.gallery_j.0,
+        )
+    }
+
+    /// Order of pairs produced by the original implementation of `prepare`.
+    fn reference_order(pairs: &[Pair]) -> (Vec<Pair>, Vec<u32>) {
+        let mut forward = pairs.to_vec();
+        forward.sort_by_key(|pair| (pair.probe_k, pair.gallery_k, pair.probe_j));
+        let mut backward: Vec<u32> = (0..forward.len() as u32).collect();
+        backward.sort_by_key(|&index| {
+            let pair = &forward[index as usize];
+            (pair.probe_j, pair.gallery_j)
+        });
+        (forward, backward)
+    }
+
+    #[test]
+    fn packed_keys_preserve_the_original_order() {
+        let mut generator = Generator::new(17);
+        let mut pairs = PairHolder::new();
+        for _ in 0..6 {
+            let count = 60 + generator.next(60) as usize;
+            let rotation = generator.next(30) - 15;
+            let template = generator.raw_template(count);
+            let impression = generator.impression(&template, rotation, (5, 5));
+            let other = generator.raw_template(80);
+
+            let probe = prune(&template, 150);
+            let probe_edges = edges_of(&probe);
+            for gallery in [prune(&impression, 150), prune(&other, 150)] {
+                let gallery_edges = edges_of(&gallery);
+
+                pairs.clear();
+                match_edges_into_pairs(
+                    SortedEdges::from_sorted_unchecked(&probe_edges),
+                    &probe,
+                    SortedEdges::from_sorted_unchecked(&gallery_edges),
+                    &gallery,
+                    &mut pairs,
+                    &MatchConfig::default(),
+                    |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+                );
+                let (forward, backward) = reference_order(pairs.pairs());
+                pairs.prepare();
+
+                let actual: Vec<_> = pairs.pairs().iter().map(endpoints).collect();
+                let expected: Vec<_> = forward.iter().map(endpoints).collect();
+                assert_eq!(actual, expected);
+                assert_eq!(pairs.backward, backward);
+            }
+        }
+    }
+}
@@ -1,10 +1,110 @@
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::ffi::OsStr;
+#[cfg(feature = "std")]
 use std::fs;
-use std::io;
-use std::io::BufRead;
-use std::path::Path;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
 
+use crate::math::float;
 use crate::types::MinutiaKind;
 
+/// Error of parsing .xyt or .min content.
+#[derive(Debug)]
+pub enum ParsingError {
+    /// The file could not be read.
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    /// A line could not be parsed; lines are numbered from 1 within the parsed content.
+    InvalidLine { line: usize, content: String },
+    /// The content has more minutiae than the limit it was parsed with.
+    TooManyMinutiae { limit: usize },
+    /// Fewer minutiae than `required` are left after pruning, too few to be matched.
+    TooFewMinutiae { found: usize, required: usize },
+}
+
+impl fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            ParsingError::Io(e) => write!(f, "cannot read file: {}", e),
+            ParsingError::InvalidLine { line, content } => {
+                write!(f, "invalid line {}: {:?}", line, content)
+            }
+            ParsingError::TooManyMinutiae { limit } => {
+                write!(f, "more than {} minutiae", limit)
+            }
+            ParsingError::TooFewMinutiae { found, required } => {
+                write!(f, "{} minutiae, at least {} are needed", found, required)
+            }
+        }
+    }
+}
+
+impl core::error::Error for ParsingError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            ParsingError::Io(e) => Some(e),
+            ParsingError::InvalidLine { .. }
+            | ParsingError::TooManyMinutiae { .. }
+            | ParsingError::TooFewMinutiae { .. } => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for ParsingError {
+    fn from(e: io::Error) -> Self {
+        ParsingError::Io(e)
+    }
+}
+
+/// First bytes of a gzip stream.
+#[cfg(feature = "std")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Reads a text file, decompressing it while reading when it starts with the gzip magic bytes.
+#[cfg(feature = "std")]
+fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut magic = vec![];
+    file.by_ref()
+        .take(GZIP_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    let mut reader = magic.as_slice().chain(file);
+
+    let mut content = String::new();
+    if magic == GZIP_MAGIC {
+        flate2::read::MultiGzDecoder::new(reader).read_to_string(&mut content)?;
+    } else {
+        reader.read_to_string(&mut content)?;
+    }
+    Ok(content)
+}
+
+/// The .min file next to a .xyt file, if there is one; `f.xyt.gz` has `f.min` or `f.min.gz`.
+#[cfg(feature = "std")]
+fn min_path_next_to(xyt_path: &Path) -> Option<PathBuf> {
+    let min_path = if xyt_path.extension() == Some(OsStr::new("gz")) {
+        xyt_path.with_extension("").with_extension("min")
+    } else {
+        xyt_path.with_extension("min")
+    };
+    if min_path.exists() {
+        return Some(min_path);
+    }
+    Some(min_path.with_extension("min.gz")).filter(|it| it.exists())
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct RawMinutia {
     pub(crate) x: i32,
@@ -13,17 +113,63 @@ pub struct RawMinutia {
     pub(crate) q: i32,
 }
 
-pub fn parse_xyt(path: impl AsRef<Path>) -> Result<Vec<RawMinutia>, io::Error> {
-    let file = fs::File::open(path)?;
-    let reader = io::BufReader::new(file);
+#[cfg(feature = "std")]
+pub fn parse_xyt(path: impl AsRef<Path>) -> Result<Vec<RawMinutia>, ParsingError> {
+    parse_xyt_str(&read_to_string(path)?)
+}
+
+/// Largest absolute value of a coordinate accepted in a .xyt file, the largest coordinate of
+/// ISO/IEC 19794-2 as well.
+///
+/// Squared distances of minutiae within this range fit in `i32`.
+pub const MAX_COORDINATE: i32 = (1 << 14) - 1;
+
+/// Largest absolute value of an angle accepted in a .xyt file.
+pub const MAX_ANGLE: i32 = 360;
+
+/// Largest quality accepted in a .xyt file; qualities cannot be negative.
+pub const MAX_QUALITY: i32 = 255;
+
+/// Parses the content of a .xyt file: `x y theta [quality]` on every line.
+///
+/// Lines with values out of the ranges above are invalid, so that content from untrusted
+/// sources cannot overflow the arithmetic of matching.
+pub fn parse_xyt_str(content: &str) -> Result<Vec<RawMinutia>, ParsingError> {
+    parse_xyt_str_with_limit(content, usize::MAX)
+}
+
+/// Same as `parse_xyt_str`, but fails on content with more than `max_minutiae` lines before
+/// parsing the lines over the limit.
+pub fn parse_xyt_str_with_limit(
+    content: &str,
+    max_minutiae: usize,
+) -> Result<Vec<RawMinutia>, ParsingError> {
     let mut minutiae = vec![];
-    for line in reader.lines() {
-        let line = line?;
-        let mut parts = line.split(' ').map(|it| it.parse::<i32>().unwrap());
-        let x = parts.next().unwrap();
-        let y = parts.next().unwrap();
-        let t = parts.next().unwrap();
-        let q = parts.next().unwrap_or(0);
+    for (i, line) in content.lines().enumerate() {
+        if i >= max_minutiae {
+            return Err(ParsingError::TooManyMinutiae {
+                limit: max_minutiae,
+            });
+        }
+        let invalid = || ParsingError::InvalidLine {
+            line: i + 1,
+            content: line.to_owned(),
+        };
+        let mut parts = line
+            .split(' ')
+            .map(|it| it.parse::<i32>().map_err(|_| invalid()));
+        let x = parts.next().ok_or_else(invalid)??;
+        let y = parts.next().ok_or_else(invalid)??;
+        let t = parts.next().ok_or_else(invalid)??;
+        let q = parts.next().transpose()?.unwrap_or(0);
+        let coordinates = -MAX_COORDINATE..=MAX_COORDINATE;
+        if !coordinates.contains(&x)
+            || !coordinates.contains(&y)
+            || !(-MAX_ANGLE..=MAX_ANGLE).contains(&t)
+            || !(0..=MAX_QUALITY).contains(&q)
+        {
+            return Err(invalid());
+        }
 
         minutiae.push(RawMinutia { x, y, t, q });
     }
@@ -31,37 +177,184 @@ pub fn parse_xyt(path: impl AsRef<Path>) -> Result<Vec<RawMinutia>, io::Error> {
     Ok(minutiae)
 }
 
+/// Range and unit of the angles of a .xyt file.
+///
+/// `parse` assumes degrees; `normalize_angles` maps any convention to the internal range of
+/// -179..=180 degrees.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AngleConvention {
+    /// Degrees within -179..=180, the NIST internal convention kept by `parse`.
+    Signed,
+    /// Degrees within 0..360.
+    Unsigned,
+    /// Units of 360/256 degrees within 0..=255, as in ISO/IEC 19794-2 templates.
+    IsoUnits,
+}
+
+/// Fewest minutiae for which angles within 0..=255 are taken for ISO units; with fewer, the
+/// lack of angles above 255 is too likely to be chance.
+pub const MIN_MINUTIAE_FOR_ISO_UNITS: usize = 20;
+
+impl AngleConvention {
+    pub fn name(self) -> &'static str {
+        match self {
+            AngleConvention::Signed => "signed",
+            AngleConvention::Unsigned => "unsigned",
+            AngleConvention::IsoUnits => "iso",
+        }
+    }
+
+    /// The angle `t` in degrees within -179..=180.
+    fn normalize(self, t: i32) -> i32 {
+        let degrees = match self {
+            AngleConvention::Signed | AngleConvention::Unsigned => t,
+            AngleConvention::IsoUnits => (t * 45 + 16) / 32,
+        };
+        let degrees = degrees.rem_euclid(360);
+        if degrees > 180 {
+            degrees - 360
+        } else {
+            degrees
+        }
+    }
+}
+
+impl fmt::Display for AngleConvention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AngleConvention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "signed" => Ok(AngleConvention::Signed),
+            "unsigned" => Ok(AngleConvention::Unsigned),
+            "iso" => Ok(AngleConvention::IsoUnits),
+            _ => Err(format!("unknown angle convention: {}", s)),
+        }
+    }
+}
+
+/// Guesses the angle convention of `minutiae` from the range of their angles.
+///
+/// Negative angles mean `Signed` and angles above 255 `Unsigned`. Angles within 0..=255 with
+/// some above 180 mean `IsoUnits` when there are at least `MIN_MINUTIAE_FOR_ISO_UNITS`
+/// minutiae. Anything else is `Unsigned`, which maps angles within 0..=180 like `Signed`.
+pub fn detect_angle_convention(minutiae: &[RawMinutia]) -> AngleConvention {
+    let min = minutiae.iter().map(|it| it.t).min().unwrap_or(0);
+    let max = minutiae.iter().map(|it| it.t).max().unwrap_or(0);
+    if min < 0 {
+        AngleConvention::Signed
+    } else if max > 255 {
+        AngleConvention::Unsigned
+    } else if max > 180 && minutiae.len() >= MIN_MINUTIAE_FOR_ISO_UNITS {
+        AngleConvention::IsoUnits
+    } else {
+        AngleConvention::Unsigned
+    }
+}
+
+/// Converts the angles of `minutiae` from `convention` to degrees within -179..=180.
+pub fn normalize_angles(minutiae: &mut [RawMinutia], convention: AngleConvention) {
+    for minutia in minutiae {
+        minutia.t = convention.normalize(minutia.t);
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct RawMinutiaExtended {
     pub(crate) kind: MinutiaKind,
+    /// Reliability of `mindtct` scaled to the range of .xyt qualities, like `mindtct` does
+    /// when it writes the .xyt file.
+    pub(crate) q: i32,
 }
 
-pub fn parse_min(xyt_path: impl AsRef<Path>) -> Result<Vec<RawMinutiaExtended>, io::Error> {
-    let file = fs::File::open(xyt_path)?;
-    let reader = io::BufReader::new(file);
+#[cfg(feature = "std")]
+pub fn parse_min(min_path: impl AsRef<Path>) -> Result<Vec<RawMinutiaExtended>, ParsingError> {
+    parse_min_str(&read_to_string(min_path)?)
+}
 
+/// Parses the content of a .min file written by `mindtct`; the first 4 lines are a header.
+///
+/// Reliabilities must be within `0.0..=1.0`.
+pub fn parse_min_str(content: &str) -> Result<Vec<RawMinutiaExtended>, ParsingError> {
+    parse_min_str_with_limit(content, usize::MAX)
+}
+
+fn parse_min_str_with_limit(
+    content: &str,
+    max_minutiae: usize,
+) -> Result<Vec<RawMinutiaExtended>, ParsingError> {
     let mut minutiae = vec![];
-    for line in reader.lines().skip(4) {
-        let line = line?;
-        let mut columns = line.split(':');
-        let _index = columns.next().unwrap();
-        let _position = columns.next().unwrap();
-        let _feature_id = columns.next().unwrap();
-        let _reliability = columns.next().unwrap();
-        let kind = columns.next().unwrap();
-        let _mode = columns.next().unwrap();
+    for (i, line) in content.lines().enumerate().skip(4) {
+        if i - 4 >= max_minutiae {
+            return Err(ParsingError::TooManyMinutiae {
+                limit: max_minutiae,
+            });
+        }
+        let invalid = || ParsingError::InvalidLine {
+            line: i + 1,
+            content: line.to_owned(),
+        };
+        // index : position : feature id : reliability : kind : mode
+        let columns: Vec<_> = line.split(':').collect();
+        if columns.len() < 6 {
+            return Err(invalid());
+        }
+        let reliability = columns[3].trim().parse::<f32>().map_err(|_| invalid())?;
+        if !(0.0..=1.0).contains(&reliability) {
+            return Err(invalid());
+        }
         minutiae.push(RawMinutiaExtended {
-            kind: match kind.trim() {
-                "RIG" => MinutiaKind::Type0,
-                "BIF" => MinutiaKind::Type1,
-                _ => unimplemented!(),
+            kind: match columns[4].trim() {
+                "RIG" => MinutiaKind::Ending,
+                "BIF" => MinutiaKind::Bifurcation,
+                _ => MinutiaKind::Unknown,
             },
+            q: float::round(reliability * 100.0) as i32,
         })
     }
 
     Ok(minutiae)
 }
 
+/// Where the quality of a minutia comes from when both a .xyt and a .min file are parsed.
+///
+/// Extractors do not agree on the 4th column of .xyt files, so the two sources can differ.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum QualitySource {
+    /// The 4th column of the .xyt file, 0 when it is missing.
+    XytColumn,
+    /// The reliability column of the .min file times 100, 0 without a .min file.
+    MinReliability,
+    /// The larger of the two.
+    Max,
+    /// The .xyt column, or the .min reliability where the .xyt quality is 0.
+    PreferXyt,
+}
+
+impl Default for QualitySource {
+    /// The .xyt column, like before the .min reliability was read.
+    fn default() -> Self {
+        QualitySource::XytColumn
+    }
+}
+
+impl QualitySource {
+    fn select(self, xyt: i32, min: i32) -> i32 {
+        match self {
+            QualitySource::XytColumn => xyt,
+            QualitySource::MinReliability => min,
+            QualitySource::Max => xyt.max(min),
+            QualitySource::PreferXyt if xyt != 0 => xyt,
+            QualitySource::PreferXyt => min,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct RawMinutiaCombined {
     pub x: i32,
@@ -71,26 +364,466 @@ pub struct RawMinutiaCombined {
     pub kind: MinutiaKind,
 }
 
-pub fn parse(xyt_path: impl AsRef<Path>) -> Result<Vec<RawMinutiaCombined>, io::Error> {
+/// Parses a .xyt file together with the .min file next to it, if there is one.
+///
+/// Either of them may be compressed with gzip, e.g. `f.xyt.gz` with `f.min.gz` next to it.
+#[cfg(feature = "std")]
+pub fn parse(xyt_path: impl AsRef<Path>) -> Result<Vec<RawMinutiaCombined>, ParsingError> {
+    parse_with(xyt_path, QualitySource::default())
+}
+
+/// Same as `parse`, with the qualities taken from `quality_source`.
+#[cfg(feature = "std")]
+pub fn parse_with(
+    xyt_path: impl AsRef<Path>,
+    quality_source: QualitySource,
+) -> Result<Vec<RawMinutiaCombined>, ParsingError> {
     let xyt_path = xyt_path.as_ref();
-    let a = parse_xyt(xyt_path)?;
-    let mut min: Vec<_> = a
+    let xyt = read_to_string(xyt_path)?;
+    let min = min_path_next_to(xyt_path).map(read_to_string).transpose()?;
+
+    parse_combined_with(&xyt, min.as_deref(), quality_source)
+}
+
+/// Same as `parse_with`, with the angles converted from `angles`, or from the convention
+/// detected by `detect_angle_convention` when it is `None`.
+#[cfg(feature = "std")]
+pub fn parse_with_angles(
+    xyt_path: impl AsRef<Path>,
+    quality_source: QualitySource,
+    angles: Option<AngleConvention>,
+) -> Result<Vec<RawMinutiaCombined>, ParsingError> {
+    parse_with_limit(xyt_path, quality_source, angles, usize::MAX)
+}
+
+/// Same as `parse_with_angles`, but fails on files with more than `max_minutiae` minutiae, see
+/// `parse_xyt_str_with_limit`.
+#[cfg(feature = "std")]
+pub fn parse_with_limit(
+    xyt_path: impl AsRef<Path>,
+    quality_source: QualitySource,
+    angles: Option<AngleConvention>,
+    max_minutiae: usize,
+) -> Result<Vec<RawMinutiaCombined>, ParsingError> {
+    let xyt_path = xyt_path.as_ref();
+    let xyt = read_to_string(xyt_path)?;
+    let min = min_path_next_to(xyt_path).map(read_to_string).transpose()?;
+
+    parse_combined_with_limit(&xyt, min.as_deref(), quality_source, angles, max_minutiae)
+}
+
+/// Same as `parse`, but with the content of the .xyt and .min files.
+pub fn parse_combined(
+    xyt: &str,
+    min: Option<&str>,
+) -> Result<Vec<RawMinutiaCombined>, ParsingError> {
+    parse_combined_with(xyt, min, QualitySource::default())
+}
+
+/// Same as `parse_with`, but with the content of the .xyt and .min files.
+///
+/// Warns when every quality is 0, which makes the quality order of `prune` meaningless.
+pub fn parse_combined_with(
+    xyt: &str,
+    min: Option<&str>,
+    quality_source: QualitySource,
+) -> Result<Vec<RawMinutiaCombined>, ParsingError> {
+    combine(parse_xyt_str(xyt)?, min, quality_source)
+}
+
+/// Same as `parse_with_angles`, but with the content of the .xyt and .min files.
+pub fn parse_combined_with_angles(
+    xyt: &str,
+    min: Option<&str>,
+    quality_source: QualitySource,
+    angles: Option<AngleConvention>,
+) -> Result<Vec<RawMinutiaCombined>, ParsingError> {
+    parse_combined_with_limit(xyt, min, quality_source, angles, usize::MAX)
+}
+
+/// Same as `parse_with_limit`, but with the content of the .xyt and .min files.
+pub fn parse_combined_with_limit(
+    xyt: &str,
+    min: Option<&str>,
+    quality_source: QualitySource,
+    angles: Option<AngleConvention>,
+    max_minutiae: usize,
+) -> Result<Vec<RawMinutiaCombined>, ParsingError> {
+    let mut xyt = parse_xyt_str_with_limit(xyt, max_minutiae)?;
+    let angles = angles.unwrap_or_else(|| detect_angle_convention(&xyt));
+    normalize_angles(&mut xyt, angles);
+    let min = min
+        .map(|it| parse_min_str_with_limit(it, max_minutiae))
+        .transpose()?;
+    Ok(combine_parsed(xyt, min.unwrap_or_default(), quality_source))
+}
+
+fn combine(
+    xyt: Vec<RawMinutia>,
+    min: Option<&str>,
+    quality_source: QualitySource,
+) -> Result<Vec<RawMinutiaCombined>, ParsingError> {
+    let min = min.map(parse_min_str).transpose()?.unwrap_or_default();
+    Ok(combine_parsed(xyt, min, quality_source))
+}
+
+fn combine_parsed(
+    xyt: Vec<RawMinutia>,
+    min: Vec<RawMinutiaExtended>,
+    quality_source: QualitySource,
+) -> Vec<RawMinutiaCombined> {
+    let minutiae: Vec<_> = xyt
         .into_iter()
-        .map(|it| RawMinutiaCombined {
+        .enumerate()
+        .map(|(i, it)| RawMinutiaCombined {
             x: it.x,
             y: it.y,
             t: if it.t > 180 { it.t - 360 } else { it.t },
-            q: it.q,
-            kind: MinutiaKind::Type0,
+            q: quality_source.select(it.q, min.get(i).map_or(0, |it| it.q)),
+            // without a .min file every minutia is treated as a ridge ending, like before
+            kind: min.get(i).map_or(MinutiaKind::Ending, |it| it.kind),
         })
         .collect();
 
-    let min_path = xyt_path.with_extension("min");
-    if min_path.exists() {
-        for (i, m) in parse_min(min_path)?.into_iter().enumerate() {
-            min[i].kind = m.kind;
+    #[cfg(feature = "log")]
+    if !minutiae.is_empty() && minutiae.iter().all(|it| it.q == 0) {
+        log::warn!(
+            minutiae = minutiae.len(), source:? = quality_source;
+            "every minutia has quality 0"
+        );
+    }
+
+    minutiae
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "std")]
+    use std::path::{Path, PathBuf};
+
+    use crate::parsing::{
+        detect_angle_convention, normalize_angles, parse_combined, parse_combined_with,
+        parse_combined_with_angles, parse_combined_with_limit, parse_xyt_str,
+        parse_xyt_str_with_limit, AngleConvention, ParsingError, QualitySource, MAX_ANGLE,
+        MAX_COORDINATE, MAX_QUALITY, MIN_MINUTIAE_FOR_ISO_UNITS,
+    };
+    #[cfg(feature = "std")]
+    use crate::parsing::{parse, parse_xyt, RawMinutia, RawMinutiaCombined};
+
+    #[cfg(feature = "std")]
+    fn data(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/data")
+            .join(name)
+    }
+
+    #[cfg(feature = "std")]
+    fn fields(minutiae: &[RawMinutiaCombined]) -> Vec<String> {
+        minutiae.iter().map(|it| format!("{:?}", it)).collect()
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn string_parsing_matches_file_parsing() {
+        // f2_1 has a .min file next to it, f1_1 does not
+        for (xyt, min) in &[("f1_1.xyt", None), ("f2_1.xyt", Some("f2_1.min"))] {
+            let content = std::fs::read_to_string(data(xyt)).unwrap();
+            let min = min.map(|it| std::fs::read_to_string(data(it)).unwrap());
+
+            let from_file = parse(data(xyt)).unwrap();
+            let from_str = parse_combined(&content, min.as_deref()).unwrap();
+            assert_eq!(fields(&from_file), fields(&from_str));
+
+            let raw = |minutiae: Vec<RawMinutia>| -> Vec<_> {
+                minutiae.iter().map(|it| (it.x, it.y, it.t, it.q)).collect()
+            };
+            assert_eq!(
+                raw(parse_xyt(data(xyt)).unwrap()),
+                raw(parse_xyt_str(&content).unwrap())
+            );
+        }
+
+        let with_min = parse(data("f2_1.xyt")).unwrap();
+        let without_min =
+            parse_combined(&std::fs::read_to_string(data("f2_1.xyt")).unwrap(), None).unwrap();
+        assert_ne!(fields(&with_min), fields(&without_min));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn gzip_compressed_files_are_parsed_like_plain_ones() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let directory = std::env::temp_dir().join(format!("parsing-gzip-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let compress = |name: &str, to: &str| {
+            let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+            encoder
+                .write_all(&std::fs::read(data(name)).unwrap())
+                .unwrap();
+            std::fs::write(directory.join(to), encoder.finish().unwrap()).unwrap();
+        };
+        compress("f2_1.xyt", "f2_1.xyt.gz");
+        compress("f2_1.min", "f2_1.min.gz");
+        // detected by the content, not the name
+        compress("f2_1.xyt", "compressed.xyt");
+
+        let plain = fields(&parse(data("f2_1.xyt")).unwrap());
+        let with_min = parse(directory.join("f2_1.xyt.gz")).unwrap();
+        let without_min = parse(directory.join("compressed.xyt")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(fields(&with_min), plain);
+        assert_ne!(fields(&without_min), plain);
+        assert_eq!(without_min.len(), with_min.len());
+    }
+
+    #[test]
+    fn errors_carry_line_numbers_of_the_content() {
+        let error = parse_xyt_str("1 2 3 4\n5 6 7\n8 x 9\n").unwrap_err();
+        assert!(matches!(
+            error,
+            ParsingError::InvalidLine { line: 3, ref content } if content == "8 x 9"
+        ));
+        assert!(matches!(
+            parse_xyt_str("1 2\n"),
+            Err(ParsingError::InvalidLine { line: 1, .. })
+        ));
+
+        let header = "header\n\n\nindex : position : id : reliability : kind : mode\n";
+        let min = format!("{}0 : 1, 2 : 3 : 0.5 : BIF : LOOP\nbroken\n", header);
+        assert!(matches!(
+            parse_combined("1 2 3\n4 5 6\n", Some(&min)),
+            Err(ParsingError::InvalidLine { line: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn values_out_of_range_are_invalid() {
+        let valid = format!(
+            "{} {} {} {}\n-3 0 -{} 0\n",
+            MAX_COORDINATE, -MAX_COORDINATE, MAX_ANGLE, MAX_QUALITY, MAX_ANGLE
+        );
+        assert_eq!(parse_xyt_str(&valid).unwrap().len(), 2);
+
+        for line in &[
+            format!("{} 0 0", MAX_COORDINATE + 1),
+            format!("0 {} 0", i32::MIN),
+            format!("0 0 {}", MAX_ANGLE + 1),
+            format!("0 0 0 {}", MAX_QUALITY + 1),
+            "0 0 0 -1".to_owned(),
+        ] {
+            assert!(
+                matches!(
+                    parse_xyt_str(line),
+                    Err(ParsingError::InvalidLine { line: 1, .. })
+                ),
+                "{}",
+                line
+            );
         }
     }
 
-    Ok(min)
+    fn min_file(reliabilities: &[&str]) -> String {
+        let mut min = "header\n\n\nindex : position : id : reliability : kind : mode\n".to_owned();
+        for (i, reliability) in reliabilities.iter().enumerate() {
+            min += &format!("{} : 1, 2 : 3 : {} : RIG : APPEARING\n", i, reliability);
+        }
+        min
+    }
+
+    fn qualities(xyt: &str, min: Option<&str>, source: QualitySource) -> Vec<i32> {
+        parse_combined_with(xyt, min, source)
+            .unwrap()
+            .iter()
+            .map(|it| it.q)
+            .collect()
+    }
+
+    #[test]
+    fn quality_sources_resolve_conflicting_qualities() {
+        let xyt = "1 1 0 40\n2 2 0 0\n3 3 0 90\n4 4 0 15\n";
+        let min = min_file(&["0.100", "0.350", "0.900", "0.004"]);
+        let min = Some(min.as_str());
+
+        assert_eq!(
+            qualities(xyt, min, QualitySource::XytColumn),
+            [40, 0, 90, 15]
+        );
+        assert_eq!(
+            qualities(xyt, min, QualitySource::MinReliability),
+            [10, 35, 90, 0]
+        );
+        assert_eq!(qualities(xyt, min, QualitySource::Max), [40, 35, 90, 15]);
+        assert_eq!(
+            qualities(xyt, min, QualitySource::PreferXyt),
+            [40, 35, 90, 15]
+        );
+
+        assert_eq!(QualitySource::default(), QualitySource::XytColumn);
+        let default: Vec<_> = parse_combined(xyt, min)
+            .unwrap()
+            .iter()
+            .map(|it| it.q)
+            .collect();
+        assert_eq!(default, qualities(xyt, min, QualitySource::XytColumn));
+    }
+
+    #[test]
+    fn quality_sources_without_reliabilities() {
+        let xyt = "1 1 0 40\n2 2 0\n3 3 0 90\n";
+        // the .min file is shorter than the .xyt file
+        let min = min_file(&["0.500"]);
+
+        assert_eq!(
+            qualities(xyt, None, QualitySource::MinReliability),
+            [0, 0, 0]
+        );
+        assert_eq!(qualities(xyt, None, QualitySource::Max), [40, 0, 90]);
+        assert_eq!(
+            qualities(xyt, Some(&min), QualitySource::MinReliability),
+            [50, 0, 0]
+        );
+        assert_eq!(
+            qualities(xyt, Some(&min), QualitySource::PreferXyt),
+            [40, 0, 90]
+        );
+    }
+
+    #[test]
+    fn content_over_the_minutiae_limit_is_rejected() {
+        let xyt = "1 1 0 40\n2 2 0 0\n3 3 0 90\n";
+        assert_eq!(parse_xyt_str_with_limit(xyt, 3).unwrap().len(), 3);
+        assert!(matches!(
+            parse_xyt_str_with_limit(xyt, 2),
+            Err(ParsingError::TooManyMinutiae { limit: 2 })
+        ));
+        // lines over the limit are not parsed at all
+        assert!(matches!(
+            parse_xyt_str_with_limit("1 1 0\nnot a minutia\n", 1),
+            Err(ParsingError::TooManyMinutiae { limit: 1 })
+        ));
+
+        let parse = |min: &str, limit| {
+            parse_combined_with_limit(xyt, Some(min), QualitySource::default(), None, limit)
+        };
+        assert_eq!(
+            parse(&min_file(&["0.1", "0.2", "0.3"]), 3).unwrap().len(),
+            3
+        );
+        assert!(matches!(
+            parse(&min_file(&["0.1", "0.2", "0.3", "0.4"]), 3),
+            Err(ParsingError::TooManyMinutiae { limit: 3 })
+        ));
+    }
+
+    #[test]
+    fn reliabilities_out_of_range_are_invalid() {
+        for reliability in &["-0.1", "1.5", "high"] {
+            assert!(matches!(
+                parse_combined("1 2 3\n", Some(&min_file(&[reliability]))),
+                Err(ParsingError::InvalidLine { line: 5, .. })
+            ));
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn min_reliability_of_mindtct_matches_its_xyt_quality() {
+        // mindtct writes the reliability times 100 into the .xyt file
+        let xyt = std::fs::read_to_string(data("f2_1.xyt")).unwrap();
+        let min = std::fs::read_to_string(data("f2_1.min")).unwrap();
+        assert_eq!(
+            qualities(&xyt, Some(&min), QualitySource::MinReliability),
+            qualities(&xyt, Some(&min), QualitySource::XytColumn)
+        );
+    }
+
+    /// A .xyt file with `count` minutiae whose angles in degrees are written by `angle`.
+    fn xyt_with_angles(count: i32, angle: impl Fn(i32) -> i32) -> String {
+        (0..count)
+            .map(|i| format!("{} {} {} 50\n", i * 7, i * 11, angle(i * 37 % 360)))
+            .collect()
+    }
+
+    fn angles(xyt: &str, convention: Option<AngleConvention>) -> Vec<i32> {
+        parse_combined_with_angles(xyt, None, QualitySource::default(), convention)
+            .unwrap()
+            .iter()
+            .map(|it| it.t)
+            .collect()
+    }
+
+    #[test]
+    fn angle_conventions_are_detected_by_their_range() {
+        let detect = |xyt: &str| detect_angle_convention(&parse_xyt_str(xyt).unwrap());
+        let count = MIN_MINUTIAE_FOR_ISO_UNITS as i32;
+        let signed = |t: i32| if t > 180 { t - 360 } else { t };
+        let iso = |t: i32| (t * 256 + 180) / 360 % 256;
+
+        assert_eq!(
+            detect(&xyt_with_angles(count, signed)),
+            AngleConvention::Signed
+        );
+        assert_eq!(
+            detect(&xyt_with_angles(count, |t| t)),
+            AngleConvention::Unsigned
+        );
+        assert_eq!(
+            detect(&xyt_with_angles(count, iso)),
+            AngleConvention::IsoUnits
+        );
+        // too few minutiae to tell ISO units from degrees
+        assert_eq!(
+            detect(&xyt_with_angles(count - 1, iso)),
+            AngleConvention::Unsigned
+        );
+        assert_eq!(detect("1 2 90\n3 4 180\n"), AngleConvention::Unsigned);
+        assert_eq!(detect(""), AngleConvention::Unsigned);
+    }
+
+    #[test]
+    fn every_angle_convention_is_normalized_to_the_internal_one() {
+        let count = MIN_MINUTIAE_FOR_ISO_UNITS as i32;
+        let expected = angles(
+            &xyt_with_angles(count, |t| t),
+            Some(AngleConvention::Signed),
+        );
+        assert!(expected.iter().all(|t| (-179..=180).contains(t)));
+
+        let signed = xyt_with_angles(count, |t| if t > 180 { t - 360 } else { t });
+        let unsigned = xyt_with_angles(count, |t| t);
+        assert_eq!(angles(&signed, None), expected);
+        assert_eq!(angles(&unsigned, None), expected);
+        // degrees without a forced convention are parsed like before
+        let legacy: Vec<_> = parse_combined(&unsigned, None)
+            .unwrap()
+            .iter()
+            .map(|it| it.t)
+            .collect();
+        assert_eq!(legacy, expected);
+
+        // ISO units lose less than a degree
+        let iso = xyt_with_angles(count, |t| (t * 256 + 180) / 360 % 256);
+        let from_iso = angles(&iso, None);
+        for (t, expected) in from_iso.iter().zip(&expected) {
+            assert!((t - expected + 540) % 360 - 180 <= 1, "{} {}", t, expected);
+            assert!((t - expected + 540) % 360 - 180 >= -1, "{} {}", t, expected);
+        }
+        // forcing degrees on ISO units reads them as they are
+        assert_ne!(angles(&iso, Some(AngleConvention::Unsigned)), from_iso);
+
+        let mut minutiae = parse_xyt_str("0 0 -180\n0 0 360\n0 0 255\n").unwrap();
+        normalize_angles(&mut minutiae, AngleConvention::Unsigned);
+        assert_eq!(
+            minutiae.iter().map(|it| it.t).collect::<Vec<_>>(),
+            [180, 0, -105]
+        );
+        let mut minutiae = parse_xyt_str("0 0 255\n0 0 128\n0 0 64\n").unwrap();
+        normalize_angles(&mut minutiae, AngleConvention::IsoUnits);
+        assert_eq!(
+            minutiae.iter().map(|it| it.t).collect::<Vec<_>>(),
+            [-1, 180, 90]
+        );
+    }
 }
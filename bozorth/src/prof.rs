@@ -1,43 +1,128 @@
-// use std::panic::Location;
-// use std::collections::HashMap;
-// use std::time::Duration;
+//! Lightweight timing of selected stages of matching.
+//!
+//! With the `profiling` feature enabled every call of `timeit` is accounted to its call site.
+//! Without it, `timeit` only calls the given closure and `report` is always empty.
 
-// static mut STATS: Option<HashMap<usize, (u128, u64)>> = None;
-// static mut REPORTS: u64 = 0;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::panic::Location;
+use core::time::Duration;
 
-#[track_caller]
-#[inline]
+/// Whether the crate was built with the `profiling` feature.
+pub const ENABLED: bool = cfg!(feature = "profiling");
+
+/// Accumulated timing of a single call site of `timeit`.
+#[derive(Debug, Clone)]
+pub struct ProfEntry {
+    pub location: &'static Location<'static>,
+    pub calls: u64,
+    pub total: Duration,
+}
+
+#[cfg(not(feature = "profiling"))]
+#[inline(always)]
 pub fn timeit<T>(f: impl FnOnce() -> T) -> T {
-    return f();
-    // let a = std::time::Instant::now();
-    // let result = f();
-    // let b = a.elapsed();
-
-    // unsafe {
-    //     if STATS.is_none() {
-    //         STATS = Some(HashMap::new());
-    //     }
-    //     REPORTS += 1;
-
-    //     let stats = STATS.as_mut().unwrap();
-    //     stats.entry(Location::caller() as *const _ as usize).and_modify(|v| {
-    //         v.0 += b.as_nanos();
-    //         v.1 += 1;
-    //     }).or_insert((b.as_nanos(), 1));
-
-    //     if REPORTS % 1000000 == 0 {
-    //         let total_time: u128 = stats.values().map(|it| it.0).sum();
-
-    //         eprintln!("Summary:");
-    //         for (ptr, (time, _events)) in stats.iter() {
-    //             eprintln!("{} {:?} {:.02}%",
-    //                       std::mem::transmute::<_, &'static Location<'static>>(*ptr),
-    //                       Duration::from_micros((*time / 1000) as u64),
-    //                       (*time as f64) / (total_time as f64) * 100.0
-    //             );
-    //         }
-    //     }
-    // }
-
-    // result
+    f()
+}
+
+/// Returns timings of all call sites, the most expensive first.
+#[cfg(not(feature = "profiling"))]
+pub fn report() -> Vec<ProfEntry> {
+    vec![]
+}
+
+/// Forgets all timings collected so far.
+#[cfg(not(feature = "profiling"))]
+pub fn reset() {}
+
+#[cfg(feature = "profiling")]
+pub use registry::{report, reset, timeit};
+
+#[cfg(feature = "profiling")]
+mod registry {
+    use std::collections::HashMap;
+    use std::panic::Location;
+    use std::sync::{Mutex, OnceLock};
+    use std::time::{Duration, Instant};
+
+    use super::ProfEntry;
+
+    type Registry = Mutex<HashMap<&'static Location<'static>, (u64, Duration)>>;
+
+    fn registry() -> &'static Registry {
+        static REGISTRY: OnceLock<Registry> = OnceLock::new();
+        REGISTRY.get_or_init(|| Mutex::new(HashMap::new()))
+    }
+
+    #[track_caller]
+    #[inline]
+    pub fn timeit<T>(f: impl FnOnce() -> T) -> T {
+        let location = Location::caller();
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+
+        let mut registry = registry().lock().unwrap_or_else(|it| it.into_inner());
+        let entry = registry.entry(location).or_default();
+        entry.0 += 1;
+        entry.1 += elapsed;
+        result
+    }
+
+    pub fn report() -> Vec<ProfEntry> {
+        let registry = registry().lock().unwrap_or_else(|it| it.into_inner());
+        let mut entries: Vec<_> = registry
+            .iter()
+            .map(|(&location, &(calls, total))| ProfEntry {
+                location,
+                calls,
+                total,
+            })
+            .collect();
+        entries.sort_by_key(|it| std::cmp::Reverse(it.total));
+        entries
+    }
+
+    pub fn reset() {
+        registry()
+            .lock()
+            .unwrap_or_else(|it| it.into_inner())
+            .clear();
+    }
+}
+
+#[cfg(all(test, feature = "profiling"))]
+mod tests {
+    use crate::prof::{report, timeit};
+
+    #[test]
+    fn concurrent_calls_are_not_lost() {
+        const THREADS: u64 = 8;
+        const CALLS: u64 = 10_000;
+
+        let line = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut line = 0;
+                        for _ in 0..CALLS {
+                            line = timeit(|| std::panic::Location::caller().line());
+                        }
+                        line
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|it| it.join().unwrap())
+                .last()
+                .unwrap()
+        });
+
+        let entry = report()
+            .into_iter()
+            .find(|it| it.location.file() == file!() && it.location.line() == line)
+            .unwrap();
+        assert_eq!(entry.calls, THREADS * CALLS);
+    }
 }
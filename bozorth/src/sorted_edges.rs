@@ -0,0 +1,125 @@
+use core::ops::Deref;
+
+use crate::utils::limit_edges;
+use crate::Edge;
+
+/// Edges sorted by their length, as required by `match_edges_into_pairs`.
+///
+/// Matching walks both lists in order of length and stops early, so unsorted edges silently
+/// lose pairs. `find_edges` returns its edges in this form; edges from elsewhere have to be
+/// checked with `SortedEdges::new`.
+///
+/// A plain slice is not accepted:
+///
+/// ```compile_fail
+/// use bozorth::{match_edges_into_pairs, Edge, MatchConfig, Minutia, PairHolder};
+///
+/// fn unsorted(edges: &[Edge], minutiae: &[Minutia], pairs: &mut PairHolder) {
+///     let points = |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1;
+///     let config = MatchConfig::default();
+///     match_edges_into_pairs(edges, minutiae, edges, minutiae, pairs, &config, points);
+/// }
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct SortedEdges<'a> {
+    edges: &'a [Edge],
+    truncated: bool,
+}
+
+impl<'a> SortedEdges<'a> {
+    /// Returns `None` unless the edges are sorted by `distance_squared`.
+    pub fn new(edges: &'a [Edge]) -> Option<Self> {
+        if is_sorted(edges) {
+            Some(SortedEdges {
+                edges,
+                truncated: false,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Skips the check of `new`, for edges that are known to be sorted, e.g. a prefix of edges
+    /// returned by `find_edges`.
+    ///
+    /// Unsorted edges are not unsafe, but their matches miss pairs. Debug builds still check.
+    pub fn from_sorted_unchecked(edges: &'a [Edge]) -> Self {
+        debug_assert!(is_sorted(edges));
+        SortedEdges {
+            edges,
+            truncated: false,
+        }
+    }
+
+    pub(crate) fn truncated(self, truncated: bool) -> Self {
+        SortedEdges { truncated, ..self }
+    }
+
+    /// Keeps only the edges selected by `limit_edges`.
+    pub fn limited(self) -> Self {
+        SortedEdges {
+            edges: &self.edges[..limit_edges(self.edges)],
+            ..self
+        }
+    }
+
+    /// `find_edges` stopped at `MAX_NUMBER_OF_EDGES - 1` edges, so the edges of the remaining
+    /// pairs of minutiae are missing and matches of the template may score lower.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn as_slice(&self) -> &'a [Edge] {
+        self.edges
+    }
+}
+
+impl Deref for SortedEdges<'_> {
+    type Target = [Edge];
+
+    fn deref(&self) -> &[Edge] {
+        self.edges
+    }
+}
+
+pub(crate) fn is_sorted(edges: &[Edge]) -> bool {
+    edges
+        .windows(2)
+        .all(|pair| pair[0].distance_squared <= pair[1].distance_squared)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::MatchConfig;
+    use crate::match_edges::cpu::scalar_match_edges_into_pairs;
+    use crate::synthetic::Generator;
+    use crate::{find_edges, Edge, Format, Minutia, PairHolder, SortedEdges};
+
+    fn pair_count(edges: &[Edge], minutiae: &[Minutia]) -> usize {
+        let mut pairs = PairHolder::new();
+        scalar_match_edges_into_pairs(
+            edges,
+            minutiae,
+            edges,
+            minutiae,
+            &mut pairs,
+            &MatchConfig::default(),
+            |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+        );
+        pairs.pairs().len()
+    }
+
+    #[test]
+    fn unsorted_edges_lose_pairs_and_are_rejected() {
+        let minutiae = Generator::new(3).template(30);
+        let mut edges = vec![];
+        let sorted = find_edges(&minutiae, &mut edges, Format::NistInternal).limited();
+        assert!(SortedEdges::new(&sorted).is_some());
+
+        let mut reversed = sorted.to_vec();
+        reversed.reverse();
+
+        assert!(pair_count(&reversed, &minutiae) < pair_count(&sorted, &minutiae));
+        assert!(SortedEdges::new(&reversed).is_none());
+    }
+}
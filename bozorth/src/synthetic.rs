@@ -0,0 +1,93 @@
+//! Deterministic generator of synthetic templates used in tests, also of other crates with the
+//! `testing` feature.
+#![allow(dead_code)]
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::parsing::RawMinutiaCombined;
+use crate::types::{Degrees, Minutia, MinutiaKind};
+
+/// Linear congruential generator; the same seed always gives the same templates.
+pub struct Generator {
+    seed: u32,
+}
+
+impl Generator {
+    pub fn new(seed: u32) -> Self {
+        Generator { seed }
+    }
+
+    /// Returns a pseudo-random number in range `0..modulo`.
+    pub fn next(&mut self, modulo: u32) -> i32 {
+        self.seed = self.seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        ((self.seed >> 16) % modulo) as i32
+    }
+
+    /// Generates a template with minutiae scattered over a 400x400 area.
+    pub fn raw_template(&mut self, count: usize) -> Vec<RawMinutiaCombined> {
+        (0..count)
+            .map(|_| RawMinutiaCombined {
+                x: 50 + self.next(400),
+                y: 50 + self.next(400),
+                t: self.next(360) - 179,
+                q: 1 + self.next(100),
+                kind: if self.next(2) == 0 {
+                    MinutiaKind::Ending
+                } else {
+                    MinutiaKind::Bifurcation
+                },
+            })
+            .collect()
+    }
+
+    /// Generates another impression of the template: rotated by `rotation` degrees
+    /// around the center, translated, with jittered positions and a few minutiae missing.
+    pub fn impression(
+        &mut self,
+        template: &[RawMinutiaCombined],
+        rotation: i32,
+        (dx, dy): (i32, i32),
+    ) -> Vec<RawMinutiaCombined> {
+        let (sin, cos) = (rotation as f32).to_radians().sin_cos();
+        let mut minutiae = vec![];
+        for m in template {
+            if self.next(10) == 0 {
+                continue;
+            }
+
+            let x = (m.x - 250) as f32;
+            let y = (m.y - 250) as f32;
+            let mut t = m.t + rotation + self.next(5) - 2;
+            if t > 180 {
+                t -= 360;
+            } else if t <= -180 {
+                t += 360;
+            }
+            minutiae.push(RawMinutiaCombined {
+                x: 250 + (x * cos - y * sin).round() as i32 + dx + self.next(3) - 1,
+                y: 250 + (x * sin + y * cos).round() as i32 + dy + self.next(3) - 1,
+                t,
+                ..*m
+            });
+        }
+        minutiae
+    }
+
+    /// Generates a template sorted the same way `prune` sorts minutiae.
+    pub fn template(&mut self, count: usize) -> Vec<Minutia> {
+        let mut minutiae: Vec<_> = self
+            .raw_template(count)
+            .into_iter()
+            .map(|it| Minutia {
+                x: it.x,
+                y: it.y,
+                theta: Degrees(it.t),
+                kind: it.kind,
+                quality: it.q,
+            })
+            .collect();
+        minutiae.sort_by_key(|it| (it.x, it.y));
+        minutiae
+    }
+}
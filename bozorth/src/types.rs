@@ -1,10 +1,110 @@
-use std::fmt;
+use core::fmt;
+use core::ops::{Add, AddAssign, Neg, Sub, SubAssign};
 
+use crate::consts::MAX_NUMBER_OF_MINUTIAE;
+use crate::math::{are_angles_equal_with_tolerance, are_angles_opposite, normalize_angle};
+
+/// Type of a minutia.
+///
+/// Discriminants are stable and can be used as an FFI representation.
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum MinutiaKind {
-    Type0,
-    Type1,
+    /// Ridge ending (`RIG` in `.min` files).
+    Ending = 0,
+    /// Ridge bifurcation (`BIF` in `.min` files).
+    Bifurcation = 1,
+    /// Type declared explicitly as "other" by the template format.
+    Other = 2,
+    /// Type was not recognized or is not available.
+    Unknown = 3,
+}
+
+#[allow(non_upper_case_globals)]
+impl MinutiaKind {
+    /// Alias of `MinutiaKind::Ending` kept for compatibility.
+    pub const Type0: MinutiaKind = MinutiaKind::Ending;
+    /// Alias of `MinutiaKind::Bifurcation` kept for compatibility.
+    pub const Type1: MinutiaKind = MinutiaKind::Bifurcation;
+
+    /// Whether this kind is compatible with any other kind.
+    #[inline]
+    pub fn is_wildcard(self) -> bool {
+        matches!(self, MinutiaKind::Other | MinutiaKind::Unknown)
+    }
+
+    /// Compares kinds of two corresponding minutiae.
+    #[inline]
+    pub fn agreement(self, other: MinutiaKind) -> KindAgreement {
+        if self.is_wildcard() || other.is_wildcard() {
+            KindAgreement::Wildcard
+        } else if self == other {
+            KindAgreement::Same
+        } else {
+            KindAgreement::Different
+        }
+    }
+}
+
+/// Relation between kinds of two corresponding minutiae.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KindAgreement {
+    /// Both minutiae have the same, known kind.
+    Same,
+    /// At least one of the minutiae has a kind that matches anything.
+    Wildcard,
+    /// Minutiae have different, known kinds.
+    Different,
+}
+
+/// Calculates points for a pair of edges based on the kinds of their endpoints.
+///
+/// `points[n]` is the number of points for a pair with `n` endpoints of the same kind.
+/// Endpoints with a wildcard kind contribute half-way between compatible and incompatible,
+/// so pairs without wildcards get exactly the same points as a plain equality check.
+pub fn kind_points(
+    points: [u32; 3],
+    probe_k: &Minutia,
+    probe_j: &Minutia,
+    gallery_k: &Minutia,
+    gallery_j: &Minutia,
+) -> u32 {
+    let mut same = 0;
+    let mut wildcards = 0;
+    for agreement in [
+        probe_k.kind.agreement(gallery_k.kind),
+        probe_j.kind.agreement(gallery_j.kind),
+    ] {
+        match agreement {
+            KindAgreement::Same => same += 1,
+            KindAgreement::Wildcard => wildcards += 1,
+            KindAgreement::Different => {}
+        }
+    }
+    (points[same] + points[same + wildcards]) / 2
+}
+
+/// Highest quality of a minutia, as written by `mindtct`.
+pub const MAX_MINUTIA_QUALITY: i32 = 100;
+
+/// Weights `points` of a pair of edges by the product of the qualities of its four endpoints.
+///
+/// Qualities are clamped to 0-`MAX_MINUTIA_QUALITY`, so a pair of perfect minutiae gets all the
+/// `points` and a pair with an endpoint of quality 0 gets none. The result is rounded down,
+/// `points` should therefore be large (e.g. 100) for the weights to matter.
+pub fn quality_points(
+    points: u32,
+    probe_k: &Minutia,
+    probe_j: &Minutia,
+    gallery_k: &Minutia,
+    gallery_j: &Minutia,
+) -> u32 {
+    let max = MAX_MINUTIA_QUALITY as u64;
+    let product = [probe_k, probe_j, gallery_k, gallery_j]
+        .iter()
+        .map(|it| it.quality.clamp(0, MAX_MINUTIA_QUALITY) as u64)
+        .product::<u64>();
+    (points as u64 * product / max.pow(4)) as u32
 }
 
 /// Represents a single minutia.
@@ -14,10 +114,92 @@ pub struct Minutia {
     pub x: i32,
     /// Y coordinate.
     pub y: i32,
-    /// Orientation in degrees.
-    pub theta: i32,
+    /// Orientation.
+    pub theta: Degrees,
     /// Type of the minutia.
     pub kind: MinutiaKind,
+    /// Quality as given by the extractor, usually 0-100; 0 when it is not known.
+    pub quality: i32,
+}
+
+/// Angle in whole degrees.
+///
+/// Keeps angles apart from coordinates and distances, which are plain `i32` as well.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[repr(transparent)]
+pub struct Degrees(pub i32);
+
+impl Degrees {
+    /// Wraps the angle into range (-180, 180].
+    #[inline]
+    pub fn normalized(self) -> Degrees {
+        Degrees(normalize_angle(self.0))
+    }
+
+    /// Checks whether the angles differ by no more than the angle tolerance.
+    #[inline]
+    pub fn is_close_to(self, other: Degrees) -> bool {
+        are_angles_equal_with_tolerance(self.0, other.0)
+    }
+
+    /// Checks whether the angles point in exactly opposite directions.
+    #[inline]
+    pub fn is_opposite_to(self, other: Degrees) -> bool {
+        are_angles_opposite(self.0, other.0)
+    }
+}
+
+impl From<i32> for Degrees {
+    fn from(degrees: i32) -> Self {
+        Degrees(degrees)
+    }
+}
+
+impl From<Degrees> for i32 {
+    fn from(degrees: Degrees) -> Self {
+        degrees.0
+    }
+}
+
+impl Add for Degrees {
+    type Output = Degrees;
+
+    #[inline]
+    fn add(self, other: Degrees) -> Degrees {
+        Degrees(self.0 + other.0)
+    }
+}
+
+impl Sub for Degrees {
+    type Output = Degrees;
+
+    #[inline]
+    fn sub(self, other: Degrees) -> Degrees {
+        Degrees(self.0 - other.0)
+    }
+}
+
+impl Neg for Degrees {
+    type Output = Degrees;
+
+    #[inline]
+    fn neg(self) -> Degrees {
+        Degrees(-self.0)
+    }
+}
+
+impl AddAssign for Degrees {
+    #[inline]
+    fn add_assign(&mut self, other: Degrees) {
+        self.0 += other.0;
+    }
+}
+
+impl SubAssign for Degrees {
+    #[inline]
+    fn sub_assign(&mut self, other: Degrees) {
+        self.0 -= other.0;
+    }
 }
 
 /// Represents a type-safe index of a minutia in the list of minutiae.
@@ -57,8 +239,9 @@ impl Into<Endpoint> for u32 {
 impl Into<Endpoint> for usize {
     #[inline(never)]
     fn into(self) -> Endpoint {
+        #[cfg(feature = "log")]
         if self >= 200 {
-            dbg!(self);
+            log::warn!(index = self; "minutia index out of range, clamped to 200");
         }
         Endpoint(self.min(200) as _)
     }
@@ -68,7 +251,7 @@ impl Into<Endpoint> for usize {
 #[derive(Debug, Copy, Clone)]
 pub struct Pair {
     /// Difference between theta angles on both fingerprints.
-    pub delta_theta: i32,
+    pub delta_theta: Degrees,
     /// Endpoint K on the Probe fingerprint.
     pub probe_k: Endpoint,
     /// Corresponding endpoint K the Gallery fingerprint.
@@ -109,9 +292,286 @@ pub struct Edge {
     pub beta_order: BetaOrder,
 }
 
-#[derive(Copy, Clone)]
+impl Edge {
+    /// Creates an edge between minutiae with indices `endpoint_k` and `endpoint_j`, which should
+    /// be the leftmost and the rightmost endpoint respectively.
+    ///
+    /// `beta_k` and `beta_j` are the angles between the line passing through both endpoints and
+    /// the orientation of the minutia, `min_beta`, `max_beta` and `beta_order` follow from them.
+    /// Fails if an index is not below `MAX_NUMBER_OF_MINUTIAE` or both indices are equal.
+    pub fn new(
+        endpoint_k: usize,
+        endpoint_j: usize,
+        distance_squared: i32,
+        theta_kj: i32,
+        beta_k: i32,
+        beta_j: i32,
+    ) -> Result<Edge, InvalidEdge> {
+        for &index in &[endpoint_k, endpoint_j] {
+            if index >= MAX_NUMBER_OF_MINUTIAE {
+                return Err(InvalidEdge::EndpointOutOfRange(index));
+            }
+        }
+        if endpoint_k == endpoint_j {
+            return Err(InvalidEdge::SameEndpoints(endpoint_k));
+        }
+
+        Ok(Edge::with_betas(
+            Endpoint(endpoint_k as u32),
+            Endpoint(endpoint_j as u32),
+            distance_squared,
+            theta_kj,
+            beta_k,
+            beta_j,
+        ))
+    }
+
+    pub(crate) fn with_betas(
+        endpoint_k: Endpoint,
+        endpoint_j: Endpoint,
+        distance_squared: i32,
+        theta_kj: i32,
+        beta_k: i32,
+        beta_j: i32,
+    ) -> Edge {
+        let (min_beta, max_beta, beta_order) = if beta_k < beta_j {
+            (beta_k, beta_j, BetaOrder::KJ)
+        } else {
+            (beta_j, beta_k, BetaOrder::JK)
+        };
+
+        Edge {
+            distance_squared,
+            min_beta,
+            max_beta,
+            endpoint_k,
+            endpoint_j,
+            theta_kj,
+            beta_order,
+        }
+    }
+}
+
+/// Reason why `Edge::new` rejected the endpoints.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum InvalidEdge {
+    /// Index is not below `MAX_NUMBER_OF_MINUTIAE`.
+    EndpointOutOfRange(usize),
+    /// Both endpoints refer to the same minutia.
+    SameEndpoints(usize),
+}
+
+impl fmt::Display for InvalidEdge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidEdge::EndpointOutOfRange(index) => write!(
+                f,
+                "endpoint {} is out of range, at most {} minutiae are supported",
+                index, MAX_NUMBER_OF_MINUTIAE
+            ),
+            InvalidEdge::SameEndpoints(index) => {
+                write!(f, "both endpoints refer to minutia {}", index)
+            }
+        }
+    }
+}
+
+impl core::error::Error for InvalidEdge {}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum Format {
     NistInternal,
     #[allow(unused)]
     Ansi,
 }
+
+impl Format {
+    /// Origin of the coordinates of templates in this format.
+    pub fn coordinate_origin(self) -> CoordinateOrigin {
+        match self {
+            Format::NistInternal => CoordinateOrigin::BottomLeft,
+            Format::Ansi => CoordinateOrigin::TopLeft,
+        }
+    }
+
+    /// Origin of the angles of templates in this format; counterclockwise in all of them.
+    pub fn angle_origin(self) -> AngleOrigin {
+        AngleOrigin::BottomLeft
+    }
+}
+
+/// Corner of the image the coordinates of minutiae start at.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CoordinateOrigin {
+    /// y grows downwards, as in ANSI/ISO templates.
+    TopLeft,
+    /// y grows upwards, as in NIST internal .xyt files.
+    BottomLeft,
+}
+
+impl CoordinateOrigin {
+    /// `dy` with y growing upwards, the direction edges are measured in.
+    #[inline]
+    pub(crate) fn upwards(self, dy: i32) -> i32 {
+        match self {
+            CoordinateOrigin::TopLeft => -dy,
+            CoordinateOrigin::BottomLeft => dy,
+        }
+    }
+}
+
+/// Corner of the image whose axes the angles of minutiae are measured between, from the x axis
+/// towards the y axis.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AngleOrigin {
+    /// Clockwise as seen on the image, like `atan2` of coordinates with y growing downwards.
+    TopLeft,
+    /// Counterclockwise as seen on the image, as in both NIST and ANSI/ISO templates.
+    BottomLeft,
+}
+
+impl AngleOrigin {
+    /// `theta` counterclockwise, the direction edges are measured in.
+    #[inline]
+    pub(crate) fn counterclockwise(self, theta: Degrees) -> Degrees {
+        match self {
+            AngleOrigin::TopLeft => -theta,
+            AngleOrigin::BottomLeft => theta,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::MAX_NUMBER_OF_MINUTIAE;
+    use crate::synthetic::Generator;
+    use crate::types::{
+        kind_points, quality_points, BetaOrder, Degrees, Edge, InvalidEdge, Minutia, MinutiaKind,
+    };
+    use crate::{find_edges, Format};
+
+    fn minutia(kind: MinutiaKind) -> Minutia {
+        Minutia {
+            x: 0,
+            y: 0,
+            theta: Degrees(0),
+            kind,
+            quality: 0,
+        }
+    }
+
+    #[test]
+    fn quality_points_are_weighted_by_all_four_endpoints() {
+        let with_quality = |quality| Minutia {
+            quality,
+            ..minutia(MinutiaKind::Ending)
+        };
+        let (perfect, half, none) = (with_quality(100), with_quality(50), with_quality(0));
+
+        assert_eq!(
+            quality_points(100, &perfect, &perfect, &perfect, &perfect),
+            100
+        );
+        assert_eq!(quality_points(100, &half, &perfect, &perfect, &perfect), 50);
+        assert_eq!(quality_points(160, &half, &half, &half, &half), 10);
+        assert_eq!(quality_points(100, &none, &perfect, &perfect, &perfect), 0);
+        // qualities out of the range are clamped
+        let (high, low) = (with_quality(250), with_quality(-5));
+        assert_eq!(
+            quality_points(100, &high, &perfect, &perfect, &perfect),
+            100
+        );
+        assert_eq!(quality_points(100, &low, &perfect, &perfect, &perfect), 0);
+    }
+
+    #[test]
+    fn wildcard_kinds_get_intermediate_points() {
+        let ending = minutia(MinutiaKind::Ending);
+        let bifurcation = minutia(MinutiaKind::Bifurcation);
+        let other = minutia(MinutiaKind::Other);
+        let unknown = minutia(MinutiaKind::Unknown);
+        let points = [2, 3, 4];
+
+        assert_eq!(kind_points(points, &ending, &ending, &ending, &ending), 4);
+        assert_eq!(
+            kind_points(points, &ending, &ending, &ending, &bifurcation),
+            3
+        );
+        assert_eq!(
+            kind_points(points, &ending, &ending, &bifurcation, &bifurcation),
+            2
+        );
+
+        // one wildcard among otherwise equal kinds lands between 3 and 4
+        assert_eq!(
+            kind_points([20, 30, 40], &ending, &other, &ending, &ending),
+            35
+        );
+        // a wildcard against an incompatible pair lands between 2 and 3
+        assert_eq!(
+            kind_points([20, 30, 40], &unknown, &ending, &bifurcation, &bifurcation),
+            25
+        );
+        // only wildcards span the whole range
+        assert_eq!(
+            kind_points(points, &other, &unknown, &ending, &bifurcation),
+            3
+        );
+    }
+
+    #[test]
+    fn degrees_arithmetic_wraps_only_when_normalized() {
+        let a = Degrees(170);
+        let b = Degrees::from(-170);
+
+        assert_eq!(a - b, Degrees(340));
+        assert_eq!((a - b).normalized(), Degrees(-20));
+        assert_eq!((a + Degrees(10)).normalized(), Degrees(180));
+        assert_eq!(-a, Degrees(-170));
+        assert_eq!(i32::from(a), 170);
+        assert!(!a.is_close_to(b));
+        assert!(Degrees(175).is_close_to(Degrees(-178)));
+        assert!(Degrees(30).is_opposite_to(Degrees(-150)));
+    }
+
+    #[test]
+    fn edges_built_by_hand_equal_found_edges() {
+        let minutiae = Generator::new(17).template(60);
+        let mut edges = vec![];
+        find_edges(&minutiae, &mut edges, Format::NistInternal);
+        assert!(!edges.is_empty());
+
+        for edge in &edges {
+            let (beta_k, beta_j) = match edge.beta_order {
+                BetaOrder::KJ => (edge.min_beta, edge.max_beta),
+                BetaOrder::JK => (edge.max_beta, edge.min_beta),
+            };
+            let built = Edge::new(
+                edge.endpoint_k.as_usize(),
+                edge.endpoint_j.as_usize(),
+                edge.distance_squared,
+                edge.theta_kj,
+                beta_k,
+                beta_j,
+            )
+            .unwrap();
+            assert_eq!(format!("{:?}", built), format!("{:?}", edge));
+        }
+    }
+
+    #[test]
+    fn edges_reject_invalid_endpoints() {
+        assert_eq!(
+            Edge::new(3, MAX_NUMBER_OF_MINUTIAE, 100, 0, 10, 20).unwrap_err(),
+            InvalidEdge::EndpointOutOfRange(MAX_NUMBER_OF_MINUTIAE)
+        );
+        assert_eq!(
+            Edge::new(5, 5, 100, 0, 10, 20).unwrap_err(),
+            InvalidEdge::SameEndpoints(5)
+        );
+
+        let edge = Edge::new(0, MAX_NUMBER_OF_MINUTIAE - 1, 100, 45, 30, -20).unwrap();
+        assert_eq!((edge.min_beta, edge.max_beta), (-20, 30));
+        assert_eq!(edge.beta_order, BetaOrder::JK);
+    }
+}
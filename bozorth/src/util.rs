@@ -0,0 +1,114 @@
+//! Small building blocks of the matcher that are useful on their own.
+
+use core::iter::Peekable;
+
+/// Iterator over the common elements of two sorted iterators, see `intersection_of_sorted`.
+pub struct Intersection<T, I, J>
+where
+    T: Eq + Ord,
+    I: Iterator<Item = T>,
+    J: Iterator<Item = T>,
+{
+    first: Peekable<I>,
+    second: Peekable<J>,
+}
+
+impl<T, I, J> Iterator for Intersection<T, I, J>
+where
+    T: Eq + Ord,
+    I: Iterator<Item = T>,
+    J: Iterator<Item = T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let (Some(a), Some(b)) = (self.first.peek(), self.second.peek()) {
+            use core::cmp::Ordering::*;
+
+            match a.cmp(b) {
+                Greater => {
+                    self.second.next();
+                }
+                Equal => {
+                    self.first.next();
+                    return self.second.next();
+                }
+                Less => {
+                    self.first.next();
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Intersects two iterators sorted in ascending order, in a single pass over both.
+///
+/// The result is sorted too. An element repeated in both inputs is returned as many times as it
+/// occurs in the input with fewer repetitions. Unsorted inputs give no meaningful result.
+///
+/// ```
+/// use bozorth::util::intersection_of_sorted;
+///
+/// let common: Vec<_> = intersection_of_sorted([1, 3, 3, 5, 8].iter(), [3, 3, 3, 8, 9].iter())
+///     .copied()
+///     .collect();
+/// assert_eq!(common, [3, 3, 8]);
+/// ```
+pub fn intersection_of_sorted<T, I, J>(first: I, second: J) -> Intersection<T, I, J>
+where
+    T: Eq + Ord,
+    I: Iterator<Item = T>,
+    J: Iterator<Item = T>,
+{
+    Intersection {
+        first: first.peekable(),
+        second: second.peekable(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::intersection_of_sorted;
+    use alloc::vec::Vec;
+
+    fn intersection(first: &[u32], second: &[u32]) -> Vec<u32> {
+        intersection_of_sorted(first.iter().copied(), second.iter().copied()).collect()
+    }
+
+    #[test]
+    fn simple() {
+        let a = 2..10;
+        let b = 3..5;
+
+        let mut c = intersection_of_sorted(a, b);
+        assert_eq!(c.next(), Some(3));
+        assert_eq!(c.next(), Some(4));
+        assert_eq!(c.next(), None);
+    }
+
+    #[test]
+    fn empty_inputs_have_nothing_in_common() {
+        assert!(intersection(&[], &[]).is_empty());
+        assert!(intersection(&[1, 2], &[]).is_empty());
+        assert!(intersection(&[], &[1, 2]).is_empty());
+    }
+
+    #[test]
+    fn disjoint_inputs_have_nothing_in_common() {
+        assert!(intersection(&[1, 3, 5], &[2, 4, 6]).is_empty());
+        assert!(intersection(&[1, 2], &[7, 8]).is_empty());
+        assert!(intersection(&[7, 8], &[1, 2]).is_empty());
+    }
+
+    #[test]
+    fn equal_inputs_are_their_own_intersection() {
+        assert_eq!(intersection(&[1, 4, 9], &[1, 4, 9]), [1, 4, 9]);
+    }
+
+    #[test]
+    fn repeated_elements_are_kept_as_often_as_in_both_inputs() {
+        assert_eq!(intersection(&[1, 1, 2, 2, 2], &[1, 2, 2]), [1, 2, 2]);
+        assert_eq!(intersection(&[3], &[3, 3, 3]), [3]);
+    }
+}
@@ -1,22 +1,136 @@
-use std::cmp::Ord;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ord;
 
-use crate::consts::{max_minutia_distance_squared, MAX_FILE_MINUTIAE, MIN_NUMBER_OF_EDGES};
+use crate::consts::{
+    edge_retention_max_distance_squared, MAX_FILE_MINUTIAE, MAX_NUMBER_OF_MINUTIAE,
+    MIN_NUMBER_OF_EDGES,
+};
 use crate::parsing::RawMinutiaCombined;
 use crate::weird_sort::sort_order_decreasing;
-use crate::{is_strict_mode, Edge, Minutia};
+use crate::{default_mode, Degrees, Edge, MatchConfig, Minutia};
 
+/// Options of `prune_with`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PruneOptions {
+    /// Maximum number of minutiae to keep; 0 means no limit other than `MAX_NUMBER_OF_MINUTIAE`.
+    pub max_minutiae: u32,
+    /// Minutiae of quality lower than this are discarded before selecting the best ones.
+    pub min_quality: i32,
+    /// Prune like the original implementation, see `prune_with`; in the relaxed mode files of
+    /// any size are handled and the best minutiae are kept by a stable sort.
+    pub strict: bool,
+}
+
+impl Default for PruneOptions {
+    fn default() -> Self {
+        PruneOptions {
+            max_minutiae: 150,
+            min_quality: 0,
+            strict: default_mode(),
+        }
+    }
+}
+
+/// Pruning of the two sides of a match, e.g. to keep every minutia of enrolled galleries but
+/// only the best ones of noisier probes.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct MatchPruning {
+    pub probe: PruneOptions,
+    pub gallery: PruneOptions,
+}
+
+impl MatchPruning {
+    /// Prunes both sides with the same `options`.
+    pub fn symmetric(options: PruneOptions) -> Self {
+        MatchPruning {
+            probe: options,
+            gallery: options,
+        }
+    }
+
+    /// Keeps at most `probe_max_minutiae` minutiae of probes and `gallery_max_minutiae` of
+    /// galleries, 0 meaning no limit like in `PruneOptions::max_minutiae`.
+    pub fn with_max_minutiae(self, probe_max_minutiae: u32, gallery_max_minutiae: u32) -> Self {
+        MatchPruning {
+            probe: PruneOptions {
+                max_minutiae: probe_max_minutiae,
+                ..self.probe
+            },
+            gallery: PruneOptions {
+                max_minutiae: gallery_max_minutiae,
+                ..self.gallery
+            },
+        }
+    }
+}
+
+/// Selects at most `max_minutiae` minutiae of the highest quality and sorts them by position.
+///
+/// `max_minutiae` of 0 means no limit other than `MAX_NUMBER_OF_MINUTIAE`.
 pub fn prune(minutiae: &[RawMinutiaCombined], max_minutiae: u32) -> Vec<Minutia> {
-    let mut minutiae = minutiae.to_vec();
+    prune_with(
+        minutiae,
+        PruneOptions {
+            max_minutiae,
+            ..PruneOptions::default()
+        },
+    )
+}
 
-    if is_strict_mode() {
-        minutiae = if minutiae.len() > max_minutiae as usize {
-            let mut quality = [0; MAX_FILE_MINUTIAE];
-            for i in 0..minutiae.len() {
-                quality[i] = minutiae[i].q;
-            }
+/// Discards minutiae below `min_quality` and then selects the best ones like `prune`.
+///
+/// In strict mode only the first `MAX_FILE_MINUTIAE` minutiae are considered, as the original
+/// implementation stops loading a file there; otherwise files of any size are handled.
+pub fn prune_with(minutiae: &[RawMinutiaCombined], options: PruneOptions) -> Vec<Minutia> {
+    prune_with_indices(minutiae, options).minutiae
+}
+
+/// Pruned minutiae together with their indices in the slice given to `prune_with_indices`.
+#[derive(Debug)]
+pub struct PrunedMinutiae {
+    pub minutiae: Vec<Minutia>,
+    /// `original_indices[i]` is the index of the input minutia that became `minutiae[i]`.
+    pub original_indices: Vec<u32>,
+    /// Indices of the input minutiae that were not kept, in increasing order.
+    pub dropped: Vec<u32>,
+}
+
+/// Same as `prune_with`, but also tells where every kept minutia came from, so that endpoints
+/// of a match can be mapped back to the source file.
+pub fn prune_with_indices(
+    minutiae: &[RawMinutiaCombined],
+    options: PruneOptions,
+) -> PrunedMinutiae {
+    let PruneOptions {
+        max_minutiae,
+        min_quality,
+        strict,
+    } = options;
+    let max_minutiae = if max_minutiae == 0 {
+        MAX_NUMBER_OF_MINUTIAE as u32
+    } else {
+        max_minutiae.min(MAX_NUMBER_OF_MINUTIAE as u32)
+    };
+    let all = minutiae;
+    let loaded = if strict {
+        &all[..all.len().min(MAX_FILE_MINUTIAE)]
+    } else {
+        all
+    };
+    let mut minutiae: Vec<_> = loaded
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, it)| it.q >= min_quality)
+        .collect();
 
-            let mut order = [0; MAX_FILE_MINUTIAE];
-            sort_order_decreasing(&quality[..minutiae.len()], &mut order[..minutiae.len()]);
+    if strict {
+        minutiae = if minutiae.len() > max_minutiae as usize {
+            let quality: Vec<_> = minutiae.iter().map(|(_, it)| it.q).collect();
+            let mut order = vec![0; minutiae.len()];
+            sort_order_decreasing(&quality, &mut order);
             order[..max_minutiae as usize]
                 .iter()
                 .map(|&index| minutiae[index])
@@ -26,28 +140,56 @@ pub fn prune(minutiae: &[RawMinutiaCombined], max_minutiae: u32) -> Vec<Minutia>
         }
     } else {
         if minutiae.len() > max_minutiae as usize {
-            minutiae.sort_by_key(|m| -m.q);
+            minutiae.sort_by_key(|(_, m)| -m.q);
             minutiae.truncate(max_minutiae as usize);
         }
     }
 
-    minutiae.sort_by_key(|it| (it.x, it.y));
-    minutiae
+    minutiae.sort_by_key(|(_, it)| (it.x, it.y));
+    let mut kept = vec![false; loaded.len()];
+    for &(index, _) in &minutiae {
+        kept[index] = true;
+    }
+    let dropped = (0..loaded.len())
+        .filter(|&index| !kept[index])
+        .chain(loaded.len()..all.len())
+        .map(|index| index as u32)
+        .collect();
+    let (original_indices, minutiae) = minutiae
         .into_iter()
-        .map(|it| Minutia {
-            x: it.x,
-            y: it.y,
-            theta: it.t,
-            kind: it.kind,
+        .map(|(index, it)| {
+            let minutia = Minutia {
+                x: it.x,
+                y: it.y,
+                theta: Degrees(it.t),
+                kind: it.kind,
+                quality: it.q,
+            };
+            (index as u32, minutia)
         })
-        .collect()
+        .unzip();
+    PrunedMinutiae {
+        minutiae,
+        original_indices,
+        dropped,
+    }
 }
 
+/// Returns how many of the sorted edges to keep, see `edge_retention_max_distance`.
 pub fn limit_edges(edges: &[Edge]) -> usize {
-    let limit = if is_strict_mode() {
-        limit_edges_by_length(edges, max_minutia_distance_squared())
+    limit_edges_with(edges, &MatchConfig::default())
+}
+
+/// Same as `limit_edges`, in the mode of the `config`.
+pub fn limit_edges_with(edges: &[Edge], config: &MatchConfig) -> usize {
+    limit_edges_to(edges, edge_retention_max_distance_squared(), config.strict)
+}
+
+pub(crate) fn limit_edges_to(edges: &[Edge], max_distance_squared: i32, strict: bool) -> usize {
+    let limit = if strict {
+        limit_edges_by_length(edges, max_distance_squared)
     } else {
-        match edges.binary_search_by_key(&max_minutia_distance_squared(), |e| e.distance_squared) {
+        match edges.binary_search_by_key(&max_distance_squared, |e| e.distance_squared) {
             Ok(pos) | Err(pos) => pos,
         }
     };
@@ -72,3 +214,269 @@ fn limit_edges_by_length(edges: &[Edge], max_distance: i32) -> usize {
 
     current.min(edges.len())
 }
+
+/// Options of `thin_edges`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ThinOptions {
+    /// Edges kept for every endpoint and sector; 0 keeps all the edges.
+    pub max_per_sector: u32,
+    /// Width of a sector of `theta_kj` in degrees.
+    pub sector_degrees: u32,
+}
+
+impl Default for ThinOptions {
+    fn default() -> Self {
+        ThinOptions {
+            max_per_sector: 0,
+            sector_degrees: 20,
+        }
+    }
+}
+
+/// Keeps at most `max_per_sector` of the shortest edges leaving every `endpoint_k` in every
+/// sector of `theta_kj`; returns how many edges were removed.
+///
+/// Dense templates have many short, nearly parallel edges that add candidate pairs but little
+/// information. Meant to run after `limit_edges`; the remaining edges keep their order, so
+/// sorted edges stay sorted.
+pub fn thin_edges(edges: &mut Vec<Edge>, options: &ThinOptions) -> usize {
+    if options.max_per_sector == 0 {
+        return 0;
+    }
+    let sector_degrees = options.sector_degrees.clamp(1, 360) as i32;
+
+    // stable, so edges of equal length are taken in their order
+    let mut by_length: Vec<_> = (0..edges.len()).collect();
+    by_length.sort_by_key(|&index| edges[index].distance_squared);
+
+    let mut counts = BTreeMap::new();
+    let mut keep = vec![false; edges.len()];
+    for index in by_length {
+        let edge = &edges[index];
+        let sector = edge.theta_kj.rem_euclid(360) / sector_degrees;
+        let count = counts.entry((edge.endpoint_k, sector)).or_insert(0);
+        if *count < options.max_per_sector {
+            *count += 1;
+            keep[index] = true;
+        }
+    }
+
+    let before = edges.len();
+    let mut keep = keep.into_iter();
+    edges.retain(|_| keep.next().unwrap_or(true));
+    before - edges.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::consts::{
+        edge_retention_max_distance, MAX_FILE_MINUTIAE, MAX_NUMBER_OF_MINUTIAE, MIN_NUMBER_OF_EDGES,
+    };
+    use crate::synthetic::Generator;
+    use crate::utils::{
+        limit_edges, limit_edges_to, prune, prune_with, prune_with_indices, thin_edges,
+        PruneOptions, ThinOptions,
+    };
+    use crate::{find_edges, Edge, Format, Minutia};
+    use alloc::collections::BTreeMap;
+
+    #[test]
+    fn zero_max_minutiae_keeps_all_up_to_the_cap() {
+        let mut generator = Generator::new(5);
+        let small = generator.raw_template(30);
+        let large = generator.raw_template(MAX_NUMBER_OF_MINUTIAE + 50);
+
+        assert_eq!(prune(&small, 0).len(), small.len());
+        assert_eq!(prune(&large, 0).len(), MAX_NUMBER_OF_MINUTIAE);
+        assert_eq!(prune(&small, 10).len(), 10);
+    }
+
+    #[test]
+    fn original_indices_point_at_the_kept_minutiae() {
+        let mut generator = Generator::new(29);
+        let raw = generator.raw_template(120);
+        let options = PruneOptions {
+            max_minutiae: 80,
+            min_quality: 20,
+            ..PruneOptions::default()
+        };
+
+        let pruned = prune_with_indices(&raw, options);
+        assert_eq!(pruned.minutiae.len(), pruned.original_indices.len());
+        let key = |m: &Minutia| (m.x, m.y, m.theta, m.kind);
+        assert_eq!(
+            pruned.minutiae.iter().map(key).collect::<Vec<_>>(),
+            prune_with(&raw, options)
+                .iter()
+                .map(key)
+                .collect::<Vec<_>>()
+        );
+        for (minutia, &index) in pruned.minutiae.iter().zip(&pruned.original_indices) {
+            let source = &raw[index as usize];
+            assert!(source.q >= 20);
+            assert_eq!(
+                (
+                    minutia.x,
+                    minutia.y,
+                    minutia.theta.0,
+                    minutia.kind,
+                    minutia.quality
+                ),
+                (source.x, source.y, source.t, source.kind, source.q)
+            );
+        }
+    }
+
+    #[test]
+    fn mapping_reproduces_the_pruned_minutiae() {
+        let mut generator = Generator::new(41);
+        for round in 0..50 {
+            let raw = generator.raw_template(20 + round * 7);
+            let options = PruneOptions {
+                max_minutiae: [0, 10, 60, 150][round % 4],
+                min_quality: [0, 15, 40][round % 3],
+                ..PruneOptions::default()
+            };
+
+            let pruned = prune_with_indices(&raw, options);
+            let mapped: Vec<_> = pruned
+                .original_indices
+                .iter()
+                .map(|&index| {
+                    let it = &raw[index as usize];
+                    (it.x, it.y, it.t, it.kind, it.q)
+                })
+                .collect();
+            let output: Vec<_> = pruned
+                .minutiae
+                .iter()
+                .map(|m| (m.x, m.y, m.theta.0, m.kind, m.quality))
+                .collect();
+            assert_eq!(mapped, output);
+
+            let mut all: Vec<_> = pruned
+                .original_indices
+                .iter()
+                .chain(&pruned.dropped)
+                .copied()
+                .collect();
+            all.sort_unstable();
+            assert_eq!(all, (0..raw.len() as u32).collect::<Vec<_>>());
+            assert!(pruned.dropped.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+
+    #[test]
+    fn min_quality_is_applied_before_selecting_the_best() {
+        let mut generator = Generator::new(9);
+        let minutiae = generator.raw_template(100);
+        let good = minutiae.iter().filter(|it| it.q >= 60).count();
+
+        let options = PruneOptions {
+            max_minutiae: 0,
+            min_quality: 60,
+            ..PruneOptions::default()
+        };
+        assert_eq!(prune_with(&minutiae, options).len(), good);
+
+        let options = PruneOptions {
+            max_minutiae: 10,
+            min_quality: 60,
+            ..PruneOptions::default()
+        };
+        let positions = |minutiae: Vec<Minutia>| -> Vec<_> {
+            minutiae.into_iter().map(|it| (it.x, it.y)).collect()
+        };
+        assert_eq!(
+            positions(prune_with(&minutiae, options)),
+            positions(prune(&minutiae, 10))
+        );
+
+        let options = PruneOptions {
+            max_minutiae: 0,
+            min_quality: 101,
+            ..PruneOptions::default()
+        };
+        assert!(prune_with(&minutiae, options).is_empty());
+    }
+
+    #[test]
+    fn minutiae_beyond_the_file_limit_are_ignored() {
+        let mut generator = Generator::new(23);
+        let minutiae = generator.raw_template(MAX_FILE_MINUTIAE * 3);
+
+        let positions = |minutiae: Vec<Minutia>| -> Vec<_> {
+            minutiae.into_iter().map(|it| (it.x, it.y)).collect()
+        };
+        assert_eq!(
+            positions(prune(&minutiae, 150)),
+            positions(prune(&minutiae[..MAX_FILE_MINUTIAE], 150))
+        );
+    }
+
+    #[test]
+    fn retained_edges_grow_with_the_retention_distance() {
+        let mut generator = Generator::new(31);
+        let minutiae = prune(&generator.raw_template(150), 150);
+        let mut edges = vec![];
+        find_edges(&minutiae, &mut edges, Format::NistInternal);
+        assert!(edges.len() > MIN_NUMBER_OF_EDGES);
+
+        let retained: Vec<_> = (0..=150)
+            .step_by(5)
+            .map(|distance: i32| limit_edges_to(&edges, distance.pow(2), true))
+            .collect();
+        assert!(retained.windows(2).all(|it| it[0] <= it[1]));
+        assert_eq!(retained[0], MIN_NUMBER_OF_EDGES);
+        assert_eq!(*retained.last().unwrap(), edges.len());
+
+        assert_eq!(
+            limit_edges(&edges),
+            limit_edges_to(&edges, edge_retention_max_distance().pow(2), true)
+        );
+    }
+
+    #[test]
+    fn thinning_keeps_the_shortest_edges_of_every_sector() {
+        let minutiae = prune(&Generator::new(47).raw_template(150), 150);
+        let mut edges = vec![];
+        find_edges(&minutiae, &mut edges, Format::NistInternal);
+        edges.truncate(limit_edges(&edges));
+        let all = edges.clone();
+
+        assert_eq!(thin_edges(&mut edges, &ThinOptions::default()), 0);
+        assert_eq!(edges.len(), all.len());
+
+        let options = ThinOptions {
+            max_per_sector: 2,
+            sector_degrees: 30,
+        };
+        let removed = thin_edges(&mut edges, &options);
+        assert!(removed > 0);
+        assert_eq!(edges.len() + removed, all.len());
+        assert!(edges
+            .windows(2)
+            .all(|it| it[0].distance_squared <= it[1].distance_squared));
+
+        let bucket = |edge: &Edge| (edge.endpoint_k, edge.theta_kj.rem_euclid(360) / 30);
+        let mut kept = BTreeMap::new();
+        for edge in &edges {
+            kept.entry(bucket(edge))
+                .or_insert_with(Vec::new)
+                .push(edge.distance_squared);
+        }
+        assert!(kept.values().all(|it| it.len() <= 2));
+        // every removed edge is in a full bucket and at least as long as the kept ones
+        let id = |edge: &Edge| (edge.endpoint_k, edge.endpoint_j);
+        let mut remaining = edges.iter().peekable();
+        for edge in &all {
+            if remaining.peek().is_some_and(|it| id(it) == id(edge)) {
+                remaining.next();
+                continue;
+            }
+            let lengths = &kept[&bucket(edge)];
+            assert_eq!(lengths.len(), 2);
+            assert!(lengths.iter().all(|&it| it <= edge.distance_squared));
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
 struct Cell {
     index: usize,
     value: i32,
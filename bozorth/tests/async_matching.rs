@@ -0,0 +1,98 @@
+//! `score_async` on the threads of a `Matcher`: dropped futures give their match up, a full
+//! queue fails with `MatchError::Busy` and a panicking match with `MatchError::Panicked`.
+
+#![cfg(feature = "async")]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use bozorth::parsing::RawMinutiaCombined;
+
+use bozorth::{
+    score_async, Fingerprint, Format, MatchError, Matcher, MatcherOptions, MinutiaKind,
+    PruneOptions,
+};
+
+/// Parallel minutiae on a `side` x `side` lattice; matched against itself nearly every edge
+/// pairs with many others, which makes the match take long.
+fn lattice(side: i32) -> Arc<Fingerprint> {
+    let minutiae: Vec<_> = (0..side * side)
+        .map(|i| RawMinutiaCombined {
+            x: 100 + 12 * (i % side),
+            y: 100 + 12 * (i / side),
+            t: 0,
+            q: 50,
+            kind: MinutiaKind::Ending,
+        })
+        .collect();
+    Arc::new(Fingerprint::from_raw(
+        &minutiae,
+        PruneOptions::default(),
+        Format::NistInternal,
+    ))
+}
+
+fn single_thread(queue_capacity: usize) -> Matcher {
+    Matcher::new(MatcherOptions {
+        threads: 1,
+        queue_capacity,
+        ..MatcherOptions::default()
+    })
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn dropped_future_abandons_its_match() {
+    let matcher = single_thread(4);
+    let slow = lattice(6);
+
+    let timed_out = tokio::time::timeout(
+        Duration::from_millis(10),
+        score_async(&matcher, Arc::clone(&slow), Arc::clone(&slow)),
+    )
+    .await;
+    assert!(timed_out.is_err());
+
+    // the single thread takes the next match only once it gave the dropped one up
+    let small = lattice(4);
+    let score = score_async(&matcher, Arc::clone(&small), small).await;
+    assert!(score.is_ok());
+    assert_eq!(matcher.cancelled_matches(), 1);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn full_queue_is_busy() {
+    let matcher = single_thread(1);
+    let slow = lattice(6);
+
+    // the matches that were queued are given up on the timeout
+    let limited = || {
+        tokio::time::timeout(
+            Duration::from_millis(10),
+            score_async(&matcher, Arc::clone(&slow), Arc::clone(&slow)),
+        )
+    };
+    let (first, second, third) = tokio::join!(limited(), limited(), limited());
+    let busy = [first, second, third]
+        .iter()
+        .filter(|it| matches!(it, Ok(Err(MatchError::Busy))))
+        .count();
+    assert!(busy >= 1);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn panicking_match_fails_and_the_thread_keeps_serving() {
+    let matcher = single_thread(4);
+
+    // edges pointing past the minutiae make the match index out of bounds
+    let mut broken = Arc::try_unwrap(lattice(5)).unwrap();
+    let mut minutiae = broken.minutiae.into_vec();
+    minutiae.truncate(15);
+    broken.minutiae = minutiae.into();
+    let broken = Arc::new(broken);
+    let failed = score_async(&matcher, Arc::clone(&broken), broken).await;
+    assert_eq!(failed, Err(MatchError::Panicked));
+
+    let small = lattice(4);
+    let expected = score_async(&matcher, Arc::clone(&small), Arc::clone(&small)).await;
+    assert!(matches!(expected, Ok(score) if score > 0));
+}
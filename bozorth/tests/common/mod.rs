@@ -0,0 +1,10 @@
+//! Helpers shared by the integration tests, included by each of them with `mod common;`.
+
+use std::path::{Path, PathBuf};
+
+/// Directory with the fixture templates and their expected scores.
+pub fn data_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data")
+}
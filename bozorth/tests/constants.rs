@@ -0,0 +1,31 @@
+//! Changes the global constants, so it runs in its own process.
+
+#![cfg(feature = "std")]
+
+use bozorth::consts::{
+    score_threshold, set_angle_diff, set_factor, set_max_number_of_clusters, set_score_threshold,
+    snapshot,
+};
+
+#[test]
+fn snapshot_restores_changed_constants() {
+    let defaults = snapshot();
+    assert_eq!(defaults.score_threshold, 8);
+
+    set_score_threshold(20);
+    set_angle_diff(13);
+    set_factor(0.075);
+    set_max_number_of_clusters(100);
+    assert_eq!(score_threshold(), 20);
+    let changed = snapshot();
+    assert_ne!(changed, defaults);
+    assert_eq!(changed.angle_diff, 13);
+
+    defaults.restore();
+    assert_eq!(snapshot(), defaults);
+    assert_eq!(score_threshold(), 8);
+
+    changed.restore();
+    assert_eq!(snapshot(), changed);
+    defaults.restore();
+}
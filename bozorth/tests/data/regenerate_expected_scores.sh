@@ -0,0 +1,26 @@
+#!/bin/sh
+# Regenerates expected_scores.txt with the reference C bozorth3 of NBIS, which runs in what is
+# the strict mode of this crate.
+#
+# Usage: regenerate_expected_scores.sh [path/to/bozorth3]
+#
+# The first time, also drop the #[ignore] of strict_scores_match_the_reference in golden.rs.
+
+set -eu
+
+bozorth3=${1:-bozorth3}
+cd "$(dirname "$0")"
+
+files=$(ls ./*.xyt | sed 's|^\./||' | LC_ALL=C sort)
+version=$("$bozorth3" -version 2>&1 | head -n 1)
+
+{
+    echo "# Strict-mode scores of all pairs of the fixtures, \`probe gallery score\` per line."
+    echo "# Generated by the reference C bozorth3 ($version) with, for every probe:"
+    echo "#   bozorth3 -A outfmt=pgs -p <probe> <all fixtures>"
+    for probe in $files; do
+        # shellcheck disable=SC2086
+        "$bozorth3" -A outfmt=pgs -p "$probe" $files
+    done
+} > expected_scores.txt.new
+mv expected_scores.txt.new expected_scores.txt
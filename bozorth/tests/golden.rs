@@ -0,0 +1,146 @@
+//! Compares scores of all pairs of the fixture templates with the golden values.
+//!
+//! `data/expected_scores.txt` holds the strict scores of the reference C bozorth3, written by
+//! `data/regenerate_expected_scores.sh` with the C binary and never blessed from this crate.
+//! Until it is committed, the comparison is ignored and only `data/regression_scores.txt`, the
+//! scores of this crate, guards against unintended changes.
+
+#![cfg(feature = "std")]
+
+use std::fmt::Write;
+use std::path::Path;
+
+use bozorth::consts::{
+    edge_generation_max_distance_squared, edge_retention_max_distance_squared,
+    set_edge_generation_max_distance, set_edge_retention_max_distance,
+};
+use bozorth::{
+    find_edges, limit_edges, match_edges_into_pairs, match_score, parse, prune, BozorthState, Edge,
+    Format, MatchConfig, Minutia, PairHolder, SortedEdges,
+};
+
+mod common;
+
+use common::data_dir;
+
+struct Fingerprint {
+    minutiae: Vec<Minutia>,
+    edges: Vec<Edge>,
+}
+
+fn load(path: &Path) -> Fingerprint {
+    let minutiae = prune(&parse(path).unwrap(), 150);
+    let mut edges = vec![];
+    find_edges(&minutiae, &mut edges, Format::NistInternal);
+    edges.truncate(limit_edges(&edges));
+    Fingerprint { minutiae, edges }
+}
+
+fn match_fingerprints(
+    probe: &Fingerprint,
+    gallery: &Fingerprint,
+    pairs: &mut PairHolder,
+    state: &mut BozorthState,
+) -> u32 {
+    pairs.clear();
+    match_edges_into_pairs(
+        SortedEdges::new(&probe.edges).unwrap(),
+        &probe.minutiae,
+        SortedEdges::new(&gallery.edges).unwrap(),
+        &gallery.minutiae,
+        pairs,
+        &MatchConfig::default(),
+        |_pk: &Minutia, _pj: &Minutia, _gk: &Minutia, _gj: &Minutia| 1,
+    );
+    if pairs.pairs().is_empty() {
+        return 0;
+    }
+    pairs.prepare();
+
+    match_score(
+        pairs,
+        &probe.minutiae,
+        &gallery.minutiae,
+        Format::NistInternal,
+        state,
+    )
+    .unwrap_or_default()
+    .0
+}
+
+/// Scores of all pairs of the fixtures, one `probe gallery score` line each.
+fn scores_of_fixtures() -> String {
+    let mut files: Vec<_> = std::fs::read_dir(data_dir())
+        .unwrap()
+        .map(|it| it.unwrap().path())
+        .filter(|it| it.extension().and_then(|ext| ext.to_str()) == Some("xyt"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty());
+
+    let fingerprints: Vec<_> = files.iter().map(|it| load(it)).collect();
+    let mut pairs = PairHolder::new();
+    let mut state = BozorthState::new();
+
+    let mut actual = String::new();
+    for (probe_path, probe) in files.iter().zip(fingerprints.iter()) {
+        for (gallery_path, gallery) in files.iter().zip(fingerprints.iter()) {
+            let score = match_fingerprints(probe, gallery, &mut pairs, &mut state);
+            writeln!(
+                actual,
+                "{} {} {}",
+                probe_path.file_name().unwrap().to_str().unwrap(),
+                gallery_path.file_name().unwrap().to_str().unwrap(),
+                score
+            )
+            .unwrap();
+        }
+    }
+    actual
+}
+
+/// Lines of the scores file in `data` without its header of `#` comments, and the header.
+fn read_scores(name: &str) -> (String, String) {
+    let contents = std::fs::read_to_string(data_dir().join(name)).unwrap();
+    let (header, scores): (Vec<_>, Vec<_>) =
+        contents.lines().partition(|line| line.starts_with('#'));
+    let lines = |lines: Vec<&str>| lines.iter().map(|line| format!("{}\n", line)).collect();
+    (lines(scores), lines(header))
+}
+
+fn assert_same_scores(actual: &str, expected: &str) {
+    for (line, (actual, expected)) in actual.lines().zip(expected.lines()).enumerate() {
+        assert_eq!(actual, expected, "mismatch at line {}", line + 1);
+    }
+    assert_eq!(actual.lines().count(), expected.lines().count());
+}
+
+#[test]
+#[ignore = "data/expected_scores.txt has to be written by regenerate_expected_scores.sh first"]
+fn strict_scores_match_the_reference() {
+    let (expected, header) = read_scores("expected_scores.txt");
+    assert!(
+        header.contains("Generated by the reference C bozorth3"),
+        "expected_scores.txt was not written by the C binary"
+    );
+    assert_same_scores(&scores_of_fixtures(), &expected);
+}
+
+#[test]
+fn strict_scores_are_unchanged() {
+    assert_same_scores(
+        &scores_of_fixtures(),
+        &read_scores("regression_scores.txt").0,
+    );
+}
+
+#[test]
+fn default_edge_distances_are_explicit() {
+    // setting the defaults explicitly must not change anything, also for the test above
+    set_edge_generation_max_distance(125);
+    set_edge_retention_max_distance(75);
+    assert_eq!(edge_generation_max_distance_squared(), 125 * 125);
+    assert_eq!(edge_retention_max_distance_squared(), 75 * 75);
+
+    assert_eq!(scores_of_fixtures(), read_scores("regression_scores.txt").0);
+}
@@ -0,0 +1,97 @@
+//! Matches the fixtures the way a build without `std` has to: the global constants are never
+//! set, the templates are parsed from strings and every match gets its own `MatchConfig`.
+//!
+//! Runs in its own process, so the constants still have their initial values.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use bozorth::consts::{snapshot, DEFAULTS};
+use bozorth::{
+    find_edges, limit_edges, match_edges_into_pairs, match_score_with, parse_combined, prune,
+    BozorthState, Edge, Format, MatchConfig, Minutia, PairHolder, PruneOptions, SortedEdges,
+};
+
+mod common;
+
+use common::data_dir;
+
+fn load(path: &Path) -> (Vec<Minutia>, Vec<Edge>) {
+    let xyt = std::fs::read_to_string(path).unwrap();
+    let min = std::fs::read_to_string(path.with_extension("min")).ok();
+    let minutiae = prune(&parse_combined(&xyt, min.as_deref()).unwrap(), 150);
+    let mut edges = vec![];
+    find_edges(&minutiae, &mut edges, Format::NistInternal);
+    edges.truncate(limit_edges(&edges));
+    (minutiae, edges)
+}
+
+#[test]
+fn initial_constants_are_the_defaults() {
+    assert_eq!(snapshot(), DEFAULTS);
+    assert!(MatchConfig::default().strict);
+    assert!(PruneOptions::default().strict);
+}
+
+#[test]
+fn scores_with_match_config_match_golden_values() {
+    let mut files: Vec<_> = std::fs::read_dir(data_dir())
+        .unwrap()
+        .map(|it| it.unwrap().path())
+        .filter(|it| it.extension().and_then(|ext| ext.to_str()) == Some("xyt"))
+        .collect();
+    files.sort();
+
+    let fingerprints: Vec<_> = files.iter().map(|it| load(it)).collect();
+    let config = MatchConfig::default();
+    let mut pairs = PairHolder::new();
+    let mut state = BozorthState::new();
+
+    let mut actual = String::new();
+    for (probe_path, probe) in files.iter().zip(fingerprints.iter()) {
+        for (gallery_path, gallery) in files.iter().zip(fingerprints.iter()) {
+            pairs.clear();
+            match_edges_into_pairs(
+                SortedEdges::new(&probe.1).unwrap(),
+                &probe.0,
+                SortedEdges::new(&gallery.1).unwrap(),
+                &gallery.0,
+                &mut pairs,
+                &config,
+                |_pk: &Minutia, _pj: &Minutia, _gk: &Minutia, _gj: &Minutia| 1,
+            );
+            let score = if pairs.pairs().is_empty() {
+                0
+            } else {
+                pairs.prepare_with(&config);
+                match_score_with(
+                    &pairs,
+                    &probe.0,
+                    &gallery.0,
+                    Format::NistInternal,
+                    &mut state,
+                    &config,
+                )
+                .unwrap_or_default()
+                .0
+            };
+            writeln!(
+                actual,
+                "{} {} {}",
+                probe_path.file_name().unwrap().to_str().unwrap(),
+                gallery_path.file_name().unwrap().to_str().unwrap(),
+                score
+            )
+            .unwrap();
+        }
+    }
+
+    // the scores of `golden.rs`, without their header
+    let expected = std::fs::read_to_string(data_dir().join("regression_scores.txt")).unwrap();
+    let expected: String = expected
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    assert_eq!(actual, expected);
+}
@@ -0,0 +1,153 @@
+//! Score impact of every `CompatQuirks` quirk on the fixture templates: the strict scores of
+//! the pairs that change when the quirk alone is turned off.
+//!
+//! Set `BOZORTH_BLESS=1` to regenerate `data/quirk_impact.txt` after an intentional change of
+//! the scores.
+
+#![cfg(feature = "std")]
+
+use std::fmt::Write;
+
+use bozorth::{
+    match_edges_into_pairs, match_score_with, parse, BozorthState, CompatQuirks, Fingerprint,
+    Format, MatchConfig, Minutia, PairHolder, PruneOptions,
+};
+
+mod common;
+
+use common::data_dir;
+
+fn strict(quirks: Option<CompatQuirks>) -> MatchConfig {
+    MatchConfig {
+        strict: true,
+        quirks,
+        ..MatchConfig::default()
+    }
+}
+
+fn fixtures() -> Vec<(String, Fingerprint)> {
+    let mut files: Vec<_> = std::fs::read_dir(data_dir())
+        .unwrap()
+        .map(|it| it.unwrap().path())
+        .filter(|it| it.extension().and_then(|ext| ext.to_str()) == Some("xyt"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty());
+
+    let options = PruneOptions {
+        strict: true,
+        ..PruneOptions::default()
+    };
+    files
+        .iter()
+        .map(|it| {
+            let name = it.file_name().unwrap().to_str().unwrap().to_owned();
+            let raw = parse(it).unwrap();
+            (
+                name,
+                Fingerprint::from_raw(&raw, options, Format::NistInternal),
+            )
+        })
+        .collect()
+}
+
+/// Score of the pair and the quirks that changed its match.
+fn match_with(
+    probe: &Fingerprint,
+    gallery: &Fingerprint,
+    config: &MatchConfig,
+    pairs: &mut PairHolder,
+    state: &mut BozorthState,
+) -> (u32, CompatQuirks) {
+    pairs.clear();
+    match_edges_into_pairs(
+        probe.sorted_edges(),
+        &probe.minutiae,
+        gallery.sorted_edges(),
+        &gallery.minutiae,
+        pairs,
+        config,
+        |_: &Minutia, _: &Minutia, _: &Minutia, _: &Minutia| 1,
+    );
+    if pairs.pairs().is_empty() {
+        return (0, CompatQuirks::NONE);
+    }
+    pairs.prepare();
+    let score = match_score_with(
+        pairs,
+        &probe.minutiae,
+        &gallery.minutiae,
+        Format::NistInternal,
+        state,
+        config,
+    )
+    .map_or(0, |it| it.0);
+    (score, state.applied_quirks())
+}
+
+/// One `quirk probe gallery strict-score score-without-the-quirk` line for every pair whose
+/// score the quirk changes.
+fn quirk_impact() -> String {
+    let fixtures = fixtures();
+    let (mut pairs, mut state) = (PairHolder::new(), BozorthState::new());
+    let mut impact = String::new();
+    for (quirk, name) in CompatQuirks::ALL.iter() {
+        let without = strict(Some(CompatQuirks::ALL - quirk));
+        for (probe_name, probe) in &fixtures {
+            for (gallery_name, gallery) in &fixtures {
+                let mut score = |config: &MatchConfig| {
+                    match_with(probe, gallery, config, &mut pairs, &mut state).0
+                };
+                let (with, without) = (score(&strict(None)), score(&without));
+                if with != without {
+                    writeln!(
+                        impact,
+                        "{} {} {} {} {}",
+                        name, probe_name, gallery_name, with, without
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+    impact
+}
+
+#[test]
+fn score_impact_of_every_quirk_matches_recorded_values() {
+    let actual = quirk_impact();
+
+    let expected_path = data_dir().join("quirk_impact.txt");
+    if std::env::var_os("BOZORTH_BLESS").is_some() {
+        std::fs::write(&expected_path, &actual).unwrap();
+    }
+
+    let expected = std::fs::read_to_string(&expected_path).unwrap();
+    for (line, (actual, expected)) in actual.lines().zip(expected.lines()).enumerate() {
+        assert_eq!(actual, expected, "mismatch at line {}", line + 1);
+    }
+    assert_eq!(actual.lines().count(), expected.lines().count());
+}
+
+#[test]
+fn quirks_follow_the_mode_unless_chosen() {
+    let fixtures = fixtures();
+    let (mut pairs, mut state) = (PairHolder::new(), BozorthState::new());
+    let relaxed = MatchConfig {
+        strict: false,
+        ..MatchConfig::default()
+    };
+    let mut applied = CompatQuirks::NONE;
+    for (_, probe) in &fixtures {
+        for (_, gallery) in &fixtures {
+            let mut scores =
+                |config: &MatchConfig| match_with(probe, gallery, config, &mut pairs, &mut state);
+            let (score, quirks) = scores(&strict(None));
+            assert_eq!((score, quirks), scores(&strict(Some(CompatQuirks::ALL))));
+            applied = applied | quirks;
+            assert_eq!(scores(&relaxed).1, CompatQuirks::NONE);
+        }
+    }
+    // the quirks detected in `match_score` change the course of some matches of the fixtures
+    assert!(!applied.is_empty());
+}
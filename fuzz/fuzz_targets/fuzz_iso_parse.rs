@@ -0,0 +1,13 @@
+#![no_main]
+
+use isoparser::{parse_iso, Parser};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(record) = parse_iso(data) {
+        // every minutia takes at least 5 bytes of the record
+        let minutiae: usize = record.views.iter().map(|it| it.minutiae.len()).sum();
+        assert!(minutiae * 5 <= data.len());
+    }
+    let _ = Parser::strict().parse(data);
+});
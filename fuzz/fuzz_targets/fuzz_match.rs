@@ -0,0 +1,78 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use bozorth::parsing::{RawMinutiaCombined, MAX_ANGLE, MAX_COORDINATE, MAX_QUALITY};
+use bozorth::types::MinutiaKind;
+use bozorth::{
+    match_fingerprints_with, BozorthState, Fingerprint, Format, MatchConfig, PairHolder,
+    PruneOptions,
+};
+use libfuzzer_sys::fuzz_target;
+
+/// Minutia with values in the ranges accepted by the .xyt parser.
+#[derive(Arbitrary, Debug)]
+struct InputMinutia {
+    x: i32,
+    y: i32,
+    theta: i32,
+    quality: i32,
+    kind: u8,
+}
+
+impl InputMinutia {
+    fn raw(&self) -> RawMinutiaCombined {
+        let clamp = |value: i32, max: i32| value.rem_euclid(2 * max + 1) - max;
+        RawMinutiaCombined {
+            x: clamp(self.x, MAX_COORDINATE),
+            y: clamp(self.y, MAX_COORDINATE),
+            t: clamp(self.theta, MAX_ANGLE),
+            q: self.quality.rem_euclid(MAX_QUALITY + 1),
+            kind: match self.kind % 4 {
+                0 => MinutiaKind::Ending,
+                1 => MinutiaKind::Bifurcation,
+                2 => MinutiaKind::Other,
+                _ => MinutiaKind::Unknown,
+            },
+        }
+    }
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    strict: bool,
+    ansi: bool,
+    max_minutiae: u8,
+    probe: Vec<InputMinutia>,
+    gallery: Vec<InputMinutia>,
+}
+
+fuzz_target!(|input: Input| {
+    let format = if input.ansi {
+        Format::Ansi
+    } else {
+        Format::NistInternal
+    };
+    let options = PruneOptions {
+        max_minutiae: u32::from(input.max_minutiae),
+        strict: input.strict,
+        ..PruneOptions::default()
+    };
+    let config = MatchConfig {
+        strict: input.strict,
+        ..MatchConfig::default()
+    };
+    let fingerprint = |minutiae: &[InputMinutia]| {
+        let raw: Vec<_> = minutiae.iter().map(InputMinutia::raw).collect();
+        Fingerprint::from_raw(&raw, options, format)
+    };
+    let probe = fingerprint(&input.probe);
+    let gallery = fingerprint(&input.gallery);
+
+    let mut pairs = PairHolder::new();
+    let mut state = BozorthState::new();
+    for (first, second) in [(&probe, &gallery), (&gallery, &probe)] {
+        // every pair of edges is worth a single point in either direction
+        let score = match_fingerprints_with(first, second, format, &mut pairs, &mut state, &config);
+        assert!(score as usize <= pairs.pairs().len());
+    }
+});
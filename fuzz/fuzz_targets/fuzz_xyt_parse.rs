@@ -0,0 +1,18 @@
+#![no_main]
+
+use bozorth::parse_combined;
+use bozorth::parsing::{parse_min_str, parse_xyt_str};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let content = match std::str::from_utf8(data) {
+        Ok(content) => content,
+        Err(_) => return,
+    };
+    if let Ok(minutiae) = parse_xyt_str(content) {
+        assert!(minutiae.len() <= content.lines().count());
+    }
+    let _ = parse_min_str(content);
+    // the same content as both files of a template
+    let _ = parse_combined(content, Some(content));
+});
@@ -1,22 +1,69 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::io::Read;
 use std::path::Path;
 
 #[derive(Debug)]
 pub struct Record {
+    pub version: IsoVersion,
     pub capture_equipment: u16,
     pub x_image_size: u16,
     pub y_image_size: u16,
     pub x_resolution: u16,
     pub y_resolution: u16,
     pub views: Vec<View>,
+    /// How the record length of the header was interpreted.
+    pub header_variant: HeaderVariant,
+}
+
+/// Interpretation of the record length in the header, see `Parser::tolerant_header`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum HeaderVariant {
+    /// 4-byte length of the whole record, as the standard defines it.
+    Exact,
+    /// 4-byte length of the record without the 8 bytes of the format identifier and version.
+    ExcludingSignature,
+    /// 2-byte length of the whole record, the header is 2 bytes shorter.
+    ShortLength,
 }
 
 #[derive(Debug)]
 pub struct View {
-    pub finger_position: u8,
-    pub impr_type: u8,
+    pub finger_position: FingerPosition,
+    pub impr_type: ImpressionType,
     pub finger_quality: u8,
     pub minutiae: Vec<Minutia>,
+    /// Representation number (2011 only).
+    pub representation_number: Option<u8>,
+    /// Quality records of the representation (2011 only).
+    pub quality_records: Option<Vec<QualityRecord>>,
+    /// Size of the scanned image in pixels (2011 only; 2005 has it in the record header).
+    pub image_size: Option<(u16, u16)>,
+    /// Horizontal and vertical resolution in pixels per cm (2011 only).
+    pub resolution: Option<(u16, u16)>,
+    /// Ridge ending type: 0 for ridge skeleton endpoints, 1 for valley skeleton bifurcations
+    /// (2011 only).
+    pub ridge_ending_type: Option<u8>,
+}
+
+/// Quality score of a representation together with the algorithm that computed it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct QualityRecord {
+    /// Quality in range 0-100; 254 means not computed and 255 a failed attempt.
+    pub score: u8,
+    pub vendor_id: u16,
+    pub algorithm_id: u16,
+}
+
+impl View {
+    /// Finger position code as stored in the record, also when it is not defined by the standard.
+    pub fn finger_position_code(&self) -> u8 {
+        self.finger_position.code()
+    }
+
+    /// Impression type code as stored in the record, also when it is not defined by the standard.
+    pub fn impression_type_code(&self) -> u8 {
+        self.impr_type.code()
+    }
 }
 
 #[derive(Debug)]
@@ -32,9 +79,68 @@ pub struct Minutia {
 pub enum ParseError {
     InvalidFormat,
     InvalidLength,
+    UnsupportedVersion([u8; 4]),
+    /// Finger position code not defined by the standard, reported by the strict parser.
+    InvalidFingerPosition(u8),
+    /// Impression type code not defined by the standard, reported by the strict parser.
+    InvalidImpressionType(u8),
+    /// The record is larger than a limit of `ParseLimits`.
+    LimitExceeded(Limit),
     Io(std::io::Error),
 }
 
+/// Limit of `ParseLimits` that a record exceeded.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Limit {
+    Views,
+    MinutiaePerView,
+    TotalMinutiae,
+    RecordBytes,
+}
+
+/// Largest records the parser accepts, for records from untrusted sources.
+///
+/// Counts declared by a record are checked before anything is allocated for them. The default
+/// limits accept every record.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ParseLimits {
+    pub max_views: usize,
+    pub max_minutiae_per_view: usize,
+    /// Minutiae of all views together.
+    pub max_total_minutiae: usize,
+    pub max_record_bytes: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_views: usize::MAX,
+            max_minutiae_per_view: usize::MAX,
+            max_total_minutiae: usize::MAX,
+            max_record_bytes: usize::MAX,
+        }
+    }
+}
+
+impl ParseLimits {
+    fn check(value: usize, limit: usize, which: Limit) -> Result<(), ParseError> {
+        if value > limit {
+            return Err(ParseError::LimitExceeded(which));
+        }
+        Ok(())
+    }
+
+    /// Checks the minutiae of the next view, given the minutiae of the views before it.
+    fn check_minutiae(&self, view: usize, before: usize) -> Result<(), ParseError> {
+        Self::check(view, self.max_minutiae_per_view, Limit::MinutiaePerView)?;
+        Self::check(
+            before.saturating_add(view),
+            self.max_total_minutiae,
+            Limit::TotalMinutiae,
+        )
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 #[repr(u8)]
 pub enum MinutiaType {
@@ -43,78 +149,549 @@ pub enum MinutiaType {
     RidgeBifurcation = 0b10,
 }
 
-pub fn load_iso(path: impl AsRef<Path>) -> Result<Record, ParseError> {
-    let file = std::fs::read(path).map_err(ParseError::Io)?;
+/// Finger position as coded by ISO/IEC 19794-2.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum FingerPosition {
+    Unknown,
+    RightThumb,
+    RightIndex,
+    RightMiddle,
+    RightRing,
+    RightLittle,
+    LeftThumb,
+    LeftIndex,
+    LeftMiddle,
+    LeftRing,
+    LeftLittle,
+    /// Code not defined by the standard, kept by the lenient parser.
+    Undefined(u8),
+}
+
+impl FingerPosition {
+    const ALL: [FingerPosition; 11] = [
+        FingerPosition::Unknown,
+        FingerPosition::RightThumb,
+        FingerPosition::RightIndex,
+        FingerPosition::RightMiddle,
+        FingerPosition::RightRing,
+        FingerPosition::RightLittle,
+        FingerPosition::LeftThumb,
+        FingerPosition::LeftIndex,
+        FingerPosition::LeftMiddle,
+        FingerPosition::LeftRing,
+        FingerPosition::LeftLittle,
+    ];
+
+    /// Returns the position with the given ISO code, if it is defined.
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            FingerPosition::Undefined(code) => code,
+            defined => Self::ALL.iter().position(|&it| it == defined).unwrap() as u8,
+        }
+    }
+
+    /// Name in kebab case, e.g. `right-thumb`.
+    pub fn name(self) -> &'static str {
+        match self {
+            FingerPosition::Unknown => "unknown",
+            FingerPosition::RightThumb => "right-thumb",
+            FingerPosition::RightIndex => "right-index",
+            FingerPosition::RightMiddle => "right-middle",
+            FingerPosition::RightRing => "right-ring",
+            FingerPosition::RightLittle => "right-little",
+            FingerPosition::LeftThumb => "left-thumb",
+            FingerPosition::LeftIndex => "left-index",
+            FingerPosition::LeftMiddle => "left-middle",
+            FingerPosition::LeftRing => "left-ring",
+            FingerPosition::LeftLittle => "left-little",
+            FingerPosition::Undefined(_) => "undefined",
+        }
+    }
+}
+
+impl TryFrom<u8> for FingerPosition {
+    type Error = ParseError;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        FingerPosition::from_code(code).ok_or(ParseError::InvalidFingerPosition(code))
+    }
+}
+
+impl std::str::FromStr for FingerPosition {
+    type Err = String;
 
-    let format_id: [u8; 4] = file[0..4]
-        .try_into()
-        .map_err(|_| ParseError::InvalidFormat)?;
-    if &format_id != b"FMR\0" {
+    /// Parses either the ISO code or the name of the position.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let position = match s.parse::<u8>() {
+            Ok(code) => FingerPosition::from_code(code),
+            Err(_) => Self::ALL.iter().copied().find(|it| it.name() == s),
+        };
+        position.ok_or_else(|| format!("unknown finger position: {}", s))
+    }
+}
+
+/// Impression type as coded by ISO/IEC 19794-2.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ImpressionType {
+    LiveScanPlain,
+    LiveScanRolled,
+    NonLiveScanPlain,
+    NonLiveScanRolled,
+    Swipe,
+    /// Code not defined by the standard, kept by the lenient parser.
+    Undefined(u8),
+}
+
+impl ImpressionType {
+    /// Returns the impression type with the given ISO code, if it is defined.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(ImpressionType::LiveScanPlain),
+            1 => Some(ImpressionType::LiveScanRolled),
+            2 => Some(ImpressionType::NonLiveScanPlain),
+            3 => Some(ImpressionType::NonLiveScanRolled),
+            8 => Some(ImpressionType::Swipe),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            ImpressionType::LiveScanPlain => 0,
+            ImpressionType::LiveScanRolled => 1,
+            ImpressionType::NonLiveScanPlain => 2,
+            ImpressionType::NonLiveScanRolled => 3,
+            ImpressionType::Swipe => 8,
+            ImpressionType::Undefined(code) => code,
+        }
+    }
+}
+
+impl TryFrom<u8> for ImpressionType {
+    type Error = ParseError;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        ImpressionType::from_code(code).ok_or(ParseError::InvalidImpressionType(code))
+    }
+}
+
+impl Record {
+    /// Views of the given finger, in the order of the record.
+    pub fn views_for_position(&self, position: FingerPosition) -> impl Iterator<Item = &View> {
+        self.views
+            .iter()
+            .filter(move |view| view.finger_position == position)
+    }
+}
+
+/// Revision of the ISO/IEC 19794-2 standard.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum IsoVersion {
+    /// ISO/IEC 19794-2:2005, version " 20\0".
+    V2005,
+    /// ISO/IEC 19794-2:2011, version "030\0".
+    V2011,
+}
+
+impl IsoVersion {
+    /// Detects the version from the header of a record.
+    pub fn detect(file: &[u8]) -> Result<Self, ParseError> {
+        let mut reader = Reader::new(file);
+        if reader.bytes(4)? != b"FMR\0" {
+            return Err(ParseError::InvalidFormat);
+        }
+
+        let version: [u8; 4] = reader.bytes(4)?.try_into().unwrap();
+        match &version {
+            b" 20\0" => Ok(IsoVersion::V2005),
+            b"030\0" => Ok(IsoVersion::V2011),
+            _ => Err(ParseError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+/// Bounds-checked big-endian reader of a record.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], ParseError> {
+        if self.data.len() < count {
+            return Err(ParseError::InvalidLength);
+        }
+        let (head, tail) = self.data.split_at(count);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ParseError> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Checks that `count` items of at least `size` bytes each can still be read, so that counts
+    /// declared by a record are not trusted beyond the data that is actually there.
+    fn expect_items(&self, count: usize, size: usize) -> Result<(), ParseError> {
+        if count.saturating_mul(size) > self.data.len() {
+            return Err(ParseError::InvalidLength);
+        }
+        Ok(())
+    }
+}
+
+fn read_minutia(reader: &mut Reader, with_quality: bool) -> Result<Minutia, ParseError> {
+    let raw_x = reader.u16()?;
+    let raw_y = reader.u16()?;
+    const MASK: u16 = 0b11000000_00000000;
+    let ty = (raw_x & MASK) >> (MASK.trailing_zeros() as u16);
+    let x = raw_x & !MASK;
+    let y = raw_y & !MASK;
+
+    let angle = reader.u8()?;
+    let quality = if with_quality { reader.u8()? } else { 0 };
+    Ok(Minutia {
+        ty: match ty {
+            0b00 => MinutiaType::Other,
+            0b01 => MinutiaType::RidgeEnding,
+            0b10 => MinutiaType::RidgeBifurcation,
+            _ => return Err(ParseError::InvalidFormat),
+        },
+        x,
+        y,
+        angle: angle as f32 * 1.40625f32,
+        quality,
+    })
+}
+
+fn skip_extended_data(reader: &mut Reader) -> Result<(), ParseError> {
+    // the block is optional in the last view of records that omit it entirely
+    if reader.is_empty() {
+        return Ok(());
+    }
+    let length = reader.u16()?;
+    reader.bytes(length as usize)?;
+    Ok(())
+}
+
+/// Checks the format identifier and the record length; returns the data after the length and
+/// how the length was interpreted.
+fn check_header<'a>(
+    parser: &Parser,
+    file: &'a [u8],
+    expected: IsoVersion,
+) -> Result<(Reader<'a>, HeaderVariant), ParseError> {
+    let version = IsoVersion::detect(file)?;
+    if version != expected {
         return Err(ParseError::InvalidFormat);
     }
 
-    let length = u32::from_be_bytes(file[8..12].try_into().unwrap());
-    if length != file.len() as u32 {
-        return Err(ParseError::InvalidLength);
+    let mut reader = Reader::new(&file[8..]);
+    let length = reader.u32()? as usize;
+    if length == file.len() {
+        return Ok((reader, HeaderVariant::Exact));
+    }
+    if parser.tolerant_header {
+        if length.checked_add(8) == Some(file.len()) {
+            return Ok((reader, HeaderVariant::ExcludingSignature));
+        }
+        let mut reader = Reader::new(&file[8..]);
+        if reader.u16()? as usize == file.len() {
+            return Ok((reader, HeaderVariant::ShortLength));
+        }
+    }
+    Err(ParseError::InvalidLength)
+}
+
+/// Parser of records with options for handling content that does not follow the standard.
+///
+/// The `load_iso*` and `parse_iso*` functions use the default, lenient parser.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Parser {
+    /// Rejects finger positions and impression types not defined by the standard instead of
+    /// keeping them as `Undefined`.
+    pub strict: bool,
+    pub limits: ParseLimits,
+    /// Accepts record lengths of known encoders that do not follow the standard, see
+    /// `HeaderVariant`, instead of rejecting them as `InvalidLength`.
+    pub tolerant_header: bool,
+}
+
+impl Parser {
+    pub fn strict() -> Self {
+        Parser {
+            strict: true,
+            ..Parser::default()
+        }
+    }
+
+    /// Loads a record of any supported version, from a gzip-compressed file as well.
+    ///
+    /// Reads at most one byte over `max_record_bytes` of the limits, counted after decompression.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<Record, ParseError> {
+        let limit = (self.limits.max_record_bytes as u64).saturating_add(1);
+        let file = read_file(path, limit).map_err(ParseError::Io)?;
+        self.parse(&file)
+    }
+
+    /// Parses a record of any supported version, detected from its header.
+    pub fn parse(&self, file: &[u8]) -> Result<Record, ParseError> {
+        ParseLimits::check(file.len(), self.limits.max_record_bytes, Limit::RecordBytes)?;
+        match IsoVersion::detect(file)? {
+            IsoVersion::V2005 => parse_v2005(self, file),
+            IsoVersion::V2011 => parse_v2011(self, file),
+        }
+    }
+
+    fn finger_position(&self, code: u8) -> Result<FingerPosition, ParseError> {
+        match FingerPosition::try_from(code) {
+            Err(_) if !self.strict => Ok(FingerPosition::Undefined(code)),
+            result => result,
+        }
+    }
+
+    fn impression_type(&self, code: u8) -> Result<ImpressionType, ParseError> {
+        match ImpressionType::try_from(code) {
+            Err(_) if !self.strict => Ok(ImpressionType::Undefined(code)),
+            result => result,
+        }
     }
+}
+
+/// First bytes of a gzip stream; records start with "FMR" instead.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-    let capture_equipment = u16::from_be_bytes(file[12..14].try_into().unwrap());
-    let x_image_size = u16::from_be_bytes(file[14..16].try_into().unwrap());
-    let y_image_size = u16::from_be_bytes(file[16..18].try_into().unwrap());
-    let x_resolution = u16::from_be_bytes(file[18..20].try_into().unwrap());
-    let y_resolution = u16::from_be_bytes(file[20..22].try_into().unwrap());
-    let n_finger_views = file[22];
-    let _reserved_byte = file[23];
+/// Reads at most `limit` bytes of a file, decompressing it while reading when it starts with
+/// the gzip magic bytes.
+fn read_file(path: impl AsRef<Path>, limit: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut magic = vec![];
+    file.by_ref()
+        .take(GZIP_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+    let reader = magic.as_slice().chain(file);
+
+    let mut content = vec![];
+    if magic == GZIP_MAGIC {
+        flate2::read::MultiGzDecoder::new(reader)
+            .take(limit)
+            .read_to_end(&mut content)?;
+    } else {
+        reader.take(limit).read_to_end(&mut content)?;
+    }
+    Ok(content)
+}
+
+/// Loads a record of any supported version.
+pub fn load_iso(path: impl AsRef<Path>) -> Result<Record, ParseError> {
+    Parser::default().load(path)
+}
+
+/// Loads an ISO/IEC 19794-2:2005 record.
+pub fn load_iso_v2005(path: impl AsRef<Path>) -> Result<Record, ParseError> {
+    let file = read_file(path, u64::MAX).map_err(ParseError::Io)?;
+    parse_iso_v2005(&file)
+}
+
+/// Loads an ISO/IEC 19794-2:2011 record.
+pub fn load_iso_v2011(path: impl AsRef<Path>) -> Result<Record, ParseError> {
+    let file = read_file(path, u64::MAX).map_err(ParseError::Io)?;
+    parse_iso_v2011(&file)
+}
+
+/// Parses a record of any supported version, detected from its header.
+pub fn parse_iso(file: &[u8]) -> Result<Record, ParseError> {
+    Parser::default().parse(file)
+}
+
+pub fn parse_iso_v2005(file: &[u8]) -> Result<Record, ParseError> {
+    parse_v2005(&Parser::default(), file)
+}
+
+pub fn parse_iso_v2011(file: &[u8]) -> Result<Record, ParseError> {
+    parse_v2011(&Parser::default(), file)
+}
+
+fn parse_v2005(parser: &Parser, file: &[u8]) -> Result<Record, ParseError> {
+    let (mut reader, header_variant) = check_header(parser, file, IsoVersion::V2005)?;
+
+    let capture_equipment = reader.u16()?;
+    let x_image_size = reader.u16()?;
+    let y_image_size = reader.u16()?;
+    let x_resolution = reader.u16()?;
+    let y_resolution = reader.u16()?;
+    let n_finger_views = reader.u8()?;
+    let _reserved_byte = reader.u8()?;
 
     let mut record = Record {
+        version: IsoVersion::V2005,
         capture_equipment,
         x_image_size,
         y_image_size,
         x_resolution,
         y_resolution,
         views: Vec::new(),
+        header_variant,
     };
 
-    let views = &file[24..];
+    ParseLimits::check(
+        n_finger_views as usize,
+        parser.limits.max_views,
+        Limit::Views,
+    )?;
+    // position, impression type, quality and minutia count
+    reader.expect_items(n_finger_views as usize, 4)?;
+    let mut total_minutiae = 0;
     for _ in 0..n_finger_views as usize {
-        let finger_position = views[0];
-        let impr_type = views[1];
-        let finger_quality = views[2];
-        let minutiae = views[3];
+        let finger_position = parser.finger_position(reader.u8()?)?;
+        let impr_type = parser.impression_type(reader.u8()?)?;
+        let finger_quality = reader.u8()?;
+        let minutiae = reader.u8()?;
 
         let mut view = View {
             finger_position,
             impr_type,
             finger_quality,
             minutiae: Vec::new(),
+            representation_number: None,
+            quality_records: None,
+            image_size: None,
+            resolution: None,
+            ridge_ending_type: None,
         };
 
-        let mut views = &views[4..];
+        parser
+            .limits
+            .check_minutiae(minutiae as usize, total_minutiae)?;
+        total_minutiae += minutiae as usize;
+        reader.expect_items(minutiae as usize, 6)?;
         for _ in 0..minutiae as usize {
-            let raw_x = u16::from_be_bytes(views[0..2].try_into().unwrap());
-            let raw_y = u16::from_be_bytes(views[2..4].try_into().unwrap());
-            const MASK: u16 = 0b11000000_00000000;
-            let ty = (raw_x & MASK) >> (MASK.trailing_zeros() as u16);
-            let x = raw_x & !MASK;
-            let y = raw_y & !MASK;
-
-            let angle = views[4];
-            let quality = views[5];
-            view.minutiae.push(Minutia {
-                ty: match ty {
-                    0b00 => MinutiaType::Other,
-                    0b01 => MinutiaType::RidgeEnding,
-                    0b10 => MinutiaType::RidgeBifurcation,
-                    _ => return Err(ParseError::InvalidFormat),
-                },
-                x: x as u16,
-                y: y as u16,
-                angle: angle as f32 * 1.40625f32,
-                quality,
-            });
-            views = &views[6..];
+            view.minutiae.push(read_minutia(&mut reader, true)?);
         }
+        skip_extended_data(&mut reader)?;
         record.views.push(view);
     }
     Ok(record)
 }
+
+fn parse_v2011(parser: &Parser, file: &[u8]) -> Result<Record, ParseError> {
+    let (mut reader, header_variant) = check_header(parser, file, IsoVersion::V2011)?;
+
+    let n_representations = reader.u16()?;
+    let certification_flag = reader.u8()?;
+
+    ParseLimits::check(
+        n_representations as usize,
+        parser.limits.max_views,
+        Limit::Views,
+    )?;
+    // every representation starts with its length
+    reader.expect_items(n_representations as usize, 4)?;
+    let mut views = Vec::new();
+    let mut total_minutiae = 0;
+    let mut capture_equipment = 0;
+    for _ in 0..n_representations {
+        let representation_length = reader.u32()? as usize;
+        // the length includes its own 4 bytes
+        let body = representation_length
+            .checked_sub(4)
+            .ok_or(ParseError::InvalidLength)?;
+        let mut view_reader = Reader::new(reader.bytes(body)?);
+
+        let _capture_date_time = view_reader.bytes(9)?;
+        let _technology_id = view_reader.u8()?;
+        let _vendor_id = view_reader.u16()?;
+        let device_type_id = view_reader.u16()?;
+
+        let mut quality_records = vec![];
+        for _ in 0..view_reader.u8()? {
+            quality_records.push(QualityRecord {
+                score: view_reader.u8()?,
+                vendor_id: view_reader.u16()?,
+                algorithm_id: view_reader.u16()?,
+            });
+        }
+
+        if certification_flag != 0 {
+            let certifications = view_reader.u8()?;
+            view_reader.bytes(3 * certifications as usize)?;
+        }
+
+        let finger_position = parser.finger_position(view_reader.u8()?)?;
+        let representation_number = view_reader.u8()?;
+        let x_resolution = view_reader.u16()?;
+        let y_resolution = view_reader.u16()?;
+        let impr_type = parser.impression_type(view_reader.u8()?)?;
+        let x_image_size = view_reader.u16()?;
+        let y_image_size = view_reader.u16()?;
+        let field_length_and_ending_type = view_reader.u8()?;
+        let minutia_field_length = field_length_and_ending_type >> 4;
+        let ridge_ending_type = field_length_and_ending_type & 0x0f;
+        let with_quality = match minutia_field_length {
+            5 => false,
+            6 => true,
+            _ => return Err(ParseError::InvalidFormat),
+        };
+
+        let n_minutiae = view_reader.u8()?;
+        parser
+            .limits
+            .check_minutiae(n_minutiae as usize, total_minutiae)?;
+        total_minutiae += n_minutiae as usize;
+        let minutia_size = if with_quality { 6 } else { 5 };
+        view_reader.expect_items(n_minutiae as usize, minutia_size)?;
+        let mut minutiae = Vec::with_capacity(n_minutiae as usize);
+        for _ in 0..n_minutiae {
+            minutiae.push(read_minutia(&mut view_reader, with_quality)?);
+        }
+        skip_extended_data(&mut view_reader)?;
+
+        if views.is_empty() {
+            capture_equipment = device_type_id;
+        }
+        views.push(View {
+            finger_position,
+            impr_type,
+            finger_quality: quality_records.first().map_or(0, |it| it.score),
+            minutiae,
+            representation_number: Some(representation_number),
+            quality_records: Some(quality_records),
+            image_size: Some((x_image_size, y_image_size)),
+            resolution: Some((x_resolution, y_resolution)),
+            ridge_ending_type: Some(ridge_ending_type),
+        });
+    }
+
+    // the record header of 2005 holds the image properties; take them from the first view
+    let (x_image_size, y_image_size) = views.first().and_then(|it| it.image_size).unwrap_or((0, 0));
+    let (x_resolution, y_resolution) = views.first().and_then(|it| it.resolution).unwrap_or((0, 0));
+    Ok(Record {
+        version: IsoVersion::V2011,
+        capture_equipment,
+        x_image_size,
+        y_image_size,
+        x_resolution,
+        y_resolution,
+        views,
+        header_variant,
+    })
+}
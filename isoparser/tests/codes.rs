@@ -0,0 +1,68 @@
+use isoparser::{FingerPosition, ImpressionType, ParseError, Parser, Record};
+
+mod common;
+
+use common::fixture;
+
+/// Offsets of the finger position and impression type of the first view, see `generate.py`.
+const V2005_OFFSETS: (usize, usize) = (24, 25);
+const V2011_OFFSETS: (usize, usize) = (39, 45);
+
+/// Parses fixtures with the codes of the first view replaced.
+fn parse_with_codes(
+    parser: Parser,
+    finger_position: u8,
+    impr_type: u8,
+) -> Vec<Result<Record, ParseError>> {
+    [("v2005.ist", V2005_OFFSETS), ("v2011.ist", V2011_OFFSETS)]
+        .iter()
+        .map(|&(name, (position_offset, impr_offset))| {
+            let mut file = std::fs::read(fixture(name)).unwrap();
+            file[position_offset] = finger_position;
+            file[impr_offset] = impr_type;
+            parser.parse(&file)
+        })
+        .collect()
+}
+
+#[test]
+fn defined_codes_are_parsed_in_both_modes() {
+    for parser in &[Parser::default(), Parser::strict()] {
+        for code in 0..=10 {
+            for record in parse_with_codes(*parser, code, 0) {
+                let view = &record.unwrap().views[0];
+                assert_eq!(Some(view.finger_position), FingerPosition::from_code(code));
+                assert_ne!(view.finger_position.name(), "undefined");
+                assert_eq!(view.finger_position_code(), code);
+            }
+        }
+        for &code in &[0, 1, 2, 3, 8] {
+            for record in parse_with_codes(*parser, 1, code) {
+                let view = &record.unwrap().views[0];
+                assert_eq!(Some(view.impr_type), ImpressionType::from_code(code));
+                assert_eq!(view.impression_type_code(), code);
+            }
+        }
+    }
+}
+
+#[test]
+fn undefined_codes_are_kept_by_lenient_parser() {
+    for record in parse_with_codes(Parser::default(), 11, 5) {
+        let view = &record.unwrap().views[0];
+        assert_eq!(view.finger_position, FingerPosition::Undefined(11));
+        assert_eq!(view.finger_position_code(), 11);
+        assert_eq!(view.impr_type, ImpressionType::Undefined(5));
+        assert_eq!(view.impression_type_code(), 5);
+    }
+}
+
+#[test]
+fn undefined_codes_are_rejected_by_strict_parser() {
+    for record in parse_with_codes(Parser::strict(), 11, 0) {
+        assert!(matches!(record, Err(ParseError::InvalidFingerPosition(11))));
+    }
+    for record in parse_with_codes(Parser::strict(), 1, 5) {
+        assert!(matches!(record, Err(ParseError::InvalidImpressionType(5))));
+    }
+}
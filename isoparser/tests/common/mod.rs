@@ -0,0 +1,10 @@
+//! Helpers shared by the integration tests, included by each of them with `mod common;`.
+
+use std::path::PathBuf;
+
+/// Path of a file in `tests/data`, see `tests/data/generate.py`.
+pub fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
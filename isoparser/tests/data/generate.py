@@ -0,0 +1,81 @@
+#!/usr/bin/env python3
+"""Generates the ISO/IEC 19794-2 fixtures used by the tests of the parser."""
+
+import struct
+from pathlib import Path
+
+HERE = Path(__file__).parent
+
+# (type, x, y, angle in units of 1.40625 degrees, quality)
+MINUTIAE = [
+    (1, 100, 120, 0, 60),
+    (2, 210, 95, 64, 70),
+    (1, 150, 300, 128, 80),
+    (0, 45, 250, 255, 90),
+]
+
+
+def minutia(ty, x, y, angle, quality, with_quality=True):
+    data = struct.pack(">HHB", (ty << 14) | x, y, angle)
+    return data + (struct.pack(">B", quality) if with_quality else b"")
+
+
+def record_length(length, variant):
+    """Record length of the header as written by encoders that deviate from the standard:
+    `exact`, `excluding-signature` without the 8 bytes of the identifier and version, or
+    `short` in 2 bytes, which makes the header 2 bytes shorter."""
+    if variant == "exact":
+        return struct.pack(">I", length)
+    if variant == "excluding-signature":
+        return struct.pack(">I", length - 8)
+    return struct.pack(">H", length - 2)
+
+
+def v2005(fingers=((2, 75, MINUTIAE), (7, 40, MINUTIAE[:2])), variant="exact"):
+    views = b""
+    for position, quality, minutiae in fingers:
+        views += struct.pack(">BBBB", position, 0, quality, len(minutiae))
+        views += b"".join(minutia(*m) for m in minutiae)
+        # extended data block with 3 bytes of vendor data
+        views += struct.pack(">H", 3) + b"\x01\x02\x03"
+    header_length = 24
+    header = b"FMR\0" + b" 20\0"
+    header += record_length(header_length + len(views), variant)
+    header += struct.pack(">HHHHHBB", 0x0123, 500, 550, 197, 197, len(fingers), 0)
+    return header + views
+
+
+def v2011_representation(position, number, qualities, minutiae, with_quality):
+    body = b"\x07\xdd\x05\x11\x0c\x1e\x2d\xff\xff"  # capture date and time
+    body += struct.pack(">BHH", 1, 0x0031, 0x4567)  # technology, vendor, device type
+    body += struct.pack(">B", len(qualities))
+    body += b"".join(struct.pack(">BHH", *q) for q in qualities)
+    body += struct.pack(">BBHHB", position, number, 197, 200, 0)
+    body += struct.pack(">HH", 400, 480)
+    body += struct.pack(">B", ((6 if with_quality else 5) << 4) | 0)
+    body += struct.pack(">B", len(minutiae))
+    body += b"".join(minutia(*m, with_quality=with_quality) for m in minutiae)
+    body += struct.pack(">H", 0)  # no extended data
+    return struct.pack(">I", 4 + len(body)) + body
+
+
+def v2011(variant="exact"):
+    representations = v2011_representation(2, 0, [(75, 0x0031, 0x0002)], MINUTIAE, True)
+    representations += v2011_representation(7, 1, [], MINUTIAE[:2], False)
+    header_length = 15
+    header = b"FMR\0" + b"030\0"
+    header += record_length(header_length + len(representations), variant)
+    header += struct.pack(">HB", 2, 0)
+    return header + representations
+
+
+if __name__ == "__main__":
+    (HERE / "v2005.ist").write_bytes(v2005())
+    (HERE / "v2011.ist").write_bytes(v2011())
+    # record lengths of encoders that deviate from the standard
+    (HERE / "v2005_excluding_signature.ist").write_bytes(v2005(variant="excluding-signature"))
+    (HERE / "v2005_short_length.ist").write_bytes(v2005(variant="short"))
+    (HERE / "v2011_excluding_signature.ist").write_bytes(v2011(variant="excluding-signature"))
+    (HERE / "v2011_short_length.ist").write_bytes(v2011(variant="short"))
+    # right thumb with 3 minutiae followed by right index with 4
+    (HERE / "thumb_and_index.ist").write_bytes(v2005(((1, 50, MINUTIAE[1:]), (2, 60, MINUTIAE))))
@@ -0,0 +1,110 @@
+use isoparser::{load_iso, HeaderVariant, ParseError, Parser, Record};
+
+mod common;
+
+use common::fixture;
+
+fn tolerant() -> Parser {
+    Parser {
+        tolerant_header: true,
+        ..Parser::default()
+    }
+}
+
+fn minutiae(record: &Record) -> Vec<Vec<(u16, u16, u8)>> {
+    record
+        .views
+        .iter()
+        .map(|view| {
+            view.minutiae
+                .iter()
+                .map(|m| (m.x, m.y, m.quality))
+                .collect()
+        })
+        .collect()
+}
+
+/// Fixtures with the record lengths of encoders that deviate from the standard, see
+/// `tests/data/generate.py`, with the fixture of the same record and the expected variant.
+const DEVIATIONS: [(&str, &str, HeaderVariant); 4] = [
+    (
+        "v2005_excluding_signature.ist",
+        "v2005.ist",
+        HeaderVariant::ExcludingSignature,
+    ),
+    (
+        "v2005_short_length.ist",
+        "v2005.ist",
+        HeaderVariant::ShortLength,
+    ),
+    (
+        "v2011_excluding_signature.ist",
+        "v2011.ist",
+        HeaderVariant::ExcludingSignature,
+    ),
+    (
+        "v2011_short_length.ist",
+        "v2011.ist",
+        HeaderVariant::ShortLength,
+    ),
+];
+
+#[test]
+fn standard_lengths_are_exact() {
+    for name in ["v2005.ist", "v2011.ist"] {
+        assert_eq!(
+            load_iso(fixture(name)).unwrap().header_variant,
+            HeaderVariant::Exact
+        );
+        assert_eq!(
+            tolerant().load(fixture(name)).unwrap().header_variant,
+            HeaderVariant::Exact
+        );
+    }
+}
+
+#[test]
+fn deviating_lengths_are_rejected_by_default() {
+    for (name, _, _) in DEVIATIONS {
+        assert!(
+            matches!(load_iso(fixture(name)), Err(ParseError::InvalidLength)),
+            "{}",
+            name
+        );
+        assert!(matches!(
+            Parser::strict().load(fixture(name)),
+            Err(ParseError::InvalidLength)
+        ));
+    }
+}
+
+#[test]
+fn tolerant_parser_reads_deviating_lengths_like_standard_ones() {
+    for (name, standard, variant) in DEVIATIONS {
+        let record = tolerant().load(fixture(name)).unwrap();
+        let expected = load_iso(fixture(standard)).unwrap();
+        assert_eq!(record.header_variant, variant, "{}", name);
+        assert_eq!(record.version, expected.version);
+        assert_eq!(record.capture_equipment, expected.capture_equipment);
+        assert_eq!(minutiae(&record), minutiae(&expected));
+    }
+}
+
+#[test]
+fn tolerant_parser_rejects_other_lengths() {
+    let mut file = std::fs::read(fixture("v2005.ist")).unwrap();
+    // one byte of padding matches none of the interpretations
+    file.push(0);
+    assert!(matches!(
+        tolerant().parse(&file),
+        Err(ParseError::InvalidLength)
+    ));
+
+    // neither does a length of zero
+    let mut file = std::fs::read(fixture("v2011.ist")).unwrap();
+    file[8..12].copy_from_slice(&[0; 4]);
+    assert!(matches!(
+        tolerant().parse(&file),
+        Err(ParseError::InvalidLength)
+    ));
+}
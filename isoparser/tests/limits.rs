@@ -0,0 +1,149 @@
+use isoparser::{Limit, ParseError, ParseLimits, Parser};
+
+mod common;
+
+use common::fixture;
+
+fn parser(limits: ParseLimits) -> Parser {
+    Parser {
+        limits,
+        ..Parser::default()
+    }
+}
+
+/// A 2005 record whose views declare `minutiae` minutiae each, without the data of any of them.
+fn v2005_declaring(views: u8, minutiae: u8) -> Vec<u8> {
+    let mut file = b"FMR\0 20\0".to_vec();
+    file.extend_from_slice(&[0; 4]);
+    file.extend_from_slice(&[0; 10]);
+    file.extend_from_slice(&[views, 0]);
+    if views > 0 {
+        file.extend_from_slice(&[2, 0, 60, minutiae]);
+    }
+    let length = file.len() as u32;
+    file[8..12].copy_from_slice(&length.to_be_bytes());
+    file
+}
+
+fn assert_limit_exceeded(result: Result<isoparser::Record, ParseError>, limit: Limit) {
+    match result {
+        Err(ParseError::LimitExceeded(exceeded)) => assert_eq!(exceeded, limit),
+        other => panic!("expected {:?} to be exceeded, got {:?}", limit, other),
+    }
+}
+
+#[test]
+fn records_at_the_limits_are_parsed_and_larger_ones_rejected() {
+    for name in ["v2005.ist", "v2011.ist", "thumb_and_index.ist"] {
+        let file = std::fs::read(fixture(name)).unwrap();
+        let record = Parser::default().parse(&file).unwrap();
+        let views = record.views.len();
+        let per_view = record
+            .views
+            .iter()
+            .map(|it| it.minutiae.len())
+            .max()
+            .unwrap();
+        let total = record.views.iter().map(|it| it.minutiae.len()).sum();
+
+        let exact = ParseLimits {
+            max_views: views,
+            max_minutiae_per_view: per_view,
+            max_total_minutiae: total,
+            max_record_bytes: file.len(),
+        };
+        assert!(parser(exact).parse(&file).is_ok(), "{}", name);
+
+        let cases = [
+            (
+                ParseLimits {
+                    max_views: views - 1,
+                    ..exact
+                },
+                Limit::Views,
+            ),
+            (
+                ParseLimits {
+                    max_minutiae_per_view: per_view - 1,
+                    ..exact
+                },
+                Limit::MinutiaePerView,
+            ),
+            (
+                ParseLimits {
+                    max_total_minutiae: total - 1,
+                    ..exact
+                },
+                Limit::TotalMinutiae,
+            ),
+            (
+                ParseLimits {
+                    max_record_bytes: file.len() - 1,
+                    ..exact
+                },
+                Limit::RecordBytes,
+            ),
+        ];
+        for (limits, limit) in cases {
+            assert_limit_exceeded(parser(limits).parse(&file), limit);
+        }
+    }
+}
+
+#[test]
+fn declared_counts_are_checked_before_the_data_is_read() {
+    let limits = ParseLimits {
+        max_views: 4,
+        max_minutiae_per_view: 100,
+        ..ParseLimits::default()
+    };
+    assert_limit_exceeded(parser(limits).parse(&v2005_declaring(255, 0)), Limit::Views);
+    assert_limit_exceeded(
+        parser(limits).parse(&v2005_declaring(1, 255)),
+        Limit::MinutiaePerView,
+    );
+
+    // within the limits, the missing data is what is reported
+    assert!(matches!(
+        parser(limits).parse(&v2005_declaring(1, 100)),
+        Err(ParseError::InvalidLength)
+    ));
+}
+
+#[test]
+fn loading_stops_at_the_byte_limit() {
+    let path = std::env::temp_dir().join(format!("limits-{}.ist", std::process::id()));
+    let mut file = v2005_declaring(1, 255);
+    file.resize(1 << 20, 0);
+    std::fs::write(&path, &file).unwrap();
+
+    let limits = ParseLimits {
+        max_record_bytes: 1000,
+        ..ParseLimits::default()
+    };
+    let result = parser(limits).load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_limit_exceeded(result, Limit::RecordBytes);
+}
+
+#[test]
+fn loading_counts_the_byte_limit_after_decompression() {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    // a megabyte of zeros compresses to about a kilobyte
+    let path = std::env::temp_dir().join(format!("limits-{}.ist.gz", std::process::id()));
+    let mut file = v2005_declaring(1, 255);
+    file.resize(1 << 20, 0);
+    let mut encoder = GzEncoder::new(vec![], flate2::Compression::best());
+    encoder.write_all(&file).unwrap();
+    std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+    let limits = ParseLimits {
+        max_record_bytes: 100_000,
+        ..ParseLimits::default()
+    };
+    let result = parser(limits).load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_limit_exceeded(result, Limit::RecordBytes);
+}
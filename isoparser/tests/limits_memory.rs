@@ -0,0 +1,94 @@
+//! A single test, so that no other test allocates while the peak is measured.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use isoparser::{Limit, ParseError, ParseLimits, Parser};
+
+/// Allocator that keeps track of the most bytes allocated at once.
+struct Counting;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let current = CURRENT.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        PEAK.fetch_max(current, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+#[test]
+fn oversized_records_are_rejected_without_allocating_for_them() {
+    // 2005 record of 255 views with 255 minutiae each
+    let mut file = b"FMR\0 20\0".to_vec();
+    file.extend_from_slice(&[0; 14]);
+    file.extend_from_slice(&[255, 0]);
+    for _ in 0..255 {
+        file.extend_from_slice(&[2, 0, 60, 255]);
+        file.extend_from_slice(&[0x40, 10, 0, 20, 0, 50].repeat(255));
+        file.extend_from_slice(&[0, 0]);
+    }
+    let length = file.len() as u32;
+    file[8..12].copy_from_slice(&length.to_be_bytes());
+    let path = std::env::temp_dir().join(format!("limits-memory-{}.ist", std::process::id()));
+    std::fs::write(&path, &file).unwrap();
+
+    let cases = [
+        (
+            ParseLimits {
+                max_record_bytes: 4096,
+                ..ParseLimits::default()
+            },
+            Limit::RecordBytes,
+        ),
+        (
+            ParseLimits {
+                max_views: 4,
+                ..ParseLimits::default()
+            },
+            Limit::Views,
+        ),
+        (
+            ParseLimits {
+                max_total_minutiae: 300,
+                ..ParseLimits::default()
+            },
+            Limit::TotalMinutiae,
+        ),
+    ];
+    for (limits, limit) in cases {
+        let parser = Parser {
+            limits,
+            ..Parser::default()
+        };
+        let before = CURRENT.load(Ordering::SeqCst);
+        PEAK.store(before, Ordering::SeqCst);
+        let result = parser.parse(&file);
+        let parsed = PEAK.load(Ordering::SeqCst) - before;
+        assert!(matches!(result, Err(ParseError::LimitExceeded(it)) if it == limit));
+        // at most the views before the limit is hit, nothing for all the declared minutiae
+        assert!(parsed < 64 * 1024, "{:?}: {} bytes", limit, parsed);
+
+        if limit == Limit::RecordBytes {
+            let before = CURRENT.load(Ordering::SeqCst);
+            PEAK.store(before, Ordering::SeqCst);
+            let result = parser.load(&path);
+            let loaded = PEAK.load(Ordering::SeqCst) - before;
+            assert!(matches!(
+                result,
+                Err(ParseError::LimitExceeded(Limit::RecordBytes))
+            ));
+            assert!(loaded < 16 * 1024, "{} bytes", loaded);
+        }
+    }
+    std::fs::remove_file(&path).unwrap();
+}
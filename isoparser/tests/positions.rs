@@ -0,0 +1,49 @@
+use isoparser::{load_iso, FingerPosition};
+
+mod common;
+
+use common::fixture;
+
+#[test]
+fn selects_views_of_the_requested_finger() {
+    let record = load_iso(fixture("thumb_and_index.ist")).unwrap();
+
+    let thumbs: Vec<_> = record
+        .views_for_position(FingerPosition::RightThumb)
+        .collect();
+    assert_eq!(thumbs.len(), 1);
+    assert_eq!(thumbs[0].finger_position, FingerPosition::RightThumb);
+    assert_eq!(thumbs[0].minutiae.len(), 3);
+
+    let indices: Vec<_> = record
+        .views_for_position(FingerPosition::RightIndex)
+        .collect();
+    assert_eq!(indices.len(), 1);
+    assert_eq!(indices[0].finger_position_code(), 2);
+    assert_eq!(indices[0].minutiae.len(), 4);
+
+    assert!(record
+        .views_for_position(FingerPosition::LeftIndex)
+        .next()
+        .is_none());
+}
+
+#[test]
+fn positions_map_iso_codes() {
+    for code in 0..=10 {
+        let position = FingerPosition::from_code(code).unwrap();
+        assert_eq!(position.code(), code);
+        assert_eq!(position.name().parse::<FingerPosition>(), Ok(position));
+        assert_eq!(code.to_string().parse::<FingerPosition>(), Ok(position));
+    }
+    assert_eq!(
+        FingerPosition::from_code(1),
+        Some(FingerPosition::RightThumb)
+    );
+    assert_eq!(
+        FingerPosition::from_code(10),
+        Some(FingerPosition::LeftLittle)
+    );
+    assert_eq!(FingerPosition::from_code(11), None);
+    assert!("left-toe".parse::<FingerPosition>().is_err());
+}
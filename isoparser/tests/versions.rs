@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+
+use isoparser::{
+    load_iso, load_iso_v2005, load_iso_v2011, parse_iso, IsoVersion, MinutiaType, ParseError,
+    QualityRecord, Record,
+};
+
+mod common;
+
+use common::fixture;
+
+/// Both fixtures encode the same minutiae, see `tests/data/generate.py`.
+fn assert_common_minutiae(record: &Record) {
+    assert_eq!(record.views.len(), 2);
+    assert_eq!(record.views[0].finger_position_code(), 2);
+    assert_eq!(record.views[1].finger_position_code(), 7);
+    assert_eq!(record.views[1].minutiae.len(), 2);
+
+    let minutiae = &record.views[0].minutiae;
+    assert_eq!(minutiae.len(), 4);
+    let coordinates: Vec<_> = minutiae.iter().map(|m| (m.x, m.y)).collect();
+    assert_eq!(coordinates, [(100, 120), (210, 95), (150, 300), (45, 250)]);
+    let angles: Vec<_> = minutiae.iter().map(|m| m.angle).collect();
+    assert_eq!(angles, [0.0, 90.0, 180.0, 358.59375]);
+    assert!(matches!(minutiae[0].ty, MinutiaType::RidgeEnding));
+    assert!(matches!(minutiae[1].ty, MinutiaType::RidgeBifurcation));
+    assert!(matches!(minutiae[3].ty, MinutiaType::Other));
+}
+
+#[test]
+fn parses_2005_record() {
+    let record = load_iso(fixture("v2005.ist")).unwrap();
+    assert_eq!(record.version, IsoVersion::V2005);
+    assert_common_minutiae(&record);
+    assert_eq!(record.capture_equipment, 0x0123);
+    assert_eq!((record.x_image_size, record.y_image_size), (500, 550));
+    assert_eq!((record.x_resolution, record.y_resolution), (197, 197));
+
+    let view = &record.views[0];
+    assert_eq!(view.finger_quality, 75);
+    assert_eq!(view.minutiae[2].quality, 80);
+    assert!(view.quality_records.is_none());
+    assert!(view.image_size.is_none());
+    assert!(view.representation_number.is_none());
+}
+
+#[test]
+fn parses_2011_record() {
+    let record = load_iso(fixture("v2011.ist")).unwrap();
+    assert_eq!(record.version, IsoVersion::V2011);
+    assert_common_minutiae(&record);
+    assert_eq!(record.capture_equipment, 0x4567);
+    assert_eq!((record.x_image_size, record.y_image_size), (400, 480));
+    assert_eq!((record.x_resolution, record.y_resolution), (197, 200));
+
+    let view = &record.views[0];
+    assert_eq!(view.finger_quality, 75);
+    assert_eq!(view.minutiae[2].quality, 80);
+    assert_eq!(view.representation_number, Some(0));
+    assert_eq!(
+        view.quality_records.as_deref(),
+        Some(
+            &[QualityRecord {
+                score: 75,
+                vendor_id: 0x0031,
+                algorithm_id: 0x0002,
+            }][..]
+        )
+    );
+    assert_eq!(view.image_size, Some((400, 480)));
+    assert_eq!(view.resolution, Some((197, 200)));
+    assert_eq!(view.ridge_ending_type, Some(0));
+
+    // the second representation has 5-byte minutiae without quality and no quality records
+    let view = &record.views[1];
+    assert_eq!(view.representation_number, Some(1));
+    assert_eq!(view.finger_quality, 0);
+    assert!(view.minutiae.iter().all(|m| m.quality == 0));
+}
+
+#[test]
+fn version_specific_loaders_reject_other_versions() {
+    assert!(load_iso_v2005(fixture("v2005.ist")).is_ok());
+    assert!(load_iso_v2011(fixture("v2011.ist")).is_ok());
+    assert!(matches!(
+        load_iso_v2005(fixture("v2011.ist")),
+        Err(ParseError::InvalidFormat)
+    ));
+    assert!(matches!(
+        load_iso_v2011(fixture("v2005.ist")),
+        Err(ParseError::InvalidFormat)
+    ));
+}
+
+#[test]
+fn unknown_versions_and_truncated_records_are_errors() {
+    let mut file = std::fs::read(fixture("v2011.ist")).unwrap();
+    assert!(matches!(
+        IsoVersion::detect(&file[..6]),
+        Err(ParseError::InvalidLength)
+    ));
+
+    let truncated = file.len() - 10;
+    file.truncate(truncated);
+    file[8..12].copy_from_slice(&(truncated as u32).to_be_bytes());
+    assert!(matches!(parse_iso(&file), Err(ParseError::InvalidLength)));
+
+    file[4..8].copy_from_slice(b"040\0");
+    assert!(matches!(
+        parse_iso(&file),
+        Err(ParseError::UnsupportedVersion(version)) if &version == b"040\0"
+    ));
+}
+
+#[test]
+fn counts_beyond_the_data_are_rejected_up_front() {
+    // headers declaring the most views there can be, without any data of them
+    let mut v2005 = std::fs::read(fixture("v2005.ist")).unwrap();
+    v2005.truncate(24);
+    v2005[8..12].copy_from_slice(&24u32.to_be_bytes());
+    v2005[22] = 255;
+    assert!(matches!(parse_iso(&v2005), Err(ParseError::InvalidLength)));
+
+    let mut v2011 = std::fs::read(fixture("v2011.ist")).unwrap();
+    v2011.truncate(15);
+    v2011[8..12].copy_from_slice(&15u32.to_be_bytes());
+    v2011[12..14].copy_from_slice(&u16::MAX.to_be_bytes());
+    assert!(matches!(parse_iso(&v2011), Err(ParseError::InvalidLength)));
+}
+
+#[test]
+fn loads_gzip_compressed_records() {
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    for (name, load) in [
+        ("v2005.ist", load_iso_v2005 as fn(PathBuf) -> _),
+        ("v2011.ist", load_iso_v2011),
+    ] {
+        let path = std::env::temp_dir().join(format!("{}-{}.gz", name, std::process::id()));
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder
+            .write_all(&std::fs::read(fixture(name)).unwrap())
+            .unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let any_version = load_iso(&path);
+        let this_version = load(path.clone());
+        std::fs::remove_file(&path).unwrap();
+        assert_common_minutiae(&any_version.unwrap());
+        assert_common_minutiae(&this_version.unwrap());
+    }
+}
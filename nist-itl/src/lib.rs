@@ -0,0 +1,340 @@
+//! Minutiae of ANSI/NIST-ITL transactions in the traditional encoding.
+//!
+//! Only Type-9 records with minutiae in the standard format (fields 9.001-9.012) are read;
+//! other records are skipped using the content field of the Type-1 record.
+
+use std::fmt;
+use std::path::Path;
+
+use bozorth::parsing::RawMinutiaCombined;
+use bozorth::types::MinutiaKind;
+use bozorth::FingerPosition;
+
+/// Resolution the coordinates are converted to, the one assumed by the distance constants
+/// of bozorth.
+pub const TARGET_PPI: i32 = 500;
+
+const FS: u8 = 0x1c;
+const GS: u8 = 0x1d;
+const RS: char = '\x1e';
+const US: char = '\x1f';
+
+/// Record types with a 4 byte binary length instead of tagged fields.
+const BINARY_RECORD_TYPES: [u32; 6] = [3, 4, 5, 6, 7, 8];
+
+/// Minutiae of a single Type-9 record.
+#[derive(Debug, Clone)]
+pub struct Finger {
+    /// Information designation character of the record, field 9.002.
+    pub idc: u32,
+    /// Impression type code, field 9.003.
+    pub impression_type: u8,
+    /// First of the possible finger positions of field 9.006.
+    pub position: FingerPosition,
+    /// Minutiae of field 9.012 in pixels at `TARGET_PPI` with the origin in the bottom left
+    /// corner and angles in degrees within -179..=180, like parsed .xyt files in the NIST
+    /// internal format.
+    pub minutiae: Vec<RawMinutiaCombined>,
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Io(std::io::Error),
+    /// The content does not start with a Type-1 record.
+    NotATransaction,
+    /// Record `record` (0 is the Type-1 record) is shorter than its length or not terminated.
+    InvalidLength {
+        record: usize,
+    },
+    /// Record type listed in the Type-1 record that is not defined by the standard.
+    UnsupportedRecordType(u32),
+    /// A field is missing or its value cannot be parsed.
+    InvalidField {
+        record: usize,
+        tag: String,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "cannot read file: {}", e),
+            ParseError::NotATransaction => write!(f, "not an ANSI/NIST-ITL transaction"),
+            ParseError::InvalidLength { record } => {
+                write!(f, "invalid length of record {}", record)
+            }
+            ParseError::UnsupportedRecordType(ty) => write!(f, "unsupported record type {}", ty),
+            ParseError::InvalidField { record, tag } => {
+                write!(f, "invalid field {} in record {}", tag, record)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Loads the fingers of all Type-9 records in the standard format, in the order of the file.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Finger>, ParseError> {
+    let file = std::fs::read(path).map_err(ParseError::Io)?;
+    parse(&file)
+}
+
+/// Parses the fingers of all Type-9 records in the standard format, in the order of the file.
+pub fn parse(file: &[u8]) -> Result<Vec<Finger>, ParseError> {
+    if !file.starts_with(b"1.") {
+        return Err(ParseError::NotATransaction);
+    }
+    let (header, mut rest) = split_tagged_record(file, 0)?;
+    let header = TaggedRecord::parse(header, 0, 1)?;
+    let record_types = header.content()?;
+
+    let mut fingers = vec![];
+    for (index, &record_type) in record_types.iter().enumerate() {
+        let record = index + 1;
+        let body = if BINARY_RECORD_TYPES.contains(&record_type) {
+            let (body, tail) = split_binary_record(rest, record)?;
+            rest = tail;
+            body
+        } else if is_tagged(record_type) {
+            let (body, tail) = split_tagged_record(rest, record)?;
+            rest = tail;
+            body
+        } else {
+            return Err(ParseError::UnsupportedRecordType(record_type));
+        };
+
+        if record_type == 9 {
+            let fields = TaggedRecord::parse(body, record, 9)?;
+            if let Some(finger) = fields.finger()? {
+                fingers.push(finger);
+            }
+        }
+    }
+    Ok(fingers)
+}
+
+fn is_tagged(record_type: u32) -> bool {
+    matches!(record_type, 2 | 9..=22 | 98 | 99)
+}
+
+/// Splits off a tagged record using its length in the first field, `R.001`.
+fn split_tagged_record(data: &[u8], record: usize) -> Result<(&[u8], &[u8]), ParseError> {
+    let invalid = || ParseError::InvalidLength { record };
+    let end = data
+        .iter()
+        .position(|&it| it == GS || it == FS)
+        .ok_or_else(invalid)?;
+    let length = std::str::from_utf8(&data[..end])
+        .ok()
+        .and_then(|it| it.split_once(':'))
+        .and_then(|(_, length)| length.parse::<usize>().ok())
+        .ok_or_else(invalid)?;
+    if length <= end || length > data.len() || data[length - 1] != FS {
+        return Err(invalid());
+    }
+    Ok(data.split_at(length))
+}
+
+/// Splits off a binary record using its length in the first 4 bytes.
+fn split_binary_record(data: &[u8], record: usize) -> Result<(&[u8], &[u8]), ParseError> {
+    let invalid = || ParseError::InvalidLength { record };
+    let length = data
+        .get(..4)
+        .map(|it| u32::from_be_bytes([it[0], it[1], it[2], it[3]]) as usize)
+        .ok_or_else(invalid)?;
+    if length < 4 || length > data.len() {
+        return Err(invalid());
+    }
+    Ok(data.split_at(length))
+}
+
+/// Fields of a tagged record as pairs of the field number and the value.
+struct TaggedRecord<'a> {
+    record: usize,
+    record_type: u32,
+    fields: Vec<(u32, &'a str)>,
+}
+
+impl<'a> TaggedRecord<'a> {
+    fn parse(body: &'a [u8], record: usize, record_type: u32) -> Result<Self, ParseError> {
+        // the terminating separator is checked by `split_tagged_record`
+        let body = &body[..body.len() - 1];
+        let mut fields = vec![];
+        for field in body.split(|&it| it == GS) {
+            let invalid = || ParseError::InvalidField {
+                record,
+                tag: String::from_utf8_lossy(field).chars().take(16).collect(),
+            };
+            let (tag, value) = std::str::from_utf8(field)
+                .ok()
+                .and_then(|it| it.split_once(':'))
+                .ok_or_else(invalid)?;
+            let (ty, number) = tag.split_once('.').ok_or_else(invalid)?;
+            if ty.parse::<u32>().ok() != Some(record_type) {
+                return Err(invalid());
+            }
+            fields.push((number.parse().map_err(|_| invalid())?, value));
+        }
+        Ok(TaggedRecord {
+            record,
+            record_type,
+            fields,
+        })
+    }
+
+    fn invalid(&self, number: u32) -> ParseError {
+        ParseError::InvalidField {
+            record: self.record,
+            tag: format!("{}.{:03}", self.record_type, number),
+        }
+    }
+
+    fn get(&self, number: u32) -> Option<&'a str> {
+        self.fields
+            .iter()
+            .find(|(it, _)| *it == number)
+            .map(|(_, value)| *value)
+    }
+
+    fn require(&self, number: u32) -> Result<&'a str, ParseError> {
+        self.get(number).ok_or_else(|| self.invalid(number))
+    }
+
+    fn number<T: std::str::FromStr>(&self, number: u32) -> Result<T, ParseError> {
+        self.require(number)?
+            .trim()
+            .parse()
+            .map_err(|_| self.invalid(number))
+    }
+
+    /// Types of the records after the Type-1 record, from field 1.003.
+    fn content(&self) -> Result<Vec<u32>, ParseError> {
+        let invalid = || self.invalid(3);
+        let mut subfields = self.require(3)?.split(RS);
+        let count = subfields
+            .next()
+            .and_then(|it| it.split(US).nth(1))
+            .and_then(|it| it.parse::<usize>().ok())
+            .ok_or_else(invalid)?;
+        let types = subfields
+            .map(|it| it.split(US).next().unwrap().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        if types.len() != count {
+            return Err(invalid());
+        }
+        Ok(types)
+    }
+
+    /// The finger of a Type-9 record; `None` when its minutiae are not in the standard format.
+    fn finger(&self) -> Result<Option<Finger>, ParseError> {
+        if self.require(4)? != "S" {
+            return Ok(None);
+        }
+
+        let position = match self.get(6) {
+            Some(value) => value
+                .split(RS)
+                .next()
+                .and_then(|it| it.trim().parse::<u8>().ok())
+                .ok_or_else(|| self.invalid(6))?,
+            None => FingerPosition::UNKNOWN.0,
+        };
+        let minutiae = match self.get(12) {
+            Some(value) if !value.is_empty() => value
+                .split(RS)
+                .map(|it| self.minutia(it))
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => vec![],
+        };
+        if self.number::<usize>(10)? != minutiae.len() {
+            return Err(self.invalid(10));
+        }
+
+        Ok(Some(Finger {
+            idc: self.number(2)?,
+            impression_type: self.number(3)?,
+            position: FingerPosition(position),
+            minutiae,
+        }))
+    }
+
+    /// Parses a subfield of field 9.012: index, `XXXXYYYYTTT` in 0.01 mm and degrees, quality
+    /// and type, optionally followed by ridge counts.
+    fn minutia(&self, subfield: &str) -> Result<RawMinutiaCombined, ParseError> {
+        let invalid = || self.invalid(12);
+        let items: Vec<_> = subfield.split(US).collect();
+        if items.len() < 4 {
+            return Err(invalid());
+        }
+        let location = items[1];
+        if location.len() != 11 || !location.bytes().all(|it| it.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let value = |range: std::ops::Range<usize>| location[range].parse::<i32>().unwrap();
+        let (x, y, t) = (value(0..4), value(4..8), value(8..11));
+        let quality = items[2].parse::<i32>().map_err(|_| invalid())?;
+        if t >= 360 || !(0..=63).contains(&quality) {
+            return Err(invalid());
+        }
+
+        Ok(RawMinutiaCombined {
+            x: to_pixels(x),
+            y: to_pixels(y),
+            // like the angles of parsed .xyt files
+            t: if t > 180 { t - 360 } else { t },
+            q: to_xyt_quality(quality),
+            kind: match items[3] {
+                "A" => MinutiaKind::Ending,
+                "B" => MinutiaKind::Bifurcation,
+                "C" => MinutiaKind::Other,
+                "D" => MinutiaKind::Unknown,
+                _ => return Err(invalid()),
+            },
+        })
+    }
+}
+
+/// Converts 0.01 mm to pixels at `TARGET_PPI`, rounding half away from zero.
+fn to_pixels(hundredths_of_mm: i32) -> i32 {
+    (hundredths_of_mm * TARGET_PPI + 1270) / 2540
+}
+
+/// Scales a quality of 2-63 to the 0-100 range of .xyt files; 0 (encoded manually) and 1 (not
+/// available) carry no measure and become 0.
+fn to_xyt_quality(quality: i32) -> i32 {
+    if quality < 2 {
+        0
+    } else {
+        (quality * 100 + 31) / 63
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_pixels, to_xyt_quality};
+
+    #[test]
+    fn units_are_converted_to_pixels_and_xyt_qualities() {
+        // 1 inch
+        assert_eq!(to_pixels(2540), 500);
+        assert_eq!(to_pixels(0), 0);
+        // pixels survive the conversion to 0.01 mm and back
+        for pixels in 0..2000 {
+            let encoded = (pixels * 2540 + 250) / 500;
+            assert_eq!(to_pixels(encoded), pixels);
+        }
+
+        assert_eq!(to_xyt_quality(0), 0);
+        assert_eq!(to_xyt_quality(1), 0);
+        assert_eq!(to_xyt_quality(2), 3);
+        assert_eq!(to_xyt_quality(63), 100);
+    }
+}
@@ -0,0 +1,97 @@
+#!/usr/bin/env python3
+"""Generates the ANSI/NIST-ITL fixtures used by the tests of the parser.
+
+The transactions follow the layout of the NIST sample files in the traditional encoding; their
+Type-9 records hold the minutiae of .xyt fixtures of bozorth converted to 0.01 mm. Real NIST
+sample transactions belong to nist/, see nist/README.md.
+"""
+
+import struct
+from pathlib import Path
+
+HERE = Path(__file__).parent
+XYT = HERE / "../../../bozorth/tests/data"
+
+FS, GS, RS, US = "\x1c", "\x1d", "\x1e", "\x1f"
+
+
+def tagged(record_type, fields):
+    """Record of tagged fields; the length in field 001 includes itself."""
+    body = "".join(GS + "{}.{:03}:{}".format(record_type, number, value) for number, value in fields) + FS
+    length = len(body)
+    while True:
+        record = "{}.001:{}".format(record_type, length) + body
+        if len(record) == length:
+            return record.encode("ascii")
+        length = len(record)
+
+
+def binary_type4(idc, position, image):
+    header = struct.pack(">BB6sBHHB", idc, 1, bytes([position, 255, 255, 255, 255, 255]), 0, 4, 2, 0)
+    return struct.pack(">I", 4 + len(header) + len(image)) + header + image
+
+
+def minutiae_of(xyt):
+    """Minutiae of a .xyt file in 0.01 mm with qualities scaled to 2-63 and alternating types."""
+    minutiae = []
+    for line in (XYT / xyt).read_text().splitlines():
+        x, y, t, q = (int(it) for it in line.split())
+        to_mm = lambda pixels: (pixels * 2540 + 250) // 500
+        quality = min(63, max(2, (q * 63 + 50) // 100))
+        kind = "ABCD"[len(minutiae) % 4]
+        minutiae.append((to_mm(x), to_mm(y), t % 360, quality, kind))
+    return minutiae
+
+
+def type9(idc, position, minutiae):
+    mrc = RS.join(
+        US.join(["{:03}".format(i + 1), "{:04}{:04}{:03}".format(x, y, t), str(q), kind])
+        for i, (x, y, t, q, kind) in enumerate(minutiae)
+    )
+    return tagged(9, [
+        (2, idc),
+        (3, 0),
+        (4, "S"),
+        (5, "NBIS" + US + "A"),
+        (6, position),
+        (7, "T" + US + "UC"),
+        (10, len(minutiae)),
+        (11, 0),
+        (12, mrc),
+    ])
+
+
+def user_defined_type9(idc, position):
+    return tagged(9, [(2, idc), (3, 0), (4, "U"), (126, "27" + US + "0" + US + "0")])
+
+
+def transaction(records):
+    content = RS.join(["1" + US + str(len(records))] + [
+        "{}{}{:02}".format(ty, US, idc) for ty, idc, _ in records
+    ])
+    header = tagged(1, [
+        (2, "0400"),
+        (3, content),
+        (4, "CRM"),
+        (5, "20260101"),
+        (7, "DAI000000"),
+        (8, "ORI000000"),
+        (9, "TCN-0001"),
+        (11, "19.69"),
+        (12, "19.69"),
+    ])
+    return header + b"".join(data for _, _, data in records)
+
+
+if __name__ == "__main__":
+    (HERE / "f1_1.an2").write_bytes(transaction([
+        (2, 0, tagged(2, [(2, "00")])),
+        (4, 1, binary_type4(1, 1, bytes(range(8)))),
+        (9, 1, type9("01", 1, minutiae_of("f1_1.xyt"))),
+        (9, 1, user_defined_type9("01", 1)),
+    ]))
+    # the same finger of the second impression next to a right index without minutiae
+    (HERE / "f1_2.an2").write_bytes(transaction([
+        (9, 2, type9("02", 1, minutiae_of("f1_2.xyt"))),
+        (9, 3, type9("03", 2, [])),
+    ]))
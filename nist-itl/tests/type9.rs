@@ -0,0 +1,171 @@
+use std::path::{Path, PathBuf};
+
+use bozorth::types::MinutiaKind;
+use bozorth::{
+    match_fingerprints, parse as parse_xyt, BozorthState, FingerPosition, Fingerprint, Format,
+    PairHolder, PruneOptions,
+};
+use nist_itl::{load, parse, Finger, ParseError};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+fn xyt(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../bozorth/tests/data")
+        .join(name)
+}
+
+fn fingerprint(finger: &Finger) -> Fingerprint {
+    Fingerprint::from_raw(
+        &finger.minutiae,
+        PruneOptions::default(),
+        Format::NistInternal,
+    )
+}
+
+/// Offset of the first minutia of field 9.012 in `file`.
+fn first_minutia(file: &[u8]) -> usize {
+    let tag = b"9.012:";
+    file.windows(tag.len()).position(|it| it == tag).unwrap() + tag.len()
+}
+
+#[test]
+fn standard_format_minutiae_are_read_in_pixels() {
+    // the binary Type-4 record and the user-defined Type-9 record are skipped
+    let fingers = load(fixture("f1_1.an2")).unwrap();
+    assert_eq!(fingers.len(), 1);
+    let finger = &fingers[0];
+    assert_eq!(finger.idc, 1);
+    assert_eq!(finger.impression_type, 0);
+    assert_eq!(finger.position, FingerPosition::RIGHT_THUMB);
+
+    // see `tests/data/generate.py`
+    let expected = parse_xyt(xyt("f1_1.xyt")).unwrap();
+    assert_eq!(finger.minutiae.len(), expected.len());
+    for (actual, expected) in finger.minutiae.iter().zip(&expected) {
+        assert_eq!(
+            (actual.x, actual.y, actual.t),
+            (expected.x, expected.y, expected.t)
+        );
+        assert!((actual.q - expected.q).abs() <= 1, "{:?}", actual);
+    }
+    let kinds: Vec<_> = finger.minutiae[..4].iter().map(|it| it.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            MinutiaKind::Ending,
+            MinutiaKind::Bifurcation,
+            MinutiaKind::Other,
+            MinutiaKind::Unknown
+        ]
+    );
+}
+
+#[test]
+fn fingers_follow_the_order_of_records() {
+    let fingers = load(fixture("f1_2.an2")).unwrap();
+    let positions: Vec<_> = fingers.iter().map(|it| it.position).collect();
+    assert_eq!(
+        positions,
+        [FingerPosition::RIGHT_THUMB, FingerPosition::RIGHT_INDEX]
+    );
+    assert_eq!(fingers[0].minutiae.len(), 46);
+    assert!(fingers[1].minutiae.is_empty());
+}
+
+#[test]
+fn scores_equal_the_scores_of_the_xyt_files() {
+    let score = |probe: &Fingerprint, gallery: &Fingerprint| {
+        let mut pairs = PairHolder::new();
+        let mut state = BozorthState::new();
+        match_fingerprints(probe, gallery, Format::NistInternal, &mut pairs, &mut state)
+    };
+    let from_xyt = |name| {
+        Fingerprint::from_raw(
+            &parse_xyt(xyt(name)).unwrap(),
+            PruneOptions::default(),
+            Format::NistInternal,
+        )
+    };
+
+    let probe = fingerprint(&load(fixture("f1_1.an2")).unwrap()[0]);
+    let gallery = fingerprint(&load(fixture("f1_2.an2")).unwrap()[0]);
+    assert_eq!(
+        score(&probe, &gallery),
+        score(&from_xyt("f1_1.xyt"), &from_xyt("f1_2.xyt"))
+    );
+}
+
+#[test]
+fn invalid_transactions_are_rejected() {
+    let file = std::fs::read(fixture("f1_1.an2")).unwrap();
+
+    assert!(matches!(
+        parse(&file[1..]),
+        Err(ParseError::NotATransaction)
+    ));
+    assert!(matches!(
+        parse(&file[..file.len() - 1]),
+        Err(ParseError::InvalidLength { record: 4 })
+    ));
+
+    // X of the first minutia is not a number
+    let mut corrupted = file.clone();
+    corrupted[first_minutia(&file) + 4] = b'x';
+    match parse(&corrupted) {
+        Err(ParseError::InvalidField { record: 3, tag }) => assert_eq!(tag, "9.012"),
+        other => panic!("{:?}", other),
+    }
+
+    // angle of the first minutia out of range
+    let mut corrupted = file.clone();
+    let angle = first_minutia(&file) + 4 + 8;
+    corrupted[angle..angle + 3].copy_from_slice(b"360");
+    assert!(parse(&corrupted).is_err());
+
+    // a record type that is not defined
+    let content = b"\x1e4\x1f01";
+    let offset = file
+        .windows(content.len())
+        .position(|it| it == content)
+        .unwrap();
+    let mut corrupted = file;
+    corrupted[offset + 1] = b'1';
+    assert!(matches!(
+        parse(&corrupted),
+        Err(ParseError::UnsupportedRecordType(1))
+    ));
+}
+
+/// Real transactions in `tests/data/nist`, see the README there; ignored until one is added.
+#[test]
+#[ignore = "needs NIST sample transactions in tests/data/nist"]
+fn nist_samples_give_the_expected_minutiae() {
+    let mut samples: Vec<_> = std::fs::read_dir(fixture("nist"))
+        .unwrap()
+        .map(|it| it.unwrap().path())
+        .filter(|it| it.extension().and_then(|ext| ext.to_str()) == Some("an2"))
+        .collect();
+    samples.sort();
+    assert!(!samples.is_empty(), "no samples in tests/data/nist");
+
+    for sample in &samples {
+        let fingers = load(sample).unwrap();
+        let expected = parse_xyt(sample.with_extension("xyt")).unwrap();
+        let actual = &fingers[0].minutiae;
+        assert_eq!(actual.len(), expected.len(), "{}", sample.display());
+        // qualities are rescaled differently by other tools, so only positions are compared
+        for (actual, expected) in actual.iter().zip(&expected) {
+            assert_eq!(
+                (actual.x, actual.y, actual.t),
+                (expected.x, expected.y, expected.t),
+                "{}",
+                sample.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! `bz bench`, formerly `bench`: times matching of every pair of .xyt files in a directory and
+//! checks the scores against the ones of the original implementation.
+
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rayon::iter::ParallelIterator;
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator};
+use structopt::StructOpt;
+
+use bozorth::{
+    match_edges_into_pairs, match_score, parse, prune, timeit, BozorthState, Fingerprint, Format,
+    MatchConfig, Minutia, PairHolder,
+};
+
+fn extract_edges(file: impl AsRef<Path>) -> Fingerprint {
+    Fingerprint::new(prune(&parse(file).unwrap(), 150), Format::NistInternal)
+}
+
+fn iter_lines<P>(path: P) -> impl Iterator<Item = String>
+where
+    P: AsRef<Path>,
+{
+    let f = std::fs::File::open(path).unwrap();
+    let r = std::io::BufReader::new(f);
+    r.lines().flat_map(|it| it.ok())
+}
+
+struct MatchResult {
+    first: u32,
+    second: u32,
+    expected: u32,
+    actual: u32,
+}
+
+/// Time matching of every pair of .xyt files in a directory and compare the scores with the ones
+/// of the original implementation
+#[derive(StructOpt, Debug)]
+pub struct Options {
+    /// Do not compare the scores with the expected ones
+    #[structopt(long)]
+    no_check: bool,
+
+    /// Match on a single thread
+    #[structopt(long)]
+    no_parallel: bool,
+
+    /// Directory with the .xyt files
+    #[structopt(short = "i", long)]
+    input: Option<PathBuf>,
+
+    /// Scores of the original implementation, the last column of every line, in the order the
+    /// files are listed in the directory
+    #[structopt(long)]
+    expected: Option<PathBuf>,
+}
+
+/// Rewrites the words the former `bench` binary was configured with into flags.
+pub fn legacy_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    args.into_iter()
+        .map(|arg| match arg.to_str() {
+            Some("no_check") => "--no-check".into(),
+            Some("no-parallel") => "--no-parallel".into(),
+            _ => arg,
+        })
+        .collect()
+}
+
+pub fn run(options: Options) {
+    let no_check = options.no_check;
+    let no_parallel = options.no_parallel;
+
+    let (default_expected, default_input) = if cfg!(target_os = "windows") {
+        (r"C:\Users\Host\Documents/all", r"E:/xxxx/backup/xyt")
+    } else {
+        (
+            r"/mnt/c/Projects/grbg/cmake-build-release/all",
+            r"/mnt/e/xxxx/backup/xyt",
+        )
+    };
+    let expected_path = options
+        .expected
+        .unwrap_or_else(|| PathBuf::from(default_expected));
+    let xyt_path = options
+        .input
+        .unwrap_or_else(|| PathBuf::from(default_input));
+
+    let paths: Arc<[PathBuf]> = std::fs::read_dir(xyt_path)
+        .unwrap()
+        .map(|it| it.unwrap().path())
+        .filter_map(|it| {
+            let ext = it.extension()?;
+            if ext == "xyt" {
+                Some(it)
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>()
+        .into();
+
+    let cache: HashMap<_, Fingerprint> = paths
+        .par_iter()
+        .map(|path| {
+            let fp = extract_edges(&path);
+            (path.to_owned(), fp)
+        })
+        .collect();
+
+    let expected: Vec<u32> = if no_check {
+        Vec::new()
+    } else {
+        iter_lines(expected_path)
+            .map(|line| parse_line(&line).expect("invalid line"))
+            .collect()
+    };
+
+    let (tx, rx) = crossbeam::channel::unbounded::<MatchResult>();
+
+    let paths1 = paths.clone();
+    let handle = std::thread::spawn(move || {
+        let start = std::time::Instant::now();
+
+        let mut x = 0;
+        for item in rx {
+            x += 1;
+
+            if item.expected != item.actual {
+                println!(
+                    "❎ {} {} -> ACTUAL: {} EXPECTED: {}",
+                    display(&paths1[item.first as usize]).unwrap(),
+                    display(&paths1[item.second as usize]).unwrap(),
+                    item.actual,
+                    item.expected
+                );
+            }
+
+            if x % 10000 == 0 {
+                println!("{} {:?}", x, start.elapsed());
+            }
+        }
+    });
+
+    let start = std::time::Instant::now();
+    let executor = |i: usize| {
+        let mut pair_cacher = PairHolder::new();
+        let mut state = BozorthState::new();
+
+        (0..paths.len()).into_iter().for_each(|j| {
+            let probe_fp = cache.get(&paths[i]).unwrap();
+            let gallery_fp = cache.get(&paths[j]).unwrap();
+
+            timeit(|| pair_cacher.clear());
+            timeit(|| {
+                match_edges_into_pairs(
+                    probe_fp.sorted_edges(),
+                    &probe_fp.minutiae,
+                    gallery_fp.sorted_edges(),
+                    &gallery_fp.minutiae,
+                    &mut pair_cacher,
+                    &MatchConfig::default(),
+                    |_pk: &Minutia, _pj: &Minutia, _gk: &Minutia, _gj: &Minutia| 1,
+                )
+            });
+            let actual = if pair_cacher.pairs().is_empty() {
+                0
+            } else {
+                timeit(|| pair_cacher.prepare());
+                timeit(|| {
+                    match_score(
+                        &pair_cacher,
+                        &probe_fp.minutiae,
+                        &gallery_fp.minutiae,
+                        Format::NistInternal,
+                        &mut state,
+                    )
+                    .unwrap_or_default()
+                    .0 as u32
+                })
+            };
+
+            let expected = if no_check {
+                actual
+            } else {
+                expected[i * paths.len() + j]
+            };
+            if expected != actual {
+                println!(
+                    "❎ {} {} -> ACTUAL: {} EXPECTED: {}",
+                    display(&paths[i]).unwrap(),
+                    display(&paths[j]).unwrap(),
+                    actual,
+                    expected
+                );
+            }
+
+            tx.send(MatchResult {
+                first: i as u32,
+                second: j as u32,
+                expected,
+                actual,
+            })
+            .unwrap();
+        });
+    };
+
+    if no_parallel {
+        (0..paths.len()).for_each(executor);
+    } else {
+        (0..paths.len()).into_par_iter().for_each(executor);
+    }
+
+    print!("elapsed: {:?}", start.elapsed());
+    // the reporting thread stops once the last sender is gone
+    drop(tx);
+    handle.join().unwrap();
+}
+
+fn parse_line(line: &str) -> Result<u32, ()> {
+    let idx = line.rfind(' ').ok_or(())?;
+    line[idx + 1..].parse().map_err(|_| ())
+}
+
+fn display<P>(path: P) -> Option<String>
+where
+    P: AsRef<Path>,
+{
+    Some(path.as_ref().file_name()?.to_str()?.to_owned())
+}
@@ -0,0 +1,46 @@
+use structopt::StructOpt;
+
+use tools::{bench, convert, dedup, enroll, evaluate, evaluate2, matching, search};
+
+/// Bozorth3 tools
+///
+/// Flags mean the same in every subcommand: -T is the number of threads, -o the output, -i the
+/// input directory and -s prints only scores; the strict mode of the algorithm is --strict.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "bz")]
+// parsed once, so the size of the options does not matter
+#[allow(clippy::large_enum_variant)]
+enum Command {
+    /// Match two ISO/IEC 19794-2 templates and append the result to a file, formerly `match`
+    Match(matching::Options),
+    /// Match probes against galleries of .xyt, .min or .an2 files, formerly `bz3`
+    Search(search::Options),
+    /// Compute error rates for every threshold on a directory of impressions, formerly `evaluate`
+    Evaluate(evaluate::Options),
+    /// Compute error rates and the CMC of probes named *_n.jpg.xyt, formerly `evaluate2`
+    Evaluate2(evaluate2::Options),
+    /// Time matching of every pair of .xyt files in a directory, formerly `bench`
+    Bench(bench::Options),
+    /// Convert a view of an ISO/IEC 19794-2 template to an .xyt file
+    Convert(convert::Options),
+    /// Merge impressions of the same finger into a single .xyt template
+    Enroll(enroll::Options),
+    /// Find groups of near-duplicate templates in a gallery
+    Dedup(dedup::Options),
+}
+
+fn main() -> anyhow::Result<()> {
+    match Command::from_args() {
+        Command::Match(options) => std::process::exit(matching::run(options)),
+        Command::Search(options) => search::run(options),
+        Command::Evaluate(options) => evaluate::run(options),
+        Command::Evaluate2(options) => evaluate2::run(options),
+        Command::Bench(options) => {
+            bench::run(options);
+            Ok(())
+        }
+        Command::Convert(options) => convert::run(options),
+        Command::Enroll(options) => enroll::run(options),
+        Command::Dedup(options) => dedup::run(options),
+    }
+}
@@ -0,0 +1,74 @@
+//! `bz convert`: writes minutiae of ISO/IEC 19794-2 templates as .xyt files.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use structopt::StructOpt;
+
+use bozorth::parsing::RawMinutiaCombined;
+
+use isoparser::{FingerPosition, ParseLimits};
+
+use crate::matching::{load_my_format, TemplateError};
+
+/// Convert a view of an ISO/IEC 19794-2 template to an .xyt file, scaled to the target resolution
+/// like for matching; minutia types are not kept
+#[derive(StructOpt, Debug)]
+pub struct Options {
+    /// Finger whose first view is converted, such as right-index; the first view of the
+    /// template by default
+    #[structopt(long)]
+    finger: Option<FingerPosition>,
+
+    /// Resolution in pixels per inch the coordinates are scaled to
+    #[structopt(long, default_value = "500")]
+    target_ppi: u16,
+
+    /// Output file; standard output by default
+    #[structopt(short = "o", long)]
+    output_file: Option<PathBuf>,
+
+    /// Template to convert
+    input: PathBuf,
+}
+
+pub fn run(options: Options) -> anyhow::Result<()> {
+    let ((minutiae, _), _) = load_my_format(
+        &options.input,
+        options.finger,
+        options.target_ppi,
+        ParseLimits::default(),
+    )
+    .map_err(|err| match err {
+        TemplateError::Parse(err) => anyhow!("cannot load {}: {:?}", options.input.display(), err),
+        TemplateError::MissingFinger(position) => anyhow!(
+            "{} has no view of finger {}",
+            options.input.display(),
+            position.name()
+        ),
+    })?;
+
+    write_xyt(options.output_file.as_deref(), &minutiae)
+}
+
+/// Writes minutiae as a .xyt file, to standard output without a `path`.
+pub(crate) fn write_xyt(
+    path: Option<&Path>,
+    minutiae: &[RawMinutiaCombined],
+) -> anyhow::Result<()> {
+    let mut output: Box<dyn Write> = match path {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    for minutia in minutiae {
+        // .xyt files have angles in [0, 360)
+        let t = if minutia.t < 0 {
+            minutia.t + 360
+        } else {
+            minutia.t
+        };
+        writeln!(output, "{} {} {} {}", minutia.x, minutia.y, t, minutia.q)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,251 @@
+//! `bz dedup`: finds groups of near-duplicate templates in a gallery.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use structopt::StructOpt;
+
+use bozorth::{
+    parse, BatchMatcher, BatchSettings, Fingerprint, Format, GalleryIndex, MatchPruning,
+    PruneOptions,
+};
+
+/// Find groups of near-duplicate templates in a gallery
+#[derive(StructOpt, Debug)]
+pub struct Options {
+    /// Directory with .xyt files or a file with a list of them, one in each line
+    gallery: PathBuf,
+
+    /// Minimal score of two templates to be considered duplicates
+    #[structopt(long, default_value = "200")]
+    duplicate_threshold: u32,
+
+    /// Score both directions of every pair of templates and use the greater score
+    #[structopt(long)]
+    symmetric: bool,
+
+    /// Maximum number of minutiae to use from any file
+    #[structopt(long, default_value = "150")]
+    max_minutiae: u32,
+
+    /// All *.xyt files use representation according to ANSI INCITS 378-2004
+    #[structopt(short = "a", long)]
+    use_ansi: bool,
+
+    /// Number of worker threads, all available cores by default
+    #[structopt(short = "T", long)]
+    threads: Option<usize>,
+}
+
+/// Group of templates connected by scores above the threshold.
+#[derive(Debug)]
+struct Group {
+    /// Indices of the templates, in increasing order.
+    members: Vec<usize>,
+    /// Scores above the threshold between the members of the group.
+    scores: Vec<(usize, usize, u32)>,
+}
+
+/// Union-find over indices of templates.
+struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    fn new(len: usize) -> Self {
+        DisjointSet {
+            parents: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut item: usize) -> usize {
+        while self.parents[item] != item {
+            self.parents[item] = self.parents[self.parents[item]];
+            item = self.parents[item];
+        }
+        item
+    }
+
+    fn union(&mut self, first: usize, second: usize) {
+        let first = self.find(first);
+        let second = self.find(second);
+        if first != second {
+            self.parents[first.max(second)] = first.min(second);
+        }
+    }
+}
+
+/// Scores every pair of templates of the `gallery` on `threads` threads and groups the ones
+/// scoring at least `threshold`.
+///
+/// Only groups of at least two templates are returned; members are indices of the entries.
+fn find_duplicates<Id>(
+    gallery: GalleryIndex<Id>,
+    threshold: u32,
+    symmetric: bool,
+    format: Format,
+    threads: usize,
+) -> Vec<Group> {
+    let fingerprints: Arc<[Fingerprint]> = gallery
+        .into_entries()
+        .into_iter()
+        .map(|(_, fingerprint)| fingerprint)
+        .collect();
+    let count = fingerprints.len();
+
+    // all pairs give both directions of every pair, the second one is used only when
+    // `symmetric`; the greater score of the two is at least the threshold when either one is
+    let mut best: BTreeMap<(usize, usize), u32> = BTreeMap::new();
+    let results = BatchMatcher::new(Arc::clone(&fingerprints), fingerprints)
+        .settings(BatchSettings {
+            format,
+            ..BatchSettings::default()
+        })
+        .threads(threads)
+        .filter(move |it| {
+            let direction = it.probe < it.gallery || (symmetric && it.probe > it.gallery);
+            direction && it.score.is_some_and(|it| it.score >= threshold)
+        })
+        .run();
+    for result in results {
+        let score = result.score.map_or(0, |it| it.score);
+        let pair = (
+            result.probe.min(result.gallery),
+            result.probe.max(result.gallery),
+        );
+        let value = best.entry(pair).or_insert(score);
+        *value = (*value).max(score);
+    }
+    let scores: Vec<(usize, usize, u32)> = best
+        .into_iter()
+        .map(|((first, second), value)| (first, second, value))
+        .collect();
+
+    let mut set = DisjointSet::new(count);
+    for &(first, second, _) in &scores {
+        set.union(first, second);
+    }
+
+    let mut groups: Vec<Group> = vec![];
+    let mut group_of_root = vec![None; count];
+    for &(first, second, value) in &scores {
+        let root = set.find(first);
+        let index = *group_of_root[root].get_or_insert_with(|| {
+            groups.push(Group {
+                members: vec![],
+                scores: vec![],
+            });
+            groups.len() - 1
+        });
+        groups[index].members.extend_from_slice(&[first, second]);
+        groups[index].scores.push((first, second, value));
+    }
+
+    for group in &mut groups {
+        group.members.sort_unstable();
+        group.members.dedup();
+    }
+    groups.sort_by_key(|it| it.members[0]);
+    groups
+}
+
+fn list_gallery(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = if path.is_dir() {
+        std::fs::read_dir(path)?
+            .map(|it| it.map(|it| it.path()))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|it| it.extension().and_then(|ext| ext.to_str()) == Some("xyt"))
+            .collect()
+    } else {
+        std::fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|it| !it.is_empty())
+            .map(PathBuf::from)
+            .collect()
+    };
+    files.sort();
+    Ok(files)
+}
+
+pub fn run(opts: Options) -> anyhow::Result<()> {
+    let format = if opts.use_ansi {
+        Format::Ansi
+    } else {
+        Format::NistInternal
+    };
+
+    let prune_options = PruneOptions {
+        max_minutiae: opts.max_minutiae,
+        ..PruneOptions::default()
+    };
+    let files = list_gallery(&opts.gallery).context("cannot list gallery")?;
+    let mut gallery =
+        GalleryIndex::new(vec![], format).pruning(MatchPruning::symmetric(prune_options));
+    for (index, path) in files.iter().enumerate() {
+        let minutiae = parse(path).with_context(|| format!("cannot parse {}", path.display()))?;
+        gallery.add_raw(index, &minutiae);
+    }
+
+    let threads = opts
+        .threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |it| it.get()));
+    let groups = find_duplicates(
+        gallery,
+        opts.duplicate_threshold,
+        opts.symmetric,
+        format,
+        threads,
+    );
+    for (number, group) in groups.iter().enumerate() {
+        println!("group {} ({} templates)", number + 1, group.members.len());
+        for &member in &group.members {
+            println!("  {}", files[member].display());
+        }
+        for &(first, second, value) in &group.scores {
+            println!(
+                "  {} {} {}",
+                files[first].display(),
+                files[second].display(),
+                value
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bozorth::synthetic::Generator;
+    use bozorth::{Format, GalleryIndex, MatchPruning};
+
+    use crate::dedup::find_duplicates;
+
+    #[test]
+    fn planted_duplicates_are_grouped() {
+        let mut generator = Generator::new(42);
+        let mut templates: Vec<_> = (0..6).map(|_| generator.raw_template(50)).collect();
+        let copy_of_1 = generator.impression(&templates[1], 0, (7, -4));
+        let copy_of_4 = generator.impression(&templates[4], 0, (7, -4));
+        let another_copy_of_4 = generator.impression(&templates[4], 0, (7, -4));
+        templates.extend(vec![copy_of_1, copy_of_4, another_copy_of_4]);
+
+        for &symmetric in &[false, true] {
+            let mut gallery =
+                GalleryIndex::new(vec![], Format::NistInternal).pruning(MatchPruning::default());
+            for (index, template) in templates.iter().enumerate() {
+                gallery.add_raw(index, template);
+            }
+            let groups = find_duplicates(gallery, 40, symmetric, Format::NistInternal, 2);
+            let members: Vec<_> = groups.iter().map(|it| it.members.clone()).collect();
+            assert_eq!(members, vec![vec![1, 6], vec![4, 7, 8]]);
+            assert!(groups
+                .iter()
+                .flat_map(|it| it.scores.iter())
+                .all(|&(_, _, score)| score >= 40));
+        }
+    }
+}
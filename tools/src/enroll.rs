@@ -0,0 +1,57 @@
+//! `bz enroll`: merges impressions of the same finger into a single .xyt template.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context};
+use structopt::StructOpt;
+
+use bozorth::{merge_templates, parse, MergeOptions, PruneOptions};
+
+use crate::convert::write_xyt;
+
+/// Merge impressions of the same finger into a template with the minutiae of all of them
+#[derive(StructOpt, Debug)]
+pub struct Options {
+    /// .xyt files of the impressions; the others are aligned to the first one
+    #[structopt(long, required = true, min_values = 2)]
+    merge: Vec<PathBuf>,
+
+    /// Impressions scoring lower against the first one are left out
+    #[structopt(long, default_value = "20")]
+    min_score: u32,
+
+    /// Set maximum number of minutiae of the merged template, the ones of the highest quality;
+    /// 0 means all
+    #[structopt(short = "n", long, default_value = "150")]
+    max_minutiae: u32,
+
+    /// Output file; standard output by default
+    #[structopt(short = "o", long)]
+    output_file: Option<PathBuf>,
+}
+
+pub fn run(options: Options) -> anyhow::Result<()> {
+    let impressions = options
+        .merge
+        .iter()
+        .map(|path| parse(path).with_context(|| format!("cannot load {}", path.display())))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if impressions[0].is_empty() {
+        return Err(anyhow!("{} has no minutiae", options.merge[0].display()));
+    }
+
+    let views: Vec<_> = impressions.iter().map(|it| &it[..]).collect();
+    let merged = merge_templates(
+        &views,
+        &MergeOptions {
+            prune: PruneOptions {
+                max_minutiae: options.max_minutiae,
+                ..PruneOptions::default()
+            },
+            min_score: options.min_score,
+            max_minutiae: options.max_minutiae,
+            ..MergeOptions::default()
+        },
+    );
+    write_xyt(options.output_file.as_deref(), &merged)
+}
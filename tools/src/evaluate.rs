@@ -0,0 +1,1116 @@
+//! `bz evaluate`, formerly `evaluate`: error rates of the algorithm for every threshold on a
+//! directory of first and second impressions.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Context;
+use structopt::StructOpt;
+
+use bozorth::consts::{
+    set_angle_diff, set_edge_generation_max_distance, set_factor, set_max_number_of_clusters,
+    set_max_number_of_groups, set_min_number_of_pairs_to_build_cluster,
+};
+use bozorth::{
+    find_edges_with, kind_points, limit_edges_with, match_edges_into_pairs, match_score_with,
+    parse, prune_with, thin_edges, BozorthState, Calibration, Edge, Format, MatchConfig, Minutia,
+    PairFilter, PairHolder, PruneOptions, SortedEdges, ThinOptions,
+};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+fn parse_fingerprint(file: impl AsRef<Path>, parameters: &Parameters) -> Fingerprint {
+    let options = PruneOptions {
+        max_minutiae: 150,
+        strict: parameters.strict,
+        ..PruneOptions::default()
+    };
+    let minutiae = prune_with(&parse(file).unwrap(), options);
+    let config = parameters.match_config();
+    let mut edges = vec![];
+    find_edges_with(&minutiae, &mut edges, Format::NistInternal, &config);
+    let limit = limit_edges_with(&edges, &config);
+    edges.truncate(limit);
+    let thinned_edges = thin_edges(
+        &mut edges,
+        &ThinOptions {
+            max_per_sector: parameters.thin_edges,
+            ..ThinOptions::default()
+        },
+    );
+
+    Fingerprint {
+        minutiae: minutiae.into_boxed_slice(),
+        edges: edges.into_boxed_slice(),
+        thinned_edges,
+    }
+}
+
+struct Fingerprint {
+    minutiae: Box<[Minutia]>,
+    edges: Box<[Edge]>,
+    /// Edges removed by `--thin-edges`.
+    thinned_edges: usize,
+}
+
+fn match_files(
+    first: &Fingerprint,
+    second: &Fingerprint,
+    parameters: &Parameters,
+    state: &mut BozorthState,
+    cacher: &mut PairHolder,
+) -> u32 {
+    cacher.clear();
+    let config = parameters.match_config();
+    match_edges_into_pairs(
+        SortedEdges::from_sorted_unchecked(&first.edges),
+        &first.minutiae,
+        SortedEdges::from_sorted_unchecked(&second.edges),
+        &second.minutiae,
+        cacher,
+        &config,
+        |pk: &Minutia, pj: &Minutia, gk: &Minutia, gj: &Minutia| {
+            kind_points(parameters.points, pk, pj, gk, gj)
+        },
+    );
+    cacher.prepare();
+
+    state.clear();
+    match_score_with(
+        &cacher,
+        &first.minutiae,
+        &second.minutiae,
+        Format::Ansi,
+        state,
+        &config,
+    )
+    .unwrap_or_default()
+    .0 as u32
+}
+
+/// Compute error rates for every threshold on first (f*.png.xyt) and second (s*.png.xyt)
+/// impressions in a directory
+#[derive(StructOpt, Debug)]
+pub struct Options {
+    /// TOML file with tuning parameters; flags given on the command line override its values
+    #[structopt(long)]
+    config: Option<PathBuf>,
+
+    /// Use original version of Bozorth3
+    #[structopt(long)]
+    strict: bool,
+
+    /// Use the relaxed version, also when the config file sets strict
+    #[structopt(long, conflicts_with = "strict")]
+    no_strict: bool,
+
+    /// Path to directory with input .xyt and .min files
+    #[structopt(short = "i", long)]
+    input: PathBuf,
+
+    /// Points for no compatible minutia type
+    #[structopt(short = "0", long)]
+    points0: Option<u32>,
+
+    /// Points for one compatible minutia type
+    #[structopt(short = "1", long)]
+    points1: Option<u32>,
+
+    /// Points for two compatible minutiae types
+    #[structopt(short = "2", long)]
+    points2: Option<u32>,
+
+    /// Max threshold
+    #[structopt(short = "t", long)]
+    max_threshold: u32,
+
+    /// Name of output files
+    #[structopt(long)]
+    name: String,
+
+    /// Directory to save output files
+    #[structopt(short = "o", long)]
+    output: PathBuf,
+
+    /// Number of worker threads
+    #[structopt(short = "T", long)]
+    threads: u32,
+
+    /// Normalize score
+    #[structopt(long)]
+    normalize: bool,
+
+    /// Max score when normalization is enabled
+    #[structopt(long, default_value = "1")]
+    max_score: u32,
+
+    /// Max number of clusters to create (default: 2000)
+    #[structopt(long)]
+    max_clusters: Option<u32>,
+
+    /// Min cluster size (default: 3)
+    #[structopt(long)]
+    min_cluster_size: Option<u32>,
+
+    /// Max number of groups (default: 10)
+    #[structopt(long)]
+    max_groups: Option<u32>,
+
+    /// Angle comparison tolerance (default: 11)
+    #[structopt(long)]
+    angle_tolerance: Option<u32>,
+
+    /// Longest edge created from a pair of minutiae (default: 125)
+    #[structopt(long)]
+    max_distance: Option<u32>,
+
+    /// Factor (default: 0.05)
+    #[structopt(long)]
+    factor: Option<f32>,
+
+    /// Reject pairs whose minutiae orientations are inconsistent with the pair rotation
+    #[structopt(long)]
+    orientation_filter: bool,
+
+    /// Keep pairs of inconsistent orientations, also when the config file sets
+    /// orientation_filter
+    #[structopt(long, conflicts_with = "orientation-filter")]
+    no_orientation_filter: bool,
+
+    /// Create edges also between minutiae of exactly opposite orientations
+    #[structopt(long)]
+    keep_opposite_angles: bool,
+
+    /// Skip edges between minutiae of exactly opposite orientations, also when the config file
+    /// sets keep_opposite_angles
+    #[structopt(long, conflicts_with = "keep-opposite-angles")]
+    no_keep_opposite_angles: bool,
+
+    /// Count pairs of edges to duplicated minutiae, at the same position and angle, only once
+    #[structopt(long)]
+    dedup_pairs: bool,
+
+    /// Count every pair of edges, also when the config file sets dedup_pairs
+    #[structopt(long, conflicts_with = "dedup-pairs")]
+    no_dedup_pairs: bool,
+
+    /// Keep at most N of the shortest edges of every minutia in every 20 degree sector of edge
+    /// directions (default: 0, keeps all edges)
+    #[structopt(long)]
+    thin_edges: Option<u32>,
+
+    /// Fit a mapping of scores to probabilities and save it to this JSON file
+    #[structopt(long)]
+    calibration_output: Option<PathBuf>,
+
+    /// Calibration method: isotonic or platt
+    #[structopt(long, default_value = "isotonic")]
+    calibration_method: CalibrationMethod,
+
+    /// Save counts to <name>.partial after this many results; a later run resumes from it
+    #[structopt(long, default_value = "100000")]
+    checkpoint_every: usize,
+
+    /// Evaluate only a sample of impostor pairs: a fraction such as 0.1 or a count such as
+    /// 50000; genuine pairs are always evaluated
+    #[structopt(long)]
+    impostor_sample: Option<ImpostorSample>,
+
+    /// Seed of the impostor sample
+    #[structopt(long, default_value = "0")]
+    seed: u64,
+}
+
+/// Tuning parameters of the algorithm as given in a config file or on the command line.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct Tuning {
+    strict: Option<bool>,
+    points0: Option<u32>,
+    points1: Option<u32>,
+    points2: Option<u32>,
+    max_clusters: Option<u32>,
+    min_cluster_size: Option<u32>,
+    max_groups: Option<u32>,
+    angle_tolerance: Option<u32>,
+    max_distance: Option<u32>,
+    factor: Option<f32>,
+    orientation_filter: Option<bool>,
+    keep_opposite_angles: Option<bool>,
+    dedup_pairs: Option<bool>,
+    thin_edges: Option<u32>,
+}
+
+/// Value of a switch given as `--<name>` (`on`) or `--no-<name>` (`off`), if any.
+fn switch(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+impl Tuning {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    /// Parameters given on the command line; switches given neither as `--<name>` nor as
+    /// `--no-<name>` are left unspecified.
+    fn from_options(opts: &Options) -> Self {
+        Tuning {
+            strict: switch(opts.strict, opts.no_strict),
+            points0: opts.points0,
+            points1: opts.points1,
+            points2: opts.points2,
+            max_clusters: opts.max_clusters,
+            min_cluster_size: opts.min_cluster_size,
+            max_groups: opts.max_groups,
+            angle_tolerance: opts.angle_tolerance,
+            max_distance: opts.max_distance,
+            factor: opts.factor,
+            orientation_filter: switch(opts.orientation_filter, opts.no_orientation_filter),
+            keep_opposite_angles: switch(opts.keep_opposite_angles, opts.no_keep_opposite_angles),
+            dedup_pairs: switch(opts.dedup_pairs, opts.no_dedup_pairs),
+            thin_edges: opts.thin_edges,
+        }
+    }
+
+    /// Takes values of `overrides` where they are specified.
+    fn merge(self, overrides: Tuning) -> Self {
+        Tuning {
+            strict: overrides.strict.or(self.strict),
+            points0: overrides.points0.or(self.points0),
+            points1: overrides.points1.or(self.points1),
+            points2: overrides.points2.or(self.points2),
+            max_clusters: overrides.max_clusters.or(self.max_clusters),
+            min_cluster_size: overrides.min_cluster_size.or(self.min_cluster_size),
+            max_groups: overrides.max_groups.or(self.max_groups),
+            angle_tolerance: overrides.angle_tolerance.or(self.angle_tolerance),
+            max_distance: overrides.max_distance.or(self.max_distance),
+            factor: overrides.factor.or(self.factor),
+            orientation_filter: overrides.orientation_filter.or(self.orientation_filter),
+            keep_opposite_angles: overrides.keep_opposite_angles.or(self.keep_opposite_angles),
+            dedup_pairs: overrides.dedup_pairs.or(self.dedup_pairs),
+            thin_edges: overrides.thin_edges.or(self.thin_edges),
+        }
+    }
+
+    /// Fills in defaults of unspecified parameters; points have no default.
+    fn resolve(self) -> anyhow::Result<Parameters> {
+        let points = |value: Option<u32>, name: &str| {
+            value.with_context(|| {
+                format!(
+                    "{} is given neither on the command line nor in the config",
+                    name
+                )
+            })
+        };
+        Ok(Parameters {
+            strict: self.strict.unwrap_or(false),
+            points: [
+                points(self.points0, "points0")?,
+                points(self.points1, "points1")?,
+                points(self.points2, "points2")?,
+            ],
+            max_clusters: self.max_clusters.unwrap_or(2000),
+            min_cluster_size: self.min_cluster_size.unwrap_or(3),
+            max_groups: self.max_groups.unwrap_or(10),
+            angle_tolerance: self.angle_tolerance.unwrap_or(11),
+            max_distance: self.max_distance.unwrap_or(125),
+            factor: self.factor.unwrap_or(0.05),
+            orientation_filter: self.orientation_filter.unwrap_or(false),
+            keep_opposite_angles: self.keep_opposite_angles.unwrap_or(false),
+            dedup_pairs: self.dedup_pairs.unwrap_or(false),
+            thin_edges: self.thin_edges.unwrap_or(0),
+        })
+    }
+}
+
+/// Tuning parameters used by the evaluation.
+#[derive(Debug, PartialEq)]
+struct Parameters {
+    strict: bool,
+    points: [u32; 3],
+    max_clusters: u32,
+    min_cluster_size: u32,
+    max_groups: u32,
+    angle_tolerance: u32,
+    max_distance: u32,
+    factor: f32,
+    orientation_filter: bool,
+    keep_opposite_angles: bool,
+    dedup_pairs: bool,
+    thin_edges: u32,
+}
+
+impl Parameters {
+    fn match_config(&self) -> MatchConfig {
+        MatchConfig {
+            pair_filter: if self.orientation_filter {
+                PairFilter::Orientation
+            } else {
+                PairFilter::Off
+            },
+            skip_opposite_angles: !self.keep_opposite_angles,
+            dedup_pairs: self.dedup_pairs,
+            strict: self.strict,
+            ..MatchConfig::default()
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum CalibrationMethod {
+    Isotonic,
+    Platt,
+}
+
+impl std::str::FromStr for CalibrationMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "isotonic" => Ok(CalibrationMethod::Isotonic),
+            "platt" => Ok(CalibrationMethod::Platt),
+            _ => Err(format!("unknown calibration method: {}", s)),
+        }
+    }
+}
+
+/// Impostor pairs to evaluate, as a fraction of all of them or as their count.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ImpostorSample {
+    Fraction(f64),
+    Count(usize),
+}
+
+impl std::str::FromStr for ImpostorSample {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(count) = s.parse() {
+            return Ok(ImpostorSample::Count(count));
+        }
+        match s.parse() {
+            Ok(fraction) if fraction > 0.0 && fraction <= 1.0 => {
+                Ok(ImpostorSample::Fraction(fraction))
+            }
+            _ => Err(format!(
+                "impostor sample is neither a fraction in (0, 1] nor a count: {}",
+                s
+            )),
+        }
+    }
+}
+
+impl ImpostorSample {
+    fn count_of(self, impostors: usize) -> usize {
+        match self {
+            ImpostorSample::Fraction(fraction) => (fraction * impostors as f64).round() as usize,
+            ImpostorSample::Count(count) => count.min(impostors),
+        }
+    }
+}
+
+/// SplitMix64, a small generator whose sequence is fixed by its seed.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0.0..1.0`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn are_mates(first: &Path, second: &Path) -> bool {
+    first.file_name().unwrap().to_str().unwrap()[1..]
+        == second.file_name().unwrap().to_str().unwrap()[1..]
+}
+
+/// Pairs of first and second fingers a run evaluates: all genuine pairs and a sample of the
+/// impostor pairs.
+#[derive(Debug)]
+struct PairSelection {
+    genuine: usize,
+    impostors: usize,
+    selected_impostors: usize,
+    seed: u64,
+}
+
+impl PairSelection {
+    fn new(
+        first: &[PathBuf],
+        second: &[PathBuf],
+        sample: Option<ImpostorSample>,
+        seed: u64,
+    ) -> Self {
+        let genuine = first
+            .iter()
+            .map(|a| second.iter().filter(|b| are_mates(a, b)).count())
+            .sum();
+        let impostors = first.len() * second.len() - genuine;
+        PairSelection {
+            genuine,
+            impostors,
+            selected_impostors: sample.map_or(impostors, |it| it.count_of(impostors)),
+            seed,
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.genuine + self.selected_impostors
+    }
+
+    /// Fraction of impostor pairs that are evaluated; false positives and true negatives
+    /// divided by it estimate the counts of the full run.
+    fn impostor_rate(&self) -> f64 {
+        if self.impostors == 0 {
+            1.0
+        } else {
+            self.selected_impostors as f64 / self.impostors as f64
+        }
+    }
+
+    /// Selected pairs with whether they are mates, always in the same order for the same seed.
+    ///
+    /// Impostors are chosen by selection sampling (Knuth's algorithm S): every subset of
+    /// `selected_impostors` pairs is equally likely and the pairs are chosen in a single pass.
+    fn pairs<'a>(
+        &self,
+        first: &'a [PathBuf],
+        second: &'a [PathBuf],
+    ) -> impl Iterator<Item = (&'a PathBuf, &'a PathBuf, bool)> + 'a {
+        let mut rng = SplitMix64(self.seed);
+        let mut impostors_left = self.impostors;
+        let mut to_select = self.selected_impostors;
+        first
+            .iter()
+            .flat_map(move |a| second.iter().map(move |b| (a, b, are_mates(a, b))))
+            .filter(move |&(_, _, mates)| {
+                if mates {
+                    return true;
+                }
+                let selected = (impostors_left as f64) * rng.next_f64() < to_select as f64;
+                impostors_left -= 1;
+                if selected {
+                    to_select -= 1;
+                }
+                selected
+            })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Results {
+    true_positive: Vec<usize>,
+    false_positive: Vec<usize>,
+    true_negative: Vec<usize>,
+    false_negative: Vec<usize>,
+    /// Scores with their labels, collected only when a calibration is requested.
+    samples: Vec<(u32, bool)>,
+}
+
+impl Results {
+    fn new(max_threshold: usize) -> Self {
+        Results {
+            true_positive: vec![0; max_threshold + 1],
+            false_positive: vec![0; max_threshold + 1],
+            true_negative: vec![0; max_threshold + 1],
+            false_negative: vec![0; max_threshold + 1],
+            samples: vec![],
+        }
+    }
+
+    fn add(&mut self, score: u32, should_match: bool, keep_sample: bool) {
+        for threshold in 0..self.true_positive.len() {
+            let matches = score as usize >= threshold;
+            match (should_match, matches) {
+                (true, true) => self.true_positive[threshold] += 1,
+                (false, true) => self.false_positive[threshold] += 1,
+                (false, false) => self.true_negative[threshold] += 1,
+                (true, false) => self.false_negative[threshold] += 1,
+            }
+        }
+        if keep_sample {
+            self.samples.push((score, should_match));
+        }
+    }
+}
+
+/// Counts of the first `done` pairs of a run, saved so that an interrupted run can resume.
+///
+/// Pairs are numbered in the order of iteration over first and second fingers, and results
+/// are counted in that order, so the counts always cover a prefix of the pairs.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Checkpoint {
+    total: usize,
+    done: usize,
+    /// Seed of the impostor sample, which decides the pairs besides their total.
+    #[serde(default)]
+    seed: u64,
+    results: Results,
+}
+
+impl Checkpoint {
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        // written next to the checkpoint first, so a crash while saving keeps the previous one
+        let temporary = path.with_extension("partial.tmp");
+        std::fs::write(&temporary, serde_json::to_string(self)?)?;
+        std::fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    /// Loads the checkpoint at `path`, if there is one, and checks that it belongs to this run.
+    fn load(
+        path: &Path,
+        total: usize,
+        seed: u64,
+        max_threshold: usize,
+    ) -> anyhow::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let checkpoint: Checkpoint = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        anyhow::ensure!(
+            checkpoint.total == total && checkpoint.done <= total,
+            "checkpoint is for {} pairs, but there are {}",
+            checkpoint.total,
+            total
+        );
+        anyhow::ensure!(
+            checkpoint.seed == seed,
+            "checkpoint is for a sample with seed {}",
+            checkpoint.seed
+        );
+        anyhow::ensure!(
+            checkpoint.results.true_positive.len() == max_threshold + 1,
+            "checkpoint is for a different max threshold"
+        );
+        Ok(Some(checkpoint))
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Rewrites flags of the former `evaluate` binary that mean something else in the other tools:
+/// `-s` (now `--strict`), `-n` (now `--name`) and `-m` (now `-T`).
+pub fn legacy_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    args.into_iter()
+        .map(|arg| {
+            let renamed = match arg.to_str() {
+                Some("-s") => "--strict",
+                Some("-n") => "--name",
+                Some("-m") => "-T",
+                _ => return arg,
+            };
+            eprintln!(
+                "warning: {} is deprecated, use {}",
+                arg.to_string_lossy(),
+                renamed
+            );
+            renamed.into()
+        })
+        .collect()
+}
+
+pub fn run(opts: Options) -> Result<(), anyhow::Error> {
+    let file_tuning = match &opts.config {
+        Some(path) => Tuning::load(path)
+            .with_context(|| format!("cannot load config from {}", path.display()))?,
+        None => Tuning::default(),
+    };
+    let parameters = file_tuning.merge(Tuning::from_options(&opts)).resolve()?;
+
+    set_max_number_of_clusters(parameters.max_clusters as usize);
+    set_max_number_of_groups(parameters.max_groups as usize);
+    set_angle_diff(parameters.angle_tolerance as i32);
+    set_edge_generation_max_distance(parameters.max_distance as i32);
+    set_factor(parameters.factor);
+    set_min_number_of_pairs_to_build_cluster(parameters.min_cluster_size as usize);
+    println!("{:#?}", &opts);
+    println!("{:#?}", parameters);
+
+    if !opts.output.exists() {
+        std::fs::create_dir_all(&opts.output).unwrap();
+        println!("Created directory {}", opts.output.display());
+    }
+
+    let mut output_file_txt = opts.output.clone();
+    output_file_txt.push(&format!("{}.txt", opts.name));
+
+    let mut output_file_csv = opts.output.clone();
+    output_file_csv.push(&format!("{}.csv", opts.name));
+
+    let checkpoint_file = opts.output.join(format!("{}.partial", opts.name));
+
+    if output_file_csv.exists() || output_file_txt.exists() {
+        println!("Files already exist.");
+        return Ok(());
+    }
+
+    let mut files_first = vec![];
+    let mut files_second = vec![];
+    let mut cache = HashMap::new();
+
+    for path in std::fs::read_dir(&opts.input)? {
+        let raw_path = path?.path();
+        let name = raw_path
+            .file_name()
+            .context("no file name")?
+            .to_str()
+            .context("not utf8")?;
+        if !name.ends_with(".png.xyt") {
+            continue;
+        }
+
+        if name.starts_with("f") {
+            files_first.push(raw_path.clone());
+        } else if name.starts_with("s") {
+            files_second.push(raw_path.clone());
+        }
+
+        let fingerprint = parse_fingerprint(&raw_path, &parameters);
+        cache.insert(raw_path, fingerprint);
+    }
+
+    let edges: usize = cache.values().map(|it| it.edges.len()).sum();
+    let thinned_edges: usize = cache.values().map(|it| it.thinned_edges).sum();
+    println!("Loaded data into the cache!");
+    if parameters.thin_edges > 0 {
+        println!(
+            "Thinned {} of {} edges",
+            thinned_edges,
+            edges + thinned_edges
+        );
+    }
+
+    let max_scores: HashMap<&Path, u32> = if opts.normalize {
+        let scores = cache
+            .par_iter()
+            .map(|(path, fp)| {
+                let mut state = BozorthState::new();
+                let mut cacher = PairHolder::new();
+                let score = match_files(fp, &fp, &parameters, &mut state, &mut cacher);
+                (path.as_path(), score)
+            })
+            .collect();
+        println!("Calculated max scores!");
+        scores
+    } else {
+        HashMap::new()
+    };
+
+    anyhow::ensure!(
+        opts.checkpoint_every > 0,
+        "checkpoint interval must be positive"
+    );
+    let selection =
+        PairSelection::new(&files_first, &files_second, opts.impostor_sample, opts.seed);
+    let total = selection.total();
+    if opts.impostor_sample.is_some() {
+        println!(
+            "Sampled {} of {} impostor pairs with seed {}",
+            selection.selected_impostors, selection.impostors, selection.seed
+        );
+    }
+    let max_threshold = opts.max_threshold as usize;
+    let resumed = Checkpoint::load(&checkpoint_file, total, opts.seed, max_threshold)
+        .with_context(|| format!("cannot resume from {}", checkpoint_file.display()))?;
+    if let Some(checkpoint) = &resumed {
+        println!(
+            "Resuming from {} with {}/{} pairs done",
+            checkpoint_file.display(),
+            checkpoint.done,
+            total
+        );
+    }
+    let resumed_count = resumed.as_ref().map_or(0, |it| it.done);
+
+    ctrlc::set_handler(|| {
+        eprintln!("Interrupted, saving a checkpoint...");
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    })
+    .context("cannot install the SIGINT handler")?;
+
+    let start = std::time::Instant::now();
+    let checkpoint = crossbeam::scope(|s| {
+        let (tx_pairs, rx_pairs) =
+            crossbeam::channel::bounded::<(usize, &PathBuf, &PathBuf, bool)>(1000);
+        let (tx_scores, rx_scores) = crossbeam::channel::bounded(1000);
+
+        let files_first = &files_first[..];
+        let files_second = &files_second[..];
+
+        let selection = &selection;
+        s.spawn(move |_| {
+            // pairs before the checkpoint are selected again, so the sample stays the same
+            let pairs = selection.pairs(files_first, files_second);
+            for (index, (first_finger, second_finger, should_match)) in
+                pairs.enumerate().skip(resumed_count)
+            {
+                if INTERRUPTED.load(Ordering::SeqCst) {
+                    break;
+                }
+                tx_pairs
+                    .send((index, first_finger, second_finger, should_match))
+                    .unwrap();
+            }
+        });
+
+        for _ in 0..opts.threads {
+            let rx_pairs = rx_pairs.clone();
+            let tx_scores = tx_scores.clone();
+            let cache = &cache;
+            let max_points = &max_scores;
+            let opts = &opts;
+            let parameters = &parameters;
+            s.spawn(move |_| {
+                let mut state = BozorthState::new();
+                let mut cacher = PairHolder::new();
+
+                for (index, first_finger, second_finger, should_match) in rx_pairs {
+                    let score = match_files(
+                        &cache[first_finger],
+                        &cache[second_finger],
+                        parameters,
+                        &mut state,
+                        &mut cacher,
+                    );
+
+                    let score = if opts.normalize {
+                        let total_score = std::cmp::min(
+                            max_points[first_finger.as_path()],
+                            max_points[second_finger.as_path()],
+                        );
+
+                        let normalized_score = (score as f32) / (total_score as f32);
+                        (normalized_score * opts.max_score as f32).round() as u32
+                    } else {
+                        score
+                    };
+
+                    tx_scores.send((index, score, should_match)).unwrap();
+                }
+            });
+        }
+
+        // Drop channels that we've cloned into the workers since we don't need them any more
+        // and they are blocking the last thread
+        drop(rx_pairs);
+        drop(tx_scores);
+
+        let opts = &opts;
+        let checkpoint_file = &checkpoint_file;
+        s.spawn(move |_| {
+            let mut checkpoint = resumed.unwrap_or_else(|| Checkpoint {
+                total,
+                done: 0,
+                seed: opts.seed,
+                results: Results::new(max_threshold),
+            });
+            let keep_samples = opts.calibration_output.is_some();
+
+            // scores arrive in any order; they are counted in the order of pairs
+            let mut pending = BTreeMap::new();
+            for (index, score, should_match) in rx_scores {
+                pending.insert(index, (score, should_match));
+                while let Some((score, should_match)) = pending.remove(&checkpoint.done) {
+                    checkpoint.results.add(score, should_match, keep_samples);
+                    checkpoint.done += 1;
+
+                    if checkpoint.done % 10000 == 0 {
+                        eprintln!(
+                            "{}/{} -- {:.02}% in {:.03}s",
+                            checkpoint.done,
+                            total,
+                            (checkpoint.done as f32 / total as f32 * 100.0),
+                            start.elapsed().as_secs_f64()
+                        );
+                    }
+                    if checkpoint.done % opts.checkpoint_every == 0 {
+                        checkpoint.save(checkpoint_file).unwrap();
+                    }
+                }
+            }
+            eprintln!("Done in {:?}", start.elapsed());
+            checkpoint
+        })
+        .join()
+        .unwrap()
+    })
+    .unwrap();
+
+    if checkpoint.done < total {
+        checkpoint
+            .save(&checkpoint_file)
+            .with_context(|| format!("cannot save checkpoint to {}", checkpoint_file.display()))?;
+        println!(
+            "Saved {}/{} pairs to {}; run again to resume",
+            checkpoint.done,
+            total,
+            checkpoint_file.display()
+        );
+        return Ok(());
+    }
+    let results = checkpoint.results;
+
+    let mut f = std::fs::File::create(&output_file_csv).unwrap();
+    writeln!(f, "thres\ttp\tfn\ttn\tfp").unwrap();
+    for i in 0..=opts.max_threshold as usize {
+        writeln!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            i,
+            results.true_positive[i],
+            results.false_negative[i],
+            results.true_negative[i],
+            results.false_positive[i],
+        )
+        .unwrap();
+    }
+
+    let mut f = std::fs::File::create(&output_file_txt).unwrap();
+    writeln!(f, "{:#?}\n", &opts).unwrap();
+    writeln!(f, "{:#?}\n", parameters).unwrap();
+    writeln!(f, "time: {:?}", start.elapsed()).unwrap();
+    writeln!(
+        f,
+        "impostor pairs: {} of {} (rate {}, seed {})",
+        selection.selected_impostors,
+        selection.impostors,
+        selection.impostor_rate(),
+        selection.seed
+    )
+    .unwrap();
+    writeln!(
+        f,
+        "thinned edges: {} of {}",
+        thinned_edges,
+        edges + thinned_edges
+    )
+    .unwrap();
+
+    if let Some(path) = &opts.calibration_output {
+        let calibration = match opts.calibration_method {
+            CalibrationMethod::Isotonic => Calibration::fit_isotonic(&results.samples),
+            CalibrationMethod::Platt => Calibration::fit_platt(&results.samples),
+        };
+        calibration
+            .save(path)
+            .with_context(|| format!("cannot save calibration to {}", path.display()))?;
+        println!("Saved calibration to {}", path.display());
+    }
+
+    if checkpoint_file.exists() {
+        std::fs::remove_file(&checkpoint_file)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use structopt::StructOpt;
+
+    use super::{Checkpoint, ImpostorSample, Options, PairSelection, Results, SplitMix64, Tuning};
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let file: Tuning = toml::from_str(
+            "points0 = 1\npoints1 = 2\npoints2 = 3\nfactor = 0.1\nmax_groups = 4\nstrict = true",
+        )
+        .unwrap();
+        let command_line = Tuning {
+            points1: Some(7),
+            max_groups: Some(6),
+            ..Tuning::default()
+        };
+
+        let parameters = file.merge(command_line).resolve().unwrap();
+        assert_eq!(parameters.points, [1, 7, 3]);
+        assert_eq!(parameters.max_groups, 6);
+        assert!((parameters.factor - 0.1).abs() < f32::EPSILON);
+        assert!(parameters.strict);
+        // defaults of parameters missing in both
+        assert_eq!(parameters.max_clusters, 2000);
+        assert!(!parameters.orientation_filter);
+        assert!(!parameters.keep_opposite_angles);
+        assert!(!parameters.dedup_pairs);
+        assert_eq!(parameters.thin_edges, 0);
+    }
+
+    #[test]
+    fn negated_switches_override_the_config_file() {
+        let file: Tuning = toml::from_str(
+            "points0 = 1\npoints1 = 2\npoints2 = 3\nstrict = true\norientation_filter = true\n\
+             keep_opposite_angles = true\ndedup_pairs = true",
+        )
+        .unwrap();
+        let options = |switches: &[&str]| {
+            let args = [
+                "evaluate", "-i", "in", "-o", "out", "-t", "10", "-T", "1", "--name", "run",
+            ];
+            Options::from_iter_safe(args.iter().chain(switches))
+        };
+
+        let given = options(&[
+            "--no-strict",
+            "--no-orientation-filter",
+            "--no-keep-opposite-angles",
+            "--no-dedup-pairs",
+        ])
+        .unwrap();
+        let parameters = file.merge(Tuning::from_options(&given)).resolve().unwrap();
+        assert!(!parameters.strict);
+        assert!(!parameters.orientation_filter);
+        assert!(!parameters.keep_opposite_angles);
+        assert!(!parameters.dedup_pairs);
+
+        // switches that are not given keep the values of the file
+        let file = Tuning {
+            strict: Some(true),
+            ..Tuning::default()
+        };
+        let merged = file.merge(Tuning::from_options(&options(&[]).unwrap()));
+        assert_eq!(merged.strict, Some(true));
+
+        assert!(options(&["--strict", "--no-strict"]).is_err());
+    }
+
+    #[test]
+    fn unknown_keys_and_missing_points_are_errors() {
+        assert!(toml::from_str::<Tuning>("point0 = 1").is_err());
+        let tuning = Tuning {
+            points0: Some(1),
+            points1: Some(1),
+            ..Tuning::default()
+        };
+        assert!(tuning.resolve().is_err());
+    }
+
+    #[test]
+    fn checkpoint_resumes_only_the_same_run() {
+        let mut results = Results::new(3);
+        results.add(2, true, true);
+        results.add(0, false, true);
+        let checkpoint = Checkpoint {
+            total: 10,
+            done: 2,
+            seed: 5,
+            results,
+        };
+        let path = std::env::temp_dir().join(format!("evaluate-{}.partial", std::process::id()));
+        checkpoint.save(&path).unwrap();
+
+        let loaded = Checkpoint::load(&path, 10, 5, 3).unwrap().unwrap();
+        assert_eq!(loaded, checkpoint);
+        assert_eq!(loaded.results.true_positive, [1, 1, 1, 0]);
+        assert_eq!(loaded.results.true_negative, [0, 1, 1, 1]);
+        assert!(Checkpoint::load(&path, 12, 5, 3).is_err());
+        assert!(Checkpoint::load(&path, 10, 6, 3).is_err());
+        assert!(Checkpoint::load(&path, 10, 5, 4).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(Checkpoint::load(&path, 10, 5, 3).unwrap().is_none());
+    }
+
+    fn fingers(count: usize) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        let names = |prefix| {
+            (0..count)
+                .map(|i| PathBuf::from(format!("{}{:03}.png.xyt", prefix, i)))
+                .collect()
+        };
+        (names("f"), names("s"))
+    }
+
+    #[test]
+    fn impostor_sample_is_reproducible_and_keeps_genuine_pairs() {
+        let (first, second) = fingers(30);
+        let selected = |sample, seed| {
+            let selection = PairSelection::new(&first, &second, Some(sample), seed);
+            let pairs: Vec<_> = selection.pairs(&first, &second).collect();
+            assert_eq!(pairs.len(), selection.total());
+            pairs
+        };
+
+        let sample = selected(ImpostorSample::Fraction(0.1), 7);
+        assert_eq!(sample, selected(ImpostorSample::Fraction(0.1), 7));
+        assert_ne!(sample, selected(ImpostorSample::Fraction(0.1), 8));
+        assert_eq!(sample.iter().filter(|it| it.2).count(), 30);
+        assert_eq!(sample.iter().filter(|it| !it.2).count(), 87);
+
+        let counted = selected(ImpostorSample::Count(100), 7);
+        assert_eq!(counted.iter().filter(|it| !it.2).count(), 100);
+        assert_eq!(selected(ImpostorSample::Count(10_000), 7).len(), 900);
+
+        assert_eq!("0.25".parse(), Ok(ImpostorSample::Fraction(0.25)));
+        assert_eq!("500".parse(), Ok(ImpostorSample::Count(500)));
+        assert!("1.5".parse::<ImpostorSample>().is_err());
+    }
+
+    #[test]
+    fn sampled_false_match_rate_agrees_with_the_full_run() {
+        let (first, second) = fingers(80);
+        // scores that depend only on the pair, as real matching would
+        let score = |a: &PathBuf, b: &PathBuf, mates: bool| {
+            let mut rng = SplitMix64(
+                a.to_str()
+                    .unwrap()
+                    .bytes()
+                    .chain(b.to_str().unwrap().bytes())
+                    .fold(0, |h, it| {
+                        (h ^ u64::from(it)).wrapping_mul(0x0100_0000_01b3)
+                    }),
+            );
+            let noise = (rng.next_u64() % 60) as u32;
+            if mates {
+                30 + noise
+            } else {
+                noise
+            }
+        };
+        let run = |sample| {
+            let selection = PairSelection::new(&first, &second, sample, 3);
+            let mut results = Results::new(60);
+            for (a, b, mates) in selection.pairs(&first, &second) {
+                results.add(score(a, b, mates), mates, false);
+            }
+            (results, selection.selected_impostors)
+        };
+
+        let (full, _) = run(None);
+        let (sampled, impostors) = run(Some(ImpostorSample::Fraction(0.1)));
+        assert_eq!(sampled.true_positive, full.true_positive);
+        assert_eq!(sampled.false_negative, full.false_negative);
+        for threshold in [10, 30, 50] {
+            let rate = |results: &Results| {
+                let fp = results.false_positive[threshold] as f64;
+                fp / (fp + results.true_negative[threshold] as f64)
+            };
+            let expected = rate(&full);
+            let tolerance = 4.0 * (expected * (1.0 - expected) / impostors as f64).sqrt();
+            assert!(
+                (rate(&sampled) - expected).abs() <= tolerance,
+                "FMR at {}: {} vs {}",
+                threshold,
+                rate(&sampled),
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,574 @@
+//! `bz evaluate2`: error rates and the cumulative match characteristic of the probes of a
+//! directory of impressions.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use log::{debug, info};
+use structopt::StructOpt;
+
+use bozorth::{
+    find_edges_with, kind_points, limit_edges_with, match_edges_into_pairs, match_score_with,
+    parse, prune_with, BozorthState, Edge, Format, MatchConfig, Minutia, PairHolder, PruneOptions,
+    SortedEdges,
+};
+
+fn parse_fingerprint(file: impl AsRef<Path>, options: &Options) -> Fingerprint {
+    let prune_options = PruneOptions {
+        max_minutiae: 150,
+        strict: options.strict,
+        ..PruneOptions::default()
+    };
+    let minutiae = prune_with(&parse(file).unwrap(), prune_options);
+    let config = match_config(options);
+    let mut edges = vec![];
+    find_edges_with(&minutiae, &mut edges, Format::NistInternal, &config);
+    let limit = limit_edges_with(&edges, &config);
+    edges.truncate(limit);
+
+    Fingerprint {
+        minutiae: minutiae.into_boxed_slice(),
+        edges: edges.into_boxed_slice(),
+    }
+}
+
+struct Fingerprint {
+    minutiae: Box<[Minutia]>,
+    edges: Box<[Edge]>,
+}
+
+fn match_config(options: &Options) -> MatchConfig {
+    MatchConfig {
+        strict: options.strict,
+        ..MatchConfig::default()
+    }
+}
+
+fn match_files(
+    first: &Fingerprint,
+    second: &Fingerprint,
+    options: &Options,
+    state: &mut BozorthState,
+    cacher: &mut PairHolder,
+) -> u32 {
+    cacher.clear();
+    let config = match_config(options);
+    match_edges_into_pairs(
+        SortedEdges::from_sorted_unchecked(&first.edges),
+        &first.minutiae,
+        SortedEdges::from_sorted_unchecked(&second.edges),
+        &second.minutiae,
+        cacher,
+        &config,
+        |pk: &Minutia, pj: &Minutia, gk: &Minutia, gj: &Minutia| {
+            kind_points(
+                [options.points0, options.points1, options.points2],
+                pk,
+                pj,
+                gk,
+                gj,
+            )
+        },
+    );
+    cacher.prepare();
+
+    state.clear();
+    match_score_with(
+        &cacher,
+        &first.minutiae,
+        &second.minutiae,
+        Format::Ansi,
+        state,
+        &config,
+    )
+    .unwrap_or_default()
+    .0 as u32
+}
+
+/// Compute error rates of probes named *_n.jpg.xyt against the other impressions, and
+/// optionally their cumulative match characteristic, formerly `evaluate2`
+#[derive(StructOpt, Debug)]
+pub struct Options {
+    /// Use strict mode
+    #[structopt(short = "j", long)]
+    strict: bool,
+
+    /// Path to xyt files
+    #[structopt(long)]
+    xyt_path: PathBuf,
+
+    /// Points for incompatible minutia types
+    #[structopt(short = "0", long)]
+    points0: u32,
+
+    /// Points for one compatible minutiae type
+    #[structopt(short = "1", long)]
+    points1: u32,
+
+    /// Points for compatible minutia types
+    #[structopt(short = "2", long)]
+    points2: u32,
+
+    /// Max threshold
+    #[structopt(long)]
+    max_threshold: u32,
+
+    /// Output file name
+    #[structopt(long)]
+    name: String,
+
+    /// Used worker threads
+    #[structopt(long)]
+    threads: u32,
+
+    /// Output directory
+    #[structopt(long)]
+    output: PathBuf,
+
+    /// Also compute the cumulative match characteristic (closed-set identification ranks)
+    #[structopt(long)]
+    cmc: bool,
+
+    /// Highest rank written to the CMC file
+    #[structopt(long, default_value = "20")]
+    max_rank: usize,
+
+    /// CSV file with lines `file name,subject id`; files of the same subject are mates
+    /// (by default, files with the same finger prefix of the name are mates)
+    #[structopt(long)]
+    labels: Option<PathBuf>,
+
+    /// Log more, -v the options and -vv the number of loaded templates; RUST_LOG takes
+    /// precedence
+    #[structopt(short = "v", long, parse(from_occurrences))]
+    verbose: usize,
+
+    /// Log nothing, not even warnings
+    #[structopt(short = "q", long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Subjects of fingerprint files, given by their file names.
+struct Labels {
+    subjects: HashMap<String, String>,
+}
+
+impl Labels {
+    fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read labels from {}", path.display()))?;
+        Labels::parse(&content).with_context(|| format!("invalid labels in {}", path.display()))
+    }
+
+    /// Parses lines of `file name,subject id`; empty lines are skipped.
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let mut subjects = HashMap::new();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (file, subject) = line
+                .split_once(',')
+                .with_context(|| format!("line {}: expected `file name,subject id`", i + 1))?;
+            let (file, subject) = (file.trim(), subject.trim());
+            anyhow::ensure!(
+                !file.is_empty() && !subject.is_empty(),
+                "line {}: empty file name or subject id",
+                i + 1
+            );
+            if subjects
+                .insert(file.to_owned(), subject.to_owned())
+                .is_some()
+            {
+                anyhow::bail!("line {}: {} is labelled more than once", i + 1, file);
+            }
+        }
+        Ok(Labels { subjects })
+    }
+
+    fn subject(&self, file: &Path) -> Option<&str> {
+        let name = file.file_name()?.to_str()?;
+        self.subjects.get(name).map(String::as_str)
+    }
+}
+
+struct Results {
+    true_positive: Vec<usize>,
+    false_positive: Vec<usize>,
+    true_negative: Vec<usize>,
+    false_negative: Vec<usize>,
+}
+
+/// Scores of a single probe needed to find the rank of its mate.
+#[derive(Default)]
+struct ProbeScores {
+    best_mate: Option<u32>,
+    impostors: Vec<u32>,
+}
+
+impl ProbeScores {
+    fn push(&mut self, score: u32, is_mate: bool) {
+        if is_mate {
+            self.best_mate = self.best_mate.max(Some(score));
+        } else {
+            self.impostors.push(score);
+        }
+    }
+
+    /// Rank of the best scoring mate, where impostors with an equal score are ranked first;
+    /// `None` if the gallery contains no mate of the probe.
+    fn mate_rank(&self) -> Option<usize> {
+        let mate = self.best_mate?;
+        Some(1 + self.impostors.iter().filter(|&&it| it >= mate).count())
+    }
+}
+
+/// Fraction of probes with a mate among the first `rank` candidates, for ranks `1..=max_rank`.
+fn cumulative_match_characteristic(ranks: &[usize], max_rank: usize) -> Vec<f64> {
+    let mut identified = vec![0usize; max_rank + 1];
+    for &rank in ranks.iter().filter(|&&it| it <= max_rank) {
+        identified[rank] += 1;
+    }
+    identified
+        .iter()
+        .skip(1)
+        .scan(0, |sum, &it| {
+            *sum += it;
+            Some(*sum as f64 / ranks.len().max(1) as f64)
+        })
+        .collect()
+}
+
+fn log_level_of(options: &Options) -> log::LevelFilter {
+    if options.quiet {
+        return log::LevelFilter::Off;
+    }
+    log::LevelFilter::iter()
+        .skip_while(|&it| it != log::LevelFilter::Warn)
+        .nth(options.verbose)
+        .unwrap_or(log::LevelFilter::max())
+}
+
+pub fn run(opts: Options) -> anyhow::Result<()> {
+    env_logger::Builder::new()
+        .filter_level(log_level_of(&opts))
+        .parse_default_env()
+        .init();
+    info!("{:?}", opts);
+
+    let mut output_file_txt = opts.output.clone();
+    output_file_txt.push(&format!("{}.txt", opts.name));
+
+    let mut output_file_csv = opts.output.clone();
+    output_file_csv.push(&format!("{}.csv", opts.name));
+
+    let mut output_file_cmc = opts.output.clone();
+    output_file_cmc.push(format!("{}_cmc.csv", opts.name));
+
+    if output_file_csv.exists()
+        || output_file_txt.exists()
+        || (opts.cmc && output_file_cmc.exists())
+    {
+        println!("Files already exist.");
+        return Ok(());
+    }
+
+    let labels = opts.labels.as_deref().map(Labels::load).transpose()?;
+
+    let mut files_by_finger: HashMap<_, Vec<_>> = HashMap::new();
+    let mut cache = HashMap::new();
+
+    for path in std::fs::read_dir(&opts.xyt_path)? {
+        let raw_path = path?.path();
+        let name = raw_path
+            .file_name()
+            .context("no file name")?
+            .to_str()
+            .context("not utf8")?;
+        if !name.ends_with(".jpg.xyt") {
+            continue;
+        }
+
+        if let Some(labels) = &labels {
+            anyhow::ensure!(labels.subject(&raw_path).is_some(), "{} has no label", name);
+        }
+
+        let (finger, _) = name.rsplit_once('_').unwrap();
+        files_by_finger
+            .entry(finger.to_owned())
+            .or_default()
+            .push(raw_path.clone());
+        let fingerprint = parse_fingerprint(&raw_path, &opts);
+        cache.insert(raw_path, fingerprint);
+    }
+
+    debug!(cached = cache.len(); "templates loaded");
+
+    println!("Loaded data into the cache!");
+
+    let (results, probe_scores) = crossbeam::scope(|s| {
+        let (tx_pairs, rx_pairs) = crossbeam::channel::bounded::<(&PathBuf, &PathBuf, bool)>(1000);
+        let (tx_scores, rx_scores) = crossbeam::channel::bounded(1000);
+
+        let files_by_finger = &files_by_finger;
+        let labels = &labels;
+        s.spawn(move |_| {
+            for (first, first_finger) in files_by_finger
+                .iter()
+                .flat_map(|(finger, files)| files.iter().map(move |it| (finger, it)))
+            {
+                for (second, second_finger) in files_by_finger
+                    .iter()
+                    .flat_map(|(finger, files)| files.iter().map(move |it| (finger, it)))
+                {
+                    let first_kind = first_finger.file_name().unwrap().to_str().unwrap()
+                        [first.len()..]
+                        .split_once('.')
+                        .unwrap()
+                        .0;
+                    let second_kind = second_finger.file_name().unwrap().to_str().unwrap()
+                        [second.len()..]
+                        .split_once('.')
+                        .unwrap()
+                        .0;
+
+                    if first_kind == "_n" && second_kind != "_n" {
+                        let should_match = match labels {
+                            Some(labels) => {
+                                labels.subject(first_finger) == labels.subject(second_finger)
+                            }
+                            None => first == second,
+                        };
+                        tx_pairs
+                            .send((first_finger, second_finger, should_match))
+                            .unwrap();
+                    }
+                }
+            }
+        });
+
+        for _ in 0..opts.threads {
+            let rx_pairs = rx_pairs.clone();
+            let tx_scores = tx_scores.clone();
+            let cache = &cache;
+            let opts = &opts;
+            s.spawn(move |_| {
+                let mut state = BozorthState::new();
+                let mut cacher = PairHolder::new();
+
+                for (first_finger, second_finger, should_match) in rx_pairs {
+                    let score = match_files(
+                        &cache[first_finger],
+                        &cache[second_finger],
+                        opts,
+                        &mut state,
+                        &mut cacher,
+                    );
+                    // match score {
+                    //     Ok(score) => {
+                    tx_scores.send((first_finger, score, should_match)).unwrap();
+                    // },
+                    // Err(e) => println!(
+                    //     "error while matching: {} vs {} with {:?}",
+                    //     first_finger.display(),
+                    //     second_finger.display(),
+                    //     e
+                    // ),
+                    // }
+                }
+            });
+        }
+
+        // Drop channels that we've cloned into the workers since we don't need them any more
+        // and they are blocking the last thread
+        drop(rx_pairs);
+        drop(tx_scores);
+
+        let opts = &opts;
+        let total = files_by_finger
+            .values()
+            .map(|it| it.len() as u32)
+            .sum::<u32>()
+            .pow(2);
+
+        let results = s
+            .spawn(move |_| {
+                let threshold = opts.max_threshold as usize;
+                let mut results = Results {
+                    true_positive: vec![0; threshold + 1],
+                    false_positive: vec![0; threshold + 1],
+                    true_negative: vec![0; threshold + 1],
+                    false_negative: vec![0; threshold + 1],
+                };
+
+                let mut probe_scores: HashMap<&PathBuf, ProbeScores> = HashMap::new();
+
+                let start = std::time::Instant::now();
+                let mut done = 0;
+                for (probe, score, can_match) in rx_scores {
+                    if opts.cmc {
+                        probe_scores
+                            .entry(probe)
+                            .or_default()
+                            .push(score, can_match);
+                    }
+                    for threshold in 0..=threshold {
+                        let matches = score as usize >= threshold;
+                        match (can_match, matches) {
+                            (true, true) => results.true_positive[threshold] += 1,
+                            (false, true) => results.false_positive[threshold] += 1,
+                            (false, false) => results.true_negative[threshold] += 1,
+                            (true, false) => results.false_negative[threshold] += 1,
+                        }
+                    }
+                    done += 1;
+
+                    if done % 10000 == 0 {
+                        eprintln!(
+                            "{}/{} -- {:.02}% in {:.03}s",
+                            done,
+                            total,
+                            (done as f32 / total as f32 * 100.0),
+                            start.elapsed().as_secs_f64()
+                        );
+                    }
+                }
+                eprintln!("Done in {:?}", start.elapsed());
+                (results, probe_scores)
+            })
+            .join()
+            .unwrap();
+
+        results
+    })
+    .unwrap();
+
+    let mut f = std::fs::File::create(&output_file_csv).unwrap();
+    writeln!(f, "thres\ttp\tfn\ttn\tfp").unwrap();
+    for i in 0..=opts.max_threshold as usize {
+        writeln!(
+            f,
+            "{}\t{}\t{}\t{}\t{}",
+            i,
+            results.true_positive[i],
+            results.false_negative[i],
+            results.true_negative[i],
+            results.false_positive[i],
+        )
+        .unwrap();
+    }
+
+    let mut f = std::fs::File::create(&output_file_txt).unwrap();
+    writeln!(f, "{:#?}", &opts).unwrap();
+
+    if opts.cmc {
+        let ranks: Vec<_> = probe_scores
+            .values()
+            .filter_map(|it| it.mate_rank())
+            .collect();
+        let cmc = cumulative_match_characteristic(&ranks, opts.max_rank);
+
+        let mut csv = std::fs::File::create(&output_file_cmc).unwrap();
+        writeln!(csv, "rank\tidentified").unwrap();
+        for (rank, rate) in cmc.iter().enumerate() {
+            writeln!(csv, "{}\t{:.6}", rank + 1, rate).unwrap();
+        }
+
+        let summary = format!(
+            "{} of {} probes have a mate; rank-1: {:.2}%, rank-5: {:.2}%",
+            ranks.len(),
+            probe_scores.len(),
+            cmc.first().copied().unwrap_or_default() * 100.0,
+            cmc.get(4).copied().unwrap_or_default() * 100.0,
+        );
+        println!("{}", summary);
+        writeln!(f, "{}", summary).unwrap();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use structopt::StructOpt;
+
+    use crate::evaluate2::{
+        cumulative_match_characteristic, log_level_of, Labels, Options, ProbeScores,
+    };
+
+    #[test]
+    fn mate_rank_counts_impostors_scoring_at_least_as_high() {
+        let mut scores = ProbeScores::default();
+        assert_eq!(scores.mate_rank(), None);
+
+        for &(score, is_mate) in &[
+            (10, false),
+            (40, false),
+            (25, true),
+            (30, true),
+            (30, false),
+        ] {
+            scores.push(score, is_mate);
+        }
+        // the better mate scores 30, equal to one impostor and below another
+        assert_eq!(scores.mate_rank(), Some(3));
+    }
+
+    #[test]
+    fn cmc_accumulates_identified_probes() {
+        let cmc = cumulative_match_characteristic(&[1, 1, 2, 7], 5);
+        assert_eq!(cmc, [0.5, 0.75, 0.75, 0.75, 0.75]);
+        assert!(cumulative_match_characteristic(&[], 3)
+            .iter()
+            .all(|&it| it == 0.0));
+    }
+
+    #[test]
+    fn labels_map_file_names_to_subjects() {
+        let labels = Labels::parse("a_n.jpg.xyt, 7\n\nb_1.jpg.xyt,7\nc_n.jpg.xyt,8\n").unwrap();
+        let subject = |name: &str| labels.subject(&Path::new("data").join(name));
+        assert_eq!(subject("a_n.jpg.xyt"), Some("7"));
+        assert_eq!(subject("a_n.jpg.xyt"), subject("b_1.jpg.xyt"));
+        assert_ne!(subject("a_n.jpg.xyt"), subject("c_n.jpg.xyt"));
+        assert_eq!(subject("d_n.jpg.xyt"), None);
+
+        assert!(Labels::parse("a_n.jpg.xyt").is_err());
+        assert!(Labels::parse("a_n.jpg.xyt,\n").is_err());
+        assert!(Labels::parse("a_n.jpg.xyt,1\na_n.jpg.xyt,2").is_err());
+    }
+
+    #[test]
+    fn options_are_logged_only_when_verbose() {
+        let level = |args: &[&str]| {
+            let required = [
+                "evaluate2",
+                "--xyt-path",
+                "in",
+                "-0",
+                "0",
+                "-1",
+                "1",
+                "-2",
+                "2",
+                "--max-threshold",
+                "100",
+                "--name",
+                "run",
+                "--threads",
+                "1",
+                "--output",
+                "out",
+            ];
+            log_level_of(&Options::from_iter(required.iter().chain(args)))
+        };
+        assert_eq!(level(&[]), log::LevelFilter::Warn);
+        assert_eq!(level(&["-v"]), log::LevelFilter::Info);
+        assert_eq!(level(&["-vv"]), log::LevelFilter::Debug);
+        assert_eq!(level(&["-q"]), log::LevelFilter::Off);
+    }
+}
@@ -0,0 +1,12 @@
+//! Command line tools around bozorth; each of them is a subcommand of the `bz` binary.
+#![feature(trait_alias)]
+#![feature(try_blocks)]
+
+pub mod bench;
+pub mod convert;
+pub mod dedup;
+pub mod enroll;
+pub mod evaluate;
+pub mod evaluate2;
+pub mod matching;
+pub mod search;
@@ -0,0 +1,567 @@
+//! `bz match`, formerly `match`: matches two ISO/IEC 19794-2 templates with the interface of
+//! the original matcher, an output file the result is appended to and exit codes.
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::Path;
+
+use structopt::clap::ErrorKind;
+use structopt::StructOpt;
+
+use bozorth::consts::{set_angle_diff, set_factor, set_max_number_of_groups};
+use bozorth::parsing::RawMinutiaCombined;
+use bozorth::types::MinutiaKind;
+use bozorth::{
+    kind_points, match_edges_into_pairs, match_score, prune, BozorthState, Fingerprint, Format,
+    MatchConfig, Minutia, Normalization, PairHolder,
+};
+use isoparser::{FingerPosition, MinutiaType, ParseError, ParseLimits, Parser, Record, View};
+use serde::Serialize;
+
+/// Resolution assumed by the distance constants of bozorth, as in NIST data.
+const DEFAULT_TARGET_PPI: u16 = 500;
+
+/// Range of resolutions in pixels per cm that are considered plausible (~100-4000 ppi).
+const PLAUSIBLE_PPCM: std::ops::RangeInclusive<u16> = 40..=1600;
+
+struct Template {
+    fingerprint: Fingerprint,
+    /// Factors by which x and y coordinates were scaled to the target resolution.
+    scale: (f32, f32),
+    /// Finger of the view the minutiae come from.
+    position: FingerPosition,
+}
+
+pub(crate) enum TemplateError {
+    Parse(ParseError),
+    /// The template has no view of the requested finger.
+    MissingFinger(FingerPosition),
+}
+
+/// Factors converting coordinates of `view` to `target_ppi`.
+///
+/// Returns `None` when the record declares no or an implausible resolution. The resolution
+/// of the view is preferred over the one of the record (they differ only in 2011 records).
+fn resolution_scale(record: &Record, view: &View, target_ppi: u16) -> Option<(f32, f32)> {
+    let (x_ppcm, y_ppcm) = view
+        .resolution
+        .unwrap_or((record.x_resolution, record.y_resolution));
+    if !PLAUSIBLE_PPCM.contains(&x_ppcm) || !PLAUSIBLE_PPCM.contains(&y_ppcm) {
+        return None;
+    }
+
+    let scale = |ppcm: u16| target_ppi as f32 / (ppcm as f32 * 2.54);
+    Some((scale(x_ppcm), scale(y_ppcm)))
+}
+
+/// Minutiae with coordinates scaled to the target resolution, together with the scale factors.
+pub(crate) type Normalized = (Vec<RawMinutiaCombined>, (f32, f32));
+
+/// Converts minutiae of `view` to bozorth minutiae with coordinates scaled to `target_ppi`;
+/// orientations are kept.
+///
+/// Coordinates are not scaled when the record declares no or an implausible resolution.
+pub(crate) fn normalize_resolution(record: &Record, view: &View, target_ppi: u16) -> Normalized {
+    let scale = resolution_scale(record, view, target_ppi).unwrap_or_else(|| {
+        let (x, y) = view
+            .resolution
+            .unwrap_or((record.x_resolution, record.y_resolution));
+        eprintln!(
+            "implausible resolution of {}x{} pixels per cm, coordinates are not scaled",
+            x, y
+        );
+        (1.0, 1.0)
+    });
+
+    let minutiae = view
+        .minutiae
+        .iter()
+        .map(|m| {
+            let t = m.angle.round() as i32 % 360;
+            RawMinutiaCombined {
+                x: (m.x as f32 * scale.0).round() as i32,
+                y: (m.y as f32 * scale.1).round() as i32,
+                t: if t > 180 { t - 360 } else { t },
+                q: m.quality as _,
+                kind: match m.ty {
+                    MinutiaType::Other => MinutiaKind::Other,
+                    MinutiaType::RidgeEnding => MinutiaKind::Ending,
+                    MinutiaType::RidgeBifurcation => MinutiaKind::Bifurcation,
+                },
+            }
+        })
+        .collect();
+    (minutiae, scale)
+}
+
+/// Loads minutiae of the first view of `finger`, or of the first view when no finger is given.
+pub(crate) fn load_my_format(
+    path: impl AsRef<Path>,
+    finger: Option<FingerPosition>,
+    target_ppi: u16,
+    limits: ParseLimits,
+) -> Result<(Normalized, FingerPosition), TemplateError> {
+    let parser = Parser {
+        limits,
+        ..Parser::default()
+    };
+    let rec = parser.load(path).map_err(TemplateError::Parse)?;
+    let view = match finger {
+        Some(position) => rec
+            .views_for_position(position)
+            .next()
+            .ok_or(TemplateError::MissingFinger(position))?,
+        None => &rec.views[0],
+    };
+    Ok((
+        normalize_resolution(&rec, view, target_ppi),
+        view.finger_position,
+    ))
+}
+
+fn fingerprint_of(
+    minutiae: &[RawMinutiaCombined],
+    scale: (f32, f32),
+    position: FingerPosition,
+) -> Template {
+    Template {
+        fingerprint: Fingerprint::new(prune(minutiae, 150), Format::NistInternal),
+        scale,
+        position,
+    }
+}
+
+fn extract_edges(
+    file: impl AsRef<Path>,
+    finger: Option<FingerPosition>,
+    target_ppi: u16,
+    limits: ParseLimits,
+) -> Result<Template, TemplateError> {
+    let ((minutiae, scale), position) = load_my_format(file, finger, target_ppi, limits)?;
+    Ok(fingerprint_of(&minutiae, scale, position))
+}
+
+fn simple_match(probe: &Template, gallery: &Template) -> Result<u32, ()> {
+    let (probe_fp, gallery_fp) = (&probe.fingerprint, &gallery.fingerprint);
+    let mut pair_cacher = PairHolder::new();
+    let mut state = BozorthState::new();
+
+    pair_cacher.clear();
+    match_edges_into_pairs(
+        probe_fp.sorted_edges(),
+        &probe_fp.minutiae,
+        gallery_fp.sorted_edges(),
+        &gallery_fp.minutiae,
+        &mut pair_cacher,
+        &MatchConfig::default(),
+        |pk: &Minutia, pj: &Minutia, gk: &Minutia, gj: &Minutia| {
+            kind_points([2, 3, 4], pk, pj, gk, gj)
+        },
+    );
+    if pair_cacher.pairs().is_empty() {
+        return Err(());
+    }
+
+    pair_cacher.prepare();
+    let actual = match_score(
+        &pair_cacher,
+        &probe_fp.minutiae,
+        &gallery_fp.minutiae,
+        Format::NistInternal,
+        &mut state,
+    )
+    .map_err(|_| ())?
+    .0 as u32;
+
+    Ok(actual)
+}
+
+/// Match two ISO/IEC 19794-2 templates and append the result to a file; with csv or json
+/// output the exit code tells why a template could not be loaded
+#[derive(StructOpt, Debug)]
+pub struct Options {
+    /// Finger whose first view is matched, such as right-index; the first view of the template
+    /// by default
+    #[structopt(long)]
+    finger: Option<FingerPosition>,
+
+    /// Resolution in pixels per inch the coordinates are scaled to; 500 by default
+    #[structopt(long)]
+    target_ppi: Option<u16>,
+
+    /// Format of the result: legacy, csv or json; legacy by default
+    #[structopt(long)]
+    output_format: Option<OutputFormat>,
+
+    /// Self-scores the score is divided by: min, max, geomean or none; min by default
+    #[structopt(long)]
+    normalization: Option<Normalization>,
+
+    /// Reject templates with more minutiae in all views together
+    #[structopt(long)]
+    max_template_minutiae: Option<usize>,
+
+    /// Template of the probe
+    in1: String,
+
+    /// Template of the gallery
+    in2: String,
+
+    /// File the result is appended to
+    out: String,
+}
+
+/// Parses the command line like the original matcher, which reports syntax errors on stdout
+/// and exits with code 1.
+pub fn options_from_args(args: impl IntoIterator<Item = OsString>) -> Options {
+    match Options::from_iter_safe(args) {
+        Ok(options) => options,
+        Err(err)
+            if matches!(
+                err.kind,
+                ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed
+            ) =>
+        {
+            err.exit()
+        }
+        Err(err) => {
+            print!("\nSyntax error.\n{}\n", err.message);
+            std::process::exit(ErrorCode::SyntaxError as i32);
+        }
+    }
+}
+
+#[repr(i32)]
+enum ErrorCode {
+    Success = 0,
+    SyntaxError = 1,
+    CannotOpenOutputFile = 2,
+    CannotOpenTemplateFile = 3,
+    CannotUpdateOutputFile = 4,
+    MissingFinger = 5,
+    TemplateTooLarge = 6,
+    #[allow(unused)]
+    InitError = 100,
+    SetupError = 101,
+}
+
+/// Returns the exit code.
+pub fn run(options: Options) -> i32 {
+    match_templates(options) as i32
+}
+
+fn match_templates(options: Options) -> ErrorCode {
+    set_max_number_of_groups(0);
+    set_factor(0.075);
+    set_angle_diff(13);
+
+    let finger = options.finger;
+    let target_ppi = options.target_ppi.unwrap_or(DEFAULT_TARGET_PPI);
+    let output_format = options.output_format.unwrap_or(OutputFormat::Legacy);
+    let normalization = options.normalization.unwrap_or(Normalization::Min);
+    let limits = ParseLimits {
+        max_total_minutiae: options.max_template_minutiae.unwrap_or(usize::MAX),
+        ..ParseLimits::default()
+    };
+    let (in1, in2, out) = (&options.in1, &options.in2, &options.out);
+
+    let load = |path: &String| match extract_edges(path, finger, target_ppi, limits) {
+        Ok(fp) => Ok(fp),
+        Err(TemplateError::Parse(ParseError::InvalidFormat))
+        | Err(TemplateError::Parse(ParseError::InvalidLength))
+        | Err(TemplateError::Parse(ParseError::UnsupportedVersion(_)))
+        | Err(TemplateError::Parse(ParseError::InvalidFingerPosition(_)))
+        | Err(TemplateError::Parse(ParseError::InvalidImpressionType(_))) => {
+            Err(ErrorCode::SetupError)
+        }
+        Err(TemplateError::Parse(ParseError::Io(_))) => Err(ErrorCode::CannotOpenTemplateFile),
+        Err(TemplateError::Parse(ParseError::LimitExceeded(limit))) => {
+            eprintln!("{} exceeds the limit of {:?}", path, limit);
+            Err(ErrorCode::TemplateTooLarge)
+        }
+        Err(TemplateError::MissingFinger(position)) => {
+            eprintln!(
+                "{} has no view of finger {} ({})",
+                path,
+                position.name(),
+                position.code()
+            );
+            Err(ErrorCode::MissingFinger)
+        }
+    };
+
+    let result = std::panic::catch_unwind(|| -> Result<Outcome, ErrorCode> {
+        let probe_fp = load(in1)?;
+        let gallery_fp = load(in2)?;
+
+        let scores: Option<Scores> = try {
+            let probe_max = simple_match(&probe_fp, &probe_fp).ok()?;
+            let gallery_max = simple_match(&gallery_fp, &gallery_fp).ok()?;
+            let score = simple_match(&probe_fp, &gallery_fp).ok()?;
+            Scores {
+                normalized: normalization.apply(score, (probe_max, gallery_max)),
+                raw: score,
+                self_scores: [probe_max, gallery_max],
+            }
+        };
+
+        Ok(Outcome {
+            scores,
+            scales: Some([probe_fp.scale, gallery_fp.scale]),
+            positions: Some([probe_fp.position, gallery_fp.position]),
+        })
+    });
+    let outcome = match result {
+        Ok(Ok(outcome)) => outcome,
+        // like the original matcher, the legacy format reports templates that cannot be loaded
+        // as a failed match
+        Ok(Err(_)) if output_format == OutputFormat::Legacy => Outcome::default(),
+        Ok(Err(code)) => return code,
+        Err(_) => Outcome::default(),
+    };
+
+    let mut file = match std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(out)
+    {
+        Ok(f) => f,
+        Err(_) => return ErrorCode::CannotOpenOutputFile,
+    };
+
+    let written: std::io::Result<()> = try {
+        // the header is written only into a new file, so appended results stay below it
+        if output_format == OutputFormat::Csv && file.metadata()?.len() == 0 {
+            writeln!(&mut file, "{}", CSV_HEADER)?;
+        }
+        writeln!(&mut file, "{}", outcome.line(output_format, in1, in2))?;
+    };
+    match written {
+        Ok(_) => ErrorCode::Success,
+        Err(_) => ErrorCode::CannotUpdateOutputFile,
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum OutputFormat {
+    /// Aligned columns separated by spaces, as written by the original matcher.
+    Legacy,
+    /// Comma separated values with a header row.
+    Csv,
+    /// One JSON object per line.
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "legacy" => Ok(OutputFormat::Legacy),
+            "csv" => Ok(OutputFormat::Csv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format: {}", s)),
+        }
+    }
+}
+
+struct Scores {
+    /// Score relative to the self-scores, see `--normalization`; missing when a self-score is 0.
+    normalized: Option<f32>,
+    raw: u32,
+    /// Scores of the probe and the gallery matched with themselves.
+    self_scores: [u32; 2],
+}
+
+/// Result of a comparison; fields are missing when the templates could not be matched.
+#[derive(Default)]
+struct Outcome {
+    scores: Option<Scores>,
+    /// Factors by which coordinates of both templates were scaled.
+    scales: Option<[(f32, f32); 2]>,
+    /// Fingers of the views that were matched.
+    positions: Option<[FingerPosition; 2]>,
+}
+
+const CSV_HEADER: &str = "in1,in2,status,score,raw_score,probe_self_score,gallery_self_score,\
+probe_finger,gallery_finger,probe_scale_x,probe_scale_y,gallery_scale_x,gallery_scale_y";
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    in1: &'a str,
+    in2: &'a str,
+    status: &'static str,
+    score: f32,
+    raw_score: Option<u32>,
+    self_scores: Option<[u32; 2]>,
+    fingers: Option<[&'static str; 2]>,
+    scales: Option<[(f32, f32); 2]>,
+}
+
+impl Outcome {
+    fn normalized(&self) -> Option<f32> {
+        self.scores.as_ref().and_then(|it| it.normalized)
+    }
+
+    fn status(&self) -> &'static str {
+        if self.normalized().is_some() {
+            "OK"
+        } else {
+            "FAIL"
+        }
+    }
+
+    fn score(&self) -> f32 {
+        self.normalized().unwrap_or(0.0)
+    }
+
+    fn fingers(&self) -> Option<[&'static str; 2]> {
+        self.positions
+            .map(|[probe, gallery]| [probe.name(), gallery.name()])
+    }
+
+    /// Formats the result as a line without the line terminator.
+    fn line(&self, format: OutputFormat, in1: &str, in2: &str) -> String {
+        match format {
+            // exactly the columns of the original matcher; scales and fingers are audited in the
+            // other formats
+            OutputFormat::Legacy => format!(
+                "{:>15} {:>15} {:>4} {:.6}",
+                in1,
+                in2,
+                self.status(),
+                self.score()
+            ),
+            OutputFormat::Csv => {
+                let optional = |value: Option<String>| value.unwrap_or_default();
+                let scores = self.scores.as_ref();
+                let fingers = self.fingers();
+                let scales = self.scales;
+                let fields = [
+                    csv_field(in1),
+                    csv_field(in2),
+                    self.status().to_owned(),
+                    format!("{:.6}", self.score()),
+                    optional(scores.map(|it| it.raw.to_string())),
+                    optional(scores.map(|it| it.self_scores[0].to_string())),
+                    optional(scores.map(|it| it.self_scores[1].to_string())),
+                    optional(fingers.map(|it| it[0].to_owned())),
+                    optional(fingers.map(|it| it[1].to_owned())),
+                    optional(scales.map(|it| format!("{:.4}", it[0].0))),
+                    optional(scales.map(|it| format!("{:.4}", it[0].1))),
+                    optional(scales.map(|it| format!("{:.4}", it[1].0))),
+                    optional(scales.map(|it| format!("{:.4}", it[1].1))),
+                ];
+                fields.join(",")
+            }
+            OutputFormat::Json => {
+                let line = JsonLine {
+                    in1,
+                    in2,
+                    status: self.status(),
+                    score: self.score(),
+                    raw_score: self.scores.as_ref().map(|it| it.raw),
+                    self_scores: self.scores.as_ref().map(|it| it.self_scores),
+                    fingers: self.fingers(),
+                    scales: self.scales,
+                };
+                serde_json::to_string(&line).expect("result is serializable")
+            }
+        }
+    }
+}
+
+/// Quotes the field when it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bozorth::synthetic::Generator;
+    use isoparser::{
+        FingerPosition, HeaderVariant, ImpressionType, IsoVersion, Minutia, MinutiaType, Record,
+        View,
+    };
+
+    use super::{fingerprint_of, normalize_resolution, simple_match, Template};
+
+    /// Record with a single view of minutiae given at 500 ppi, scaled by `factor`.
+    fn record(minutiae: &[(u16, u16, u8)], factor: u16) -> Record {
+        let view = View {
+            finger_position: FingerPosition::Unknown,
+            impr_type: ImpressionType::LiveScanPlain,
+            finger_quality: 0,
+            minutiae: minutiae
+                .iter()
+                .map(|&(x, y, angle)| Minutia {
+                    ty: MinutiaType::RidgeEnding,
+                    x: x * factor,
+                    y: y * factor,
+                    angle: angle as f32 * 1.40625,
+                    quality: 50,
+                })
+                .collect(),
+            representation_number: None,
+            quality_records: None,
+            image_size: None,
+            resolution: None,
+            ridge_ending_type: None,
+        };
+        Record {
+            version: IsoVersion::V2005,
+            capture_equipment: 0,
+            x_image_size: 500 * factor,
+            y_image_size: 500 * factor,
+            x_resolution: 197 * factor,
+            y_resolution: 197 * factor,
+            views: vec![view],
+            header_variant: HeaderVariant::Exact,
+        }
+    }
+
+    fn fingerprint(record: &Record) -> Template {
+        let (minutiae, scale) = normalize_resolution(record, &record.views[0], 500);
+        fingerprint_of(&minutiae, scale, record.views[0].finger_position)
+    }
+
+    #[test]
+    fn scores_do_not_depend_on_resolution_after_normalization() {
+        let mut generator = Generator::new(7);
+        let template: Vec<_> = (0..40)
+            .map(|_| {
+                let x = 50 + generator.next(400) as u16;
+                let y = 50 + generator.next(400) as u16;
+                (x, y, generator.next(256) as u8)
+            })
+            .collect();
+        let impression: Vec<_> = template
+            .iter()
+            .map(|&(x, y, angle)| (x + 5, y - 3, angle))
+            .collect();
+
+        let probe = fingerprint(&record(&template, 1));
+        let at_500 = fingerprint(&record(&impression, 1));
+        let at_1000 = fingerprint(&record(&impression, 2));
+        assert!((at_500.scale.0 - 1.0).abs() < 0.01);
+        assert!((at_1000.scale.0 - 0.5).abs() < 0.01);
+
+        let score_500 = simple_match(&probe, &at_500).unwrap() as f32;
+        let score_1000 = simple_match(&probe, &at_1000).unwrap() as f32;
+        assert!(score_500 > 0.0);
+        assert!((score_500 - score_1000).abs() <= 0.1 * score_500);
+    }
+
+    #[test]
+    fn implausible_resolution_is_not_scaled() {
+        let mut record = record(&[(100, 200, 0)], 1);
+        record.x_resolution = 0;
+        let (minutiae, scale) = normalize_resolution(&record, &record.views[0], 500);
+        assert_eq!(scale, (1.0, 1.0));
+        assert_eq!((minutiae[0].x, minutiae[0].y), (100, 200));
+    }
+}
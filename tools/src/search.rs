@@ -0,0 +1,3500 @@
+//! `bz search`, formerly `bz3`: matches probes against galleries of .xyt, .min and .an2 files.
+
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use log::{debug, info, warn};
+use structopt::StructOpt;
+
+use bozorth::parsing::RawMinutiaCombined;
+use bozorth::{
+    find_edges_with_stats, match_templates, parse_with_angles, parse_with_limit, prune_with,
+    AngleConvention, BatchMatcher, BatchMode, BatchSettings, BozorthState, Calibration,
+    Fingerprint, Format, FusionStrategy, MatchConfig, MatchFeatures, PairHolder, PruneOptions,
+    QualitySource, TemplateScore, ThinOptions,
+};
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::Serialize;
+
+/// Which matches are reported.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum MatchMode {
+    /// Every score, regardless of the threshold: `all`, or `any` as before.
+    Any,
+    /// Only the first pair of every probe with at least the threshold: `first-match`.
+    OnlyFirstMatch,
+    /// Every pair with at least the threshold: `threshold`, or `all-matches` as before.
+    ThresholdAll,
+}
+
+impl FromStr for MatchMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" | "any" => Ok(MatchMode::Any),
+            "first-match" => Ok(MatchMode::OnlyFirstMatch),
+            "threshold" | "all-matches" => Ok(MatchMode::ThresholdAll),
+            _ => Err(format!(
+                "invalid mode {:?}, expected one of: all, first-match, threshold",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Range {
+    first: u32,
+    last: u32,
+}
+
+#[allow(unused)]
+impl Range {
+    fn first(&self) -> u32 {
+        self.first
+    }
+    fn last(&self) -> u32 {
+        self.last
+    }
+    fn len(&self) -> u32 {
+        self.last - self.first + 1
+    }
+}
+
+impl std::fmt::Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.first + 1, self.last + 1)
+    }
+}
+
+impl FromStr for Range {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (first, last) = s.split_once("-").ok_or("no separator")?;
+        let first: u32 = first.parse().map_err(|_| "invalid start of range")?;
+        let last: u32 = last.parse().map_err(|_| "invalid end of range")?;
+
+        if first >= 1 && first <= last {
+            Ok(Range {
+                first: first - 1,
+                last: last - 1,
+            })
+        } else {
+            Err("invalid order")
+        }
+    }
+}
+
+/// Format of template files.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum InputFormat {
+    /// An2 for files with the `.an2` extension, xyt otherwise.
+    Auto,
+    /// A .xyt file, with the .min file next to it if there is one.
+    Xyt,
+    /// Type-9 records of an ANSI/NIST-ITL transaction, one finger per record.
+    An2,
+}
+
+impl InputFormat {
+    /// Format of `path`, resolving `Auto` by the extension.
+    fn of(self, path: &Path) -> InputFormat {
+        match self {
+            InputFormat::Auto if path.extension() == Some(OsStr::new("an2")) => InputFormat::An2,
+            InputFormat::Auto => InputFormat::Xyt,
+            format => format,
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(InputFormat::Auto),
+            "xyt" => Ok(InputFormat::Xyt),
+            "an2" => Ok(InputFormat::An2),
+            _ => Err("invalid input format"),
+        }
+    }
+}
+
+/// Shard `index` of `count` (1-based) of a gallery split across several runs.
+#[derive(Debug, Copy, Clone)]
+struct Shard {
+    index: u32,
+    count: u32,
+}
+
+impl Shard {
+    /// Assigns the file by a hash of its path relative to the canonical `root` of the gallery, so
+    /// that the assignment depends neither on the order of files, nor on other files of the
+    /// gallery, nor on where the gallery is mounted.
+    fn contains(&self, root: &Path, path: &Path) -> anyhow::Result<bool> {
+        Ok(self.owns(&shard_key(root, path)?))
+    }
+
+    fn owns(&self, key: &str) -> bool {
+        // FNV-1a; unlike the std hasher it is stable across Rust releases
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        hash % u64::from(self.count) == u64::from(self.index - 1)
+    }
+}
+
+/// Path of the file relative to the canonical `root`, with `/` separators on every platform.
+fn shard_key(root: &Path, path: &Path) -> anyhow::Result<String> {
+    let canonical = std::fs::canonicalize(path)
+        .with_context(|| format!("cannot resolve {} to assign it a shard", path.display()))?;
+    let relative = canonical.strip_prefix(root).with_context(|| {
+        format!(
+            "{} is not in the gallery root {}",
+            canonical.display(),
+            root.display()
+        )
+    })?;
+    let components: Vec<_> = relative
+        .components()
+        .map(|it| it.as_os_str().to_string_lossy())
+        .collect();
+    Ok(components.join("/"))
+}
+
+/// Directory the paths of gallery files are hashed relative to by `Shard`: the gallery
+/// directory, the directory of the gallery list, or the current directory.
+fn gallery_root(gallery_files: Option<&Path>) -> anyhow::Result<PathBuf> {
+    let root = match gallery_files {
+        Some(directory) if directory.is_dir() => directory,
+        Some(list) => match list.parent() {
+            Some(parent) if parent != Path::new("") => parent,
+            _ => Path::new("."),
+        },
+        None => Path::new("."),
+    };
+    std::fs::canonicalize(root)
+        .with_context(|| format!("cannot resolve the gallery root {}", root.display()))
+}
+
+impl FromStr for Shard {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, count) = s.split_once('/').ok_or("no separator")?;
+        let index: u32 = index.parse().map_err(|_| "invalid shard index")?;
+        let count: u32 = count.parse().map_err(|_| "invalid number of shards")?;
+
+        if index >= 1 && index <= count {
+            Ok(Shard { index, count })
+        } else {
+            Err("shard index out of range")
+        }
+    }
+}
+
+/// Mode of the algorithm, see `MatchConfig::strict`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum AlgorithmMode {
+    /// Identical to the original Bozorth3, including its quirks.
+    Strict,
+    /// Fixes the quirks of the original Bozorth3.
+    Relaxed,
+}
+
+impl AlgorithmMode {
+    fn name(self) -> &'static str {
+        match self {
+            AlgorithmMode::Strict => "strict",
+            AlgorithmMode::Relaxed => "relaxed",
+        }
+    }
+}
+
+impl std::fmt::Display for AlgorithmMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AlgorithmMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "strict" => Ok(AlgorithmMode::Strict),
+            "relaxed" => Ok(AlgorithmMode::Relaxed),
+            _ => Err("invalid algorithm mode, expected strict or relaxed"),
+        }
+    }
+}
+
+/// Thresholds `start`, `start + step`, ... up to `stop` of `--threshold-sweep`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Sweep {
+    start: u32,
+    stop: u32,
+    step: u32,
+}
+
+impl Sweep {
+    fn thresholds(self) -> impl Iterator<Item = u32> {
+        (self.start..=self.stop).step_by(self.step as usize)
+    }
+}
+
+impl FromStr for Sweep {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let mut next = |error| {
+            parts
+                .next()
+                .and_then(|it| it.parse::<u32>().ok())
+                .ok_or(error)
+        };
+        let start = next("invalid start of sweep")?;
+        let stop = next("invalid end of sweep")?;
+        let step = next("invalid step of sweep")?;
+        if parts.next().is_some() {
+            return Err("too many parts of sweep");
+        }
+
+        if step == 0 {
+            Err("step of sweep must be positive")
+        } else if start > stop {
+            Err("start of sweep is above its end")
+        } else {
+            Ok(Sweep { start, stop, step })
+        }
+    }
+}
+
+/// Bozorth3 matcher tool
+#[derive(StructOpt, Debug)]
+pub struct Options {
+    /// All *.xyt files use representation according to ANSI INCITS 378-2004
+    #[structopt(short = "a", long)]
+    use_ansi: bool,
+
+    /// Matches to report: all (every score, regardless of the threshold), first-match (the first
+    /// gallery of every probe with at least the threshold) or threshold (every pair with at
+    /// least the threshold)
+    #[structopt(short = "m", long, default_value = "all")]
+    mode: MatchMode,
+
+    /// Set match score threshold
+    #[structopt(short = "t", long, default_value = "40")]
+    threshold: u32,
+
+    /// Only print the filenames between which match scores would be computed
+    #[structopt(short = "d", long)]
+    dry_run: bool,
+
+    /// Set maximum number of minutiae to use from any file; allowed range 0-200, 0 means all (up to 200)
+    #[structopt(short = "n", long, default_value = "150")]
+    max_minutiae: u32,
+
+    /// Discard minutiae of quality lower than this before selecting the best ones
+    #[structopt(long, default_value = "0")]
+    min_quality: i32,
+
+    /// Report score 0 without scoring when fewer candidate pairs are found
+    #[structopt(long, default_value = "0")]
+    min_pairs: usize,
+
+    /// Load all templates before matching, in parallel even with a single matching thread
+    #[structopt(long)]
+    preload: bool,
+
+    /// Number of threads to use
+    #[structopt(short = "T", long, default_value = "1")]
+    threads: u32,
+
+    /// Size of a chunk in parallel mode
+    #[structopt(long, default_value = "1000")]
+    chunk_size: u32,
+
+    /// File containing list of pairs to compare, one file in each line
+    #[structopt(short = "M", long)]
+    pair_file: Option<PathBuf>,
+
+    /// Match pairs of the pair file even if some of its files do not exist; their pairs score -1
+    #[structopt(long)]
+    allow_missing: bool,
+
+    /// File containing list of probe files or directory
+    #[structopt(short = "P", long)]
+    probe_files: Option<PathBuf>,
+
+    /// File containing list of gallery files or directory
+    #[structopt(short = "G", long)]
+    gallery_files: Option<PathBuf>,
+
+    /// Single probe file
+    #[structopt(short = "p", long)]
+    fixed_probe: Option<PathBuf>,
+
+    /// Single gallery file
+    #[structopt(short = "g", long)]
+    fixed_gallery: Option<PathBuf>,
+
+    /// Subset of files in the probe list to process
+    #[structopt(long)]
+    probe_range: Option<Range>,
+
+    /// Subset of files in the gallery file to process
+    #[structopt(long)]
+    gallery_range: Option<Range>,
+
+    /// Process only shard i of n of the gallery, e.g. 2/8; see `merge-results`. Files are
+    /// assigned by their path relative to the gallery directory, or to the directory of the
+    /// gallery list
+    #[structopt(long)]
+    shard: Option<Shard>,
+
+    /// Print only scores without filenames
+    #[structopt(short = "s", long)]
+    only_scores: bool,
+
+    /// Do not preserve order; can run slightly faster
+    #[structopt(short = "r", long)]
+    relaxed_output_order: bool,
+
+    /// Output file
+    #[structopt(short = "o", long)]
+    output_file: Option<PathBuf>,
+
+    /// Print time spent in matching stages at exit (requires the `profiling` feature)
+    #[structopt(long)]
+    profile: bool,
+
+    /// Treat probe and gallery entries that are directories as templates of several fingers
+    /// (one file per finger, paired by file order) and fuse their scores: sum, mean or max
+    #[structopt(long, parse(try_from_str = parse_fusion))]
+    fuse: Option<FusionStrategy>,
+
+    /// Print only the N best scoring galleries of every probe, best first
+    #[structopt(long)]
+    top_k: Option<usize>,
+
+    /// Print the number of matched minutiae pairs of the winning clusters after each score
+    #[structopt(long)]
+    with_pairs: bool,
+
+    /// Keep at most N of the shortest edges of every minutia in every 20° sector of edge
+    /// directions and print the number of removed edges after each score; 0 keeps all edges
+    #[structopt(long, default_value = "0")]
+    thin_edges: u32,
+
+    /// Print a histogram and statistics of the scores to standard error after all matches
+    #[structopt(long)]
+    summary: bool,
+
+    /// Print only the summary, without the score of every match
+    #[structopt(long)]
+    summary_only: bool,
+
+    /// Width of a single bucket of the summary histogram
+    #[structopt(long, default_value = "10")]
+    summary_bucket_width: u32,
+
+    /// Write the summary as JSON into this file as well
+    #[structopt(long)]
+    summary_file: Option<PathBuf>,
+
+    /// Count the matches with at least each threshold start, start + step, ... up to stop and
+    /// print the counts to standard error after all matches, e.g. 0:100:10
+    #[structopt(long)]
+    threshold_sweep: Option<Sweep>,
+
+    /// Write the cluster counters of every match into this file as CSV, see `MatchFeatures`
+    #[structopt(long)]
+    features_output: Option<PathBuf>,
+
+    /// Calibration file written by `evaluate`; probabilities are printed next to scores
+    #[structopt(long)]
+    calibration: Option<PathBuf>,
+
+    /// Format of template files: xyt, an2 (Type-9 records of ANSI/NIST-ITL transactions in the
+    /// traditional encoding, one finger per record) or auto, an2 for files named *.an2
+    #[structopt(long, default_value = "auto")]
+    input_format: InputFormat,
+
+    /// Angles of .xyt files: signed (degrees within -179..=180), unsigned (degrees within
+    /// 0..360) or iso (units of 360/256 degrees); detected for every file when not given
+    #[structopt(long)]
+    angle_convention: Option<AngleConvention>,
+
+    /// Reject template files with more minutiae than this, e.g. for untrusted input; unlike
+    /// -n, which keeps the best minutiae, the whole file is rejected
+    #[structopt(long)]
+    max_template_minutiae: Option<usize>,
+
+    /// Give up matching a pair after this many milliseconds; such pairs are reported with the
+    /// best score found so far followed by "timeout"
+    #[structopt(long)]
+    match_timeout_ms: Option<u64>,
+
+    /// Count pairs of edges to duplicated minutiae, at the same position and angle, only once;
+    /// otherwise templates with such duplicates score higher against each other
+    #[structopt(long)]
+    dedup_pairs: bool,
+
+    /// Print statistics of edges found in given file and exit
+    #[structopt(long)]
+    stats: Option<PathBuf>,
+
+    /// Log level: off, error, warn, info, debug or trace; RUST_LOG takes precedence
+    #[structopt(long, default_value = "warn")]
+    log_level: log::LevelFilter,
+
+    /// Raise the log level by one step per occurrence; -v logs the options and timings
+    #[structopt(short = "v", long, parse(from_occurrences))]
+    verbose: usize,
+
+    /// Log nothing, not even warnings
+    #[structopt(short = "q", long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+
+    inputs: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Match probes appearing in a directory against a fixed gallery until interrupted;
+    /// matcher flags such as -n, -a, -T or --min-pairs go before the subcommand
+    Watch(WatchOptions),
+    /// Merge outputs of runs over gallery shards, sorted by probe and descending score
+    MergeResults(MergeOptions),
+    /// Match a list of pairs in two modes of the algorithm and report how their scores differ;
+    /// matcher flags such as -n, -a or -T go before the subcommand
+    CompareModes(CompareModesOptions),
+    /// Print the minutiae and edges of a template as they are matched, in the format of
+    /// `bozorth::edge_dump`; matcher flags such as -n or -a go before the subcommand
+    DumpEdges(DumpEdgesOptions),
+}
+
+#[derive(StructOpt, Debug)]
+struct MergeOptions {
+    /// Keep only the N best scoring galleries of every probe
+    #[structopt(long)]
+    top_k: Option<usize>,
+
+    /// Output file
+    #[structopt(short = "o", long)]
+    output_file: Option<PathBuf>,
+
+    /// Outputs of the shards, with file names
+    files: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct CompareModesOptions {
+    /// File with probe and gallery files on alternating lines, like for -M
+    #[structopt(short = "M", long)]
+    pair_file: PathBuf,
+
+    /// Mode of the first run: strict or relaxed
+    #[structopt(long, default_value = "strict")]
+    first: AlgorithmMode,
+
+    /// Mode of the second run: strict or relaxed
+    #[structopt(long, default_value = "relaxed")]
+    second: AlgorithmMode,
+
+    /// Number of pairs with the largest score differences listed in the summary
+    #[structopt(long, default_value = "10")]
+    largest: usize,
+
+    /// CSV file with the scores of every pair; printed to standard output without it
+    #[structopt(short = "o", long)]
+    output_file: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+struct DumpEdgesOptions {
+    /// Output file; the dump is printed to standard output without it
+    #[structopt(short = "o", long)]
+    output_file: Option<PathBuf>,
+
+    /// Template of a single finger
+    template: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+struct WatchOptions {
+    /// Directory to watch for new *.xyt probe files
+    #[structopt(long)]
+    probe_dir: PathBuf,
+
+    /// File containing list of gallery files or directory
+    #[structopt(long)]
+    gallery: PathBuf,
+
+    /// Report matches with at least this score
+    #[structopt(short = "t", long, default_value = "40")]
+    threshold: u32,
+
+    /// File to append hits to; hits are printed to standard output without it
+    #[structopt(short = "o", long)]
+    output_file: Option<PathBuf>,
+
+    /// Directory processed probes are moved to, relative to the probe directory
+    #[structopt(long, default_value = "done")]
+    done_dir: PathBuf,
+
+    /// Directory probes that cannot be loaded are moved to, relative to the probe directory
+    #[structopt(long, default_value = "failed")]
+    failed_dir: PathBuf,
+
+    /// Interval between scans of the probe directory, in milliseconds
+    #[structopt(long, default_value = "500")]
+    poll_interval: u64,
+}
+
+fn parse_fusion(s: &str) -> Result<FusionStrategy, String> {
+    match s {
+        "sum" => Ok(FusionStrategy::Sum),
+        "mean" => Ok(FusionStrategy::Mean),
+        "max" => Ok(FusionStrategy::Max),
+        _ => Err(format!("unknown fusion strategy: {}", s)),
+    }
+}
+
+/// Reads probe and gallery files from alternating lines of a pair file.
+///
+/// A last probe without a gallery is skipped with a warning. Unless `allow_missing` is set, every
+/// file of the pairs has to exist, otherwise the error lists the missing ones.
+fn find_items_from_pairs(
+    file_name: impl AsRef<Path>,
+    allow_missing: bool,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>), anyhow::Error> {
+    let file_name = file_name.as_ref();
+    let file = std::fs::File::open(file_name)
+        .with_context(|| format!("cannot load pairs from {}", file_name.display()))?;
+    let buff = std::io::BufReader::new(file);
+
+    let mut probes: Vec<PathBuf> = vec![];
+    let mut galleries = vec![];
+
+    for (i, line) in buff.lines().enumerate() {
+        let line = line.context("error while reading line")?;
+        if i % 2 == 0 {
+            probes.push(line.into());
+        } else {
+            galleries.push(line.into());
+        }
+    }
+
+    if probes.len() != galleries.len() {
+        let probe = probes.pop().unwrap();
+        warn!(
+            path:% = file_name.display();
+            "pair file has {} lines, expected an even number; skipping probe {} on the last line",
+            probes.len() + galleries.len() + 1,
+            probe.display()
+        );
+    }
+
+    if !allow_missing {
+        let mut seen = HashSet::new();
+        let missing: Vec<_> = probes
+            .iter()
+            .zip(galleries.iter())
+            .flat_map(|(probe, gallery)| [probe, gallery])
+            .filter(|it| seen.insert(*it) && !it.exists())
+            .map(|it| it.display().to_string())
+            .collect();
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "{} files of pair file {} do not exist (--allow-missing matches the other pairs):\n{}",
+                missing.len(),
+                file_name.display(),
+                missing.join("\n")
+            );
+        }
+    }
+
+    Ok((probes, galleries))
+}
+
+fn get_items_from_file(file_name: impl AsRef<Path>) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let file = std::fs::File::open(file_name).context("cannot load pairs from file")?;
+    let buff = std::io::BufReader::new(file);
+
+    let mut files = vec![];
+    for line in buff.lines() {
+        let line = line.context("cannot read line")?;
+        files.push(line.into());
+    }
+
+    Ok(files)
+}
+
+fn get_items_from_directory(directory: impl AsRef<Path>) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut files = vec![];
+
+    for entry in std::fs::read_dir(directory).context("cannot read directory")? {
+        let entry = entry.context("cannot read entry")?;
+        let meta = entry.metadata().context("cannot read file metadata")?;
+        if !meta.is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        if !is_xyt(&path) && path.extension() != Some(OsStr::new("an2")) {
+            continue;
+        }
+
+        files.push(path);
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Whether the file is a .xyt template, plain or compressed as .xyt.gz.
+fn is_xyt(path: &Path) -> bool {
+    let path = match path.file_stem() {
+        Some(stem) if path.extension() == Some(OsStr::new("gz")) => Path::new(stem),
+        _ => path,
+    };
+    path.extension() == Some(OsStr::new("xyt"))
+}
+
+fn get_items_from_file_or_directory(path: impl AsRef<Path>) -> Result<Vec<PathBuf>, anyhow::Error> {
+    if path.as_ref().is_file() {
+        get_items_from_file(path)
+    } else if path.as_ref().is_dir() {
+        get_items_from_directory(path)
+    } else {
+        if path.as_ref().exists() {
+            Err(anyhow::Error::msg("cannot read path"))
+        } else {
+            Err(anyhow::Error::msg("path does not exist"))
+        }
+    }
+}
+
+fn get_slice_by_range<T>(slice: &[T], range: Range) -> Option<&'_ [T]> {
+    if range.last < slice.len() as u32 {
+        Some(&slice[range.first as usize..=range.last as usize])
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum CompareMode {
+    OneToOne,
+    EveryProbeWithEachGallery,
+    OneToMany,
+}
+
+pub fn run(opt: Options) -> anyhow::Result<()> {
+    env_logger::Builder::new()
+        .filter_level(log_level_of(&opt))
+        .parse_default_env()
+        .init();
+    info!("{:?}", opt);
+
+    if let Some(path) = &opt.stats {
+        print_edge_stats(
+            path,
+            prune_options_of(&opt),
+            format_of(&opt),
+            opt.angle_convention,
+        )?;
+        return Ok(());
+    }
+
+    match &opt.command {
+        Some(Command::Watch(watch_options)) => return watch(&opt, watch_options),
+        Some(Command::MergeResults(merge_options)) => return merge_results(merge_options),
+        Some(Command::CompareModes(compare_options)) => {
+            return compare_modes(&opt, compare_options)
+        }
+        Some(Command::DumpEdges(dump_options)) => return dump_edges(&opt, dump_options),
+        None => {}
+    }
+
+    let mut errors = vec![];
+    if opt.max_minutiae > 200 {
+        errors.push("invalid number of computable minutaie");
+    }
+
+    if opt.pair_file.is_some() && opt.probe_files.is_some() {
+        errors.push(r#"flags "-M" and "-P" are incompatible"#)
+    }
+
+    if opt.pair_file.is_some() && opt.gallery_files.is_some() {
+        errors.push(r#"flags "-M" and "-G" are incompatible"#);
+    }
+
+    if opt.pair_file.is_some() && opt.fixed_probe.is_some() {
+        errors.push(r#"flags "-M" and "-p" are incompatible"#);
+    }
+
+    if opt.pair_file.is_some() && opt.fixed_gallery.is_some() {
+        errors.push(r#"flags "-M" and "-g" are incompatible"#);
+    }
+
+    if opt.probe_files.is_some() && opt.fixed_probe.is_some() {
+        errors.push(r#"flags "-P" and "-p" are incompatible"#);
+    }
+
+    if opt.gallery_files.is_some() && opt.fixed_gallery.is_some() {
+        errors.push(r#"flags "-G" and "-g" are incompatible"#);
+    }
+
+    if opt.mode != MatchMode::Any && opt.pair_file.is_some() {
+        errors.push(r#"flag "-M" is not compatible with modes other than "all"#);
+    }
+
+    if opt.top_k == Some(0) {
+        errors.push(r#"flag "--top-k" requires at least one candidate"#);
+    }
+
+    if opt.top_k.is_some() && opt.mode == MatchMode::OnlyFirstMatch {
+        errors.push(r#"flag "--top-k" is not compatible with mode "first-match""#);
+    }
+
+    if opt.threshold_sweep.is_some() && opt.mode != MatchMode::Any {
+        // other modes pass only the matches above `--threshold` to the writer
+        errors.push(r#"flag "--threshold-sweep" is not compatible with modes other than "all""#);
+    }
+
+    if opt.summary_bucket_width == 0 {
+        errors.push(r#"flag "--summary-bucket-width" must be positive"#);
+    }
+
+    if !errors.is_empty() {
+        eprintln!("Parsing errors:");
+        for error in errors {
+            eprintln!(" - {}", error);
+        }
+        exit(-1);
+    }
+
+    let mode = match opt.mode {
+        MatchMode::Any => CompareMode::EveryProbeWithEachGallery,
+        _ => CompareMode::OneToMany,
+    };
+
+    let (probes, galleries, mode) = if let Some(pair_file) = &opt.pair_file {
+        let (probes, galleries) = find_items_from_pairs(pair_file, opt.allow_missing)?;
+        (probes, galleries, CompareMode::OneToMany)
+    } else if opt.fixed_probe.is_some() && opt.fixed_gallery.is_some() {
+        (
+            vec![opt.fixed_probe.clone().unwrap()],
+            vec![opt.fixed_gallery.clone().unwrap()],
+            mode,
+        )
+    } else if let Some(fixed_probe) = &opt.fixed_probe {
+        let probes = vec![fixed_probe.clone()];
+        let galleries = if let Some(gallery_files) = &opt.gallery_files {
+            get_items_from_directory(gallery_files)?
+        } else if !opt.inputs.is_empty() {
+            opt.inputs
+        } else {
+            eprintln!("missing gallery files");
+            exit(-1);
+        };
+        (probes, galleries, mode)
+    } else if let Some(fixed_gallery) = &opt.fixed_gallery {
+        let galleries = vec![fixed_gallery.clone()];
+        let probes = if let Some(probe_files) = &opt.probe_files {
+            get_items_from_directory(probe_files)?
+        } else if !opt.inputs.is_empty() {
+            opt.inputs
+        } else {
+            eprintln!("missing probe files");
+            exit(-1);
+        };
+        (probes, galleries, mode)
+    } else if opt.probe_files.is_some() && opt.gallery_files.is_some() {
+        let probes = get_items_from_file_or_directory(opt.probe_files.as_ref().unwrap())?;
+        let galleries = get_items_from_file_or_directory(opt.gallery_files.as_ref().unwrap())?;
+        (probes, galleries, mode)
+    } else if opt.probe_files.is_some() && !opt.inputs.is_empty() {
+        let probes = get_items_from_file_or_directory(opt.probe_files.as_ref().unwrap())?;
+        let galleries = opt.inputs;
+        (probes, galleries, mode)
+    } else if opt.gallery_files.is_some() && !opt.inputs.is_empty() {
+        let probes = opt.inputs;
+        let galleries = get_items_from_file_or_directory(opt.gallery_files.as_ref().unwrap())?;
+        (probes, galleries, mode)
+    } else if !opt.inputs.is_empty() {
+        if opt.inputs.len() % 2 == 1 {
+            eprintln!("Number of files to compare is odd");
+            exit(-1);
+        }
+
+        let mut probes = Vec::with_capacity(opt.inputs.len() / 2);
+        let mut galleries = Vec::with_capacity(opt.inputs.len() / 2);
+
+        for (i, path) in opt.inputs.iter().cloned().enumerate() {
+            if i % 2 == 0 {
+                probes.push(path);
+            } else {
+                galleries.push(path);
+            }
+        }
+        (probes, galleries, CompareMode::OneToOne)
+    } else {
+        eprintln!("missing input data");
+        exit(-1);
+    };
+
+    let whole = |files: &[PathBuf]| Range {
+        first: 0,
+        last: (files.len() as u32).saturating_sub(1),
+    };
+    let probe_selection = opt.probe_range.unwrap_or_else(|| whole(&probes));
+    let probe_range = match opt.probe_range {
+        Some(r) => get_slice_by_range(&probes, r).context("out of bounds")?,
+        None => &probes,
+    };
+
+    let gallery_selection = opt.gallery_range.unwrap_or_else(|| whole(&galleries));
+    let gallery_range = match opt.gallery_range {
+        Some(r) => get_slice_by_range(&galleries, r).context("out of bounds")?,
+        None => &galleries,
+    };
+
+    let gallery_shard: Vec<_>;
+    let mut gallery_indices: Vec<u32> = (gallery_selection.first..)
+        .take(gallery_range.len())
+        .collect();
+    let gallery_range = match opt.shard {
+        Some(shard) => {
+            let root = gallery_root(opt.gallery_files.as_deref())?;
+            let mut indices = vec![];
+            let mut files = vec![];
+            for (&index, file) in gallery_indices.iter().zip(gallery_range.iter()) {
+                if shard.contains(&root, file)? {
+                    indices.push(index);
+                    files.push(file.clone());
+                }
+            }
+            gallery_indices = indices;
+            gallery_shard = files;
+            &gallery_shard[..]
+        }
+        None => gallery_range,
+    };
+
+    let calibration = match &opt.calibration {
+        Some(path) => Some(
+            Calibration::load(path)
+                .with_context(|| format!("cannot load calibration from {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    let opt_profile = opt.profile;
+    if opt.dry_run {
+        dry_run(probe_range, gallery_range, mode);
+    } else {
+        ctrlc::set_handler(|| {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                eprintln!("Interrupted again, exiting without writing the output");
+                exit(EXIT_FORCED);
+            }
+            eprintln!(
+                "Interrupted, finishing the matches in progress; press Ctrl-C again to exit now"
+            );
+        })
+        .context("cannot install the SIGINT handler")?;
+
+        let s = Instant::now();
+        let stopped = match_range(
+            probe_range,
+            gallery_range,
+            mode,
+            &Options {
+                inputs: vec![],
+                ..opt
+            },
+            calibration.as_ref(),
+        )?;
+
+        if let Some(stopped) = stopped {
+            let probe_indices: Vec<u32> =
+                (probe_selection.first..).take(probe_range.len()).collect();
+            let hints = resume_hints(
+                &stopped,
+                mode,
+                Selection {
+                    indices: &probe_indices,
+                    range: probe_selection,
+                },
+                Selection {
+                    indices: &gallery_indices,
+                    range: gallery_selection,
+                },
+            );
+            report_interrupted(&stopped, s.elapsed(), &hints);
+            if opt_profile {
+                print_profile();
+            }
+            exit(EXIT_INTERRUPTED);
+        }
+
+        info!(elapsed:? = s.elapsed(); "matching finished");
+    }
+
+    if opt_profile {
+        print_profile();
+    }
+
+    Ok(())
+}
+
+fn log_level_of(options: &Options) -> log::LevelFilter {
+    if options.quiet {
+        return log::LevelFilter::Off;
+    }
+    log::LevelFilter::iter()
+        .skip_while(|&it| it != options.log_level)
+        .nth(options.verbose)
+        .unwrap_or(log::LevelFilter::max())
+}
+
+fn format_of(options: &Options) -> Format {
+    if options.use_ansi {
+        Format::Ansi
+    } else {
+        Format::NistInternal
+    }
+}
+
+fn prune_options_of(options: &Options) -> PruneOptions {
+    PruneOptions {
+        max_minutiae: options.max_minutiae,
+        min_quality: options.min_quality,
+        strict: true,
+    }
+}
+
+fn match_timeout_of(options: &Options) -> Option<Duration> {
+    options.match_timeout_ms.map(Duration::from_millis)
+}
+
+fn thin_options_of(options: &Options) -> ThinOptions {
+    ThinOptions {
+        max_per_sector: options.thin_edges,
+        ..ThinOptions::default()
+    }
+}
+
+fn print_edge_stats(
+    path: &Path,
+    prune_options: PruneOptions,
+    format: Format,
+    angles: Option<AngleConvention>,
+) -> anyhow::Result<()> {
+    let minutiae =
+        parse_with_angles(path, QualitySource::default(), angles).context("cannot parse file")?;
+    let minutiae = prune_with(&minutiae, prune_options);
+    if minutiae.is_empty() {
+        anyhow::bail!("no minutiae in {}", path.display());
+    }
+
+    let mut edges = vec![];
+    let stats = find_edges_with_stats(&minutiae, &mut edges, format);
+    println!("{}", path.display());
+    println!("{:<24}{:>10}", "minutiae", minutiae.len());
+    print!("{}", stats);
+    Ok(())
+}
+
+fn print_profile() {
+    if !bozorth::prof::ENABLED {
+        eprintln!("profiling is not available, rebuild with `--features profiling`");
+        return;
+    }
+
+    eprintln!("{:>12} {:>14}  location", "calls", "total");
+    for entry in bozorth::prof::report() {
+        eprintln!(
+            "{:>12} {:>14}  {}",
+            entry.calls,
+            format!("{:.3?}", entry.total),
+            entry.location
+        );
+    }
+}
+
+fn dry_run(probes: &[PathBuf], galleries: &[PathBuf], mode: CompareMode) {
+    match mode {
+        CompareMode::OneToOne => {
+            assert_eq!(probes.len(), galleries.len());
+            for (probe, gallery) in probes.iter().zip(galleries.iter()) {
+                println!("{} {}", probe.display(), gallery.display());
+            }
+        }
+        CompareMode::EveryProbeWithEachGallery | CompareMode::OneToMany => {
+            for probe in probes {
+                for gallery in galleries {
+                    println!("{} {}", probe.display(), gallery.display());
+                }
+            }
+        }
+    }
+}
+
+/// Set by the first SIGINT; no new pairs are matched once it is set.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Exit code after a SIGINT, once the matches in progress are written.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Exit code after a second SIGINT, without writing the output.
+const EXIT_FORCED: i32 = 131;
+
+fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Matching stopped by SIGINT: the number of matched pairs and the position of the first pair
+/// that was not matched, in the probes and galleries given to `run`. All pairs before it, in
+/// the order of the compare mode, are matched.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Stopped {
+    completed: u64,
+    probe: usize,
+    gallery: usize,
+}
+
+/// Files given to `run` as 0-based indices into a list of `--probe-range` or `--gallery-range`,
+/// and the range they were selected from.
+#[derive(Copy, Clone)]
+struct Selection<'a> {
+    indices: &'a [u32],
+    range: Range,
+}
+
+/// Range flags of the runs that match the remaining pairs, one run per line.
+fn resume_hints(
+    stopped: &Stopped,
+    mode: CompareMode,
+    probes: Selection,
+    galleries: Selection,
+) -> Vec<String> {
+    let flags = |probe: Range, gallery: Range| {
+        format!("--probe-range {} --gallery-range {}", probe, gallery)
+    };
+    let from = |selection: Selection, position: usize| Range {
+        first: selection.indices[position],
+        last: selection.range.last,
+    };
+
+    match mode {
+        CompareMode::OneToOne => vec![flags(
+            from(probes, stopped.probe),
+            from(galleries, stopped.gallery),
+        )],
+        CompareMode::EveryProbeWithEachGallery | CompareMode::OneToMany => {
+            let mut hints = vec![];
+            let mut next_probe = stopped.probe;
+            if stopped.gallery > 0 {
+                let probe = probes.indices[stopped.probe];
+                hints.push(flags(
+                    Range {
+                        first: probe,
+                        last: probe,
+                    },
+                    from(galleries, stopped.gallery),
+                ));
+                next_probe += 1;
+            }
+            if next_probe < probes.indices.len() {
+                hints.push(flags(from(probes, next_probe), galleries.range));
+            }
+            hints
+        }
+    }
+}
+
+fn report_interrupted(stopped: &Stopped, elapsed: Duration, hints: &[String]) {
+    let seconds = elapsed.as_secs_f64();
+    eprintln!(
+        "Interrupted after {} pairs in {:.3}s ({:.1} pairs/s)",
+        stopped.completed,
+        seconds,
+        if seconds > 0.0 {
+            stopped.completed as f64 / seconds
+        } else {
+            0.0
+        }
+    );
+    eprintln!("To match the remaining pairs, run again with the same options and:");
+    for hint in hints {
+        eprintln!("  {}", hint);
+    }
+}
+
+type CallbackResult = bool;
+
+struct MatchResult<'data> {
+    probe: &'data PathBuf,
+    gallery: &'data PathBuf,
+    score: Option<u32>,
+    /// Number of matched minutiae pairs, see `BozorthState::matched_pairs`.
+    matched_pairs: Option<usize>,
+    /// Edges of both templates removed by `--thin-edges`.
+    thinned_edges: Option<usize>,
+    features: Option<MatchFeatures>,
+    /// The match ran out of `--match-timeout-ms`; the score is the best one found until then.
+    timed_out: bool,
+}
+
+impl<'data> MatchResult<'data> {
+    /// Result of a pair; pairs without a score are the ones whose templates could not be loaded.
+    fn new(probe: &'data PathBuf, gallery: &'data PathBuf, score: Option<TemplateScore>) -> Self {
+        MatchResult {
+            probe,
+            gallery,
+            score: score.map(|it| it.score),
+            matched_pairs: score.map(|it| it.matched_pairs),
+            thinned_edges: score.map(|it| it.thinned_edges),
+            features: score.map(|it| it.features),
+            timed_out: score.is_some_and(|it| it.timed_out),
+        }
+    }
+}
+
+/// Orders results by score, then by gallery file name, so that the best result is the greatest.
+struct Ranked<'data>(MatchResult<'data>);
+
+impl Ranked<'_> {
+    fn key(&self) -> (Option<u32>, std::cmp::Reverse<&PathBuf>) {
+        (self.0.score, std::cmp::Reverse(self.0.gallery))
+    }
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Keeps the `k` best results of every probe; probes follow the order of `probes`,
+/// their results are sorted from the best.
+fn top_k_per_probe<'data>(
+    results: impl IntoIterator<Item = MatchResult<'data>>,
+    probes: &[PathBuf],
+    k: usize,
+) -> Vec<MatchResult<'data>> {
+    let mut best: HashMap<&PathBuf, BinaryHeap<std::cmp::Reverse<Ranked>>> = HashMap::new();
+    for result in results {
+        let heap = best.entry(result.probe).or_default();
+        heap.push(std::cmp::Reverse(Ranked(result)));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    let mut ordered = vec![];
+    for probe in probes {
+        if let Some(heap) = best.remove(probe) {
+            ordered.extend(heap.into_sorted_vec().into_iter().map(|it| (it.0).0));
+        }
+    }
+    ordered
+}
+
+/// Scores of all matches written by `run`, accumulated by the writer thread.
+struct Summary {
+    bucket_width: u32,
+    threshold: u32,
+    /// Number of matches of every score.
+    counts: BTreeMap<u32, u64>,
+    /// Matches without a score, because a template could not be loaded.
+    failed: u64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct SummaryReport {
+    compared: u64,
+    failed: u64,
+    min: Option<u32>,
+    median: Option<u32>,
+    mean: Option<f64>,
+    max: Option<u32>,
+    threshold: u32,
+    /// Fraction of compared pairs with at least the threshold score.
+    above_threshold: f64,
+    bucket_width: u32,
+    /// Number of matches in buckets of `bucket_width` scores, from score 0.
+    histogram: Vec<u64>,
+}
+
+impl Summary {
+    fn new(bucket_width: u32, threshold: u32) -> Self {
+        Summary {
+            bucket_width,
+            threshold,
+            counts: BTreeMap::new(),
+            failed: 0,
+        }
+    }
+
+    fn add(&mut self, score: Option<u32>) {
+        match score {
+            Some(score) => *self.counts.entry(score).or_default() += 1,
+            None => self.failed += 1,
+        }
+    }
+
+    fn report(&self) -> SummaryReport {
+        let scored: u64 = self.counts.values().sum();
+        let compared = scored + self.failed;
+
+        let mut histogram = vec![];
+        for (&score, &count) in &self.counts {
+            let bucket = (score / self.bucket_width) as usize;
+            if bucket >= histogram.len() {
+                histogram.resize(bucket + 1, 0);
+            }
+            histogram[bucket] += count;
+        }
+
+        let mut seen = 0;
+        let median = self.counts.iter().find_map(|(&score, &count)| {
+            seen += count;
+            (2 * seen > scored).then_some(score)
+        });
+        let sum: u64 = self
+            .counts
+            .iter()
+            .map(|(&score, &count)| score as u64 * count)
+            .sum();
+        let above: u64 = self.counts.range(self.threshold..).map(|it| it.1).sum();
+
+        SummaryReport {
+            compared,
+            failed: self.failed,
+            min: self.counts.keys().next().copied(),
+            median,
+            mean: (scored > 0).then(|| sum as f64 / scored as f64),
+            max: self.counts.keys().next_back().copied(),
+            threshold: self.threshold,
+            above_threshold: if compared > 0 {
+                above as f64 / compared as f64
+            } else {
+                0.0
+            },
+            bucket_width: self.bucket_width,
+            histogram,
+        }
+    }
+}
+
+impl std::fmt::Display for SummaryReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_owned());
+        writeln!(f, "{:<24}{:>10}", "compared", self.compared)?;
+        writeln!(f, "{:<24}{:>10}", "failed", self.failed)?;
+        writeln!(
+            f,
+            "{:<24}{:>10}",
+            "min",
+            or_dash(self.min.map(|it| it.to_string()))
+        )?;
+        writeln!(
+            f,
+            "{:<24}{:>10}",
+            "median",
+            or_dash(self.median.map(|it| it.to_string()))
+        )?;
+        writeln!(
+            f,
+            "{:<24}{:>10}",
+            "mean",
+            or_dash(self.mean.map(|it| format!("{:.2}", it)))
+        )?;
+        writeln!(
+            f,
+            "{:<24}{:>10}",
+            "max",
+            or_dash(self.max.map(|it| it.to_string()))
+        )?;
+        writeln!(
+            f,
+            "{:<24}{:>10.4}",
+            format!("above threshold {}", self.threshold),
+            self.above_threshold
+        )?;
+        writeln!(f, "score histogram:")?;
+        for (bucket, count) in self.histogram.iter().enumerate() {
+            let start = bucket as u32 * self.bucket_width;
+            writeln!(
+                f,
+                "  {:>4}-{:<4}{:>26}",
+                start,
+                start + self.bucket_width - 1,
+                count
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Number of matches with at least each threshold of a sweep, accumulated by the writer thread.
+struct SweepCounts {
+    thresholds: Vec<u32>,
+    counts: Vec<u64>,
+    /// Matches seen, including those without a score.
+    compared: u64,
+}
+
+impl SweepCounts {
+    fn new(sweep: Sweep) -> Self {
+        let thresholds: Vec<_> = sweep.thresholds().collect();
+        SweepCounts {
+            counts: vec![0; thresholds.len()],
+            thresholds,
+            compared: 0,
+        }
+    }
+
+    fn add(&mut self, score: Option<u32>) {
+        self.compared += 1;
+        if let Some(score) = score {
+            let passed = self.thresholds.partition_point(|&it| it <= score);
+            self.counts[..passed].iter_mut().for_each(|it| *it += 1);
+        }
+    }
+}
+
+impl std::fmt::Display for SweepCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{:>9}{:>15}{:>10}", "threshold", "matches", "fraction")?;
+        for (threshold, &count) in self.thresholds.iter().zip(&self.counts) {
+            let fraction = if self.compared > 0 {
+                count as f64 / self.compared as f64
+            } else {
+                0.0
+            };
+            writeln!(f, "{:>9}{:>15}{:>10.4}", threshold, count, fraction)?;
+        }
+        Ok(())
+    }
+}
+
+const FEATURES_CSV_HEADER: &str = "probe,gallery,score,cluster_count,max_cluster_points,\
+winning_combination_size,probe_endpoints,gallery_endpoints";
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Row of `--features-output`; the fields of matches without a score are empty.
+fn features_row(result: &MatchResult) -> String {
+    let optional = |value: Option<String>| value.unwrap_or_default();
+    let features = result.features;
+    let fields = [
+        csv_field(&result.probe.display().to_string()),
+        csv_field(&result.gallery.display().to_string()),
+        optional(result.score.map(|it| it.to_string())),
+        optional(features.map(|it| it.cluster_count.to_string())),
+        optional(features.map(|it| it.max_cluster_points.to_string())),
+        optional(features.map(|it| it.winning_combination_size.to_string())),
+        optional(features.map(|it| it.probe_endpoints.to_string())),
+        optional(features.map(|it| it.gallery_endpoints.to_string())),
+    ];
+    fields.join(",")
+}
+
+/// Matches the pairs and writes their results; returns where matching stopped after SIGINT.
+fn match_range(
+    probes: &[PathBuf],
+    galleries: &[PathBuf],
+    compare_mode: CompareMode,
+    options: &Options,
+    calibration: Option<&Calibration>,
+) -> anyhow::Result<Option<Stopped>> {
+    // created up front, the writer has to receive every result once matching started
+    let features_output = match &options.features_output {
+        Some(path) => Some(
+            std::fs::File::create(path)
+                .map(std::io::BufWriter::new)
+                .with_context(|| format!("cannot create {}", path.display()))?,
+        ),
+        None => None,
+    };
+
+    crossbeam::scope(move |scope| {
+        let (tx_match_done, rx_match_done) = crossbeam::channel::unbounded::<MatchResult>();
+        let output_file = options.output_file.clone();
+
+        let matcher = scope.spawn(move |_| {
+            let (mode, threshold) = (options.mode, options.threshold);
+            let score_callback = move |score: Option<u32>| -> CallbackResult {
+                if mode == MatchMode::Any {
+                    true
+                } else {
+                    score >= Some(threshold)
+                }
+            };
+
+            let settings = MatchSettings {
+                format: format_of(options),
+                min_pairs: options.min_pairs,
+                fusion: options.fuse,
+                thin: thin_options_of(options),
+                input: options.input_format,
+                angles: options.angle_convention,
+                max_template_minutiae: options.max_template_minutiae,
+                strict: true,
+                deadline: match_timeout_of(options),
+                dedup_pairs: options.dedup_pairs,
+            };
+            if options.threads > 1 {
+                execute_parallel(
+                    compare_mode,
+                    &ExecuteOptions {
+                        match_mode: options.mode,
+                        probes,
+                        galleries,
+                        score_callback,
+                        match_done: tx_match_done,
+                        prune_options: prune_options_of(options),
+                        settings,
+                        threads: options.threads,
+                        chunk_size: options.chunk_size,
+                        relaxed_order: options.relaxed_output_order,
+                    },
+                )
+            } else {
+                let mut cache = Cache::new(prune_options_of(options), settings);
+                if options.preload {
+                    cache.preload(probes.iter().chain(galleries.iter()));
+                }
+                execute_sequential(
+                    compare_mode,
+                    options.mode,
+                    probes,
+                    galleries,
+                    score_callback,
+                    tx_match_done,
+                    cache,
+                    settings,
+                )
+            }
+        });
+
+        let writer = scope.spawn(move |_| -> anyhow::Result<()> {
+            fn print_into_stream<'data>(
+                output: &mut impl Write,
+                rx: impl IntoIterator<Item = MatchResult<'data>>,
+                only_scores: bool,
+                with_pairs: bool,
+                with_thinned: bool,
+                calibration: Option<&Calibration>,
+            ) -> std::io::Result<()> {
+                let column = |enabled: bool, count: Option<usize>| match (enabled, count) {
+                    (true, Some(count)) => format!(" {}", count),
+                    (true, None) => " -".to_owned(),
+                    (false, _) => String::new(),
+                };
+                for MatchResult {
+                    probe,
+                    gallery,
+                    score,
+                    matched_pairs,
+                    thinned_edges,
+                    timed_out,
+                    ..
+                } in rx
+                {
+                    let matched_pairs =
+                        column(with_pairs, matched_pairs) + &column(with_thinned, thinned_edges);
+                    let probability = match (calibration, score) {
+                        (Some(calibration), Some(score)) => {
+                            format!(" {:.4}", calibration.probability(score))
+                        }
+                        (Some(_), None) => " -".to_owned(),
+                        (None, _) => String::new(),
+                    };
+                    let score = score.map(|s| s as i32).unwrap_or(-1);
+                    let marker = if timed_out { " timeout" } else { "" };
+                    if only_scores {
+                        writeln!(
+                            output,
+                            "{}{}{}{}",
+                            score, matched_pairs, probability, marker
+                        )?;
+                    } else {
+                        writeln!(
+                            output,
+                            "{} {} {}{}{}{}",
+                            probe.display(),
+                            gallery.display(),
+                            score,
+                            matched_pairs,
+                            probability,
+                            marker
+                        )?;
+                    }
+                }
+                output.flush()
+            }
+
+            let mut summary = Summary::new(options.summary_bucket_width, options.threshold);
+            let summarize = options.summary || options.summary_only;
+            let mut sweep = options.threshold_sweep.map(SweepCounts::new);
+            let mut features_output = features_output;
+            let mut features_written = match &mut features_output {
+                Some(file) => writeln!(file, "{}", FEATURES_CSV_HEADER),
+                None => Ok(()),
+            };
+            let received = rx_match_done.into_iter().inspect(|it| {
+                if summarize {
+                    summary.add(it.score);
+                }
+                if let Some(sweep) = &mut sweep {
+                    sweep.add(it.score);
+                }
+                if let (Some(file), Ok(())) = (&mut features_output, &features_written) {
+                    features_written = writeln!(file, "{}", features_row(it));
+                }
+            });
+            let mut results: Box<dyn Iterator<Item = MatchResult>> = match options.top_k {
+                Some(k) => Box::new(top_k_per_probe(received, probes, k).into_iter()),
+                None => Box::new(received),
+            };
+
+            let written = if options.summary_only {
+                Ok(())
+            } else if let Some(path) = output_file.as_ref() {
+                std::fs::File::create(path)
+                    .and_then(|file| {
+                        print_into_stream(
+                            &mut std::io::BufWriter::new(file),
+                            results.by_ref(),
+                            options.only_scores,
+                            options.with_pairs,
+                            options.thin_edges > 0,
+                            calibration,
+                        )
+                    })
+                    .with_context(|| format!("cannot write results into {}", path.display()))
+            } else {
+                let stdout = std::io::stdout();
+                print_into_stream(
+                    &mut std::io::BufWriter::new(stdout.lock()),
+                    results.by_ref(),
+                    options.only_scores,
+                    options.with_pairs,
+                    options.thin_edges > 0,
+                    calibration,
+                )
+                .context("cannot write results")
+            };
+            // the matcher stops only when all its results are received
+            results.for_each(drop);
+            written?;
+            if let (Some(file), Some(path)) = (&mut features_output, &options.features_output) {
+                features_written
+                    .and_then(|()| file.flush())
+                    .with_context(|| format!("cannot write features into {}", path.display()))?;
+            }
+
+            if summarize {
+                let report = summary.report();
+                eprint!("{}", report);
+                if let Some(path) = &options.summary_file {
+                    let json =
+                        serde_json::to_string_pretty(&report).expect("summary is serializable");
+                    std::fs::write(path, json).expect("cannot write summary file");
+                }
+            }
+            if let Some(sweep) = sweep {
+                eprint!("{}", sweep);
+            }
+            Ok(())
+        });
+
+        let stopped = matcher.join().expect("matching failed");
+        writer.join().expect("writing failed")?;
+        Ok(stopped)
+    })
+    .expect("cannot spawn tasks")
+}
+
+/// Fingers of a single template file, or of all template files in a directory with `--fuse`.
+type Template = Box<[Fingerprint]>;
+
+fn extract_edges(
+    file: impl AsRef<Path>,
+    prune_options: PruneOptions,
+    settings: MatchSettings,
+) -> anyhow::Result<Fingerprint> {
+    let max_minutiae = settings.max_template_minutiae.unwrap_or(usize::MAX);
+    let minutiae = parse_with_limit(
+        file,
+        QualitySource::default(),
+        settings.angles,
+        max_minutiae,
+    )
+    .context("cannot parse file")?;
+    Ok(fingerprint_of(&minutiae, prune_options, settings))
+}
+
+fn fingerprint_of(
+    minutiae: &[RawMinutiaCombined],
+    prune_options: PruneOptions,
+    settings: MatchSettings,
+) -> Fingerprint {
+    let mut fingerprint = Fingerprint::from_raw(minutiae, prune_options, settings.format);
+    fingerprint.thin_edges(&settings.thin);
+    fingerprint
+}
+
+/// Loads the fingers of the Type-9 records of an ANSI/NIST-ITL transaction; only the first
+/// one without `--fuse`.
+fn load_an2(
+    path: &Path,
+    prune_options: PruneOptions,
+    settings: MatchSettings,
+) -> anyhow::Result<Template> {
+    let mut fingers = nist_itl::load(path).context("cannot parse file")?;
+    if fingers.is_empty() {
+        anyhow::bail!("no Type-9 records with minutiae in the standard format");
+    }
+    if let Some(max) = settings.max_template_minutiae {
+        if let Some(finger) = fingers.iter().find(|it| it.minutiae.len() > max) {
+            anyhow::bail!("record {} has more than {} minutiae", finger.idc, max);
+        }
+    }
+    if settings.fusion.is_none() {
+        fingers.truncate(1);
+    }
+    Ok(fingers
+        .iter()
+        .map(|it| fingerprint_of(&it.minutiae, prune_options, settings))
+        .collect())
+}
+
+fn load_template(
+    path: &Path,
+    prune_options: PruneOptions,
+    settings: MatchSettings,
+) -> anyhow::Result<Template> {
+    let template = read_template(path, prune_options, settings)?;
+    for (finger, it) in template.iter().enumerate() {
+        debug!(
+            path:% = path.display(), finger, minutiae_used = it.minutiae_used,
+            edges_generated = it.edges_generated, edges_kept = it.edges_kept,
+            thinned_edges = it.thinned_edges;
+            "template loaded"
+        );
+    }
+    Ok(template)
+}
+
+fn read_template(
+    path: &Path,
+    prune_options: PruneOptions,
+    settings: MatchSettings,
+) -> anyhow::Result<Template> {
+    if settings.input.of(path) == InputFormat::An2 {
+        return load_an2(path, prune_options, settings);
+    }
+    if settings.fusion.is_none() || !path.is_dir() {
+        return Ok(vec![extract_edges(path, prune_options, settings)?].into_boxed_slice());
+    }
+
+    let mut files: Vec<_> = std::fs::read_dir(path)
+        .context("cannot read directory")?
+        .map(|it| it.map(|it| it.path()))
+        .collect::<Result<_, _>>()?;
+    files.retain(|it| is_xyt(it));
+    files.sort();
+    if files.is_empty() {
+        anyhow::bail!("no .xyt files in {}", path.display());
+    }
+
+    files
+        .iter()
+        .map(|it| extract_edges(it, prune_options, settings))
+        .collect()
+}
+
+struct Cache {
+    cache: HashMap<PathBuf, Arc<Template>>,
+    /// Files that could not be loaded; each is reported only once.
+    failed: HashSet<PathBuf>,
+    prune_options: PruneOptions,
+    settings: MatchSettings,
+}
+
+impl Cache {
+    fn new(prune_options: PruneOptions, settings: MatchSettings) -> Self {
+        Self {
+            cache: HashMap::new(),
+            failed: HashSet::new(),
+            prune_options,
+            settings,
+        }
+    }
+
+    /// Loads all the given files in parallel, so that matching does not wait for them later.
+    ///
+    /// Files that cannot be loaded are skipped; `get_or_load` reports their errors.
+    fn preload<'a>(&mut self, files: impl Iterator<Item = &'a PathBuf> + Send) {
+        let (prune_options, settings) = (self.prune_options, self.settings);
+        let loaded: Vec<_> = files
+            .filter(|it| !self.cache.contains_key(it.as_path()))
+            .collect::<HashSet<_>>()
+            .into_par_iter()
+            .filter_map(|it| {
+                let template = load_template(it, prune_options, settings).ok()?;
+                Some((it.clone(), Arc::new(template)))
+            })
+            .collect();
+        self.cache.extend(loaded);
+    }
+
+    fn get_or_load(&mut self, file_name: impl AsRef<Path>) -> anyhow::Result<Arc<Template>> {
+        let path = file_name.as_ref();
+        if let Some(fp) = self.cache.get(path) {
+            return Ok(fp.clone());
+        }
+        if self.failed.contains(path) {
+            anyhow::bail!("{} could not be loaded", path.display());
+        }
+
+        debug!(path:% = path.display(); "loading template");
+        let fp = match load_template(path, self.prune_options, self.settings) {
+            Ok(fp) => fp,
+            Err(e) => {
+                warn!(path:% = path.display(); "cannot load template: {:#}", e);
+                self.failed.insert(path.to_owned());
+                return Err(e);
+            }
+        };
+        let fp = Arc::new(fp);
+        self.cache.insert(file_name.as_ref().to_owned(), fp.clone());
+        Ok(fp)
+    }
+
+    #[allow(unused)]
+    fn get(&self, file_name: impl AsRef<Path>) -> anyhow::Result<Arc<Template>> {
+        Ok(self.cache.get(file_name.as_ref()).unwrap().clone())
+    }
+}
+
+trait ScoreCallback = Fn(Option<u32>) -> bool + Clone + Send + Sync + 'static;
+
+struct ExecuteOptions<'data, SC: ScoreCallback> {
+    match_mode: MatchMode,
+    probes: &'data [PathBuf],
+    galleries: &'data [PathBuf],
+    score_callback: SC,
+    match_done: crossbeam::channel::Sender<MatchResult<'data>>,
+    prune_options: PruneOptions,
+    settings: MatchSettings,
+    threads: u32,
+    chunk_size: u32,
+    relaxed_order: bool,
+}
+
+/// Options of a single match shared by both executors.
+#[derive(Copy, Clone)]
+struct MatchSettings {
+    format: Format,
+    /// Matches with fewer candidate pairs are scored 0 without running `match_score`.
+    min_pairs: usize,
+    /// Fuse scores of corresponding fingers of multi-finger templates.
+    fusion: Option<FusionStrategy>,
+    /// Thinning of the edges of loaded templates.
+    thin: ThinOptions,
+    /// Format of the template files.
+    input: InputFormat,
+    /// Angle convention of .xyt files, detected for every file when `None`.
+    angles: Option<AngleConvention>,
+    /// Template files with more minutiae are rejected.
+    max_template_minutiae: Option<usize>,
+    /// Mode of the match, see `MatchConfig::strict`.
+    strict: bool,
+    /// Time limit of a single match, see `MatchConfig::deadline`.
+    deadline: Option<Duration>,
+    /// Collapse pairs of coincident minutiae, see `MatchConfig::dedup_pairs`.
+    dedup_pairs: bool,
+}
+
+impl MatchSettings {
+    fn batch(&self) -> BatchSettings {
+        BatchSettings {
+            format: self.format,
+            config: MatchConfig {
+                strict: self.strict,
+                deadline: self.deadline,
+                dedup_pairs: self.dedup_pairs,
+                ..MatchConfig::default()
+            },
+            min_pairs: self.min_pairs,
+            fusion: self.fusion,
+        }
+    }
+}
+
+/// Returns where matching stopped after SIGINT; the pairs already taken by workers are matched.
+///
+/// Unless `relaxed_order` is set, results are reported in the order the pairs were produced;
+/// for [`CompareMode::OneToOne`] that is the order of the pair file.
+fn execute_parallel<SC: ScoreCallback>(
+    compare_mode: CompareMode,
+    options: &ExecuteOptions<'_, SC>,
+) -> Option<Stopped> {
+    let loaded: HashMap<&Path, Arc<Template>> = options
+        .probes
+        .iter()
+        .chain(options.galleries.iter())
+        .collect::<HashSet<_>>()
+        .into_par_iter()
+        .filter_map(
+            |it| match load_template(it, options.prune_options, options.settings) {
+                Ok(fp) => Some((it.as_path(), Arc::new(fp))),
+                Err(e) => {
+                    warn!(path:% = it.display(); "cannot load template: {:#}", e);
+                    None
+                }
+            },
+        )
+        .collect();
+    // templates that cannot be loaded are matched without a score
+    let templates = |files: &[PathBuf]| -> Vec<_> {
+        files
+            .iter()
+            .map(|it| loaded.get(it.as_path()).cloned())
+            .collect()
+    };
+
+    let first_match = options.match_mode == MatchMode::OnlyFirstMatch;
+    let score_callback = options.score_callback.clone();
+    let matcher = BatchMatcher::new(templates(options.probes), templates(options.galleries))
+        .mode(match compare_mode {
+            CompareMode::OneToOne => BatchMode::Pairwise,
+            CompareMode::EveryProbeWithEachGallery | CompareMode::OneToMany => BatchMode::AllPairs,
+        })
+        .settings(options.settings.batch())
+        .threads(options.threads as usize)
+        .chunk_size(options.chunk_size as usize)
+        .filter(move |it| score_callback(it.score.map(|it| it.score)))
+        .first_match_per_probe(first_match)
+        .relaxed_order(options.relaxed_order)
+        .stop_when(interrupted);
+
+    let mut results = matcher.run();
+    for result in results.by_ref() {
+        let probe = &options.probes[result.probe];
+        let gallery = &options.galleries[result.gallery];
+        options
+            .match_done
+            .send(MatchResult::new(probe, gallery, result.score))
+            .unwrap();
+        // as in `execute_sequential`, only one-to-many looks for the first match of every probe
+        if first_match && compare_mode != CompareMode::OneToMany {
+            return None;
+        }
+    }
+    results.stopped().map(|it| Stopped {
+        completed: it.completed as u64,
+        probe: it.probe,
+        gallery: it.gallery,
+    })
+}
+
+/// Returns where matching stopped after SIGINT.
+fn execute_sequential<'data>(
+    compare_mode: CompareMode,
+    match_mode: MatchMode,
+    probes: &'data [PathBuf],
+    galleries: &'data [PathBuf],
+    mut score_callback: impl FnMut(Option<u32>) -> bool,
+    match_done: crossbeam::channel::Sender<MatchResult<'data>>,
+    mut cache: Cache,
+    settings: MatchSettings,
+) -> Option<Stopped> {
+    let mut pair_cacher = PairHolder::new();
+    let mut state = BozorthState::new();
+    let settings = settings.batch();
+
+    let mut execute = move |probe: &PathBuf, gallery: &PathBuf| -> Option<TemplateScore> {
+        let gallery_cache = cache.get_or_load(gallery);
+        let probe_cache = cache.get_or_load(probe);
+
+        if let (Ok(gallery_fp), Ok(probe_fp)) = (gallery_cache, probe_cache) {
+            match_templates(
+                &probe_fp,
+                &gallery_fp,
+                &settings,
+                &mut pair_cacher,
+                &mut state,
+            )
+        } else {
+            None
+        }
+    };
+
+    let mut completed = 0;
+    let stopped = |completed, probe, gallery| {
+        Some(Stopped {
+            completed,
+            probe,
+            gallery,
+        })
+    };
+    match compare_mode {
+        CompareMode::OneToOne => {
+            for (index, (probe, gallery)) in probes.iter().zip(galleries.iter()).enumerate() {
+                if interrupted() {
+                    return stopped(completed, index, index);
+                }
+                let result = execute(probe, gallery);
+                completed += 1;
+                if score_callback(result.map(|it| it.score)) {
+                    match_done
+                        .send(MatchResult::new(probe, gallery, result))
+                        .unwrap();
+                    if match_mode == MatchMode::OnlyFirstMatch {
+                        return None;
+                    }
+                }
+            }
+        }
+        CompareMode::EveryProbeWithEachGallery => {
+            for (probe_index, probe) in probes.iter().enumerate() {
+                for (gallery_index, gallery) in galleries.iter().enumerate() {
+                    if interrupted() {
+                        return stopped(completed, probe_index, gallery_index);
+                    }
+                    let result = execute(probe, gallery);
+                    completed += 1;
+                    if score_callback(result.map(|it| it.score)) {
+                        match_done
+                            .send(MatchResult::new(probe, gallery, result))
+                            .unwrap();
+                        if match_mode == MatchMode::OnlyFirstMatch {
+                            return None;
+                        }
+                    }
+                }
+            }
+        }
+        CompareMode::OneToMany => {
+            for (probe_index, probe) in probes.iter().enumerate() {
+                for (gallery_index, gallery) in galleries.iter().enumerate() {
+                    if interrupted() {
+                        return stopped(completed, probe_index, gallery_index);
+                    }
+                    let result = execute(probe, gallery);
+                    completed += 1;
+                    if score_callback(result.map(|it| it.score)) {
+                        match_done
+                            .send(MatchResult::new(probe, gallery, result))
+                            .unwrap();
+                        if match_mode == MatchMode::OnlyFirstMatch {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Line of the output with file names: probe, gallery, score and optional further columns.
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct OutputLine {
+    probe: String,
+    gallery: String,
+    score: i64,
+    rest: String,
+}
+
+impl FromStr for OutputLine {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut columns = line.splitn(4, ' ');
+        let mut next = || columns.next().context("missing column");
+        let probe = next()?.to_owned();
+        let gallery = next()?.to_owned();
+        let score = next()?.parse().context("invalid score")?;
+        let rest = columns.next().unwrap_or_default().to_owned();
+        Ok(OutputLine {
+            probe,
+            gallery,
+            score,
+            rest,
+        })
+    }
+}
+
+impl std::fmt::Display for OutputLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.probe, self.gallery, self.score)?;
+        if !self.rest.is_empty() {
+            write!(f, " {}", self.rest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sorts lines by probe, then from the best score, and keeps `top_k` lines of every probe.
+fn merge_lines(mut lines: Vec<OutputLine>, top_k: Option<usize>) -> Vec<OutputLine> {
+    lines.sort_by(|a, b| {
+        (&a.probe, std::cmp::Reverse(a.score), &a.gallery).cmp(&(
+            &b.probe,
+            std::cmp::Reverse(b.score),
+            &b.gallery,
+        ))
+    });
+    lines.dedup();
+
+    let mut kept = 0;
+    let mut previous: Option<String> = None;
+    lines.retain(|line| {
+        if previous.as_ref() != Some(&line.probe) {
+            previous = Some(line.probe.clone());
+            kept = 0;
+        }
+        kept += 1;
+        top_k.is_none_or(|k| kept <= k)
+    });
+    lines
+}
+
+fn merge_results(options: &MergeOptions) -> anyhow::Result<()> {
+    let mut lines = vec![];
+    for path in &options.files {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read {}", path.display()))?;
+        for (i, line) in content.lines().enumerate() {
+            lines.push(line.parse::<OutputLine>().with_context(|| {
+                format!(
+                    "{}:{}: not an output with file names",
+                    path.display(),
+                    i + 1
+                )
+            })?);
+        }
+    }
+
+    let mut output: Box<dyn Write> = match &options.output_file {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("cannot create {}", path.display()))?,
+        )),
+        None => Box::new(std::io::BufWriter::new(std::io::stdout())),
+    };
+    for line in merge_lines(lines, options.top_k) {
+        writeln!(output, "{}", line)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// Score differences of pairs matched in two modes of the algorithm.
+#[derive(Debug, PartialEq)]
+struct ModeComparison {
+    /// Index of the pair and its second score minus the first one, for pairs scored in both modes.
+    deltas: Vec<(usize, i64)>,
+    /// Pairs without a score in at least one of the modes.
+    failed: u64,
+}
+
+impl ModeComparison {
+    fn new(first: &[Option<u32>], second: &[Option<u32>]) -> Self {
+        let mut deltas = vec![];
+        let mut failed = 0;
+        for (index, scores) in first.iter().zip(second).enumerate() {
+            match scores {
+                (Some(first), Some(second)) => {
+                    deltas.push((index, i64::from(*second) - i64::from(*first)))
+                }
+                _ => failed += 1,
+            }
+        }
+        ModeComparison { deltas, failed }
+    }
+
+    /// Up to `count` pairs with different scores, the largest absolute differences first.
+    fn largest(&self, count: usize) -> Vec<(usize, i64)> {
+        let mut changed: Vec<_> = self.deltas.iter().copied().filter(|it| it.1 != 0).collect();
+        changed.sort_by(|a, b| b.1.abs().cmp(&a.1.abs()).then(a.0.cmp(&b.0)));
+        changed.truncate(count);
+        changed
+    }
+}
+
+impl std::fmt::Display for ModeComparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut counts = BTreeMap::new();
+        for &(_, delta) in &self.deltas {
+            *counts.entry(delta).or_insert(0u64) += 1;
+        }
+        let changed = self.deltas.len() as u64 - counts.get(&0).copied().unwrap_or(0);
+        let mean = |value: fn(i64) -> i64| {
+            if self.deltas.is_empty() {
+                "-".to_owned()
+            } else {
+                let sum: i64 = self.deltas.iter().map(|it| value(it.1)).sum();
+                format!("{:.2}", sum as f64 / self.deltas.len() as f64)
+            }
+        };
+
+        writeln!(f, "{:<24}{:>10}", "compared", self.deltas.len())?;
+        writeln!(f, "{:<24}{:>10}", "failed", self.failed)?;
+        writeln!(f, "{:<24}{:>10}", "changed", changed)?;
+        writeln!(f, "{:<24}{:>10}", "mean delta", mean(|it| it))?;
+        writeln!(f, "{:<24}{:>10}", "mean absolute delta", mean(i64::abs))?;
+        writeln!(f, "delta histogram:")?;
+        for (delta, count) in counts {
+            writeln!(f, "  {:>+6}{:>26}", delta, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Scores of the pairs in `mode`; `None` for pairs with a template that cannot be loaded.
+fn scores_in_mode(
+    probes: &[PathBuf],
+    galleries: &[PathBuf],
+    mode: AlgorithmMode,
+    prune_options: PruneOptions,
+    settings: MatchSettings,
+    pool: &rayon::ThreadPool,
+) -> Vec<Option<u32>> {
+    let strict = mode == AlgorithmMode::Strict;
+    let prune_options = PruneOptions {
+        strict,
+        ..prune_options
+    };
+    let settings = MatchSettings { strict, ..settings };
+    // the modes prune minutiae differently, so templates are extracted again for every mode
+    let mut cache = Cache::new(prune_options, settings);
+    pool.install(|| cache.preload(probes.iter().chain(galleries)));
+    let templates: Vec<_> = probes
+        .iter()
+        .zip(galleries)
+        .map(|(probe, gallery)| {
+            Some((
+                cache.get_or_load(probe).ok()?,
+                cache.get_or_load(gallery).ok()?,
+            ))
+        })
+        .collect();
+    let settings = settings.batch();
+    pool.install(|| {
+        templates
+            .par_iter()
+            .map_init(
+                || (PairHolder::new(), BozorthState::new()),
+                |(pairs, state), templates| {
+                    let (probe, gallery) = templates.as_ref()?;
+                    let score = match_templates(probe, gallery, &settings, pairs, state)?;
+                    Some(score.score)
+                },
+            )
+            .collect()
+    })
+}
+
+fn dump_edges(options: &Options, dump_options: &DumpEdgesOptions) -> anyhow::Result<()> {
+    let settings = MatchSettings {
+        format: format_of(options),
+        min_pairs: options.min_pairs,
+        fusion: options.fuse,
+        thin: thin_options_of(options),
+        input: options.input_format,
+        angles: options.angle_convention,
+        max_template_minutiae: options.max_template_minutiae,
+        strict: true,
+        deadline: match_timeout_of(options),
+        dedup_pairs: options.dedup_pairs,
+    };
+    let path = &dump_options.template;
+    let template = load_template(path, prune_options_of(options), settings)
+        .with_context(|| format!("cannot load {}", path.display()))?;
+    let fingerprint = match &*template {
+        [fingerprint] => fingerprint,
+        _ => anyhow::bail!(
+            "{} has {} fingers, expected one",
+            path.display(),
+            template.len()
+        ),
+    };
+
+    let dump = fingerprint.edge_dump();
+    match &dump_options.output_file {
+        Some(output) => std::fs::write(output, dump)
+            .with_context(|| format!("cannot write {}", output.display()))?,
+        None => print!("{}", dump),
+    }
+    Ok(())
+}
+
+fn compare_modes(options: &Options, compare_options: &CompareModesOptions) -> anyhow::Result<()> {
+    let (probes, galleries) =
+        find_items_from_pairs(&compare_options.pair_file, options.allow_missing)?;
+    let prune_options = prune_options_of(options);
+    let settings = MatchSettings {
+        format: format_of(options),
+        min_pairs: options.min_pairs,
+        fusion: options.fuse,
+        thin: thin_options_of(options),
+        input: options.input_format,
+        angles: options.angle_convention,
+        max_template_minutiae: options.max_template_minutiae,
+        strict: true,
+        deadline: match_timeout_of(options),
+        dedup_pairs: options.dedup_pairs,
+    };
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads as usize)
+        .build()
+        .context("cannot start worker threads")?;
+
+    let (first, second) = (compare_options.first, compare_options.second);
+    let first_scores = scores_in_mode(&probes, &galleries, first, prune_options, settings, &pool);
+    let second_scores = scores_in_mode(&probes, &galleries, second, prune_options, settings, &pool);
+
+    let mut output: Box<dyn Write> = match &compare_options.output_file {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("cannot create {}", path.display()))?,
+        )),
+        None => Box::new(std::io::BufWriter::new(std::io::stdout())),
+    };
+    let optional = |score: Option<u32>| score.map(|it| it.to_string()).unwrap_or_default();
+    writeln!(output, "probe,gallery,first,second,delta")?;
+    for (index, (probe, gallery)) in probes.iter().zip(&galleries).enumerate() {
+        let (first, second) = (first_scores[index], second_scores[index]);
+        let delta = first
+            .zip(second)
+            .map(|(first, second)| (i64::from(second) - i64::from(first)).to_string());
+        writeln!(
+            output,
+            "{},{},{},{},{}",
+            csv_field(&probe.display().to_string()),
+            csv_field(&gallery.display().to_string()),
+            optional(first),
+            optional(second),
+            delta.unwrap_or_default()
+        )?;
+    }
+    output.flush()?;
+
+    let comparison = ModeComparison::new(&first_scores, &second_scores);
+    eprintln!("{} compared to {}:", second, first);
+    eprint!("{}", comparison);
+    let largest = comparison.largest(compare_options.largest);
+    if !largest.is_empty() {
+        eprintln!("largest differences:");
+    }
+    for (index, delta) in largest {
+        eprintln!(
+            "  {} {} {} {} {:+}",
+            probes[index].display(),
+            galleries[index].display(),
+            optional(first_scores[index]),
+            optional(second_scores[index]),
+            delta
+        );
+    }
+    Ok(())
+}
+
+/// Gallery of the watch mode, loaded once.
+type IndexedGallery = Vec<(PathBuf, Arc<Template>)>;
+
+fn watch(options: &Options, watch_options: &WatchOptions) -> anyhow::Result<()> {
+    let prune_options = prune_options_of(options);
+    let settings = MatchSettings {
+        format: format_of(options),
+        min_pairs: options.min_pairs,
+        fusion: options.fuse,
+        thin: thin_options_of(options),
+        input: options.input_format,
+        angles: options.angle_convention,
+        max_template_minutiae: options.max_template_minutiae,
+        strict: true,
+        deadline: match_timeout_of(options),
+        dedup_pairs: options.dedup_pairs,
+    };
+
+    let gallery_files =
+        get_items_from_file_or_directory(&watch_options.gallery).context("cannot read gallery")?;
+    let mut cache = Cache::new(prune_options, settings);
+    cache.preload(gallery_files.iter());
+    let gallery: IndexedGallery = gallery_files
+        .into_iter()
+        .filter_map(|path| match cache.get_or_load(&path) {
+            Ok(template) => Some((path, template)),
+            Err(e) => {
+                warn!(path:% = path.display(); "skipping gallery file: {:#}", e);
+                None
+            }
+        })
+        .collect();
+    info!(templates = gallery.len(); "gallery loaded");
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads as usize)
+        .build()
+        .context("cannot start worker threads")?;
+
+    let probe_dir = &watch_options.probe_dir;
+    let done_dir = probe_dir.join(&watch_options.done_dir);
+    let failed_dir = probe_dir.join(&watch_options.failed_dir);
+    for dir in [&done_dir, &failed_dir] {
+        std::fs::create_dir_all(dir).with_context(|| format!("cannot create {}", dir.display()))?;
+    }
+
+    let mut output: Box<dyn Write> = match &watch_options.output_file {
+        Some(path) => Box::new(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("cannot open {}", path.display()))?,
+        ),
+        None => Box::new(std::io::stdout()),
+    };
+
+    let events = DirectoryEvents::new(probe_dir)?;
+    let poll_interval = std::time::Duration::from_millis(watch_options.poll_interval);
+    // sizes seen by the previous scan; a probe is matched once its size stops changing,
+    // so that files which are still being written are not picked up
+    let mut sizes = HashMap::new();
+    // probes that could not be moved away are not matched again
+    let mut stuck = HashSet::new();
+
+    loop {
+        let probes = match get_items_from_directory(probe_dir) {
+            Ok(probes) => probes,
+            Err(e) => {
+                warn!(path:% = probe_dir.display(); "cannot scan probe directory: {:#}", e);
+                vec![]
+            }
+        };
+
+        let mut ready = vec![];
+        let mut pending = HashMap::new();
+        for probe in probes {
+            if stuck.contains(&probe) {
+                continue;
+            }
+            let size = match std::fs::metadata(&probe) {
+                Ok(meta) => meta.len(),
+                Err(_) => continue,
+            };
+            if sizes.get(&probe) == Some(&size) {
+                ready.push(probe);
+            } else {
+                pending.insert(probe, size);
+            }
+        }
+        sizes = pending;
+
+        for probe in ready {
+            let destination = match match_probe(&probe, &gallery, prune_options, settings, &pool) {
+                Ok(scores) => {
+                    let timestamp = time::OffsetDateTime::now_utc().format("%Y-%m-%dT%H:%M:%SZ");
+                    for (gallery, score) in scores {
+                        if score >= watch_options.threshold {
+                            writeln!(
+                                output,
+                                "{} {} {} {}",
+                                timestamp,
+                                probe.display(),
+                                gallery.display(),
+                                score
+                            )?;
+                        }
+                    }
+                    output.flush()?;
+                    &done_dir
+                }
+                Err(e) => {
+                    warn!(path:% = probe.display(); "cannot match probe: {:#}", e);
+                    &failed_dir
+                }
+            };
+
+            let moved = probe
+                .file_name()
+                .context("probe without file name")
+                .and_then(|name| Ok(std::fs::rename(&probe, destination.join(name))?));
+            if let Err(e) = moved {
+                warn!(path:% = probe.display(); "cannot move probe: {:#}", e);
+                stuck.insert(probe);
+            }
+        }
+
+        events.wait(poll_interval);
+    }
+}
+
+/// Matches the probe with every gallery template on the pool, best scores first.
+fn match_probe<'g>(
+    probe: &Path,
+    gallery: &'g IndexedGallery,
+    prune_options: PruneOptions,
+    settings: MatchSettings,
+    pool: &rayon::ThreadPool,
+) -> anyhow::Result<Vec<(&'g PathBuf, u32)>> {
+    let probe = load_template(probe, prune_options, settings)?;
+    let settings = settings.batch();
+    let mut scores: Vec<_> = pool.install(|| {
+        gallery
+            .par_iter()
+            .map_init(
+                || (PairHolder::new(), BozorthState::new()),
+                |(pairs, state), (path, template)| {
+                    let score = match_templates(&probe, template, &settings, pairs, state)?;
+                    Some((path, score.score))
+                },
+            )
+            .flatten()
+            .collect()
+    });
+    scores.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    Ok(scores)
+}
+
+/// Wakes the watcher up early when the probe directory changes.
+#[cfg(feature = "notify")]
+struct DirectoryEvents {
+    _watcher: notify::RecommendedWatcher,
+    events: std::sync::mpsc::Receiver<notify::RawEvent>,
+}
+
+#[cfg(feature = "notify")]
+impl DirectoryEvents {
+    fn new(directory: &Path) -> anyhow::Result<Self> {
+        use notify::Watcher;
+
+        let (tx, events) = std::sync::mpsc::channel();
+        let mut watcher = notify::raw_watcher(tx).context("cannot watch probe directory")?;
+        watcher
+            .watch(directory, notify::RecursiveMode::NonRecursive)
+            .context("cannot watch probe directory")?;
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Blocks until the directory changes or the timeout passes.
+    fn wait(&self, timeout: std::time::Duration) {
+        if self.events.recv_timeout(timeout).is_ok() {
+            while self.events.try_recv().is_ok() {}
+        }
+    }
+}
+
+/// Without the `notify` feature the probe directory is polled.
+#[cfg(not(feature = "notify"))]
+struct DirectoryEvents;
+
+#[cfg(not(feature = "notify"))]
+impl DirectoryEvents {
+    fn new(_directory: &Path) -> anyhow::Result<Self> {
+        Ok(Self)
+    }
+
+    fn wait(&self, timeout: std::time::Duration) {
+        std::thread::sleep(timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use bozorth::{Format, FusionStrategy, PruneOptions, ThinOptions};
+
+    use structopt::StructOpt;
+
+    use super::{
+        execute_parallel, execute_sequential, gallery_root, get_slice_by_range, log_level_of,
+        merge_lines, resume_hints, shard_key, top_k_per_probe, AlgorithmMode, AngleConvention,
+        Cache, CompareMode, ExecuteOptions, InputFormat, MatchMode, MatchResult, MatchSettings,
+        ModeComparison, Options, OutputLine, Range, Selection, Shard, Stopped, Summary, Sweep,
+        SweepCounts,
+    };
+
+    fn fixtures() -> Vec<PathBuf> {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("../bozorth/tests/data");
+        let mut files: Vec<_> = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|it| it.unwrap().path())
+            .filter(|it| it.extension().and_then(|ext| ext.to_str()) == Some("xyt"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn collect(
+        rx: crossbeam::channel::Receiver<MatchResult>,
+    ) -> Vec<(PathBuf, PathBuf, Option<u32>, Option<usize>)> {
+        let mut results = collect_in_order(rx);
+        results.sort();
+        results
+    }
+
+    /// Results in the order they were reported.
+    fn collect_in_order(
+        rx: crossbeam::channel::Receiver<MatchResult>,
+    ) -> Vec<(PathBuf, PathBuf, Option<u32>, Option<usize>)> {
+        rx.into_iter()
+            .map(|it| {
+                (
+                    it.probe.clone(),
+                    it.gallery.clone(),
+                    it.score,
+                    it.matched_pairs,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parallel_results_equal_sequential_results() {
+        let files = fixtures();
+        let (probes, galleries) = files.split_at(files.len() / 2);
+
+        for &format in &[Format::NistInternal, Format::Ansi] {
+            let settings = MatchSettings {
+                format,
+                min_pairs: 0,
+                fusion: None,
+                thin: ThinOptions::default(),
+                input: InputFormat::Auto,
+                angles: None,
+                max_template_minutiae: None,
+                strict: true,
+                deadline: None,
+                dedup_pairs: false,
+            };
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::EveryProbeWithEachGallery,
+                MatchMode::Any,
+                probes,
+                galleries,
+                |_| true,
+                tx,
+                Cache::new(PruneOptions::default(), settings),
+                settings,
+            );
+            let in_order = collect_in_order(rx);
+            let sequential = {
+                let mut sorted = in_order.clone();
+                sorted.sort();
+                sorted
+            };
+
+            let parallel = |relaxed_order| {
+                let (tx, rx) = crossbeam::channel::unbounded();
+                execute_parallel(
+                    CompareMode::EveryProbeWithEachGallery,
+                    &ExecuteOptions {
+                        match_mode: MatchMode::Any,
+                        probes,
+                        galleries,
+                        score_callback: |_| true,
+                        match_done: tx,
+                        prune_options: PruneOptions::default(),
+                        settings,
+                        threads: 3,
+                        // small chunks, so that the threads finish them out of order
+                        chunk_size: 2,
+                        relaxed_order,
+                    },
+                );
+                collect_in_order(rx)
+            };
+
+            assert_eq!(sequential.len(), probes.len() * galleries.len());
+            let mut relaxed = parallel(true);
+            relaxed.sort();
+            assert_eq!(sequential, relaxed);
+            // without relaxed order the output is the same as the sequential one, line by line
+            assert_eq!(in_order, parallel(false));
+            assert!(sequential.iter().any(|it| it.3 > Some(0)));
+            assert!(sequential
+                .iter()
+                .all(|it| (it.2 == Some(0)) <= (it.3 == Some(0))));
+        }
+    }
+
+    #[test]
+    fn parallel_one_to_one_keeps_pair_order() {
+        let files = fixtures();
+        // a shuffled pair list: every file against a few others, several times over
+        let mut probes = Vec::new();
+        let mut galleries = Vec::new();
+        for round in 0..4 {
+            for (index, file) in files.iter().enumerate() {
+                probes.push(file.clone());
+                galleries.push(files[(index * 7 + round * 3) % files.len()].clone());
+            }
+        }
+        let settings = MatchSettings {
+            format: Format::NistInternal,
+            min_pairs: 0,
+            fusion: None,
+            thin: ThinOptions::default(),
+            input: InputFormat::Auto,
+            angles: None,
+            max_template_minutiae: None,
+            strict: true,
+            deadline: None,
+            dedup_pairs: false,
+        };
+
+        // uneven delays make the workers finish their pairs out of order
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let (tx, rx) = crossbeam::channel::unbounded();
+        execute_parallel(
+            CompareMode::OneToOne,
+            &ExecuteOptions {
+                match_mode: MatchMode::Any,
+                probes: &probes,
+                galleries: &galleries,
+                score_callback: move |_| {
+                    let call = calls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    std::thread::sleep(std::time::Duration::from_millis((call * 7 % 5) as u64));
+                    true
+                },
+                match_done: tx,
+                prune_options: PruneOptions::default(),
+                settings,
+                threads: 4,
+                chunk_size: 1000,
+                relaxed_order: false,
+            },
+        );
+        let output: Vec<_> = rx
+            .into_iter()
+            .map(|it| (it.probe.clone(), it.gallery.clone()))
+            .collect();
+
+        let input: Vec<_> = probes.into_iter().zip(galleries).collect();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn angle_conventions_are_detected_for_every_file() {
+        let data = Path::new(env!("CARGO_MANIFEST_DIR")).join("../bozorth/tests/data");
+        let scores = |angles, probe: &Path| {
+            let settings = MatchSettings {
+                format: Format::NistInternal,
+                min_pairs: 0,
+                fusion: None,
+                thin: ThinOptions::default(),
+                input: InputFormat::Xyt,
+                angles,
+                max_template_minutiae: None,
+                strict: true,
+                deadline: None,
+                dedup_pairs: false,
+            };
+            let (probes, galleries) = ([probe.to_owned()], [data.join("f1_2.xyt")]);
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::OneToOne,
+                MatchMode::Any,
+                &probes,
+                &galleries,
+                |_| true,
+                tx,
+                Cache::new(PruneOptions::default(), settings),
+                settings,
+            );
+            collect(rx)[0].2.unwrap()
+        };
+
+        // the probe rewritten with signed degrees and with ISO units
+        let rewrite = |name: &str, angle: fn(i32) -> i32| {
+            let xyt = std::fs::read_to_string(data.join("f1_1.xyt")).unwrap();
+            let content: String = xyt
+                .lines()
+                .map(|line| {
+                    let mut fields: Vec<_> = line.split(' ').map(str::to_owned).collect();
+                    let t = fields[2].parse::<i32>().unwrap().rem_euclid(360);
+                    fields[2] = angle(t).to_string();
+                    fields.join(" ") + "\n"
+                })
+                .collect();
+            let path =
+                std::env::temp_dir().join(format!("bz3-{}-{}.xyt", name, std::process::id()));
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+        let signed = rewrite("signed", |t| if t > 180 { t - 360 } else { t });
+        let iso = rewrite("iso", |t| (t * 256 + 180) / 360 % 256);
+
+        let expected = scores(None, &data.join("f1_1.xyt"));
+        assert_eq!(scores(None, &signed), expected);
+        let detected = scores(None, &iso);
+        let forced = scores(Some(AngleConvention::Unsigned), &iso);
+        std::fs::remove_file(signed).unwrap();
+        std::fs::remove_file(iso).unwrap();
+
+        // rounding to ISO units moves no angle by more than a degree
+        assert_eq!(detected, expected);
+        assert!(forced < expected / 2);
+    }
+
+    #[test]
+    fn an2_templates_score_like_their_xyt_files() {
+        let an2 = Path::new(env!("CARGO_MANIFEST_DIR")).join("../nist-itl/tests/data");
+        let xyt = Path::new(env!("CARGO_MANIFEST_DIR")).join("../bozorth/tests/data");
+        let scores = |input, probe: PathBuf, gallery: PathBuf| {
+            let settings = MatchSettings {
+                format: Format::NistInternal,
+                min_pairs: 0,
+                fusion: None,
+                thin: ThinOptions::default(),
+                input,
+                angles: None,
+                max_template_minutiae: None,
+                strict: true,
+                deadline: None,
+                dedup_pairs: false,
+            };
+            let (probes, galleries) = ([probe], [gallery]);
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::OneToOne,
+                MatchMode::Any,
+                &probes,
+                &galleries,
+                |_| true,
+                tx,
+                Cache::new(PruneOptions::default(), settings),
+                settings,
+            );
+            collect(rx)[0].2
+        };
+
+        let expected = scores(
+            InputFormat::Auto,
+            xyt.join("f1_1.xyt"),
+            xyt.join("f1_2.xyt"),
+        );
+        assert!(expected > Some(0));
+        let by_extension = scores(
+            InputFormat::Auto,
+            an2.join("f1_1.an2"),
+            xyt.join("f1_2.xyt"),
+        );
+        assert_eq!(by_extension, expected);
+
+        // any file name with the format given explicitly
+        let renamed = std::env::temp_dir().join(format!("bz3-an2-{}.eft", std::process::id()));
+        std::fs::copy(an2.join("f1_2.an2"), &renamed).unwrap();
+        let explicit = scores(InputFormat::An2, an2.join("f1_1.an2"), renamed.clone());
+        assert_eq!(
+            scores(InputFormat::Xyt, an2.join("f1_1.an2"), renamed.clone()),
+            None
+        );
+        std::fs::remove_file(&renamed).unwrap();
+        assert_eq!(explicit, expected);
+    }
+
+    #[test]
+    fn thinned_edges_are_reported_with_each_result() {
+        let files = fixtures();
+        let run = |max_per_sector| {
+            let settings = MatchSettings {
+                format: Format::NistInternal,
+                min_pairs: 0,
+                fusion: None,
+                thin: ThinOptions {
+                    max_per_sector,
+                    ..ThinOptions::default()
+                },
+                input: InputFormat::Auto,
+                angles: None,
+                max_template_minutiae: None,
+                strict: true,
+                deadline: None,
+                dedup_pairs: false,
+            };
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::OneToOne,
+                MatchMode::Any,
+                &files,
+                &files,
+                |_| true,
+                tx,
+                Cache::new(PruneOptions::default(), settings),
+                settings,
+            );
+            rx.into_iter()
+                .map(|it| (it.score.unwrap(), it.thinned_edges.unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        let all = run(0);
+        assert!(all.iter().all(|it| it.1 == 0));
+        let thinned = run(1);
+        assert_eq!(thinned.len(), files.len());
+        assert!(thinned.iter().all(|it| it.1 > 0));
+        // a template matched with itself loses edges on both sides
+        assert!(thinned.iter().all(|it| it.1 % 2 == 0));
+        assert!(thinned.iter().zip(&all).all(|(thin, all)| thin.0 <= all.0));
+    }
+
+    #[test]
+    fn summary_counts_every_compared_pair() {
+        let mut files = fixtures();
+        files.push(PathBuf::from("missing.xyt"));
+        let settings = MatchSettings {
+            format: Format::NistInternal,
+            min_pairs: 0,
+            fusion: None,
+            thin: ThinOptions::default(),
+            input: InputFormat::Auto,
+            angles: None,
+            max_template_minutiae: None,
+            strict: true,
+            deadline: None,
+            dedup_pairs: false,
+        };
+
+        let (tx, rx) = crossbeam::channel::unbounded();
+        execute_sequential(
+            CompareMode::EveryProbeWithEachGallery,
+            MatchMode::Any,
+            &files,
+            &files,
+            |_| true,
+            tx,
+            Cache::new(PruneOptions::default(), settings),
+            settings,
+        );
+        let mut sequential = Summary::new(7, 40);
+        rx.into_iter().for_each(|it| sequential.add(it.score));
+
+        let (tx, rx) = crossbeam::channel::unbounded();
+        execute_parallel(
+            CompareMode::EveryProbeWithEachGallery,
+            &ExecuteOptions {
+                match_mode: MatchMode::Any,
+                probes: &files,
+                galleries: &files,
+                score_callback: |_| true,
+                match_done: tx,
+                prune_options: PruneOptions::default(),
+                settings,
+                threads: 3,
+                chunk_size: 1000,
+                relaxed_order: true,
+            },
+        );
+        let mut parallel = Summary::new(7, 40);
+        rx.into_iter().for_each(|it| parallel.add(it.score));
+
+        let report = sequential.report();
+        assert_eq!(report, parallel.report());
+        let compared = (files.len() * files.len()) as u64;
+        assert_eq!(report.compared, compared);
+        // every pair with the missing file fails
+        assert_eq!(report.failed, 2 * files.len() as u64 - 1);
+        assert_eq!(
+            report.histogram.iter().sum::<u64>() + report.failed,
+            compared
+        );
+        let (min, median, max) = (
+            report.min.unwrap(),
+            report.median.unwrap(),
+            report.max.unwrap(),
+        );
+        assert!(min <= median && median <= max);
+        assert_eq!(report.histogram.len() as u32, max / 7 + 1);
+        assert!(report.above_threshold > 0.0 && report.above_threshold < 1.0);
+    }
+
+    #[test]
+    fn summary_statistics_of_known_scores() {
+        let mut summary = Summary::new(10, 20);
+        for score in [Some(5), Some(12), None, Some(30), Some(12)] {
+            summary.add(score);
+        }
+        let report = summary.report();
+        assert_eq!(report.compared, 5);
+        assert_eq!(report.failed, 1);
+        assert_eq!(
+            (report.min, report.median, report.max),
+            (Some(5), Some(12), Some(30))
+        );
+        assert_eq!(report.mean, Some(14.75));
+        assert_eq!(report.above_threshold, 0.2);
+        assert_eq!(report.histogram, vec![1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn mode_comparisons_rank_pairs_by_their_score_differences() {
+        let first = [Some(10), Some(50), None, Some(7), Some(30), Some(0)];
+        let second = [Some(10), Some(41), Some(3), Some(12), Some(39), None];
+        let comparison = ModeComparison::new(&first, &second);
+        assert_eq!(comparison.deltas, [(0, 0), (1, -9), (3, 5), (4, 9)]);
+        assert_eq!(comparison.failed, 2);
+        assert_eq!(comparison.largest(2), [(1, -9), (4, 9)]);
+        assert_eq!(comparison.largest(10), [(1, -9), (4, 9), (3, 5)]);
+
+        let summary = comparison.to_string();
+        assert!(
+            summary.contains("changed                          3"),
+            "{}",
+            summary
+        );
+        assert!(
+            summary.contains("mean delta                    1.25"),
+            "{}",
+            summary
+        );
+        assert!(
+            summary.contains("mean absolute delta           5.75"),
+            "{}",
+            summary
+        );
+
+        assert_eq!("relaxed".parse(), Ok(AlgorithmMode::Relaxed));
+        assert!("loose".parse::<AlgorithmMode>().is_err());
+    }
+
+    #[test]
+    fn threshold_sweeps_count_matches_with_at_least_every_threshold() {
+        let sweep: Sweep = "10:35:10".parse().unwrap();
+        assert_eq!(sweep.thresholds().collect::<Vec<_>>(), [10, 20, 30]);
+        for invalid in ["10:35", "10:35:0", "35:10:5", "10:35:5:1", "10:x:5"] {
+            assert!(invalid.parse::<Sweep>().is_err(), "{}", invalid);
+        }
+
+        let mut counts = SweepCounts::new(sweep);
+        for score in [Some(5), Some(10), Some(25), None, Some(100)] {
+            counts.add(score);
+        }
+        assert_eq!(counts.counts, [3, 2, 1]);
+        assert_eq!(counts.compared, 5);
+        let table = counts.to_string();
+        assert_eq!(
+            table.lines().nth(1),
+            Some("       10              3    0.6000")
+        );
+    }
+
+    #[test]
+    fn min_pairs_rejects_matches_with_few_pairs() {
+        let files = fixtures();
+        let run = |min_pairs| {
+            let settings = MatchSettings {
+                format: Format::NistInternal,
+                min_pairs,
+                fusion: None,
+                thin: ThinOptions::default(),
+                input: InputFormat::Auto,
+                angles: None,
+                max_template_minutiae: None,
+                strict: true,
+                deadline: None,
+                dedup_pairs: false,
+            };
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::EveryProbeWithEachGallery,
+                MatchMode::Any,
+                &files,
+                &files,
+                |_| true,
+                tx,
+                Cache::new(PruneOptions::default(), settings),
+                settings,
+            );
+            collect(rx)
+        };
+
+        let all = run(0);
+        assert!(all.iter().any(|it| it.2 > Some(0)));
+        assert_eq!(all, run(1));
+        assert!(run(usize::MAX).iter().all(|it| it.2 == Some(0)));
+    }
+
+    #[test]
+    fn fused_score_of_directories_sums_finger_scores() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("../bozorth/tests/data");
+        let root = std::env::temp_dir().join(format!("bz3-fuse-{}", std::process::id()));
+        let subject = |name: &str, fingers: &[&str]| {
+            let directory = root.join(name);
+            std::fs::create_dir_all(&directory).unwrap();
+            for (index, finger) in fingers.iter().enumerate() {
+                let target = directory.join(format!("{}.xyt", index));
+                std::fs::copy(fixtures.join(finger), target).unwrap();
+            }
+            directory
+        };
+        let probe = subject("probe", &["f1_1.xyt", "f2_1.xyt"]);
+        let gallery = subject("gallery", &["f1_2.xyt", "f2_2.xyt"]);
+
+        let run = |probes: &[PathBuf], galleries: &[PathBuf], fusion| {
+            let settings = MatchSettings {
+                format: Format::NistInternal,
+                min_pairs: 0,
+                fusion,
+                thin: ThinOptions::default(),
+                input: InputFormat::Auto,
+                angles: None,
+                max_template_minutiae: None,
+                strict: true,
+                deadline: None,
+                dedup_pairs: false,
+            };
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::OneToOne,
+                MatchMode::Any,
+                probes,
+                galleries,
+                |_| true,
+                tx,
+                Cache::new(PruneOptions::default(), settings),
+                settings,
+            );
+            collect(rx)[0].2.unwrap()
+        };
+
+        let fused = run(&[probe], &[gallery], Some(FusionStrategy::Sum));
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let first = run(
+            &[fixtures.join("f1_1.xyt")],
+            &[fixtures.join("f1_2.xyt")],
+            None,
+        );
+        let second = run(
+            &[fixtures.join("f2_1.xyt")],
+            &[fixtures.join("f2_2.xyt")],
+            None,
+        );
+        assert_eq!(fused, first + second);
+    }
+
+    #[test]
+    fn preloaded_results_equal_lazy_results() {
+        let files = fixtures();
+        let settings = MatchSettings {
+            format: Format::NistInternal,
+            min_pairs: 0,
+            fusion: None,
+            thin: ThinOptions::default(),
+            input: InputFormat::Auto,
+            angles: None,
+            max_template_minutiae: None,
+            strict: true,
+            deadline: None,
+            dedup_pairs: false,
+        };
+        let run = |preload| {
+            let mut cache = Cache::new(PruneOptions::default(), settings);
+            if preload {
+                cache.preload(files.iter().chain(files.iter()));
+                assert_eq!(cache.cache.len(), files.len());
+            }
+
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::EveryProbeWithEachGallery,
+                MatchMode::Any,
+                &files,
+                &files,
+                |_| true,
+                tx,
+                cache,
+                settings,
+            );
+            collect(rx)
+        };
+
+        assert_eq!(run(false), run(true));
+    }
+
+    #[test]
+    fn top_k_keeps_the_best_galleries_of_every_probe() {
+        let files = fixtures();
+        let settings = MatchSettings {
+            format: Format::NistInternal,
+            min_pairs: 0,
+            fusion: None,
+            thin: ThinOptions::default(),
+            input: InputFormat::Auto,
+            angles: None,
+            max_template_minutiae: None,
+            strict: true,
+            deadline: None,
+            dedup_pairs: false,
+        };
+        let results = || {
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::EveryProbeWithEachGallery,
+                MatchMode::Any,
+                &files,
+                &files,
+                |_| true,
+                tx,
+                Cache::new(PruneOptions::default(), settings),
+                settings,
+            );
+            rx
+        };
+
+        let mut expected = vec![];
+        let all = collect(results());
+        for probe in &files {
+            let mut scores: Vec<_> = all
+                .iter()
+                .filter(|it| &it.0 == probe)
+                .map(|it| (it.1.clone(), it.2))
+                .collect();
+            scores.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            expected.extend(
+                scores
+                    .into_iter()
+                    .take(2)
+                    .map(|it| (probe.clone(), it.0, it.1)),
+            );
+        }
+
+        let actual: Vec<_> = top_k_per_probe(results(), &files, 2)
+            .into_iter()
+            .map(|it| (it.probe.clone(), it.gallery.clone(), it.score))
+            .collect();
+        assert_eq!(actual.len(), 2 * files.len());
+        assert_eq!(actual, expected);
+        // every fixture matches itself best
+        assert!(actual.iter().step_by(2).all(|it| it.0 == it.1));
+    }
+
+    #[test]
+    fn ranges_select_files_inclusively() {
+        let files = [1, 2, 3, 4, 5];
+        let range = |s: &str| s.parse::<Range>().unwrap();
+        assert_eq!(get_slice_by_range(&files, range("1-5")), Some(&files[..]));
+        assert_eq!(get_slice_by_range(&files, range("2-4")), Some(&files[1..4]));
+        assert_eq!(get_slice_by_range(&files, range("5-5")), Some(&files[4..]));
+        assert_eq!(get_slice_by_range(&files, range("3-6")), None);
+        assert_eq!(range("3-6").to_string(), "3-6");
+    }
+
+    #[test]
+    fn resume_hints_cover_the_remaining_pairs() {
+        // probes 3-7, galleries of a shard of 1-6
+        let probes = Selection {
+            indices: &[2, 3, 4, 5, 6],
+            range: "3-7".parse().unwrap(),
+        };
+        let galleries = Selection {
+            indices: &[0, 2, 3, 5],
+            range: "1-6".parse().unwrap(),
+        };
+        let hints = |probe, gallery, mode| {
+            let stopped = Stopped {
+                completed: 0,
+                probe,
+                gallery,
+            };
+            resume_hints(&stopped, mode, probes, galleries)
+        };
+
+        assert_eq!(
+            hints(1, 2, CompareMode::EveryProbeWithEachGallery),
+            [
+                "--probe-range 4-4 --gallery-range 4-6",
+                "--probe-range 5-7 --gallery-range 1-6"
+            ]
+        );
+        assert_eq!(
+            hints(1, 0, CompareMode::OneToMany),
+            ["--probe-range 4-7 --gallery-range 1-6"]
+        );
+        assert_eq!(
+            hints(4, 3, CompareMode::OneToMany),
+            ["--probe-range 7-7 --gallery-range 6-6"]
+        );
+        assert_eq!(
+            hints(2, 2, CompareMode::OneToOne),
+            ["--probe-range 5-7 --gallery-range 4-6"]
+        );
+    }
+
+    fn fixtures_root() -> PathBuf {
+        gallery_root(fixtures().first().and_then(|it| it.parent())).unwrap()
+    }
+
+    #[test]
+    fn shards_partition_the_gallery() {
+        let root = fixtures_root();
+        let mut keys: Vec<_> = fixtures()
+            .iter()
+            .map(|it| shard_key(&root, it).unwrap())
+            .collect();
+        keys.extend((0..200).map(|i| format!("gallery/{:04}.xyt", i)));
+
+        for &count in &[1, 3, 7] {
+            let shards: Vec<Shard> = (1..=count)
+                .map(|i| format!("{}/{}", i, count).parse().unwrap())
+                .collect();
+            for key in &keys {
+                let owners = shards.iter().filter(|it| it.owns(key)).count();
+                assert_eq!(owners, 1, "{} in {} shards", key, owners);
+            }
+            if count > 1 {
+                assert!(shards
+                    .iter()
+                    .all(|shard| keys.iter().any(|it| shard.owns(it))));
+            }
+        }
+
+        assert!("0/3".parse::<Shard>().is_err());
+        assert!("4/3".parse::<Shard>().is_err());
+        assert!("1-3".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn shards_hash_paths_relative_to_the_gallery_root() {
+        let root = fixtures_root();
+        let file = &fixtures()[0];
+        let name = file.file_name().unwrap().to_str().unwrap();
+        assert_eq!(shard_key(&root, file).unwrap(), name);
+        // the same file reached through another path
+        let roundabout = root.join("..").join("data").join(name);
+        assert_eq!(shard_key(&root, &roundabout).unwrap(), name);
+        // with `/` separators on every platform
+        let parent = root.parent().unwrap();
+        assert_eq!(shard_key(parent, file).unwrap(), format!("data/{}", name));
+
+        let shard: Shard = "1/3".parse().unwrap();
+        assert!(shard.contains(&root, &root.join("missing.xyt")).is_err());
+        assert!(shard
+            .contains(&root.join("missing"), file)
+            .unwrap_err()
+            .to_string()
+            .contains("not in the gallery root"));
+    }
+
+    #[test]
+    fn merged_shards_equal_the_unsharded_run() {
+        let files = fixtures();
+        let settings = MatchSettings {
+            format: Format::NistInternal,
+            min_pairs: 0,
+            fusion: None,
+            thin: ThinOptions::default(),
+            input: InputFormat::Auto,
+            angles: None,
+            max_template_minutiae: None,
+            strict: true,
+            deadline: None,
+            dedup_pairs: false,
+        };
+        let lines = |galleries: &[PathBuf]| -> Vec<OutputLine> {
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::EveryProbeWithEachGallery,
+                MatchMode::Any,
+                &files,
+                galleries,
+                |_| true,
+                tx,
+                Cache::new(PruneOptions::default(), settings),
+                settings,
+            );
+            collect(rx)
+                .into_iter()
+                .map(|it| OutputLine {
+                    probe: it.0.display().to_string(),
+                    gallery: it.1.display().to_string(),
+                    score: it.2.map_or(-1, i64::from),
+                    rest: String::new(),
+                })
+                .collect()
+        };
+
+        let mut sharded = vec![];
+        for i in 1..=3 {
+            let shard: Shard = format!("{}/3", i).parse().unwrap();
+            let galleries: Vec<_> = files
+                .iter()
+                .filter(|it| shard.contains(&fixtures_root(), it).unwrap())
+                .cloned()
+                .collect();
+            sharded.extend(lines(&galleries));
+        }
+
+        let unsharded = lines(&files);
+        assert_eq!(
+            merge_lines(sharded.clone(), None),
+            merge_lines(unsharded.clone(), None)
+        );
+        let top = merge_lines(sharded, Some(2));
+        assert_eq!(top, merge_lines(unsharded, Some(2)));
+        assert_eq!(top.len(), 2 * files.len());
+
+        let line: OutputLine = "a.xyt b.xyt 42 17 0.5".parse().unwrap();
+        assert_eq!((line.score, line.rest.as_str()), (42, "17 0.5"));
+        assert_eq!(line.to_string(), "a.xyt b.xyt 42 17 0.5");
+        assert!("42".parse::<OutputLine>().is_err());
+    }
+
+    /// Logger recording the level and the `path` field of events of the current thread.
+    struct CapturingLogger;
+
+    thread_local! {
+        static EVENTS: std::cell::RefCell<Vec<(log::Level, Option<String>)>> =
+            const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let path = record
+                .key_values()
+                .get(log::kv::Key::from("path"))
+                .map(|it| it.to_string());
+            EVENTS.with(|events| events.borrow_mut().push((record.level(), path)));
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn capture_events(f: impl FnOnce()) -> Vec<(log::Level, Option<String>)> {
+        static INSTALL: std::sync::Once = std::sync::Once::new();
+        INSTALL.call_once(|| {
+            log::set_boxed_logger(Box::new(CapturingLogger)).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+
+        EVENTS.with(|events| events.borrow_mut().clear());
+        f();
+        EVENTS.with(|events| events.take())
+    }
+
+    #[test]
+    fn corrupt_file_is_reported_once() {
+        let corrupt = std::env::temp_dir().join(format!("bz3-corrupt-{}.xyt", std::process::id()));
+        std::fs::write(&corrupt, "12 34 not-an-angle\n").unwrap();
+        let galleries = fixtures();
+        let settings = MatchSettings {
+            format: Format::NistInternal,
+            min_pairs: 0,
+            fusion: None,
+            thin: ThinOptions::default(),
+            input: InputFormat::Auto,
+            angles: None,
+            max_template_minutiae: None,
+            strict: true,
+            deadline: None,
+            dedup_pairs: false,
+        };
+
+        let events = capture_events(|| {
+            let (tx, rx) = crossbeam::channel::unbounded();
+            execute_sequential(
+                CompareMode::EveryProbeWithEachGallery,
+                MatchMode::Any,
+                std::slice::from_ref(&corrupt),
+                &galleries,
+                |_| true,
+                tx,
+                Cache::new(PruneOptions::default(), settings),
+                settings,
+            );
+            let results = collect(rx);
+            assert_eq!(results.len(), galleries.len());
+            assert!(results.iter().all(|it| it.2.is_none()));
+        });
+        std::fs::remove_file(&corrupt).unwrap();
+
+        let warnings: Vec<_> = events
+            .iter()
+            .filter(|it| it.0 == log::Level::Warn)
+            .collect();
+        assert_eq!(
+            warnings,
+            [&(log::Level::Warn, Some(corrupt.display().to_string()))]
+        );
+    }
+
+    #[test]
+    fn verbosity_flags_adjust_the_log_level() {
+        let level = |args: &[&str]| {
+            log_level_of(&Options::from_iter(
+                ["bz3"].iter().chain(args).chain(&["a.xyt", "b.xyt"]),
+            ))
+        };
+        assert_eq!(level(&[]), log::LevelFilter::Warn);
+        assert_eq!(level(&["-v"]), log::LevelFilter::Info);
+        assert_eq!(level(&["-vv"]), log::LevelFilter::Debug);
+        assert_eq!(level(&["-vvvvv"]), log::LevelFilter::Trace);
+        assert_eq!(
+            level(&["--log-level", "error", "-v"]),
+            log::LevelFilter::Warn
+        );
+        assert_eq!(level(&["-q"]), log::LevelFilter::Off);
+        assert_eq!(
+            level(&["-q", "--log-level", "debug"]),
+            log::LevelFilter::Off
+        );
+        assert!(Options::from_iter_safe(["bz3", "-q", "-v", "a.xyt", "b.xyt"]).is_err());
+    }
+}
@@ -0,0 +1,37 @@
+//! Helpers shared by the integration tests, included by each of them with `mod common;`.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+/// Directory with the .xyt fixtures of bozorth.
+pub fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../bozorth/tests/data")
+}
+
+/// Directory for files of a single test, removed when the test ends.
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    /// Empty directory named after the test binary, the `name` and the process.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "{}-{}-{}",
+            env!("CARGO_CRATE_NAME"),
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn file(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
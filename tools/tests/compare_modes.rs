@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use bozorth::synthetic::Generator;
+
+mod common;
+
+use common::TempDir;
+
+/// Writes synthetic templates, every one followed by a shifted and jittered impression of it,
+/// and returns a pair file that matches every template with every file.
+fn synthetic_pairs(dir: &TempDir) -> PathBuf {
+    let mut generator = Generator::new(7);
+
+    let mut files = vec![];
+    for template in 0..4 {
+        let minutiae = generator.raw_template(30 + template * 20);
+        let impression = generator.impression(&minutiae, 0, (6, -4));
+        for (suffix, minutiae) in [("a", minutiae), ("b", impression)] {
+            let path = dir.0.join(format!("{}{}.xyt", template, suffix));
+            let lines: String = minutiae
+                .iter()
+                .map(|it| format!("{} {} {} {}\n", it.x, it.y, it.t, it.q))
+                .collect();
+            std::fs::write(&path, lines).unwrap();
+            files.push(path);
+        }
+    }
+
+    let mut pairs = String::new();
+    for probe in files.iter().step_by(2) {
+        for gallery in &files {
+            pairs += &format!("{}\n{}\n", probe.display(), gallery.display());
+        }
+    }
+    let path = dir.0.join("pairs.txt");
+    std::fs::write(&path, pairs).unwrap();
+    path
+}
+
+/// Rows of the CSV output and the summary.
+fn compare_modes(pairs: &PathBuf, first: &str, second: &str) -> (Vec<Vec<String>>, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .args([
+            "-T",
+            "2",
+            "compare-modes",
+            "--first",
+            first,
+            "--second",
+            second,
+            "-M",
+        ])
+        .arg(pairs)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("probe,gallery,first,second,delta"));
+    let rows = lines
+        .map(|line| line.split(',').map(str::to_owned).collect())
+        .collect();
+    (rows, stderr)
+}
+
+#[test]
+fn identical_modes_have_no_differences() {
+    let dir = TempDir::new("identical");
+    let pairs = synthetic_pairs(&dir);
+
+    for mode in ["strict", "relaxed"] {
+        let (rows, summary) = compare_modes(&pairs, mode, mode);
+        assert_eq!(rows.len(), 4 * 8);
+        assert!(rows.iter().any(|row| row[2].parse::<u32>().unwrap() > 40));
+        for row in &rows {
+            assert_eq!(row[2], row[3]);
+            assert_eq!(row[4], "0");
+        }
+        assert!(
+            summary.contains("changed                          0"),
+            "{}",
+            summary
+        );
+        assert!(!summary.contains("largest differences"), "{}", summary);
+    }
+}
+
+#[test]
+fn deltas_are_the_differences_of_the_scores() {
+    let dir = TempDir::new("deltas");
+    let pairs = synthetic_pairs(&dir);
+
+    let (strict, _) = compare_modes(&pairs, "strict", "strict");
+    let (relaxed, _) = compare_modes(&pairs, "relaxed", "relaxed");
+    let (rows, _) = compare_modes(&pairs, "strict", "relaxed");
+    for ((row, strict), relaxed) in rows.iter().zip(&strict).zip(&relaxed) {
+        assert_eq!(row[..2], strict[..2]);
+        assert_eq!(row[2], strict[2]);
+        assert_eq!(row[3], relaxed[2]);
+        let delta = row[3].parse::<i64>().unwrap() - row[2].parse::<i64>().unwrap();
+        assert_eq!(row[4], delta.to_string());
+    }
+}
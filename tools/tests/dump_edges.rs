@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use bozorth::{
+    match_fingerprints, parse, BozorthState, Fingerprint, Format, PairHolder, PruneOptions,
+};
+
+mod common;
+
+use common::fixtures;
+
+fn fixture(name: &str) -> PathBuf {
+    fixtures().join(name)
+}
+
+fn bz3(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .args(args)
+        .current_dir(fixture(""))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn replayed_dumps_score_like_their_templates() {
+    let gallery = Fingerprint::from_raw(
+        &parse(fixture("f1_2.xyt")).unwrap(),
+        PruneOptions::default(),
+        Format::NistInternal,
+    );
+    let mut pairs = PairHolder::new();
+    let mut state = BozorthState::new();
+
+    for probe in &["f1_1.xyt", "f2_1.xyt"] {
+        let expected: u32 = bz3(&["-p", probe, "-g", "f1_2.xyt"])
+            .split_whitespace()
+            .nth(2)
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let dump = bz3(&["dump-edges", probe]);
+        let replayed = Fingerprint::from_edge_dump(dump.as_bytes()).unwrap();
+        let score = match_fingerprints(
+            &replayed,
+            &gallery,
+            Format::NistInternal,
+            &mut pairs,
+            &mut state,
+        );
+        assert_eq!(score, expected, "{}", probe);
+    }
+}
+
+#[test]
+fn dumps_follow_matcher_flags() {
+    let nist = bz3(&["dump-edges", "f1_1.xyt"]);
+    assert!(nist.starts_with("bozorth3 edge dump 1\nformat nist\nminutiae 45\n"));
+
+    let ansi = bz3(&["-a", "-n", "20", "dump-edges", "f1_1.xyt"]);
+    let fingerprint = Fingerprint::from_edge_dump(ansi.as_bytes()).unwrap();
+    assert_eq!(fingerprint.format, Format::Ansi);
+    assert_eq!(fingerprint.minutiae.len(), 20);
+}
@@ -0,0 +1,42 @@
+use std::process::Command;
+
+mod common;
+
+use common::fixtures;
+
+/// Number of minutiae of the template written by `bz enroll --merge` with the impressions.
+fn enroll(impressions: &[&str]) -> usize {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz"))
+        .arg("enroll")
+        .arg("--merge")
+        .args(impressions)
+        .current_dir(fixtures())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap().lines().count()
+}
+
+#[test]
+fn impressions_of_the_same_finger_are_merged() {
+    let single = enroll(&["f1_1.xyt", "f2_1.xyt"]);
+    let merged = enroll(&["f1_1.xyt", "f1_2.xyt"]);
+    assert!(merged > single, "{} <= {}", merged, single);
+    // another finger does not align, so only the first impression is left
+    let first = std::fs::read_to_string(fixtures().join("f1_1.xyt")).unwrap();
+    assert_eq!(single, first.lines().count());
+}
+
+#[test]
+fn a_single_impression_is_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz"))
+        .args(["enroll", "--merge", "f1_1.xyt"])
+        .current_dir(fixtures())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+use common::fixtures;
+
+fn bz3(threads: &str, features_output: Option<&Path>) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_bz3"));
+    command
+        .args(["-T", threads, "-r", "-P"])
+        .arg(fixtures())
+        .arg("-G")
+        .arg(fixtures());
+    if let Some(path) = features_output {
+        command.arg("--features-output").arg(path);
+    }
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn features_are_written_for_every_pair() {
+    let root = std::env::temp_dir().join(format!("bz3-features-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+
+    for threads in ["1", "2"] {
+        let path = root.join(format!("features-{}.csv", threads));
+        let scores = bz3(threads, Some(&path));
+        // the scores do not depend on collecting the features; -r does not keep the order
+        let sorted = |output: &str| {
+            let mut lines: Vec<_> = output.lines().map(str::to_owned).collect();
+            lines.sort();
+            lines
+        };
+        assert_eq!(sorted(&scores), sorted(&bz3(threads, None)));
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "probe,gallery,score,cluster_count,max_cluster_points,\
+                 winning_combination_size,probe_endpoints,gallery_endpoints"
+            )
+        );
+        let rows: Vec<Vec<&str>> = lines.map(|it| it.split(',').collect()).collect();
+        assert_eq!(rows.len(), scores.lines().count());
+
+        for (row, line) in rows.iter().zip(scores.lines()) {
+            let columns: Vec<_> = line.split(' ').collect();
+            assert_eq!(&row[..3], &columns[..], "{}", line);
+
+            let value = |index: usize| row[index].parse::<u32>().unwrap();
+            let score = value(2);
+            let (clusters, max_points, winning) = (value(3), value(4), value(5));
+            let (probe_endpoints, gallery_endpoints) = (value(6), value(7));
+            assert!(max_points <= score, "{}", line);
+            assert!(winning <= clusters, "{}", line);
+            if score > 0 {
+                assert!(winning > 0 && probe_endpoints > 0 && gallery_endpoints > 0);
+            }
+            // every pair of minutiae matches one probe and one gallery minutia
+            assert_eq!(probe_endpoints == 0, gallery_endpoints == 0);
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,163 @@
+//! Outputs of the `bz` subcommands and of their aliases compared with the ones of the binaries
+//! they replace, saved in tests/golden.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+mod common;
+
+use common::{fixtures, TempDir};
+
+fn golden(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(name)
+}
+
+/// `bz <subcommand>` and the former binary of the subcommand.
+fn commands(subcommand: &str, alias: &str) -> [Command; 2] {
+    let mut bz = Command::new(env!("CARGO_BIN_EXE_bz"));
+    bz.arg(subcommand);
+    let alias = Command::new(match alias {
+        "bz3" => env!("CARGO_BIN_EXE_bz3"),
+        "match" => env!("CARGO_BIN_EXE_match"),
+        "evaluate" => env!("CARGO_BIN_EXE_evaluate"),
+        "bench" => env!("CARGO_BIN_EXE_bench"),
+        _ => unreachable!(),
+    });
+    [bz, alias]
+}
+
+fn stdout(command: &mut Command) -> String {
+    let output = command.output().unwrap();
+    assert!(
+        output.status.success(),
+        "{:?}: {}",
+        command,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn search_reproduces_bz3() {
+    let pairs = golden("pairs.txt");
+    let cases: &[(&[&str], &str)] = &[
+        (&["-P", ".", "-G", "."], "search_all.txt"),
+        (
+            &["-m", "threshold", "-t", "40", "-s", "-P", ".", "-G", "."],
+            "search_threshold.txt",
+        ),
+        (&["-M", pairs.to_str().unwrap()], "search_pairs.txt"),
+        (
+            &["-T", "3", "-a", "-P", ".", "-G", "."],
+            "search_parallel_ansi.txt",
+        ),
+    ];
+    for (args, expected) in cases {
+        let expected = std::fs::read_to_string(golden(expected)).unwrap();
+        for mut command in commands("search", "bz3") {
+            command.current_dir(fixtures()).args(*args);
+            assert_eq!(stdout(&mut command), expected, "{:?}", args);
+        }
+    }
+}
+
+#[test]
+fn match_reproduces_match() {
+    let dir = TempDir::new("match");
+    for format in &["legacy", "csv", "json"] {
+        let expected = std::fs::read_to_string(golden(&format!("match_{}.txt", format))).unwrap();
+        for index in 0..2 {
+            let out = dir.0.join(format!("{}-{}.txt", format, index));
+            for pair in &[
+                ["f1_1.ist", "f1_2.ist"],
+                ["f1_1.ist", "f2_1.ist"],
+                ["f2_1.ist", "f2_1.ist"],
+            ] {
+                let [bz, alias] = commands("match", "match");
+                let mut command = if index == 0 { bz } else { alias };
+                let status = command
+                    .current_dir(golden(""))
+                    .args(["--output-format", format])
+                    .args(pair)
+                    .arg(&out)
+                    .status()
+                    .unwrap();
+                assert!(status.success());
+            }
+            assert_eq!(std::fs::read_to_string(&out).unwrap(), expected);
+        }
+    }
+}
+
+#[test]
+fn evaluate_reproduces_evaluate() {
+    let dir = TempDir::new("evaluate");
+    let input = dir.0.join("input");
+    std::fs::create_dir(&input).unwrap();
+    for finger in 1..=3 {
+        for (impression, prefix) in [(1, "f"), (2, "s")] {
+            std::fs::copy(
+                fixtures().join(format!("f{}_{}.xyt", finger, impression)),
+                input.join(format!("{}{}.png.xyt", prefix, finger)),
+            )
+            .unwrap();
+        }
+    }
+
+    let expected = std::fs::read_to_string(golden("evaluate.csv")).unwrap();
+    let [mut bz, mut alias] = commands("evaluate", "evaluate");
+    // the alias still takes the former short flags
+    bz.args(["-T", "2", "--name", "bz"]);
+    alias.args(["-m", "2", "-n", "alias"]);
+    for (mut command, name) in [(bz, "bz"), (alias, "alias")] {
+        command
+            .arg("-i")
+            .arg(&input)
+            .arg("-o")
+            .arg(&dir.0)
+            .args(["-t", "60", "-0", "1", "-1", "2", "-2", "3"]);
+        stdout(&mut command);
+        let csv = std::fs::read_to_string(dir.0.join(format!("{}.csv", name))).unwrap();
+        assert_eq!(csv, expected);
+    }
+}
+
+#[test]
+fn bench_takes_flags_and_former_words() {
+    let [mut bz, mut alias] = commands("bench", "bench");
+    bz.args(["--no-check", "--no-parallel"]);
+    alias.args(["no_check", "no-parallel"]);
+    for mut command in [bz, alias] {
+        let output = stdout(command.arg("-i").arg(fixtures()));
+        assert!(output.contains("elapsed"));
+        assert!(!output.contains("EXPECTED"));
+    }
+}
+
+#[test]
+fn converted_templates_keep_minutiae() {
+    let output = stdout(
+        Command::new(env!("CARGO_BIN_EXE_bz"))
+            .arg("convert")
+            .arg(golden("f1_1.ist")),
+    );
+    // the templates were generated from these .xyt files at almost the target resolution
+    let original = std::fs::read_to_string(fixtures().join("f1_1.xyt")).unwrap();
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines.len(), original.lines().count());
+    for (converted, original) in lines.iter().zip(original.lines()) {
+        let parse = |line: &str| -> Vec<i32> {
+            line.split_whitespace()
+                .map(|it| it.parse().unwrap())
+                .collect()
+        };
+        let (converted, original) = (parse(converted), parse(original));
+        for axis in 0..2 {
+            assert!((converted[axis] - original[axis]).abs() <= 1);
+        }
+        let turn = (converted[2] - original[2]).rem_euclid(360);
+        assert!(turn.min(360 - turn) <= 1);
+    }
+}
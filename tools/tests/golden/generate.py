@@ -0,0 +1,29 @@
+#!/usr/bin/env python3
+"""Generates ISO/IEC 19794-2 templates from .xyt fixtures of bozorth for the golden tests."""
+
+import struct
+from pathlib import Path
+
+HERE = Path(__file__).parent
+XYT = HERE / "../../../bozorth/tests/data"
+
+
+def minutiae(name):
+    for line in (XYT / name).read_text().splitlines():
+        x, y, t, q = map(int, line.split())
+        # ridge endings; angles in units of 1.40625 degrees
+        yield 1, x, y, round(t / 1.40625) % 256, min(q, 100)
+
+
+def v2005(position, points):
+    view = struct.pack(">BBBB", position, 0, 60, len(points))
+    view += b"".join(struct.pack(">HHBB", (ty << 14) | x, y, angle, quality) for ty, x, y, angle, quality in points)
+    view += struct.pack(">H", 0)  # no extended data
+    header_length = 24
+    header = b"FMR\0" + b" 20\0"
+    header += struct.pack(">IHHHHHBB", header_length + len(view), 0, 800, 750, 197, 197, 1, 0)
+    return header + view
+
+
+for name in ["f1_1", "f1_2", "f2_1"]:
+    (HERE / f"{name}.ist").write_bytes(v2005(2, list(minutiae(f"{name}.xyt"))))
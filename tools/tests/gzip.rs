@@ -0,0 +1,55 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use flate2::write::GzEncoder;
+
+mod common;
+
+use common::fixtures;
+
+fn bz3(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn compressed_templates_score_like_plain_ones() {
+    let dir = std::env::temp_dir().join(format!("gzip-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("gallery")).unwrap();
+    for name in ["f1_2.xyt", "f2_2.xyt"] {
+        let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+        encoder
+            .write_all(&std::fs::read(fixtures().join(name)).unwrap())
+            .unwrap();
+        let compressed = dir.join("gallery").join(format!("{}.gz", name));
+        std::fs::write(compressed, encoder.finish().unwrap()).unwrap();
+    }
+    let probe = fixtures().join("f1_1.xyt");
+    let probe = probe.to_str().unwrap();
+
+    let compressed = bz3(&dir, &["-p", probe, "-G", "gallery"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    let scores = |output: &str| -> Vec<String> {
+        output
+            .lines()
+            .map(|it| it.rsplit(' ').next().unwrap().to_owned())
+            .collect()
+    };
+    let plain = ["f1_2.xyt", "f2_2.xyt"]
+        .iter()
+        .map(|gallery| bz3(&fixtures(), &["-p", "f1_1.xyt", "-g", gallery]))
+        .collect::<String>();
+    assert_eq!(compressed.lines().count(), 2, "{}", compressed);
+    assert!(compressed.contains("f1_2.xyt.gz"));
+    assert_eq!(scores(&compressed), scores(&plain));
+}
@@ -0,0 +1,138 @@
+use std::io::Read;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+mod common;
+
+use common::fixtures;
+
+/// Kills the matcher when the test ends, also when it fails.
+struct Matcher(Child);
+
+impl Drop for Matcher {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn wait_until(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    condition()
+}
+
+/// Rest of the first line of `text` starting with `prefix`.
+fn value_line<'a>(text: &'a str, prefix: &str) -> &'a str {
+    text.lines()
+        .find_map(|it| it.trim_start().strip_prefix(prefix))
+        .unwrap_or_else(|| panic!("no {:?} in {}", prefix, text))
+}
+
+#[cfg(unix)]
+#[test]
+fn interrupted_runs_write_every_completed_match() {
+    let root = std::env::temp_dir().join(format!("bz3-interrupt-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+
+    let mut galleries: Vec<_> = std::fs::read_dir(fixtures())
+        .unwrap()
+        .map(|it| it.unwrap().path())
+        .filter(|it| it.extension().and_then(|ext| ext.to_str()) == Some("xyt"))
+        .collect();
+    galleries.sort();
+    // long enough not to finish before the signal
+    let probes: Vec<_> = galleries.iter().cycle().take(2000).cloned().collect();
+    let probe_list = root.join("probes.txt");
+    let lines: Vec<_> = probes.iter().map(|it| it.to_str().unwrap()).collect();
+    std::fs::write(&probe_list, lines.join("\n")).unwrap();
+
+    for threads in ["1", "2"] {
+        let output = root.join(format!("scores-{}.txt", threads));
+        let mut matcher = Matcher(
+            Command::new(env!("CARGO_BIN_EXE_bz3"))
+                .args(["-T", threads, "-r", "-P"])
+                .arg(&probe_list)
+                .arg("-G")
+                .arg(fixtures())
+                .arg("-o")
+                .arg(&output)
+                .stdout(Stdio::null())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap(),
+        );
+
+        // the buffered writer has written some results
+        assert!(
+            wait_until(Duration::from_secs(60), || std::fs::metadata(&output)
+                .is_ok_and(|it| it.len() > 0)),
+            "no results written"
+        );
+        let killed = Command::new("kill")
+            .args(["-INT", &matcher.0.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(killed.success());
+
+        let status = matcher.0.wait().unwrap();
+        let mut stderr = String::new();
+        matcher
+            .0
+            .stderr
+            .take()
+            .unwrap()
+            .read_to_string(&mut stderr)
+            .unwrap();
+        assert_eq!(status.code(), Some(130), "{}", stderr);
+
+        let completed: usize = value_line(&stderr, "Interrupted after ")
+            .split(' ')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(completed > 0 && completed < probes.len() * galleries.len());
+
+        // results of exactly the first `completed` pairs, in any order with several threads
+        let mut actual: Vec<(String, String)> = std::fs::read_to_string(&output)
+            .unwrap()
+            .lines()
+            .map(|line| {
+                let columns: Vec<_> = line.split(' ').collect();
+                assert_eq!(columns.len(), 3, "{}", line);
+                (columns[0].to_owned(), columns[1].to_owned())
+            })
+            .collect();
+        let mut expected: Vec<(String, String)> = probes
+            .iter()
+            .flat_map(|probe| galleries.iter().map(move |gallery| (probe, gallery)))
+            .take(completed)
+            .map(|(probe, gallery)| (probe.display().to_string(), gallery.display().to_string()))
+            .collect();
+        actual.sort();
+        expected.sort();
+        assert_eq!(actual, expected);
+
+        // the hint starts with the first pair that was not matched
+        let probe = completed / galleries.len() + 1;
+        let gallery = completed % galleries.len() + 1;
+        let hint: Vec<_> = value_line(&stderr, "--probe-range ").split(' ').collect();
+        let (probe_range, gallery_range) = (hint[0], hint[2]);
+        if gallery == 1 {
+            assert_eq!(probe_range, format!("{}-{}", probe, probes.len()));
+            assert_eq!(gallery_range, format!("1-{}", galleries.len()));
+        } else {
+            assert_eq!(probe_range, format!("{}-{}", probe, probe));
+            assert_eq!(gallery_range, format!("{}-{}", gallery, galleries.len()));
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&root);
+}
@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+mod common;
+
+use common::fixtures;
+
+fn fixture(name: &str) -> PathBuf {
+    fixtures().join(name)
+}
+
+/// The score of f1_1.xyt (45 minutiae) against f1_2.xyt (46 minutiae) and the log.
+fn score_with_limit(limit: &str) -> (String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .env_remove("RUST_LOG")
+        .args(["--max-template-minutiae", limit, "-s", "-p"])
+        .arg(fixture("f1_1.xyt"))
+        .arg("-g")
+        .arg(fixture("f1_2.xyt"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+    (String::from_utf8(output.stdout).unwrap(), stderr)
+}
+
+#[test]
+fn templates_over_the_minutiae_limit_are_not_matched() {
+    let (score, stderr) = score_with_limit("46");
+    assert_eq!(score, "124\n");
+    assert!(stderr.is_empty(), "{}", stderr);
+
+    let (score, stderr) = score_with_limit("45");
+    assert_eq!(score, "-1\n");
+    assert!(stderr.contains("more than 45 minutiae"), "{}", stderr);
+    assert!(stderr.contains("f1_2.xyt"), "{}", stderr);
+}
@@ -0,0 +1,185 @@
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+use common::TempDir;
+
+fn fixture(name: &str) -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../isoparser/tests/data")
+        .join(name)
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+fn run(args: &[&str]) -> i32 {
+    Command::new(env!("CARGO_BIN_EXE_match"))
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+#[test]
+fn exit_codes() {
+    let dir = TempDir::new("exit-codes");
+    let out = dir.file("out.txt");
+    let template = fixture("thumb_and_index.ist");
+    let broken = dir.file("broken.ist");
+    std::fs::write(&broken, b"not a template").unwrap();
+    let missing = dir.file("missing.ist");
+    let unwritable = dir.file("no/such/dir/out.txt");
+
+    let cases: &[(&[&str], i32)] = &[
+        (&[&template, &template, &out], 0),
+        (&[&template, &template], 1),
+        (&["--output-format", "xml", &template, &template, &out], 1),
+        (&["--finger", "left-toe", &template, &template, &out], 1),
+        (&["--normalization", "mean", &template, &template, &out], 1),
+        (&[&template, &template, &unwritable], 2),
+        (&["--output-format", "csv", &missing, &template, &out], 3),
+        (&["--output-format", "json", &template, &missing, &out], 3),
+        (
+            &[
+                "--output-format",
+                "csv",
+                "--finger",
+                "left-index",
+                &template,
+                &template,
+                &out,
+            ],
+            5,
+        ),
+        (
+            &[
+                "--output-format",
+                "csv",
+                "--max-template-minutiae",
+                "1",
+                &template,
+                &template,
+                &out,
+            ],
+            6,
+        ),
+        (
+            &[
+                "--max-template-minutiae",
+                "many",
+                &template,
+                &template,
+                &out,
+            ],
+            1,
+        ),
+        (&["--output-format", "csv", &broken, &template, &out], 101),
+    ];
+    for (args, code) in cases {
+        assert_eq!(run(args), *code, "{:?}", args);
+    }
+
+    // writing into a full device fails after the file is opened
+    if Path::new("/dev/full").exists() {
+        assert_eq!(run(&[&template, &template, "/dev/full"]), 4);
+    }
+}
+
+#[test]
+fn legacy_format_reports_unloadable_templates_as_failed_matches() {
+    let dir = TempDir::new("legacy-fail");
+    let out = dir.file("out.txt");
+    let template = fixture("thumb_and_index.ist");
+    let broken = dir.file("broken.ist");
+    std::fs::write(&broken, b"not a template").unwrap();
+    let missing = dir.file("missing.ist");
+
+    let cases: &[&[&str]] = &[
+        &[&missing, &template, &out],
+        &[&broken, &template, &out],
+        &["--finger", "left-index", &template, &template, &out],
+        &["--max-template-minutiae", "1", &template, &template, &out],
+    ];
+    for args in cases {
+        assert_eq!(run(args), 0, "{:?}", args);
+    }
+
+    let content = std::fs::read_to_string(&out).unwrap();
+    let lines: Vec<_> = content.lines().collect();
+    assert_eq!(lines.len(), cases.len());
+    for line in lines {
+        assert_eq!(
+            &line.split_whitespace().collect::<Vec<_>>()[2..],
+            ["FAIL", "0.000000"]
+        );
+    }
+}
+
+#[test]
+fn legacy_lines_are_terminated() {
+    let dir = TempDir::new("legacy");
+    let out = dir.file("out.txt");
+    let template = fixture("thumb_and_index.ist");
+    for _ in 0..2 {
+        assert_eq!(run(&[&template, &template, &out]), 0);
+    }
+
+    let content = std::fs::read_to_string(&out).unwrap();
+    let lines: Vec<_> = content.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(content.ends_with('\n'));
+    let columns: Vec<_> = lines[0].split_whitespace().collect();
+    assert_eq!(columns[0], template);
+    assert_eq!(columns[1], template);
+    assert_eq!(columns.len(), 4);
+}
+
+#[test]
+fn csv_has_a_single_header() {
+    let dir = TempDir::new("csv");
+    let out = dir.file("out.csv");
+    let template = fixture("thumb_and_index.ist");
+    let with_comma = dir.file("a,b.ist");
+    std::fs::copy(&template, &with_comma).unwrap();
+    for probe in &[&template, &with_comma] {
+        assert_eq!(run(&["--output-format", "csv", probe, &template, &out]), 0);
+    }
+
+    let content = std::fs::read_to_string(&out).unwrap();
+    let lines: Vec<_> = content.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("in1,in2,status,score,raw_score,"));
+    assert!(lines[1].starts_with(&format!("{},{},", template, template)));
+    assert!(lines[2].starts_with(&format!("\"{}\",", with_comma)));
+}
+
+#[test]
+fn json_has_scores_used_for_normalization() {
+    let dir = TempDir::new("json");
+    let out = dir.file("out.json");
+    let template = fixture("v2005.ist");
+    assert_eq!(
+        run(&["--output-format", "json", &template, &template, &out]),
+        0
+    );
+
+    let content = std::fs::read_to_string(&out).unwrap();
+    let line: serde_json::Value = serde_json::from_str(content.trim_end()).unwrap();
+    assert_eq!(line["in1"], template.as_str());
+    assert_eq!(line["in2"], template.as_str());
+    for key in &[
+        "status",
+        "score",
+        "raw_score",
+        "self_scores",
+        "fingers",
+        "scales",
+    ] {
+        assert!(line.get(key).is_some(), "{} is missing", key);
+    }
+    assert_eq!(line["fingers"][0], "right-index");
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+use std::process::Command;
+
+mod common;
+
+use common::fixtures;
+
+fn bz3(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn pairs_over_the_timeout_are_marked() {
+    let dir = std::env::temp_dir().join(format!("match-timeout-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // a dense lattice of parallel minutiae builds as many clusters as allowed, which takes about
+    // 2 s without optimizations and 100 ms with them; the timeout is a tenth of that
+    let timeout = if cfg!(debug_assertions) { "200" } else { "10" };
+    let lattice: String = (0..36)
+        .map(|index| format!("{} {} 0 50\n", 50 + index % 6 * 12, 50 + index / 6 * 12))
+        .collect();
+    std::fs::write(dir.join("lattice.xyt"), lattice).unwrap();
+
+    let output = bz3(
+        &dir,
+        &[
+            "-p",
+            "lattice.xyt",
+            "-g",
+            "lattice.xyt",
+            "--match-timeout-ms",
+            timeout,
+        ],
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+    let fields: Vec<_> = output.split_whitespace().collect();
+    assert_eq!(fields.len(), 4, "{}", output);
+    assert!(fields[2].parse::<u32>().unwrap() > 0);
+    assert_eq!(fields[3], "timeout");
+
+    // typical pairs finish well within a generous timeout and score as without it
+    let args = ["-p", "f1_1.xyt", "-g", "f1_2.xyt"];
+    let with_timeout = bz3(
+        &fixtures(),
+        &[&args[..], &["--match-timeout-ms", "60000"]].concat(),
+    );
+    assert_eq!(with_timeout, bz3(&fixtures(), &args));
+    assert!(!with_timeout.contains("timeout"));
+}
@@ -0,0 +1,88 @@
+use std::process::Command;
+
+mod common;
+
+use common::fixtures;
+
+/// Probe, gallery and score of every reported match.
+fn matches(mode: &str) -> Vec<(String, String, u32)> {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .args(["-m", mode, "-t", "100", "-P"])
+        .arg(fixtures())
+        .arg("-G")
+        .arg(fixtures())
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            let columns: Vec<_> = line.split(' ').collect();
+            (
+                columns[0].to_owned(),
+                columns[1].to_owned(),
+                columns[2].parse().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn modes_report_scores_by_the_threshold() {
+    let all = matches("all");
+    assert!(all.iter().any(|it| it.2 < 100));
+
+    let above: Vec<_> = all.iter().filter(|it| it.2 >= 100).cloned().collect();
+    let threshold = matches("threshold");
+    assert_eq!(threshold, above);
+    assert_eq!(matches("all-matches"), threshold);
+    assert_eq!(matches("any"), all);
+
+    // the first gallery above the threshold of every probe
+    let mut first_match = above.clone();
+    first_match.dedup_by(|second, first| second.0 == first.0);
+    assert!(first_match.len() < above.len());
+    assert_eq!(matches("first-match"), first_match);
+}
+
+#[test]
+fn unknown_modes_list_the_valid_ones() {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .args(["-m", "thresold", "-p", "probe.xyt", "-g", "gallery.xyt"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("expected one of: all, first-match, threshold"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn only_scores_are_printed_in_every_mode() {
+    for mode in ["all", "first-match", "threshold"] {
+        let scores: Vec<u32> = matches(mode).into_iter().map(|it| it.2).collect();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+            .args(["-s", "-m", mode, "-t", "100", "-P"])
+            .arg(fixtures())
+            .arg("-G")
+            .arg(fixtures())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let only_scores: Vec<u32> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| line.parse().unwrap())
+            .collect();
+        assert_eq!(only_scores, scores, "{}", mode);
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+mod common;
+
+use common::fixtures;
+
+fn bz3(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+fn write_pairs(name: &str, lines: &[&str]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("{}-{}.txt", name, std::process::id()));
+    std::fs::write(&path, lines.join("\n")).unwrap();
+    path
+}
+
+#[test]
+fn missing_files_are_listed_up_front() {
+    let pairs = write_pairs(
+        "pairs-missing",
+        &[
+            "f1_1.xyt",
+            "nope_1.xyt",
+            "nope_2.xyt",
+            "f1_2.xyt",
+            "nope_1.xyt",
+            "f1_2.xyt",
+        ],
+    );
+    let output = bz3(&fixtures(), &["-M", pairs.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("2 files of pair file"), "{}", stderr);
+    assert!(stderr.contains("nope_1.xyt") && stderr.contains("nope_2.xyt"));
+    assert!(output.stdout.is_empty());
+
+    let output = bz3(
+        &fixtures(),
+        &["-M", pairs.to_str().unwrap(), "--allow-missing"],
+    );
+    std::fs::remove_file(&pairs).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn unpaired_last_line_is_skipped_with_a_warning() {
+    let pairs = write_pairs("pairs-odd", &["f1_1.xyt", "f1_2.xyt", "f2_1.xyt"]);
+    let output = bz3(&fixtures(), &["-M", pairs.to_str().unwrap()]);
+    std::fs::remove_file(&pairs).unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("pair file has 3 lines"), "{}", stderr);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1, "{}", stdout);
+    assert!(!stdout.contains("f2_1.xyt"));
+}
@@ -0,0 +1,67 @@
+use std::process::Command;
+
+mod common;
+
+use common::fixtures;
+
+#[test]
+fn sweeps_count_the_written_scores_above_every_threshold() {
+    for threads in ["1", "2"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+            .args(["-T", threads, "-r", "-P"])
+            .arg(fixtures())
+            .arg("-G")
+            .arg(fixtures())
+            .args(["--threshold-sweep", "0:120:8"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(output.status.success(), "{}", stderr);
+
+        let scores: Vec<i32> = stdout
+            .lines()
+            .map(|line| line.rsplit(' ').next().unwrap().parse().unwrap())
+            .collect();
+        assert!(scores.len() > 1);
+
+        let table: Vec<(i32, usize)> = stderr
+            .lines()
+            .skip_while(|it| !it.trim_start().starts_with("threshold"))
+            .skip(1)
+            .map(|line| {
+                let columns: Vec<_> = line.split_whitespace().collect();
+                (columns[0].parse().unwrap(), columns[1].parse().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            table.iter().map(|it| it.0).collect::<Vec<_>>(),
+            (0..=120).step_by(8).collect::<Vec<_>>()
+        );
+        for &(threshold, count) in &table {
+            assert_eq!(
+                count,
+                scores.iter().filter(|&&it| it >= threshold).count(),
+                "threshold {}",
+                threshold
+            );
+        }
+        assert!(table.windows(2).all(|it| it[0].1 >= it[1].1));
+        // the per-pair output of mode "all" does not depend on the sweep
+        assert!(table.last().unwrap().1 < scores.len());
+    }
+}
+
+#[test]
+fn sweeps_need_every_score() {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .args(["-m", "first-match", "-P"])
+        .arg(fixtures())
+        .arg("-G")
+        .arg(fixtures())
+        .args(["--threshold-sweep", "0:100:10"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--threshold-sweep"));
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+mod common;
+
+use common::fixtures;
+
+fn fixture(name: &str) -> PathBuf {
+    fixtures().join(name)
+}
+
+fn bz3(flags: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_bz3"))
+        .env_remove("RUST_LOG")
+        .args(flags)
+        .arg("-p")
+        .arg(fixture("f1_1.xyt"))
+        .arg("-g")
+        .arg(fixture("f1_2.xyt"))
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn diagnostics_go_to_stderr() {
+    let quiet = bz3(&["-q"]);
+    assert!(quiet.stderr.is_empty());
+
+    let verbose = bz3(&["-v"]);
+    assert_eq!(verbose.stdout, quiet.stdout);
+    assert_eq!(
+        String::from_utf8(verbose.stdout).unwrap().lines().count(),
+        1
+    );
+    let stderr = String::from_utf8(verbose.stderr).unwrap();
+    assert!(stderr.contains("Options {"), "{}", stderr);
+    assert!(stderr.contains("matching finished"), "{}", stderr);
+
+    let default = bz3(&[]);
+    assert!(!String::from_utf8(default.stderr)
+        .unwrap()
+        .contains("Options {"));
+}
@@ -0,0 +1,84 @@
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+mod common;
+
+use common::fixtures;
+
+/// Kills the watcher when the test ends, also when it fails.
+struct Watcher(Child);
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+fn wait_until(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if condition() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    condition()
+}
+
+#[test]
+fn new_probes_are_matched_and_moved_away() {
+    let root = std::env::temp_dir().join(format!("bz3-watch-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    let probe_dir = root.join("probes");
+    std::fs::create_dir_all(&probe_dir).unwrap();
+    let hits = root.join("hits.txt");
+
+    let _watcher = Watcher(
+        Command::new(env!("CARGO_BIN_EXE_bz3"))
+            .args(["-T", "2", "watch", "--poll-interval", "50", "--probe-dir"])
+            .arg(&probe_dir)
+            .arg("--gallery")
+            .arg(fixtures())
+            .arg("-o")
+            .arg(&hits)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap(),
+    );
+
+    // a broken probe must not stop the watcher from matching the next one
+    std::fs::write(probe_dir.join("broken.xyt"), "not a template\n").unwrap();
+    std::fs::copy(fixtures().join("f1_1.xyt"), probe_dir.join("probe.xyt")).unwrap();
+
+    let done = probe_dir.join("done/probe.xyt");
+    assert!(
+        wait_until(Duration::from_secs(30), || done.exists()),
+        "probe was not processed in time"
+    );
+    assert!(probe_dir.join("failed/broken.xyt").exists());
+    assert!(!probe_dir.join("probe.xyt").exists());
+
+    let hits = std::fs::read_to_string(&hits).unwrap();
+    let galleries: Vec<_> = hits
+        .lines()
+        .map(|line| {
+            let columns: Vec<_> = line.split(' ').collect();
+            assert_eq!(columns.len(), 4, "{}", line);
+            assert!(columns[1].ends_with("probe.xyt"));
+            assert!(columns[3].parse::<u32>().unwrap() >= 40);
+            Path::new(columns[2])
+                .file_name()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .to_owned()
+        })
+        .collect();
+    assert!(galleries.contains(&"f1_1.xyt".to_owned()));
+    assert!(galleries.contains(&"f1_2.xyt".to_owned()));
+
+    let _ = std::fs::remove_dir_all(&root);
+}